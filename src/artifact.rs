@@ -0,0 +1,181 @@
+//! `--artifact dir/`: every review of the run is written as a folder with a stable layout,
+//! to upload as a CI artifact or to attach to a pull request:
+//!
+//! - `report.md`: the review with its appendices, as shown in the terminal
+//! - `findings.json`: [`ArtifactFindings`], the findings of the review and of the checks
+//! - `prompt.txt`: the system prompt and the review input sent to the model
+//! - `metadata.toml`: [`ArtifactMetadata`], what was reviewed, how, and what was left out
+//!
+//! The first review of the run is written to `dir/` itself; runs producing several reviews
+//! (raw directory reviews split by language) write the other ones to `dir/<target slug>/`.
+
+use crate::checks::CheckFinding;
+use crate::complexity::FunctionComplexity;
+use crate::coverage::Coverage;
+use crate::findings::{finding_items, fingerprint};
+use crate::fixes::FixVerification;
+use crate::git_helpers::SkippedFile;
+use crate::report::{Report, Reporter};
+use crate::review_model::Review;
+use crate::suppress::SuppressedFinding;
+use anyhow::{Context, Result, anyhow};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Version of the artifact layout, bumped on incompatible changes
+pub const ARTIFACT_VERSION: u32 = 1;
+
+/// Item of the FINDINGS section of the review
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactFinding {
+    pub number: usize,
+    /// Same fingerprint as the one used by `.rv_suppressions`
+    pub fingerprint: String,
+    pub title: String,
+    /// Guideline rules the finding is tagged with
+    pub rules: Vec<String>,
+    /// Full text of the item, with its indented details
+    pub text: String,
+}
+
+/// Content of `findings.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactFindings {
+    pub version: u32,
+    pub findings: Vec<ArtifactFinding>,
+    pub checks: Vec<CheckFinding>,
+    pub fixes: Vec<FixVerification>,
+    pub complexity: Vec<FunctionComplexity>,
+    pub suppressed: Vec<SuppressedFinding>,
+    /// Typed review, when the model was asked for JSON (`--json-review`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review: Option<Review>,
+}
+
+/// Content of `metadata.toml`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactMetadata {
+    pub version: u32,
+    pub rv_version: String,
+    /// ID of the review in the local history
+    pub id: Option<String>,
+    pub target: String,
+    pub commit: Option<String>,
+    pub model: String,
+    pub tags: Vec<String>,
+    pub reviewed_files: Vec<String>,
+    pub coverage: Option<Coverage>,
+    pub not_reviewed: Vec<SkippedFile>,
+}
+
+impl ArtifactFindings {
+    pub fn of(report: &Report) -> ArtifactFindings {
+        let lines: Vec<&str> = report.review.lines().collect();
+        let findings = finding_items(&report.review)
+            .into_iter()
+            .map(|item| ArtifactFinding {
+                number: item.number,
+                fingerprint: fingerprint(&report.review, &item),
+                text: lines[item.lines.clone()].join("\n"),
+                title: item.title,
+                rules: item.rules,
+            })
+            .collect();
+        ArtifactFindings {
+            version: ARTIFACT_VERSION,
+            findings,
+            checks: report.checks.clone(),
+            fixes: report.fixes.clone(),
+            complexity: report.complexity.clone(),
+            suppressed: report.suppressed.clone(),
+            review: report.structured.clone(),
+        }
+    }
+}
+
+impl ArtifactMetadata {
+    pub fn of(report: &Report) -> ArtifactMetadata {
+        ArtifactMetadata {
+            version: ARTIFACT_VERSION,
+            rv_version: env!("CARGO_PKG_VERSION").to_string(),
+            id: report.id.clone(),
+            target: report.target.clone(),
+            commit: report.commit.clone(),
+            model: report.model.clone(),
+            tags: report.tags.clone(),
+            reviewed_files: report.reviewed_files.clone(),
+            coverage: report.coverage,
+            not_reviewed: report.not_reviewed.clone(),
+        }
+    }
+}
+
+/// Folder name of a review target (ex. `raw-dir-src-python`)
+fn slug(target: &str) -> String {
+    let words: Vec<String> = target
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("-")
+}
+
+/// Write the artifact of `report` into `dir`
+pub fn write_artifact(dir: &Path, report: &Report) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    std::fs::write(dir.join("report.md"), report.markdown())?;
+    std::fs::write(
+        dir.join("findings.json"),
+        serde_json::to_string_pretty(&ArtifactFindings::of(report))?,
+    )?;
+    std::fs::write(dir.join("prompt.txt"), &report.prompt)?;
+    std::fs::write(
+        dir.join("metadata.toml"),
+        toml::to_string(&ArtifactMetadata::of(report))?,
+    )?;
+    Ok(())
+}
+
+/// Write every report of the run as an artifact folder
+pub struct ArtifactReporter {
+    dir: PathBuf,
+    written: Mutex<usize>,
+}
+
+impl ArtifactReporter {
+    pub fn new(dir: PathBuf) -> ArtifactReporter {
+        ArtifactReporter {
+            dir,
+            written: Mutex::new(0),
+        }
+    }
+}
+
+impl Reporter for ArtifactReporter {
+    fn name(&self) -> &'static str {
+        "artifact directory"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let dir = {
+                let mut written = self
+                    .written
+                    .lock()
+                    .map_err(|_| anyhow!("Artifact lock poisoned"))?;
+                *written += 1;
+                if *written == 1 {
+                    self.dir.clone()
+                } else {
+                    self.dir.join(slug(&report.target))
+                }
+            };
+            write_artifact(&dir, report)
+        }
+        .boxed()
+    }
+}
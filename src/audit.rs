@@ -0,0 +1,191 @@
+//! `rv audit-merges --since 7d`: review the merges into the main branch of a time window and
+//! end with a quality digest of the period, meant to run on a schedule (ex. a weekly CI job)
+
+use crate::batch::{self, BatchMode, BatchResult, BatchTarget};
+use crate::budget::TimeBudget;
+use crate::config::RvConfig;
+use crate::findings::finding_items;
+use crate::git_helpers;
+use crate::report::Reporter;
+
+use anyhow::{Context, Result};
+use git2::Oid;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Finding titles listed for each merge in the digest
+const DIGEST_TITLES: usize = 5;
+
+/// Merge commit of the audited window
+struct Merge {
+    oid: Oid,
+    subject: String,
+    author: String,
+}
+
+fn describe_merge(oid: Oid) -> Merge {
+    let message = git_helpers::commit_message(oid).unwrap_or_default();
+    let author = git_helpers::open_repo()
+        .and_then(|repo| repo.find_commit(oid).map(|c| c.author().to_string()))
+        .unwrap_or_default();
+    Merge {
+        oid,
+        subject: message.lines().next().unwrap_or_default().to_string(),
+        author,
+    }
+}
+
+/// First line of the INTENT section of a summary
+fn summary_intent(summary: &str) -> Option<String> {
+    summary.lines().find_map(|line| {
+        let (_, intent) = line.split_once("INTENT:")?;
+        Some(intent.trim().to_string())
+    })
+}
+
+/// Human-readable window (ex. `7d`)
+fn format_window(since: Duration) -> String {
+    let seconds = since.as_secs();
+    match seconds {
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s => format!("{}m", s.div_ceil(60)),
+    }
+}
+
+/// Quality digest of the audited merges: totals of the window, then the outcome of each merge
+fn format_digest(
+    branch: &str,
+    since: Duration,
+    merges: &[Merge],
+    results: &[BatchResult],
+) -> String {
+    let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+    let findings: usize = results.iter().filter_map(|r| r.findings).sum();
+    let checks: usize = results.iter().filter_map(|r| r.checks).sum();
+    let not_reviewed: usize = results.iter().map(|r| r.not_reviewed).sum();
+
+    let mut digest = format!(
+        "AUDIT DIGEST (merges into {branch} in the last {})\n",
+        format_window(since)
+    );
+    digest.push_str(&format!(
+        "Merges       : {} ({} reviewed, {} summarized, {} failed, {} not reviewed)\n",
+        merges.len(),
+        count("reviewed"),
+        count("summarized"),
+        count("failed"),
+        count("no time") + count("no changes")
+    ));
+    if count("reviewed") > 0 {
+        let clean = results.iter().filter(|r| r.findings == Some(0)).count();
+        digest.push_str(&format!(
+            "Findings     : {findings} ({clean} merges without findings)\n"
+        ));
+    }
+    digest.push_str(&format!("Check issues : {checks}\n"));
+    digest.push_str(&format!("Not reviewed : {not_reviewed} files\n"));
+
+    for (merge, result) in merges.iter().zip(results) {
+        let outcome = match result.findings {
+            Some(findings) => format!("{findings} findings"),
+            None => result.status.clone(),
+        };
+        digest.push_str(&format!(
+            "\n- {:.8} {} ({}): {outcome}\n",
+            merge.oid.to_string(),
+            merge.subject,
+            merge.author
+        ));
+        let Some(review) = &result.review else {
+            continue;
+        };
+        if let Some(intent) = summary_intent(review)
+            && result.findings.is_none()
+        {
+            digest.push_str(&format!("    {intent}\n"));
+        }
+        let items = finding_items(review);
+        for item in items.iter().take(DIGEST_TITLES) {
+            digest.push_str(&format!("    {}. {}\n", item.number, item.title));
+        }
+        if items.len() > DIGEST_TITLES {
+            digest.push_str(&format!("    ... {} more\n", items.len() - DIGEST_TITLES));
+        }
+    }
+    digest
+}
+
+/// Review the merges into `branch` (the main branch by default) of the last `since`
+#[allow(clippy::too_many_arguments)]
+pub async fn audit_merges(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    since: Duration,
+    branch: Option<String>,
+    mode: BatchMode,
+    jobs: usize,
+    pipe: bool,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<()> {
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let (branch_name, tip) = match branch {
+        Some(name) => {
+            let tip = git_helpers::resolve_branch(&name, rvconfig.auto_fetch)
+                .with_context(|| format!("Failed to resolve {name}"))?;
+            (name, tip)
+        }
+        None => {
+            let repo = git_helpers::open_repo()?;
+            let tip = git_helpers::find_base_branch(&repo, &rvconfig.base_branches)?;
+            (String::from("the main branch"), tip)
+        }
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let start = now.saturating_sub(since).as_secs() as i64;
+    let merges: Vec<Merge> = git_helpers::merges_since(tip, start)?
+        .into_iter()
+        .map(describe_merge)
+        .collect();
+    if merges.is_empty() {
+        println!(
+            "No merges into {branch_name} in the last {}.",
+            format_window(since)
+        );
+        return Ok(());
+    }
+    if !pipe {
+        println!(
+            "Auditing {} merges into {branch_name} in the last {}...",
+            merges.len(),
+            format_window(since)
+        );
+    }
+
+    // A merge is reviewed as what it brought into the branch (the diff against its first parent)
+    let targets: Vec<BatchTarget> = merges
+        .iter()
+        .map(|merge| BatchTarget::Commit(merge.oid.to_string()))
+        .collect();
+    let Some(results) = batch::review_targets(
+        rvconfig,
+        llm_selection,
+        &targets,
+        mode,
+        jobs,
+        pipe,
+        assume_yes,
+        time_budget,
+        reporters,
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    println!("\n{}", batch::format_summary(&results));
+    println!("{}", format_digest(&branch_name, since, &merges, &results));
+    batch::batch_outcome(&results)
+}
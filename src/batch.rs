@@ -0,0 +1,540 @@
+//! `rv batch targets.txt`: review a list of targets (commits, pull requests, paths), one per line,
+//! a few at a time, and end with a summary table of the whole batch. The same machinery reviews
+//! the merges of `rv audit-merges`.
+
+use crate::budget::TimeBudget;
+use crate::checks::{CheckFinding, run_checks};
+use crate::collect::FileCollector;
+use crate::complexity::{FunctionComplexity, changed_functions};
+use crate::config::{CustomPrompt, RvConfig};
+use crate::context::estimate_tokens;
+use crate::coverage::Coverage;
+use crate::encoding::{self, Decoded};
+use crate::findings::finding_items;
+use crate::git_helpers::{self, ExpandedCommit, SkippedFile};
+use crate::github;
+use crate::history::{ReviewRecord, Usage};
+use crate::ignore::IgnoreRules;
+use crate::llm::create_llm_provider;
+use crate::minimize::{MinimizeOptions, minimize};
+use crate::profile::SUMMARY_SYSTEM_PROMPT;
+use crate::pseudonyms::{PathMap, redact_paths};
+use crate::report::{self, PartialFailure, Report, Reporter, Verdict};
+use crate::request::{self, RequestPipeline};
+use crate::review::{
+    ESTIMATED_REVIEW_OUTPUT_TOKENS, SYSTEM_PROMPT, pack_prompt, request_structured_review,
+};
+use crate::sanitize::sanitize_output;
+use crate::suppress::Suppressions;
+use crate::term_helpers;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use rig::message::Message;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Width of the target column of the summary table
+const TARGET_COLUMN: usize = 40;
+
+/// How the targets of a batch are reviewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum BatchMode {
+    /// Full review, with its findings
+    #[default]
+    Full,
+    /// Summary of the changes and their hotspots, cheaper than a review
+    Summary,
+}
+
+/// Target of a batch, from a line of the targets file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchTarget {
+    /// Commit SHA or any revision
+    Commit(String),
+    /// Pull request number or URL
+    PullRequest(String),
+    /// File or directory, reviewed like `--raw`
+    Path(PathBuf),
+}
+
+impl BatchTarget {
+    /// Parse a line of the targets file; empty lines and `#` comments are `None`.
+    /// `commit:`, `pr:` and `path:` prefixes remove the ambiguity of a line.
+    pub fn parse(line: &str) -> Option<BatchTarget> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if let Some(rev) = line.strip_prefix("commit:") {
+            return Some(BatchTarget::Commit(rev.trim().to_string()));
+        }
+        if let Some(pr) = line.strip_prefix("pr:") {
+            return Some(BatchTarget::PullRequest(pr.trim().to_string()));
+        }
+        if let Some(path) = line.strip_prefix("path:") {
+            return Some(BatchTarget::Path(PathBuf::from(path.trim())));
+        }
+
+        let number = line.strip_prefix('#').unwrap_or(line);
+        if line.contains("/pull/") || number.chars().all(|c| c.is_ascii_digit()) {
+            Some(BatchTarget::PullRequest(number.to_string()))
+        } else if Path::new(line).exists() {
+            Some(BatchTarget::Path(PathBuf::from(line)))
+        } else {
+            Some(BatchTarget::Commit(line.to_string()))
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            BatchTarget::Commit(rev) => format!("commit {rev}"),
+            BatchTarget::PullRequest(pr) => format!("pr {pr}"),
+            BatchTarget::Path(path) => format!("path {}", path.display()),
+        }
+    }
+}
+
+/// Read the targets file
+pub fn read_targets(path: &Path) -> Result<Vec<BatchTarget>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(content.lines().filter_map(BatchTarget::parse).collect())
+}
+
+/// Review input of a file or directory, with the raw mode limits and exclusions
+fn expanded_from_path(path: &Path, rvconfig: &RvConfig) -> Result<ExpandedCommit> {
+    let mut expcommit = ExpandedCommit::new();
+    let files = if path.is_dir() {
+        let mut ignore = IgnoreRules::new();
+        ignore.add_patterns(rvconfig.raw.exclude.iter().map(String::as_str));
+        ignore.add_rvignore();
+        let mut collector = FileCollector::new(path, true, rvconfig.raw.clone(), ignore)?;
+        collector.collect(path)?;
+        expcommit.skipped = collector.skipped;
+        collector.files
+    } else {
+        vec![path.to_path_buf()]
+    };
+    if files.is_empty() {
+        bail!("No files to review in {}", path.display());
+    }
+
+    let mut diffs = Vec::new();
+    for file in &files {
+        diffs.push(match encoding::read_source(file)? {
+            Decoded::Binary => format!("File: {file:?} (binary, content not shown)"),
+            Decoded::Text(content) if rvconfig.diff_profile.report_sources => {
+                format!("File: {file:?}\n{content}")
+            }
+            Decoded::Text(_) => format!("File: {file:?} (content not shown)"),
+        });
+    }
+    expcommit.sources = Some(files);
+    expcommit.diffs = Some(diffs);
+    Ok(expcommit)
+}
+
+/// Review input of a target, with the exclusions and the minimization of the Git reviews
+fn expand_target(target: &BatchTarget, rvconfig: &RvConfig) -> Result<(String, ExpandedCommit)> {
+    let (name, expcommit) = match target {
+        BatchTarget::Path(path) => {
+            return Ok((target.name(), expanded_from_path(path, rvconfig)?));
+        }
+        BatchTarget::Commit(rev) => {
+            let oid = git_helpers::get_oid(rev).with_context(|| format!("Unknown commit {rev}"))?;
+            (
+                format!("commit {oid}"),
+                git_helpers::expanded_from_commit(oid)?,
+            )
+        }
+        BatchTarget::PullRequest(pr) => (target.name(), github::expanded_commit_from_pr(pr)?),
+    };
+    let ignore = IgnoreRules::from_rvignore();
+    let expcommit = expcommit.exclude(|path| ignore.is_path_ignored(path), "excluded by .rvignore");
+    let (expcommit, _) = minimize(expcommit, &MinimizeOptions::from_config(rvconfig));
+    Ok((name, expcommit))
+}
+
+/// Line of the summary table
+pub struct BatchResult {
+    /// Position of the target in the targets file
+    pub index: usize,
+    pub target: String,
+    pub status: String,
+    pub findings: Option<usize>,
+    pub checks: Option<usize>,
+    pub not_reviewed: usize,
+    /// Review or summary, after the suppressions
+    pub review: Option<String>,
+}
+
+impl BatchResult {
+    fn new(index: usize, target: String, status: &str) -> BatchResult {
+        BatchResult {
+            index,
+            target,
+            status: status.to_string(),
+            findings: None,
+            checks: None,
+            not_reviewed: 0,
+            review: None,
+        }
+    }
+}
+
+/// Summary table of the batch, one line per target; the status is `reviewed`, `summarized`,
+/// `no changes`, `failed` or `no time` (the time budget ran out before the review)
+pub fn format_summary(results: &[BatchResult]) -> String {
+    let mut table = format!(
+        "BATCH SUMMARY ({} targets)\n{:<TARGET_COLUMN$}  {:<10}  {:>8}  {:>6}  {:>12}\n",
+        results.len(),
+        "TARGET",
+        "STATUS",
+        "FINDINGS",
+        "CHECKS",
+        "NOT REVIEWED"
+    );
+    let count = |value: Option<usize>| value.map_or(String::from("-"), |v| v.to_string());
+    for result in results {
+        let mut target = result.target.clone();
+        if target.chars().count() > TARGET_COLUMN {
+            target = target.chars().take(TARGET_COLUMN - 3).collect::<String>() + "...";
+        }
+        table.push_str(&format!(
+            "{target:<TARGET_COLUMN$}  {:<10}  {:>8}  {:>6}  {:>12}\n",
+            result.status,
+            count(result.findings),
+            count(result.checks),
+            result.not_reviewed
+        ));
+    }
+    table
+}
+
+/// Target ready to be sent
+struct PreparedTarget {
+    index: usize,
+    name: String,
+    prompt: String,
+    reviewed_files: Vec<String>,
+    skipped: Vec<SkippedFile>,
+    checks: Vec<CheckFinding>,
+    coverage: Option<Coverage>,
+    complexity: Vec<FunctionComplexity>,
+    commit: Option<String>,
+    changed_lines: Option<usize>,
+}
+
+/// Review `targets`, `jobs` at a time, dispatching every review to `reporters`.
+/// Returns the results in the order of the targets, or `None` when the run was cancelled.
+#[allow(clippy::too_many_arguments)]
+pub async fn review_targets(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    targets: &[BatchTarget],
+    mode: BatchMode,
+    jobs: usize,
+    pipe: bool,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<Option<Vec<BatchResult>>> {
+    let pipeline = RequestPipeline::new(rvconfig)?;
+    let rvconfig = &pipeline.rvconfig;
+    let _ = git_helpers::enter_workdir();
+
+    // Targets that can't be expanded (ex. unknown commits) are reported, not fatal
+    let mut results: Vec<BatchResult> = Vec::new();
+    let mut prepared: Vec<PreparedTarget> = Vec::new();
+    for (index, target) in targets.iter().enumerate() {
+        match expand_target(target, rvconfig) {
+            Ok((name, expcommit)) if expcommit.is_empty() => results.push(BatchResult {
+                not_reviewed: expcommit.skipped.len(),
+                ..BatchResult::new(index, name, "no changes")
+            }),
+            Ok((name, expcommit)) => {
+                let checks = run_checks(&rvconfig.checks, &expcommit);
+                let coverage = Coverage::of(&expcommit);
+                let complexity = changed_functions(&rvconfig.complexity, &expcommit);
+                let commit = expcommit.commits.last().map(|oid| oid.to_string());
+                let skipped = expcommit.skipped.clone();
+                let changed_lines = expcommit.changed_lines();
+                let reviewed_files = expcommit
+                    .sources
+                    .iter()
+                    .flatten()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect();
+                let prompt = pipeline.redact(expcommit.get_xml_structure(rvconfig.diff_profile));
+                prepared.push(PreparedTarget {
+                    index,
+                    name,
+                    prompt,
+                    reviewed_files,
+                    skipped,
+                    checks,
+                    coverage,
+                    complexity,
+                    commit,
+                    changed_lines,
+                });
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Failed to prepare {}: {e:#}", target.name());
+                results.push(BatchResult::new(index, target.name(), "failed"));
+            }
+        }
+    }
+    if targets
+        .iter()
+        .any(|target| matches!(target, BatchTarget::PullRequest(_)))
+    {
+        github::cleanup_pr_refs(rvconfig.keep_pr_refs);
+    }
+    if prepared.is_empty() {
+        println!("\n{}", format_summary(&results));
+        bail!("None of the targets can be reviewed");
+    }
+
+    let paths: Vec<String> = prepared
+        .iter()
+        .flat_map(|target| target.reviewed_files.clone())
+        .collect();
+    let prompt_tokens: usize = prepared.iter().map(|t| estimate_tokens(&t.prompt)).sum();
+    let (llm_configuration_key, llm_configuration) =
+        pipeline.select_llm(llm_selection, prompt_tokens, &paths, pipe)?;
+    let path_map = rvconfig
+        .redact_paths
+        .then(|| Arc::new(PathMap::new(&paths)));
+    let system_prompt = pipeline.system_prompt(pack_prompt(
+        match mode {
+            BatchMode::Full => SYSTEM_PROMPT,
+            BatchMode::Summary => SUMMARY_SYSTEM_PROMPT,
+        },
+        rvconfig,
+        Some(&llm_configuration),
+        None,
+        None,
+        &mut Vec::new(),
+    )?);
+    // The code of every target is untrusted
+    pipeline.prepare(
+        &llm_configuration,
+        &system_prompt,
+        prepared.iter_mut().map(|target| &mut target.prompt),
+    )?;
+
+    let input_tokens: usize = prepared
+        .iter()
+        .map(|target| estimate_tokens(&system_prompt) + estimate_tokens(&target.prompt))
+        .sum();
+    let requests = format!(
+        "{} reviews, {} at a time (~{} more output tokens)",
+        prepared.len(),
+        jobs,
+        ESTIMATED_REVIEW_OUTPUT_TOKENS * (prepared.len() - 1)
+    );
+    if !request::confirm_requests(
+        &llm_configuration,
+        paths.len(),
+        input_tokens,
+        Some(requests),
+        assume_yes,
+    )? {
+        return Ok(None);
+    }
+    if !pipe {
+        term_helpers::clear_term();
+        println!("Reviewing {} targets...", prepared.len());
+    }
+
+    // Summaries don't follow the structure of the reviews
+    let reformat = mode == BatchMode::Full
+        && rvconfig.reformat_invalid_reviews
+        && !matches!(
+            llm_configuration.custom_prompt,
+            Some(CustomPrompt::Replace(_))
+        );
+    let suppressions = Suppressions::load();
+    for chunk in prepared.chunks(jobs.max(1)) {
+        // Once the budget is exhausted, the remaining targets are only reported
+        if time_budget.is_exhausted() {
+            for target in chunk {
+                results.push(BatchResult {
+                    checks: Some(target.checks.len()),
+                    not_reviewed: target.reviewed_files.len() + target.skipped.len(),
+                    ..BatchResult::new(target.index, target.name.clone(), "no time")
+                });
+            }
+            continue;
+        }
+
+        let requests: Vec<_> = chunk
+            .iter()
+            .map(|target| {
+                let client = redact_paths(
+                    create_llm_provider(llm_configuration.clone()),
+                    path_map.as_ref(),
+                );
+                let system_prompt = system_prompt.clone();
+                let messages = vec![Message::user(target.prompt.clone())];
+                async move {
+                    request_structured_review(client.as_ref(), system_prompt, messages, reformat)
+                        .await
+                }
+            })
+            .collect();
+        let responses = futures::future::join_all(requests).await;
+
+        for (target, response) in chunk.iter().zip(responses) {
+            println!("\n=== {} ===\n", target.name);
+            let response = match response {
+                Ok(response) => sanitize_output(&response, rvconfig.normalize_output),
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to review {}: {e:#}", target.name);
+                    results.push(BatchResult {
+                        checks: Some(target.checks.len()),
+                        not_reviewed: target.reviewed_files.len() + target.skipped.len(),
+                        ..BatchResult::new(target.index, target.name.clone(), "failed")
+                    });
+                    continue;
+                }
+            };
+            println!("{response}");
+            let (review, suppressed) = suppressions.apply(&response);
+
+            let mut record = ReviewRecord::new(
+                target.name.clone(),
+                target.commit.clone(),
+                llm_configuration_key.clone(),
+                llm_configuration.model_id.clone(),
+                target.reviewed_files.clone(),
+                target.skipped.clone(),
+                review.clone(),
+            );
+            record.tags = rvconfig.tags.clone();
+            record.changed_lines = target.changed_lines;
+            record.set_usage(
+                rvconfig,
+                Usage::estimate(
+                    &llm_configuration,
+                    estimate_tokens(&system_prompt) + estimate_tokens(&target.prompt),
+                    estimate_tokens(&response),
+                ),
+            );
+            let review_id = match record.save() {
+                Ok(_) => Some(record.id.clone()),
+                Err(e) => {
+                    eprintln!("[WARN] Failed to save review to history: {e}");
+                    None
+                }
+            };
+            let (status, findings) = match mode {
+                BatchMode::Full => ("reviewed", Some(finding_items(&review).len())),
+                BatchMode::Summary => ("summarized", None),
+            };
+            results.push(BatchResult {
+                findings,
+                checks: Some(target.checks.len()),
+                not_reviewed: target.skipped.len(),
+                review: Some(review.clone()),
+                ..BatchResult::new(target.index, target.name.clone(), status)
+            });
+            report::dispatch(
+                reporters,
+                &Report {
+                    id: review_id,
+                    target: target.name.clone(),
+                    verdict: Verdict::Reviewed,
+                    commit: target.commit.clone(),
+                    model: llm_configuration.model_id.clone(),
+                    tags: rvconfig.tags.clone(),
+                    review,
+                    reviewed_files: target.reviewed_files.clone(),
+                    not_reviewed: target.skipped.clone(),
+                    suppressed,
+                    checks: target.checks.clone(),
+                    fixes: Vec::new(),
+                    complexity: target.complexity.clone(),
+                    coverage: target.coverage,
+                    structured: None,
+                    prompt: format!("{system_prompt}\n\n{}", target.prompt),
+                    patches: BTreeMap::new(),
+                },
+            )
+            .await;
+        }
+    }
+
+    // Results follow the order of the targets
+    results.sort_by_key(|result| result.index);
+    Ok(Some(results))
+}
+
+/// Error of a batch where some reviews failed: every one of them, or only a part
+pub fn batch_outcome(results: &[BatchResult]) -> Result<()> {
+    let failures = results.iter().filter(|r| r.status == "failed").count();
+    let sent = results
+        .iter()
+        .filter(|r| r.status == "failed" || r.review.is_some())
+        .count();
+    if failures > 0 && failures == sent {
+        bail!("Every review of the batch failed");
+    }
+    if failures > 0 {
+        return Err(PartialFailure {
+            failed: failures,
+            total: results.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Review every target of `targets_file`, `jobs` at a time
+#[allow(clippy::too_many_arguments)]
+pub async fn batch_review(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    targets_file: &Path,
+    mode: BatchMode,
+    jobs: usize,
+    pipe: bool,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<()> {
+    // Paths are relative to where rv runs, while Git reviews run from the root of the working tree
+    let targets: Vec<BatchTarget> = read_targets(targets_file)?
+        .into_iter()
+        .map(|target| match target {
+            BatchTarget::Path(path) => {
+                BatchTarget::Path(std::path::absolute(&path).unwrap_or(path))
+            }
+            target => target,
+        })
+        .collect();
+    if targets.is_empty() {
+        bail!("{} doesn't list any target", targets_file.display());
+    }
+
+    let Some(results) = review_targets(
+        rvconfig,
+        llm_selection,
+        &targets,
+        mode,
+        jobs,
+        pipe,
+        assume_yes,
+        time_budget,
+        reporters,
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    println!("\n{}", format_summary(&results));
+    batch_outcome(&results)
+}
@@ -0,0 +1,74 @@
+use std::time::{Duration, Instant};
+
+/// Reason reported for the content left out when the time budget runs out
+pub const BUDGET_EXHAUSTED_REASON: &str = "time budget exhausted";
+
+/// Wall-clock budget of a run; once exhausted, no new review request is started
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeBudget {
+    deadline: Option<Instant>,
+}
+
+impl TimeBudget {
+    /// Start a budget of `limit`, or an unlimited one; a deadline too far to be represented is none
+    pub fn new(limit: Option<Duration>) -> TimeBudget {
+        TimeBudget {
+            deadline: limit.and_then(|limit| Instant::now().checked_add(limit)),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Parse a duration such as `120s`, `2m`, `1h`, `7d`, `2w` or `90` (seconds)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration `{s}`, expected ex. `120s`, `2m` or `1h`"))?;
+
+    let multiplier: u64 = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => {
+            return Err(format!(
+                "unknown duration unit `{unit}`, expected `s`, `m`, `h`, `d` or `w`"
+            ));
+        }
+    };
+    let seconds = value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration `{s}` is too large"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_durations_overflowing_the_seconds() {
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(1_209_600)));
+        assert_eq!(
+            parse_duration(&format!("{}s", u64::MAX)),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+        let too_large = parse_duration(&format!("{}w", u64::MAX / 2)).unwrap_err();
+        assert!(too_large.contains("too large"), "{too_large}");
+    }
+
+    #[test]
+    fn has_no_deadline_beyond_the_clock() {
+        let budget = TimeBudget::new(Some(Duration::from_secs(u64::MAX)));
+        assert!(!budget.is_exhausted());
+        assert!(TimeBudget::new(Some(Duration::ZERO)).is_exhausted());
+    }
+}
@@ -0,0 +1,112 @@
+//! Cache of the reviews, keyed by a hash of the prompts and the model, so that reviewing an
+//! unchanged change again returns the same review without a request (`--no-cache` bypasses it)
+
+use crate::config::LLMConfig;
+use crate::paths::{self, Dir};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Directory of the cached reviews, in the cache directory
+pub const REVIEWS_DIR: &str = "reviews";
+
+/// Extension of the cached reviews
+const EXTENSION: &str = "md";
+
+/// FNV-1a on 128 bits, stable across Rust versions unlike the standard hasher
+fn fnv1a_128(parts: &[&str]) -> u128 {
+    let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    for part in parts {
+        // The length keeps `ab` + `c` and `a` + `bc` apart
+        for byte in part.len().to_le_bytes().iter().chain(part.as_bytes()) {
+            hash ^= u128::from(*byte);
+            hash = hash.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+    }
+    hash
+}
+
+/// Key of a review: its prompts, the model answering them and the parameters of the requests
+/// (sampling and reasoning). `boundary` is the random token of the prompt injection guard, left
+/// out so that it doesn't change the key of every run
+pub fn review_key(
+    llm_config: &LLMConfig,
+    system_prompt: &str,
+    review_prompt: &str,
+    boundary: Option<&str>,
+) -> String {
+    let stable = |prompt: &str| match boundary {
+        Some(token) => prompt.replace(token, "BOUNDARY"),
+        None => prompt.to_string(),
+    };
+    let parameters = serde_json::to_string(&(
+        llm_config.sampling,
+        llm_config.allow_reasoning,
+        llm_config.reasoning_effort,
+    ))
+    .unwrap_or_default();
+    format!(
+        "{:032x}",
+        fnv1a_128(&[
+            llm_config.provider.name(),
+            &llm_config.endpoint(),
+            &llm_config.model_id,
+            &parameters,
+            &stable(system_prompt),
+            &stable(review_prompt),
+        ])
+    )
+}
+
+fn reviews_dir() -> io::Result<PathBuf> {
+    Ok(paths::dir(Dir::Cache)?.join(REVIEWS_DIR))
+}
+
+/// Cached review of `key`, if any
+pub fn lookup(key: &str) -> Option<String> {
+    let path = reviews_dir().ok()?.join(format!("{key}.{EXTENSION}"));
+    fs::read_to_string(path)
+        .ok()
+        .filter(|review| !review.trim().is_empty())
+}
+
+/// Cache `review` under `key`
+pub fn store(key: &str, review: &str) -> io::Result<()> {
+    let dir = reviews_dir()?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{key}.{EXTENSION}")), review)
+}
+
+/// Cached reviews last written more than `max_age` ago, for `rv clean`
+pub fn expired_entries(max_age: Duration) -> io::Result<Vec<PathBuf>> {
+    let dir = reviews_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    let mut expired = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != EXTENSION)
+        {
+            continue;
+        }
+        let age = entry
+            .metadata()?
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if age > max_age {
+            expired.push(path);
+        }
+    }
+    Ok(expired)
+}
@@ -0,0 +1,377 @@
+//! Deterministic checks of the reviewed changes: they run locally, without the LLM, and their
+//! findings are reported in a CHECKS appendix along with the review
+
+use crate::config::{ChecksConfig, SignoffRequirement};
+use crate::context::resolve_project_file;
+use crate::git_helpers::{self, ExpandedCommit, FilePatch};
+use crate::markup;
+use git2::{Commit, Delta, Oid, Repository};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Files telling that the repository requires the Developer Certificate of Origin
+const DCO_FILES: &[&str] = &["DCO", "DCO.md", "DCO.txt", ".github/dco.yml"];
+
+/// Name of the copy-paste check, also used to pick its findings for the prompt
+const DUPLICATE_CHECK: &str = "duplicate-code";
+
+/// Tokens of the shingles indexing the added code
+const SHINGLE_TOKENS: usize = 10;
+
+/// Shingles found more often than this are boilerplate (ex. closing braces), not copies
+const MAX_SHINGLE_OCCURRENCES: usize = 32;
+
+/// Copies reported at most, the longest first
+const MAX_DUPLICATE_FINDINGS: usize = 10;
+
+/// Finding of a deterministic check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckFinding {
+    /// Name of the check (ex. `signature`)
+    pub check: String,
+    /// Commit or file the finding is about
+    pub location: String,
+    pub message: String,
+}
+
+impl CheckFinding {
+    pub fn new(check: &str, location: String, message: String) -> CheckFinding {
+        CheckFinding {
+            check: check.to_string(),
+            location,
+            message,
+        }
+    }
+}
+
+fn requires_signoff(requirement: SignoffRequirement) -> bool {
+    match requirement {
+        SignoffRequirement::Always => true,
+        SignoffRequirement::Never => false,
+        SignoffRequirement::Auto => DCO_FILES
+            .iter()
+            .any(|file| resolve_project_file(file).is_some()),
+    }
+}
+
+/// Kind of the signature of a commit, `None` when it isn't signed (the signature isn't verified)
+fn signature_kind(repo: &Repository, oid: Oid) -> Option<&'static str> {
+    let (signature, _) = repo.extract_signature(&oid, None).ok()?;
+    let signature = signature.as_str().unwrap_or_default();
+    Some(if signature.contains("BEGIN PGP SIGNATURE") {
+        "GPG"
+    } else if signature.contains("BEGIN SSH SIGNATURE") {
+        "SSH"
+    } else {
+        "X.509"
+    })
+}
+
+/// Problem with the `Signed-off-by` lines of `commit`, if any
+fn signoff_problem(commit: &Commit) -> Option<String> {
+    let message = commit.message().unwrap_or_default();
+    let signoffs: Vec<&str> = message
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Signed-off-by:"))
+        .map(str::trim)
+        .collect();
+    if signoffs.is_empty() {
+        return Some(String::from("missing Signed-off-by line (DCO)"));
+    }
+    let author = commit.author();
+    let email = author.email().unwrap_or_default();
+    if !email.is_empty()
+        && !signoffs
+            .iter()
+            .any(|signoff| signoff.contains(&format!("<{email}>")))
+    {
+        return Some(format!(
+            "no Signed-off-by line matches the author <{email}>"
+        ));
+    }
+    None
+}
+
+/// Signature and sign-off findings of the reviewed commits
+fn commit_checks(config: &ChecksConfig, commits: &[Oid]) -> Vec<CheckFinding> {
+    let signoff = requires_signoff(config.signoff);
+    if commits.is_empty() || (!config.signatures && !signoff) {
+        return Vec::new();
+    }
+    let Ok(repo) = git_helpers::open_repo() else {
+        return Vec::new();
+    };
+
+    let mut findings = Vec::new();
+    for oid in commits {
+        let Ok(commit) = repo.find_commit(*oid) else {
+            continue;
+        };
+        let location = format!("commit {:.8}", oid.to_string());
+        if config.signatures && signature_kind(&repo, *oid).is_none() {
+            findings.push(CheckFinding::new(
+                "signature",
+                location.clone(),
+                String::from("not signed (GPG, SSH or X.509)"),
+            ));
+        }
+        if signoff && let Some(problem) = signoff_problem(&commit) {
+            findings.push(CheckFinding::new("signoff", location, problem));
+        }
+    }
+    findings
+}
+
+/// Size of the new version of a file, read from its blob when git didn't load it
+fn new_file_size(repo: Option<&Repository>, patch: &FilePatch) -> u64 {
+    if patch.new_size > 0 {
+        return patch.new_size;
+    }
+    match (repo, patch.new_oid) {
+        (Some(repo), Some(oid)) => repo
+            .find_blob(oid)
+            .map(|blob| blob.size() as u64)
+            .unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// Human-readable size (ex. `1.5 MiB`)
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Large and binary files added or modified by the changes, which usually belong to Git LFS
+fn file_checks(config: &ChecksConfig, expcommit: &ExpandedCommit) -> Vec<CheckFinding> {
+    if config.max_added_file_bytes == 0 && !config.binary_files {
+        return Vec::new();
+    }
+    let repo = git_helpers::open_repo().ok();
+
+    let mut findings = Vec::new();
+    for (path, patch) in &expcommit.patches {
+        // Files left out of the scope of the review (ex. `--file`) aren't checked
+        if !expcommit.diff_stats.contains_key(path) {
+            continue;
+        }
+        let verb = match patch.status {
+            Some(Delta::Added | Delta::Copied | Delta::Untracked) => "added",
+            Some(Delta::Modified | Delta::Renamed | Delta::Typechange) => "modified",
+            _ => continue,
+        };
+        let location = path.to_string_lossy().to_string();
+        let size = new_file_size(repo.as_ref(), patch);
+        if config.max_added_file_bytes > 0 && size > config.max_added_file_bytes {
+            findings.push(CheckFinding::new(
+                "large-file",
+                location.clone(),
+                format!(
+                    "{verb} file of {} (limit {}), consider Git LFS",
+                    format_size(size),
+                    format_size(config.max_added_file_bytes)
+                ),
+            ));
+        } else if config.binary_files && patch.binary {
+            findings.push(CheckFinding::new(
+                "binary-file",
+                location,
+                format!("{verb} binary file, consider Git LFS"),
+            ));
+        }
+    }
+    findings
+}
+
+/// Tokens of a line of code; literals are all alike, copies often only change them
+fn code_tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let mut word = String::from(c);
+            while let Some(&next) = chars.peek()
+                && (next.is_alphanumeric() || next == '_')
+            {
+                word.push(next);
+                chars.next();
+            }
+            tokens.push(if c.is_ascii_digit() {
+                String::from("0")
+            } else {
+                word
+            });
+        } else if c == '"' || c == '\'' {
+            while let Some(next) = chars.next() {
+                if next == '\\' {
+                    chars.next();
+                } else if next == c {
+                    break;
+                }
+            }
+            tokens.push(String::from("\"\""));
+        } else {
+            tokens.push(c.to_string());
+        }
+    }
+    tokens
+}
+
+/// Code added by a hunk, as tokens with the new line of each
+struct AddedBlock<'a> {
+    path: &'a Path,
+    tokens: Vec<String>,
+    lines: Vec<u32>,
+}
+
+impl AddedBlock<'_> {
+    /// Lines of `len` tokens from `start` (ex. `src/a.rs:10-24`)
+    fn location(&self, start: usize, len: usize) -> String {
+        format!(
+            "{}:{}-{}",
+            self.path.display(),
+            self.lines[start],
+            self.lines[start + len - 1]
+        )
+    }
+}
+
+/// Blocks of added code of the reviewed files, with at least `min_tokens` tokens
+fn added_blocks(expcommit: &ExpandedCommit, min_tokens: usize) -> Vec<AddedBlock<'_>> {
+    let mut blocks = Vec::new();
+    for (path, patch) in &expcommit.patches {
+        if !expcommit.diff_stats.contains_key(path) || patch.binary {
+            continue;
+        }
+        for hunk in &patch.hunks {
+            let mut block = AddedBlock {
+                path,
+                tokens: Vec::new(),
+                lines: Vec::new(),
+            };
+            let mut new_line = hunk.new_start;
+            for line in &hunk.lines {
+                match line.origin {
+                    '+' => {
+                        for token in code_tokens(&line.content) {
+                            block.tokens.push(token);
+                            block.lines.push(new_line);
+                        }
+                        new_line += 1;
+                    }
+                    ' ' => new_line += 1,
+                    _ => {}
+                }
+            }
+            if block.tokens.len() >= min_tokens {
+                blocks.push(block);
+            }
+        }
+    }
+    blocks
+}
+
+/// Copy-pasted code of the changes: runs of at least `duplicate_min_tokens` identical tokens
+/// (literals aside) in the added code, found through shared shingles of tokens
+fn duplicate_checks(config: &ChecksConfig, expcommit: &ExpandedCommit) -> Vec<CheckFinding> {
+    if !config.duplicate_code {
+        return Vec::new();
+    }
+    let min_tokens = config.duplicate_min_tokens.max(SHINGLE_TOKENS);
+    let blocks = added_blocks(expcommit, min_tokens);
+
+    let mut shingles: HashMap<&[String], Vec<(usize, usize)>> = HashMap::new();
+    for (b, block) in blocks.iter().enumerate() {
+        for (i, shingle) in block.tokens.windows(SHINGLE_TOKENS).enumerate() {
+            shingles.entry(shingle).or_default().push((b, i));
+        }
+    }
+
+    // (length, original block, start, copy block, start)
+    let mut copies: Vec<(usize, usize, usize, usize, usize)> = Vec::new();
+    for occurrences in shingles.values() {
+        if occurrences.len() > MAX_SHINGLE_OCCURRENCES {
+            continue;
+        }
+        for (n, &(a, i)) in occurrences.iter().enumerate() {
+            for &(b, j) in &occurrences[n + 1..] {
+                let (original, copy) = (&blocks[a].tokens, &blocks[b].tokens);
+                // Each run of matching tokens is measured once, from its start
+                if i > 0 && j > 0 && original[i - 1] == copy[j - 1] {
+                    continue;
+                }
+                let mut len = SHINGLE_TOKENS;
+                while i + len < original.len()
+                    && j + len < copy.len()
+                    && original[i + len] == copy[j + len]
+                {
+                    len += 1;
+                }
+                // A block repeating itself: the copy starts where the original ends
+                if a == b {
+                    len = len.min(j - i);
+                }
+                if len >= min_tokens {
+                    copies.push((len, a, i, b, j));
+                }
+            }
+        }
+    }
+    copies.sort_by(|x, y| y.0.cmp(&x.0).then(x.cmp(y)));
+    copies.truncate(MAX_DUPLICATE_FINDINGS);
+
+    copies
+        .into_iter()
+        .map(|(len, a, i, b, j)| {
+            CheckFinding::new(
+                DUPLICATE_CHECK,
+                blocks[b].location(j, len),
+                format!(
+                    "copy of {} ({len} tokens), consider extracting the shared code",
+                    blocks[a].location(i, len)
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Run the enabled checks on the reviewed changes
+pub fn run_checks(config: &ChecksConfig, expcommit: &ExpandedCommit) -> Vec<CheckFinding> {
+    let mut findings = commit_checks(config, &expcommit.commits);
+    findings.extend(file_checks(config, expcommit));
+    findings.extend(duplicate_checks(config, expcommit));
+    findings
+}
+
+/// Copy-pasted code found by the checks, as a context element of the system prompt (empty
+/// without copies)
+pub fn format_duplicates(findings: &[CheckFinding]) -> String {
+    let copies: String = findings
+        .iter()
+        .filter(|finding| finding.check == DUPLICATE_CHECK)
+        .map(|finding| format!("- {}: {}\n", finding.location, finding.message))
+        .collect();
+    if copies.is_empty() {
+        return String::new();
+    }
+    markup::element("info", &[("checks", DUPLICATE_CHECK)], copies.trim_end())
+}
+
+/// Produce the deterministic "CHECKS" appendix of a report
+pub fn format_checks(findings: &[CheckFinding]) -> String {
+    let mut appendix = format!("CHECKS ({} findings, not from the LLM)\n", findings.len());
+    for finding in findings {
+        appendix.push_str(&format!(
+            "- [{}] {}: {}\n",
+            finding.check, finding.location, finding.message
+        ));
+    }
+    appendix
+}
@@ -0,0 +1,164 @@
+use crate::cache;
+use crate::dump;
+use crate::git_helpers;
+use crate::github::PR_REFS_NAMESPACE;
+use crate::history;
+use crate::paths::{self, Dir};
+use anyhow::Result;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Files of `dir` matching `matches` last modified more than `max_age` ago
+fn expired_files(
+    dir: &Path,
+    max_age: Duration,
+    matches: impl Fn(&Path) -> bool,
+) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    let mut expired = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !matches(&path) {
+            continue;
+        }
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if age > max_age {
+            expired.push(path);
+        }
+    }
+    Ok(expired)
+}
+
+/// Remove review history entries, cached reviews, logs and prompt dumps older than
+/// `older_than_days` and the temporary PR refs of the current repository
+pub fn clean(older_than_days: u64, dry_run: bool) -> Result<()> {
+    let action = if dry_run { "Would remove" } else { "Removed" };
+    let max_age = older_than_days.saturating_mul(SECONDS_PER_DAY);
+    let cutoff = history::now_timestamp().saturating_sub(max_age);
+    let max_age = Duration::from_secs(max_age);
+
+    let mut removed_records = 0;
+    for record in history::load_all_records()? {
+        if record.timestamp >= cutoff {
+            continue;
+        }
+        let path = history::record_path(&record.id)?;
+        if !dry_run {
+            fs::remove_file(&path)?;
+        }
+        println!("{action} review {} ({})", record.id, record.target);
+        removed_records += 1;
+    }
+
+    let mut removed_cached = 0;
+    for path in cache::expired_entries(max_age)? {
+        if !dry_run {
+            fs::remove_file(&path)?;
+        }
+        removed_cached += 1;
+    }
+    if removed_cached > 0 {
+        println!("{action} {removed_cached} cached review(s)");
+    }
+
+    // Logs, and the prompt dumps of `--log_xml` left in the temporary directory
+    let mut logs = expired_files(&paths::dir(Dir::Logs)?, max_age, |_| true)?;
+    logs.extend(expired_files(
+        &std::env::temp_dir(),
+        max_age,
+        dump::is_dump,
+    )?);
+    for path in &logs {
+        if !dry_run {
+            fs::remove_file(path)?;
+        }
+        println!("{action} {}", path.display());
+    }
+
+    let mut removed_refs = 0;
+    if let Ok(repo) = git_helpers::open_repo() {
+        let mut names: Vec<String> = Vec::new();
+        // `refs/rv/pr/*` was used by older versions
+        for glob in [
+            format!("{PR_REFS_NAMESPACE}/*"),
+            String::from("refs/rv/pr/*"),
+        ] {
+            for reference in repo.references_glob(&glob)? {
+                if let Some(name) = reference?.name() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        for name in names {
+            if !dry_run {
+                repo.find_reference(&name)?.delete()?;
+            }
+            println!("{action} {name}");
+            removed_refs += 1;
+        }
+    }
+
+    println!(
+        "{action} {removed_records} review(s) older than {older_than_days} day(s) and {removed_refs} pull request ref(s)."
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_expired_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("rv-old.xml");
+        let recent = dir.path().join("rv-recent.xml");
+        let other = dir.path().join("other.txt");
+        for path in [&old, &recent, &other] {
+            fs::write(path, "").unwrap();
+        }
+        let two_days_ago = SystemTime::now() - Duration::from_secs(2 * SECONDS_PER_DAY);
+        for path in [&old, &other] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(two_days_ago)
+                .unwrap();
+        }
+
+        let is_xml = |path: &Path| path.extension().is_some_and(|ext| ext == "xml");
+        let max_age = Duration::from_secs(SECONDS_PER_DAY);
+        assert_eq!(
+            expired_files(dir.path(), max_age, is_xml).unwrap(),
+            vec![old]
+        );
+        assert!(
+            expired_files(&dir.path().join("missing"), max_age, is_xml)
+                .unwrap()
+                .is_empty()
+        );
+        // `--older-than` beyond the clock removes nothing, instead of overflowing
+        let max_age = Duration::from_secs(u64::MAX.saturating_mul(SECONDS_PER_DAY));
+        assert!(
+            expired_files(dir.path(), max_age, |_| true)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
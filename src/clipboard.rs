@@ -0,0 +1,143 @@
+//! System clipboard, through the clipboard commands of the platform (ex. `rv snippet --clipboard`
+//! and `--copy`); without them, the copy goes through the terminal (OSC 52), so that it also works
+//! over SSH and on headless machines
+
+use crate::report::{Report, Reporter};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::ValueEnum;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Commands printing the clipboard content, tried in order (Wayland, X11, macOS, Windows)
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+    (
+        "powershell.exe",
+        &["-NoProfile", "-Command", "Get-Clipboard"],
+    ),
+];
+
+/// Commands replacing the clipboard content with their stdin, tried in order
+const COPY_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard", "-i"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+/// Read the clipboard through the first available clipboard command
+pub fn read_clipboard() -> Result<String> {
+    for (program, args) in PASTE_COMMANDS {
+        let Ok(output) = Command::new(program).args(*args).output() else {
+            continue;
+        };
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+    }
+    bail!(
+        "Failed to read the clipboard; install wl-clipboard, xclip or xsel, or pipe the code through stdin"
+    )
+}
+
+/// Copy `text` with `program`; whether it succeeded
+fn copy_with(program: &str, args: &[&str], text: &str) -> bool {
+    // The X11 and Wayland commands stay in the background to serve the clipboard, their output
+    // isn't waited for
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let written = child
+        .stdin
+        .take()
+        .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+/// Copy `text` through the terminal, with the OSC 52 escape sequence
+fn copy_with_terminal(text: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        bail!("no clipboard command (wl-clipboard, xclip, xsel) and stdout isn't a terminal");
+    }
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush().context("Failed to write to the terminal")
+}
+
+/// Replace the clipboard content with `text`
+pub fn write_clipboard(text: &str) -> Result<()> {
+    if COPY_COMMANDS
+        .iter()
+        .any(|(program, args)| copy_with(program, args, text))
+    {
+        return Ok(());
+    }
+    copy_with_terminal(text)
+}
+
+/// Form of the review placed on the clipboard by `--copy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CopyFormat {
+    /// The review as printed, with its appendices
+    Text,
+    /// The report of `--artifact` (report.md), ready to paste in a pull request comment
+    Markdown,
+}
+
+/// Copy the reviews of the run to the clipboard, once they are complete
+pub struct ClipboardReporter {
+    format: CopyFormat,
+    /// Every review of the run: raw directory reviews produce one per language
+    copied: Mutex<Vec<String>>,
+}
+
+impl ClipboardReporter {
+    pub fn new(format: CopyFormat) -> ClipboardReporter {
+        ClipboardReporter {
+            format,
+            copied: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Reporter for ClipboardReporter {
+    fn name(&self) -> &'static str {
+        "clipboard"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let text = {
+                let mut copied = self
+                    .copied
+                    .lock()
+                    .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+                copied.push(match self.format {
+                    CopyFormat::Text => report.text(),
+                    CopyFormat::Markdown => report.markdown(),
+                });
+                copied.join("\n\n")
+            };
+            write_clipboard(&text)?;
+            eprintln!("\n[Review copied to the clipboard]");
+            Ok(())
+        }
+        .boxed()
+    }
+}
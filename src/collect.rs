@@ -0,0 +1,201 @@
+use crate::config::RawConfig;
+use crate::context::estimate_tokens;
+use crate::encoding::{self, Decoded};
+use crate::git_helpers::SkippedFile;
+use crate::ignore::IgnoreRules;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Collects the files to review in raw directory mode
+pub struct FileCollector {
+    /// Reviewed directory, as given by the user
+    start_dir: PathBuf,
+    root: PathBuf,
+    recursive: bool,
+    raw_config: RawConfig,
+    ignore: IgnoreRules,
+    /// Canonical paths of the visited directories, used to detect symlink cycles
+    visited_dirs: HashSet<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+impl FileCollector {
+    pub fn new(
+        root: &Path,
+        recursive: bool,
+        raw_config: RawConfig,
+        ignore: IgnoreRules,
+    ) -> std::io::Result<Self> {
+        Ok(FileCollector {
+            start_dir: root.to_path_buf(),
+            root: root.canonicalize()?,
+            recursive,
+            raw_config,
+            ignore,
+            visited_dirs: HashSet::new(),
+            files: Vec::new(),
+            skipped: Vec::new(),
+        })
+    }
+
+    fn skip(&mut self, path: PathBuf, reason: impl Into<String>) {
+        self.skipped.push(SkippedFile {
+            path,
+            reason: reason.into(),
+        });
+    }
+
+    /// Walk `dir`, the top-level directory must be readable while errors in subdirectories are only reported
+    pub fn collect(&mut self, dir: &Path) -> std::io::Result<()> {
+        let canonical_dir = dir.canonicalize()?;
+        if !self.visited_dirs.insert(canonical_dir) {
+            self.skip(
+                dir.to_path_buf(),
+                "directory already visited (symlink cycle)",
+            );
+            return Ok(());
+        }
+
+        let mut entries: Vec<fs::DirEntry> = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => self.skip(dir.to_path_buf(), format!("unreadable entry: {e}")),
+            }
+        }
+        // Keep the collection order deterministic
+        entries.sort_by_key(|e| e.path());
+
+        for entry in entries {
+            self.visit_entry(entry.path());
+        }
+        Ok(())
+    }
+
+    fn visit_entry(&mut self, path: PathBuf) {
+        let relative_path = path.strip_prefix(&self.start_dir).unwrap_or(&path);
+        // `symlink_metadata` doesn't follow symlinks, so symlinked directories are matched as files
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        if self.ignore.is_ignored(relative_path, is_dir) {
+            self.skip(
+                path,
+                "excluded by ignore rules (use --no-ignore to include it)",
+            );
+            return;
+        }
+
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden && !self.raw_config.hidden {
+            self.skip(path, "hidden (use --hidden to include it)");
+            return;
+        }
+
+        // Don't follow symlinks here, they are handled explicitly below
+        let file_type = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            Err(e) => {
+                self.skip(path, format!("unreadable: {e}"));
+                return;
+            }
+        };
+
+        let file_type = if file_type.is_symlink() {
+            if !self.raw_config.follow_symlinks {
+                self.skip(path, "symlink (use --follow-symlinks to include it)");
+                return;
+            }
+            match path.canonicalize() {
+                Ok(target) if !target.starts_with(&self.root) => {
+                    self.skip(path, "symlink pointing outside of the reviewed directory");
+                    return;
+                }
+                Ok(_) => match fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(e) => {
+                        self.skip(path, format!("unreadable symlink target: {e}"));
+                        return;
+                    }
+                },
+                Err(e) => {
+                    self.skip(path, format!("broken symlink: {e}"));
+                    return;
+                }
+            }
+        } else {
+            file_type
+        };
+
+        if file_type.is_dir() {
+            if self.recursive
+                && let Err(e) = self.collect(&path)
+            {
+                self.skip(path, format!("unreadable directory: {e}"));
+            }
+        } else if !file_type.is_file() {
+            self.skip(path, "special file (socket, FIFO or device)");
+        } else if let Some(reason) = oversized_reason(&path, &self.raw_config) {
+            self.skip(path, reason);
+        } else if let Ok(Decoded::Binary) = encoding::read_source(&path) {
+            self.skip(path, "binary content");
+        } else {
+            self.files.push(path);
+        }
+    }
+}
+
+/// Check the size limits of `raw_config`, returning the reason to skip the file if any
+fn oversized_reason(path: &Path, raw_config: &RawConfig) -> Option<String> {
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    if raw_config.max_file_bytes > 0 && size > raw_config.max_file_bytes {
+        return Some(format!(
+            "{size} bytes, over the {} bytes limit (use --force-include to review it anyway)",
+            raw_config.max_file_bytes
+        ));
+    }
+
+    if raw_config.max_file_tokens > 0
+        && let Ok(Decoded::Text(content)) = encoding::read_source(path)
+    {
+        let tokens = estimate_tokens(&content);
+        if tokens > raw_config.max_file_tokens {
+            return Some(format!(
+                "~{tokens} tokens, over the {} tokens limit (use --force-include to review it anyway)",
+                raw_config.max_file_tokens
+            ));
+        }
+    }
+
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_a_symlink_cycle() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("src");
+        fs::create_dir(&nested).unwrap();
+        fs::write(nested.join("main.rs"), "fn main() {}\n").unwrap();
+        std::os::unix::fs::symlink(root.path(), nested.join("parent")).unwrap();
+
+        let raw_config = RawConfig {
+            follow_symlinks: true,
+            ..RawConfig::default()
+        };
+        let mut collector =
+            FileCollector::new(root.path(), true, raw_config, IgnoreRules::new()).unwrap();
+        collector.collect(root.path()).unwrap();
+
+        assert_eq!(collector.files, vec![nested.join("main.rs")]);
+        assert!(collector.skipped.iter().any(|skipped| {
+            skipped.path == nested.join("parent")
+                && skipped.reason == "directory already visited (symlink cycle)"
+        }));
+    }
+}
@@ -0,0 +1,1027 @@
+//! Complexity of the changed functions: cyclomatic complexity and nesting depth, measured on the
+//! new version of the changed files and shown in a COMPLEXITY appendix of the report.
+//! With the `tree-sitter` feature, functions are measured on the syntax tree of their file; the
+//! languages without a built-in grammar (Kotlin, Swift), the files the grammar fails to parse and
+//! the builds without the feature fall back on a lexical estimate (braces, or indentation for Python).
+
+use crate::compress::comment_syntax;
+use crate::config::ComplexityConfig;
+use crate::git_helpers::{self, ExpandedCommit, FilePatch};
+use crate::language::{Language, detect_language};
+use crate::markup;
+
+use git2::{Delta, Repository};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Added to the input format of the system prompt when the model is told about the complexity
+pub const COMPLEXITY_PROMPT: &str = r#"
+COMPLEXITY
+- <info complexity="changed functions"> lists the cyclomatic complexity and
+  nesting depth of the changed functions, most complex first. Review the
+  most complex functions first and most thoroughly.
+"#;
+
+/// Keywords opening a branch in every supported language
+const BRANCH_KEYWORDS: &[&str] = &[
+    "if", "for", "while", "case", "catch", "elif", "except", "guard", "foreach",
+];
+
+/// Keywords opening a nested control block
+const CONTROL_KEYWORDS: &[&str] = &[
+    "if", "else", "elif", "for", "foreach", "while", "loop", "do", "match", "switch", "when",
+    "try", "catch", "except", "finally", "with", "guard", "select", "case",
+];
+
+/// Words that look like a function name before `(` in the C-like languages
+const NOT_FUNCTION_NAMES: &[&str] = &[
+    "if",
+    "for",
+    "foreach",
+    "while",
+    "switch",
+    "catch",
+    "return",
+    "sizeof",
+    "new",
+    "using",
+    "lock",
+    "synchronized",
+    "fixed",
+    "else",
+    "do",
+    "try",
+];
+
+/// Complexity of a changed function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionComplexity {
+    pub path: String,
+    pub name: String,
+    /// Lines of the function in the new version of the file
+    pub line: usize,
+    pub end_line: usize,
+    /// McCabe complexity: 1 plus the branches of the function
+    pub cyclomatic: usize,
+    /// Deepest nesting of control blocks in the function
+    pub nesting: usize,
+}
+
+/// Function found in a file, before it is measured
+struct FunctionSpan {
+    name: String,
+    line: usize,
+    end_line: usize,
+    nesting: usize,
+}
+
+/// Keyword introducing the named functions of a language, `None` for the C-like languages
+/// where a function is recognized by its signature
+fn function_keyword(language: Language) -> Option<&'static str> {
+    match language {
+        Language::Rust => Some("fn"),
+        Language::Go | Language::Swift => Some("func"),
+        Language::Kotlin => Some("fun"),
+        Language::JavaScript | Language::TypeScript | Language::Php => Some("function"),
+        Language::Python => Some("def"),
+        _ => None,
+    }
+}
+
+fn is_measured(language: Language) -> bool {
+    matches!(
+        language,
+        Language::Rust
+            | Language::Go
+            | Language::Swift
+            | Language::Kotlin
+            | Language::JavaScript
+            | Language::TypeScript
+            | Language::Php
+            | Language::Python
+            | Language::C
+            | Language::Cpp
+            | Language::CSharp
+            | Language::Java
+    )
+}
+
+/// The text with comments and the contents of string literals removed, lines kept in place
+fn code_only(text: &str, language: Language) -> String {
+    let (line_comments, block_comment) = comment_syntax(language);
+    let quote_strings = matches!(
+        language,
+        Language::Python | Language::JavaScript | Language::TypeScript | Language::Php
+    );
+    let chars: Vec<char> = text.chars().collect();
+    let starts_with = |i: usize, pattern: &str| {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(n, c)| chars.get(i + n) == Some(&c))
+    };
+
+    let mut code = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(comment) = line_comments.iter().find(|comment| starts_with(i, comment)) {
+            i += comment.len();
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if let Some((start, end)) = block_comment
+            && starts_with(i, start)
+        {
+            i += start.len();
+            while i < chars.len() && !starts_with(i, end) {
+                if chars[i] == '\n' {
+                    code.push('\n');
+                }
+                i += 1;
+            }
+            i += end.len();
+        } else if c == '"' || c == '`' || (c == '\'' && quote_strings) {
+            // Python docstrings span lines
+            let triple = language == Language::Python && starts_with(i, &c.to_string().repeat(3));
+            let delimiter = if triple { 3 } else { 1 };
+            code.push(c);
+            i += delimiter;
+            while i < chars.len() {
+                if chars[i] == '\\' {
+                    i += 2;
+                    continue;
+                }
+                if chars[i] == c && (!triple || starts_with(i, &c.to_string().repeat(3))) {
+                    i += delimiter;
+                    break;
+                }
+                if chars[i] == '\n' {
+                    code.push('\n');
+                }
+                i += 1;
+            }
+            code.push(c);
+        } else if c == '\'' {
+            // A character literal (ex. '{' or '\n'); otherwise a Rust lifetime
+            let length = if chars.get(i + 1) == Some(&'\\') {
+                chars[i + 2..]
+                    .iter()
+                    .take(10)
+                    .position(|&c| c == '\'')
+                    .map(|pos| pos + 3)
+            } else {
+                (chars.get(i + 2) == Some(&'\'')).then_some(3)
+            };
+            match length {
+                Some(length) => {
+                    code.push_str("''");
+                    i += length;
+                }
+                None => {
+                    code.push(c);
+                    i += 1;
+                }
+            }
+        } else {
+            code.push(c);
+            i += 1;
+        }
+    }
+    code
+}
+
+/// Words and branching operators of a code text
+fn code_tokens(code: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let bytes = code.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_alphanumeric() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push(&code[start..i]);
+            continue;
+        }
+        let pair = code.get(i..i + 2).unwrap_or_default();
+        if matches!(pair, "&&" | "||" | "=>") {
+            tokens.push(pair);
+            i += 2;
+            continue;
+        }
+        if c == b'?'
+            && bytes
+                .get(i + 1)
+                .is_some_and(|next| next.is_ascii_whitespace())
+        {
+            tokens.push("?");
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Cyclomatic complexity of the code of a function
+fn cyclomatic(code: &str, language: Language) -> usize {
+    let tokens = code_tokens(code);
+    let count = |word: &str| tokens.iter().filter(|token| **token == word).count();
+    let mut branches = BRANCH_KEYWORDS
+        .iter()
+        .map(|word| count(word))
+        .sum::<usize>()
+        + count("&&")
+        + count("||");
+    match language {
+        // Every arm of a `match` but its first is a branch
+        Language::Rust => branches = (branches + count("=>")).saturating_sub(count("match")),
+        Language::Python => branches += count("and") + count("or"),
+        Language::C
+        | Language::Cpp
+        | Language::CSharp
+        | Language::Java
+        | Language::JavaScript
+        | Language::TypeScript
+        | Language::Php => branches += count("?"),
+        _ => {}
+    }
+    1 + branches
+}
+
+/// Identifier at the start of `text`
+fn leading_identifier(text: &str) -> &str {
+    let text = text.trim_start();
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// Name of the function declared by the code preceding a `{`, if it declares one
+fn function_name(header: &str, language: Language) -> Option<String> {
+    let header = header.split_whitespace().collect::<Vec<_>>().join(" ");
+    let words: Vec<&str> = header
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words
+        .first()
+        .is_some_and(|word| CONTROL_KEYWORDS.contains(word))
+    {
+        return None;
+    }
+    if let Some(keyword) = function_keyword(language)
+        && let Some(pos) = words.iter().position(|word| *word == keyword)
+    {
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let after = header
+            .match_indices(keyword)
+            .filter(|(idx, _)| !header[..*idx].ends_with(is_word_char))
+            .map(|(idx, _)| &header[idx + keyword.len()..])
+            .find(|rest| !rest.starts_with(is_word_char))
+            .unwrap_or_default();
+        // Go methods: `func (r *Repo) Name(...)`
+        let after = match after.trim_start().strip_prefix('(') {
+            Some(receiver) => receiver.split_once(')').map(|(_, rest)| rest).unwrap_or(""),
+            None => after,
+        };
+        let name = leading_identifier(after);
+        return Some(if name.is_empty() {
+            words
+                .get(pos.wrapping_sub(1))
+                .unwrap_or(&"<anonymous>")
+                .to_string()
+        } else {
+            name.to_string()
+        });
+    }
+    match language {
+        Language::JavaScript | Language::TypeScript if header.contains("=>") => {
+            // `const name = (args) => {`
+            let (before, _) = header.split_once('=')?;
+            before.split_whitespace().last().map(str::to_string)
+        }
+        Language::JavaScript
+        | Language::TypeScript
+        | Language::C
+        | Language::Cpp
+        | Language::CSharp
+        | Language::Java
+        | Language::Php => {
+            // `type name(args) qualifiers {`
+            let (before, after) = header.split_once('(')?;
+            if !after.contains(')') || before.contains('=') {
+                return None;
+            }
+            let name = before
+                .trim_end()
+                .rsplit(|c: char| c.is_whitespace() || c == '*' || c == '&')
+                .next()?;
+            let short = name.rsplit([':', '.']).next().unwrap_or(name);
+            let previous = before
+                .trim_end()
+                .strip_suffix(name)?
+                .split_whitespace()
+                .last();
+            let is_name = !short.is_empty()
+                && short.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '~')
+                && !NOT_FUNCTION_NAMES.contains(&short)
+                && previous != Some("new");
+            is_name.then(|| name.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Functions of a language with braces, with the nesting of their control blocks
+fn brace_functions(code: &str, language: Language) -> Vec<FunctionSpan> {
+    // Open braces: the function they open, or whether they open a control block
+    enum Brace {
+        Function(usize),
+        Control,
+        Other,
+    }
+    let mut functions: Vec<FunctionSpan> = Vec::new();
+    let mut stack: Vec<Brace> = Vec::new();
+    let mut header = String::new();
+    let mut header_line = 1;
+    // Semicolons inside parentheses (ex. `for (i = 0; i < n; i++)`) don't end the header
+    let mut parens: usize = 0;
+    let mut line = 1;
+    for c in code.chars() {
+        match c {
+            '{' => {
+                let brace = match function_name(&header, language) {
+                    Some(name) => {
+                        functions.push(FunctionSpan {
+                            name,
+                            line: header_line,
+                            end_line: line,
+                            nesting: 0,
+                        });
+                        Brace::Function(functions.len() - 1)
+                    }
+                    None if header
+                        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .any(|word| CONTROL_KEYWORDS.contains(&word)) =>
+                    {
+                        Brace::Control
+                    }
+                    None => Brace::Other,
+                };
+                stack.push(brace);
+                // Control blocks between the innermost function and this brace
+                if let Some(function) = stack.iter().rposition(|b| matches!(b, Brace::Function(_)))
+                    && let Brace::Function(idx) = stack[function]
+                {
+                    let depth = stack[function..]
+                        .iter()
+                        .filter(|b| matches!(b, Brace::Control))
+                        .count();
+                    functions[idx].nesting = functions[idx].nesting.max(depth);
+                }
+                header.clear();
+                parens = 0;
+            }
+            '}' => {
+                if let Some(Brace::Function(idx)) = stack.pop() {
+                    functions[idx].end_line = line;
+                }
+                header.clear();
+                parens = 0;
+            }
+            ';' if parens == 0 => header.clear(),
+            _ => {
+                match c {
+                    '(' => parens += 1,
+                    ')' => parens = parens.saturating_sub(1),
+                    _ => {}
+                }
+                if header.trim().is_empty() && !c.is_whitespace() {
+                    header_line = line;
+                }
+                header.push(c);
+            }
+        }
+        if c == '\n' {
+            line += 1;
+        }
+    }
+    functions
+}
+
+/// Functions of a Python file, delimited by their indentation
+fn python_functions(code: &str) -> Vec<FunctionSpan> {
+    let lines: Vec<&str> = code.lines().collect();
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut functions = Vec::new();
+    for (idx, text) in lines.iter().enumerate() {
+        let trimmed = text.trim_start();
+        let Some(signature) = trimmed
+            .strip_prefix("def ")
+            .or_else(|| trimmed.strip_prefix("async def "))
+        else {
+            continue;
+        };
+        let def_indent = indent(text);
+        // The body starts after the line ending the signature
+        let body_start = (idx..lines.len())
+            .find(|&n| lines[n].trim_end().ends_with(':'))
+            .map_or(lines.len(), |n| n + 1);
+        let mut end = body_start;
+        let mut blocks: Vec<usize> = Vec::new();
+        let mut nesting = 0;
+        for line in &lines[body_start.min(lines.len())..] {
+            if line.trim().is_empty() {
+                end += 1;
+                continue;
+            }
+            let line_indent = indent(line);
+            if line_indent <= def_indent {
+                break;
+            }
+            end += 1;
+            while blocks.last().is_some_and(|&block| block >= line_indent) {
+                blocks.pop();
+            }
+            if CONTROL_KEYWORDS.contains(&leading_identifier(line)) {
+                blocks.push(line_indent);
+                nesting = nesting.max(blocks.len());
+            }
+        }
+        // Trailing blank lines don't belong to the function
+        while end > body_start && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        functions.push(FunctionSpan {
+            name: leading_identifier(signature).to_string(),
+            line: idx + 1,
+            end_line: end.max(idx + 1),
+            nesting,
+        });
+    }
+    functions
+}
+
+/// New lines touched by the hunks of a patch; a removal touches the line following it
+fn changed_lines(patch: &FilePatch) -> BTreeSet<usize> {
+    let mut lines = BTreeSet::new();
+    for hunk in &patch.hunks {
+        let mut new_line = hunk.new_start as usize;
+        for line in &hunk.lines {
+            match line.origin {
+                '+' => {
+                    lines.insert(new_line);
+                    new_line += 1;
+                }
+                '-' => {
+                    lines.insert(new_line);
+                }
+                ' ' => new_line += 1,
+                _ => {}
+            }
+        }
+    }
+    lines
+}
+
+/// New version of a changed file: its blob, or the file of the working tree
+fn new_content(repo: Option<&Repository>, path: &Path, patch: &FilePatch) -> Option<String> {
+    if let (Some(repo), Some(oid)) = (repo, patch.new_oid)
+        && !oid.is_zero()
+        && let Ok(blob) = repo.find_blob(oid)
+    {
+        return Some(String::from_utf8_lossy(blob.content()).to_string());
+    }
+    let workdir = repo.and_then(Repository::workdir)?;
+    std::fs::read_to_string(workdir.join(path)).ok()
+}
+
+/// Functions of a file with their complexity: measured on the syntax tree when the grammar of the
+/// language is built in, lexically otherwise
+fn measure_file(path: &Path, content: &str, language: Language) -> Vec<FunctionComplexity> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(functions) = syntax::measure_file(path, content, language) {
+        return functions;
+    }
+    let code = code_only(content, language);
+    let functions = if language == Language::Python {
+        python_functions(&code)
+    } else {
+        brace_functions(&code, language)
+    };
+    let code_lines: Vec<&str> = code.lines().collect();
+    functions
+        .into_iter()
+        .map(|function| {
+            let body = code_lines
+                .get(function.line - 1..function.end_line.min(code_lines.len()))
+                .unwrap_or_default()
+                .join("\n");
+            FunctionComplexity {
+                path: path.to_string_lossy().to_string(),
+                name: function.name,
+                line: function.line,
+                end_line: function.end_line,
+                cyclomatic: cyclomatic(&body, language),
+                nesting: function.nesting,
+            }
+        })
+        .collect()
+}
+
+/// Complexity of the functions touched by the reviewed changes, most complex first
+pub fn changed_functions(
+    config: &ComplexityConfig,
+    expcommit: &ExpandedCommit,
+) -> Vec<FunctionComplexity> {
+    if !config.enabled {
+        return Vec::new();
+    }
+    let repo = git_helpers::open_repo().ok();
+    let mut measured = Vec::new();
+    for (path, patch) in &expcommit.patches {
+        let language = detect_language(path);
+        if !expcommit.diff_stats.contains_key(path)
+            || patch.binary
+            || patch.status == Some(Delta::Deleted)
+            || !is_measured(language)
+        {
+            continue;
+        }
+        let changed = changed_lines(patch);
+        let Some(content) = new_content(repo.as_ref(), path, patch) else {
+            continue;
+        };
+        measured.extend(
+            measure_file(path, &content, language)
+                .into_iter()
+                .filter(|function| {
+                    changed
+                        .range(function.line..=function.end_line)
+                        .next()
+                        .is_some()
+                }),
+        );
+    }
+    most_complex_first(&mut measured, config.max_functions);
+    measured
+}
+
+/// Sort the functions the most complex first, keeping `max_functions` of them
+pub fn most_complex_first(functions: &mut Vec<FunctionComplexity>, max_functions: usize) {
+    functions.sort_by(|a, b| {
+        b.cyclomatic
+            .cmp(&a.cyclomatic)
+            .then(b.nesting.cmp(&a.nesting))
+            .then(a.path.cmp(&b.path))
+            .then(a.line.cmp(&b.line))
+    });
+    functions.truncate(max_functions);
+}
+
+fn complexity_rows(functions: &[FunctionComplexity]) -> String {
+    let mut rows = String::from("  cyclomatic  nesting  function\n");
+    for function in functions {
+        rows.push_str(&format!(
+            "  {:>10}  {:>7}  {} ({}:{})\n",
+            function.cyclomatic, function.nesting, function.name, function.path, function.line
+        ));
+    }
+    rows
+}
+
+/// Produce the deterministic "COMPLEXITY" appendix of a report
+pub fn format_complexity(functions: &[FunctionComplexity]) -> String {
+    format!(
+        "COMPLEXITY ({} changed functions, most complex first)\n{}",
+        functions.len(),
+        complexity_rows(functions)
+    )
+}
+
+/// Complexity of the changed functions as a context element of the system prompt
+pub fn format_complexity_context(functions: &[FunctionComplexity]) -> String {
+    if functions.is_empty() {
+        return String::new();
+    }
+    markup::element(
+        "info",
+        &[("complexity", "changed functions")],
+        complexity_rows(functions).trim_end(),
+    )
+}
+
+/// Complexity measured on the syntax tree of a file, parsed with its tree-sitter grammar
+#[cfg(feature = "tree-sitter")]
+mod syntax {
+    use super::FunctionComplexity;
+    use crate::language::Language;
+
+    use std::path::Path;
+    use tree_sitter::{Node, Parser};
+
+    /// Node kinds of a grammar
+    struct Grammar {
+        language: tree_sitter::Language,
+        /// Functions measured on their own; the anonymous ones belong to the enclosing function
+        functions: &'static [&'static str],
+        /// Nodes adding a branch (`default` labels aside)
+        branches: &'static [&'static str],
+        /// Control blocks, nesting the code they contain
+        controls: &'static [&'static str],
+    }
+
+    fn grammar(path: &Path, language: Language) -> Option<Grammar> {
+        let grammar = match language {
+            Language::Rust => Grammar {
+                language: tree_sitter_rust::LANGUAGE.into(),
+                functions: &["function_item"],
+                branches: &[
+                    "if_expression",
+                    "while_expression",
+                    "for_expression",
+                    "match_arm",
+                ],
+                controls: &[
+                    "if_expression",
+                    "while_expression",
+                    "for_expression",
+                    "loop_expression",
+                    "match_expression",
+                ],
+            },
+            Language::Python => Grammar {
+                language: tree_sitter_python::LANGUAGE.into(),
+                functions: &["function_definition"],
+                branches: &[
+                    "if_statement",
+                    "elif_clause",
+                    "for_statement",
+                    "while_statement",
+                    "except_clause",
+                    "case_clause",
+                    "conditional_expression",
+                    "boolean_operator",
+                    "for_in_clause",
+                    "if_clause",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "while_statement",
+                    "try_statement",
+                    "with_statement",
+                    "match_statement",
+                ],
+            },
+            Language::JavaScript | Language::TypeScript => Grammar {
+                language: match language {
+                    Language::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+                    _ if path.extension().is_some_and(|ext| ext == "tsx") => {
+                        tree_sitter_typescript::LANGUAGE_TSX.into()
+                    }
+                    _ => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+                },
+                functions: &[
+                    "function_declaration",
+                    "generator_function_declaration",
+                    "method_definition",
+                    "function_expression",
+                    "generator_function",
+                    "arrow_function",
+                ],
+                branches: &[
+                    "if_statement",
+                    "for_statement",
+                    "for_in_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_case",
+                    "catch_clause",
+                    "ternary_expression",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "for_in_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_statement",
+                    "try_statement",
+                ],
+            },
+            Language::Go => Grammar {
+                language: tree_sitter_go::LANGUAGE.into(),
+                functions: &["function_declaration", "method_declaration"],
+                branches: &[
+                    "if_statement",
+                    "for_statement",
+                    "expression_case",
+                    "type_case",
+                    "communication_case",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "expression_switch_statement",
+                    "type_switch_statement",
+                    "select_statement",
+                ],
+            },
+            Language::Java => Grammar {
+                language: tree_sitter_java::LANGUAGE.into(),
+                functions: &["method_declaration", "constructor_declaration"],
+                branches: &[
+                    "if_statement",
+                    "for_statement",
+                    "enhanced_for_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_label",
+                    "catch_clause",
+                    "ternary_expression",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "enhanced_for_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_expression",
+                    "try_statement",
+                    "try_with_resources_statement",
+                ],
+            },
+            Language::C | Language::Cpp => Grammar {
+                language: match language {
+                    Language::C => tree_sitter_c::LANGUAGE.into(),
+                    _ => tree_sitter_cpp::LANGUAGE.into(),
+                },
+                functions: &["function_definition"],
+                branches: &[
+                    "if_statement",
+                    "for_statement",
+                    "for_range_loop",
+                    "while_statement",
+                    "do_statement",
+                    "case_statement",
+                    "catch_clause",
+                    "conditional_expression",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "for_range_loop",
+                    "while_statement",
+                    "do_statement",
+                    "switch_statement",
+                    "try_statement",
+                ],
+            },
+            Language::CSharp => Grammar {
+                language: tree_sitter_c_sharp::LANGUAGE.into(),
+                functions: &[
+                    "method_declaration",
+                    "constructor_declaration",
+                    "destructor_declaration",
+                    "local_function_statement",
+                ],
+                branches: &[
+                    "if_statement",
+                    "for_statement",
+                    "foreach_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_section",
+                    "switch_expression_arm",
+                    "catch_clause",
+                    "conditional_expression",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "foreach_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_statement",
+                    "try_statement",
+                    "using_statement",
+                    "lock_statement",
+                ],
+            },
+            Language::Php => Grammar {
+                language: tree_sitter_php::LANGUAGE_PHP.into(),
+                functions: &["function_definition", "method_declaration"],
+                branches: &[
+                    "if_statement",
+                    "else_if_clause",
+                    "for_statement",
+                    "foreach_statement",
+                    "while_statement",
+                    "do_statement",
+                    "case_statement",
+                    "catch_clause",
+                    "conditional_expression",
+                    "match_conditional_expression",
+                ],
+                controls: &[
+                    "if_statement",
+                    "for_statement",
+                    "foreach_statement",
+                    "while_statement",
+                    "do_statement",
+                    "switch_statement",
+                    "try_statement",
+                    "match_expression",
+                ],
+            },
+            _ => return None,
+        };
+        Some(grammar)
+    }
+
+    fn text<'a>(node: Node, source: &'a [u8]) -> &'a str {
+        node.utf8_text(source).unwrap_or_default()
+    }
+
+    /// Name of a function node, `None` for the anonymous functions
+    fn function_name(node: Node, source: &[u8]) -> Option<String> {
+        if let Some(name) = node.child_by_field_name("name") {
+            return Some(text(name, source).to_string());
+        }
+        // C and C++: `type *name(args)`, the name being inside the function declarator
+        let mut declarator = node.child_by_field_name("declarator");
+        while let Some(current) = declarator {
+            if current.kind() == "function_declarator" {
+                return current
+                    .child_by_field_name("declarator")
+                    .map(|name| text(name, source).to_string());
+            }
+            declarator = current.child_by_field_name("declarator").or_else(|| {
+                current
+                    .named_child_count()
+                    .checked_sub(1)
+                    .and_then(|last| current.named_child(last))
+            });
+        }
+        // `const name = (args) => {`
+        node.parent()
+            .filter(|parent| parent.kind() == "variable_declarator")
+            .and_then(|parent| parent.child_by_field_name("name"))
+            .map(|name| text(name, source).to_string())
+    }
+
+    fn is_branch(node: Node, source: &[u8], grammar: &Grammar) -> bool {
+        match node.kind() {
+            "binary_expression" => node
+                .child_by_field_name("operator")
+                .is_some_and(|operator| {
+                    matches!(text(operator, source), "&&" | "||" | "??" | "and" | "or")
+                }),
+            // Every arm of a `match` but its first is a branch
+            "match_arm" => node
+                .prev_named_sibling()
+                .is_some_and(|previous| previous.kind() == "match_arm"),
+            kind => grammar.branches.contains(&kind) && !text(node, source).starts_with("default"),
+        }
+    }
+
+    /// Whether a node opens a control block; an `else if` continues the block of its `if`
+    fn is_control(node: Node, grammar: &Grammar) -> bool {
+        grammar.controls.contains(&node.kind())
+            && !node.parent().is_some_and(|parent| {
+                parent.kind() == "else_clause"
+                    || parent.child_by_field_name("alternative") == Some(node)
+            })
+    }
+
+    /// Measure the functions under `node`; `current` is the function containing it, with the
+    /// control blocks opened since its start
+    fn visit(
+        node: Node,
+        source: &[u8],
+        grammar: &Grammar,
+        current: Option<(usize, usize)>,
+        functions: &mut Vec<FunctionComplexity>,
+    ) {
+        let mut current = current;
+        if grammar.functions.contains(&node.kind())
+            && let Some(name) = function_name(node, source)
+        {
+            functions.push(FunctionComplexity {
+                path: String::new(),
+                name,
+                line: node.start_position().row + 1,
+                end_line: node.end_position().row + 1,
+                cyclomatic: 1,
+                nesting: 0,
+            });
+            current = Some((functions.len() - 1, 0));
+        } else if let Some((idx, depth)) = current {
+            if is_branch(node, source, grammar) {
+                functions[idx].cyclomatic += 1;
+            }
+            if is_control(node, grammar) {
+                functions[idx].nesting = functions[idx].nesting.max(depth + 1);
+                current = Some((idx, depth + 1));
+            }
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            visit(child, source, grammar, current, functions);
+        }
+    }
+
+    /// Functions of a file measured on its syntax tree; `None` without a grammar for the language
+    /// or when the file doesn't parse
+    pub(super) fn measure_file(
+        path: &Path,
+        content: &str,
+        language: Language,
+    ) -> Option<Vec<FunctionComplexity>> {
+        let grammar = grammar(path, language)?;
+        let mut parser = Parser::new();
+        parser.set_language(&grammar.language).ok()?;
+        let tree = parser.parse(content, None)?;
+        if tree.root_node().has_error() {
+            return None;
+        }
+        let mut functions = Vec::new();
+        visit(
+            tree.root_node(),
+            content.as_bytes(),
+            &grammar,
+            None,
+            &mut functions,
+        );
+        let path = path.to_string_lossy().to_string();
+        for function in &mut functions {
+            function.path.clone_from(&path);
+        }
+        Some(functions)
+    }
+}
+
+#[cfg(all(test, feature = "tree-sitter"))]
+mod tests {
+    use super::*;
+
+    fn measured(path: &str, content: &str) -> Vec<(String, usize, usize, usize, usize)> {
+        let path = Path::new(path);
+        measure_file(path, content, detect_language(path))
+            .into_iter()
+            .map(|f| (f.name, f.line, f.end_line, f.cyclomatic, f.nesting))
+            .collect()
+    }
+
+    #[test]
+    fn measures_the_functions_on_the_syntax_tree() {
+        let rust = r#"fn classify(n: i32) -> &'static str {
+    let sign = |n: i32| if n < 0 { "-" } else { "+" };
+    if n == 0 && sign(n) == "+" {
+        "zero"
+    } else if n > 100 {
+        for _ in 0..n {
+            while false {}
+        }
+        "large"
+    } else {
+        match n {
+            1 => "one",
+            2 => "two",
+            _ => "many",
+        }
+    }
+}
+
+fn empty() {}
+"#;
+        assert_eq!(
+            measured("src/lib.rs", rust),
+            vec![
+                (String::from("classify"), 1, 17, 9, 3),
+                (String::from("empty"), 19, 19, 1, 0),
+            ]
+        );
+
+        let python = "def check(items):\n    for item in items:\n        if item and item.ok:\n            return [i for i in item.children if i]\n    return None\n";
+        assert_eq!(
+            measured("check.py", python),
+            vec![(String::from("check"), 1, 5, 6, 2)]
+        );
+    }
+
+    #[test]
+    fn measures_lexically_the_files_that_dont_parse() {
+        let broken = "fn broken() {\n    if a {\n}\n";
+        assert_eq!(
+            syntax::measure_file(Path::new("a.rs"), broken, Language::Rust),
+            None
+        );
+        assert_eq!(measured("a.rs", broken)[0].0, "broken");
+    }
+}
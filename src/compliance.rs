@@ -0,0 +1,310 @@
+//! `rv check-guidelines`: strict evaluation of a change against the numbered rules of the
+//! guidelines, one verdict per rule (pass, fail, n/a) instead of a free-form review
+
+use crate::config::RvConfig;
+use crate::context::{estimate_tokens, read_file};
+use crate::git_helpers::{self, ExpandedCommit};
+use crate::history::{self, Usage};
+use crate::ignore::IgnoreRules;
+use crate::llm::create_llm_provider;
+use crate::markup;
+use crate::minimize::{MinimizeOptions, minimize};
+use crate::request::{self, RequestPipeline};
+use crate::rules::{GuidelineRule, parse_numbered_rules, parse_rules};
+use crate::sanitize::sanitize_output;
+use crate::term_helpers;
+use crate::variables::PromptVariables;
+
+use anyhow::{Context, Result, bail};
+use rig::message::Message;
+use serde::Serialize;
+use std::path::Path;
+
+/// Width of the rule column of the compliance table
+const RULE_COLUMN: usize = 10;
+
+const COMPLIANCE_SYSTEM_PROMPT: &str = r#"
+You are a senior software engineer checking a change against the rules
+of a project. The rules follow these instructions, each with its ID, and
+you will receive the diffs of the change. Do NOT review the code beyond
+these rules.
+
+For EVERY rule, in the given order, output exactly one line:
+ID | VERDICT | NOTE
+- VERDICT is PASS (the change follows the rule), FAIL (the change breaks
+  it) or N/A (the change doesn't touch what the rule is about).
+- NOTE is one short sentence; for FAIL, name the file and line.
+- Judge only the changed lines, not the code they don't touch.
+
+OUTPUT FORMAT & STYLE
+- ASCII only. No emojis, no markdown, no color codes.
+- Output the lines only, without headings or other text.
+
+=============================
+"#;
+
+/// Verdict of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    Fail,
+    NotApplicable,
+    /// The model didn't give a verdict for the rule
+    Missing,
+}
+
+impl Verdict {
+    fn parse(text: &str) -> Option<Verdict> {
+        match text.trim().to_ascii_uppercase().as_str() {
+            "PASS" => Some(Verdict::Pass),
+            "FAIL" => Some(Verdict::Fail),
+            "N/A" | "NA" | "N-A" => Some(Verdict::NotApplicable),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Pass => "PASS",
+            Verdict::Fail => "FAIL",
+            Verdict::NotApplicable => "N/A",
+            Verdict::Missing => "MISSING",
+        }
+    }
+}
+
+/// Verdict of the model on a rule
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleVerdict {
+    pub id: String,
+    pub rule: String,
+    /// Guidelines file defining the rule
+    pub file: String,
+    pub verdict: Verdict,
+    pub note: String,
+}
+
+/// Machine-readable compliance report (`--json`)
+#[derive(Debug, Clone, Serialize)]
+pub struct ComplianceReport {
+    pub target: String,
+    pub model: String,
+    pub rules: Vec<RuleVerdict>,
+}
+
+/// Rules of the guidelines files, with the file defining each of them. Rules with an explicit
+/// ID (`[RG-012] ...`) are used when there are any; otherwise the numbered lists of the
+/// `.rv_guidelines` files.
+fn load_rules(rvconfig: &RvConfig) -> Vec<(String, GuidelineRule)> {
+    let variables = PromptVariables::collect(rvconfig);
+    let files: Vec<(String, String)> = rvconfig
+        .project_guidelines_files
+        .files
+        .iter()
+        .filter_map(|file| {
+            read_file(file).map(|content| (file.clone(), variables.resolve(&content)))
+        })
+        .collect();
+
+    let rules: Vec<(String, GuidelineRule)> = files
+        .iter()
+        .flat_map(|(file, content)| parse_rules(content).into_iter().map(|r| (file.clone(), r)))
+        .collect();
+    if !rules.is_empty() {
+        return rules;
+    }
+    files
+        .iter()
+        .filter(|(file, _)| file.starts_with(".rv_guidelines"))
+        .flat_map(|(file, content)| {
+            parse_numbered_rules(content)
+                .into_iter()
+                .map(|r| (file.clone(), r))
+        })
+        .collect()
+}
+
+/// Verdicts of the response, one per rule; rules without an answer are `Missing`
+pub fn parse_verdicts(response: &str, rules: &[(String, GuidelineRule)]) -> Vec<RuleVerdict> {
+    rules
+        .iter()
+        .map(|(file, rule)| {
+            let answer = response.lines().find_map(|line| {
+                let mut fields = line.splitn(3, '|').map(str::trim);
+                let id = fields
+                    .next()?
+                    .trim_matches(|c| matches!(c, '[' | ']' | '#'));
+                if id != rule.id {
+                    return None;
+                }
+                let verdict = Verdict::parse(fields.next()?)?;
+                Some((verdict, fields.next().unwrap_or_default().to_string()))
+            });
+            let (verdict, note) = answer.unwrap_or((Verdict::Missing, String::new()));
+            RuleVerdict {
+                id: rule.id.clone(),
+                rule: rule.text.clone(),
+                file: file.clone(),
+                verdict,
+                note,
+            }
+        })
+        .collect()
+}
+
+/// Compliance table, one line per rule
+pub fn format_compliance(verdicts: &[RuleVerdict]) -> String {
+    let count = |verdict: Verdict| verdicts.iter().filter(|v| v.verdict == verdict).count();
+    let mut table = format!(
+        "GUIDELINE COMPLIANCE ({} rules: {} fail, {} pass, {} n/a",
+        verdicts.len(),
+        count(Verdict::Fail),
+        count(Verdict::Pass),
+        count(Verdict::NotApplicable)
+    );
+    if count(Verdict::Missing) > 0 {
+        table.push_str(&format!(", {} missing", count(Verdict::Missing)));
+    }
+    table.push_str(&format!(
+        ")\n{:<RULE_COLUMN$}  {:<7}  NOTE\n",
+        "RULE", "VERDICT"
+    ));
+    for verdict in verdicts {
+        let note = if verdict.note.is_empty() {
+            &verdict.rule
+        } else {
+            &verdict.note
+        };
+        table.push_str(&format!(
+            "{:<RULE_COLUMN$}  {:<7}  {note}\n",
+            verdict.id,
+            verdict.verdict.name()
+        ));
+    }
+    table
+}
+
+/// Changes of `rev`: a commit, or a `base..head` range
+fn expand_rev(rev: &str) -> Result<(String, ExpandedCommit)> {
+    if rev.contains("..") {
+        let (base_oid, head_oid) =
+            git_helpers::resolve_range(rev).context("Failed to resolve the commit range")?;
+        return Ok((
+            format!("range {rev}"),
+            git_helpers::expanded_between_commits(base_oid, head_oid)?,
+        ));
+    }
+    let oid = git_helpers::get_oid(rev).with_context(|| format!("Unknown commit {rev}"))?;
+    Ok((
+        format!("commit {oid}"),
+        git_helpers::expanded_from_commit(oid)?,
+    ))
+}
+
+/// Check the changes of `rev` against the rules of the guidelines; fails when a rule is broken
+pub async fn check_guidelines(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    rev: &str,
+    json_out: Option<&Path>,
+    pipe: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let pipeline = RequestPipeline::new(rvconfig)?;
+    let rvconfig = &pipeline.rvconfig;
+
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let rules = load_rules(rvconfig);
+    if rules.is_empty() {
+        println!(
+            "[ERROR] No numbered guidelines found; write rules as `[RG-012] ...` lines in a guidelines file, or as a numbered list in .rv_guidelines."
+        );
+        return Ok(());
+    }
+
+    let (target, expcommit) = expand_rev(rev)?;
+    let ignore = IgnoreRules::from_rvignore();
+    let expcommit = expcommit.exclude(|path| ignore.is_path_ignored(path), "excluded by .rvignore");
+    let (expcommit, _) = minimize(expcommit, &MinimizeOptions::from_config(rvconfig));
+    if expcommit.is_empty() {
+        bail!("{target} has no changes to check");
+    }
+    let paths: Vec<String> = expcommit
+        .sources
+        .iter()
+        .flatten()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    let mut rules_text = String::new();
+    for (_, rule) in &rules {
+        rules_text.push_str(&format!("{} | {}\n", rule.id, rule.text));
+    }
+    let mut prompt = pipeline.redact(expcommit.get_xml_structure(rvconfig.diff_profile));
+
+    let (llm_configuration_key, llm_configuration) =
+        pipeline.select_llm(llm_selection, estimate_tokens(&prompt), &paths, pipe)?;
+    // The rules come from the guidelines, only the changes are untrusted
+    let system_prompt = pipeline.system_prompt(
+        COMPLIANCE_SYSTEM_PROMPT.to_string() + &markup::element("rules", &[], &rules_text),
+    );
+    pipeline.prepare(
+        &llm_configuration,
+        &system_prompt,
+        std::iter::once(&mut prompt),
+    )?;
+
+    let input_tokens = estimate_tokens(&system_prompt) + estimate_tokens(&prompt);
+    if !request::confirm_requests(
+        &llm_configuration,
+        paths.len(),
+        input_tokens,
+        None,
+        assume_yes,
+    )? {
+        return Ok(());
+    }
+    if !pipe {
+        term_helpers::clear_term();
+        println!("[Checking {target} against {} rules]\n", rules.len());
+    }
+
+    let model = llm_configuration.model_id.clone();
+    let client = create_llm_provider(llm_configuration.clone());
+    let response = client
+        .request(system_prompt, vec![Message::user(prompt)])
+        .await?;
+    let verdicts = parse_verdicts(&sanitize_output(&response, false), &rules);
+    let compliance = format_compliance(&verdicts);
+    println!("{compliance}");
+    let usage = Usage::estimate(&llm_configuration, input_tokens, estimate_tokens(&response));
+    history::save_command_usage(
+        rvconfig,
+        format!("guidelines of {target}"),
+        &llm_configuration_key,
+        &llm_configuration,
+        paths,
+        compliance,
+        usage,
+    );
+
+    if let Some(path) = json_out {
+        let report = ComplianceReport {
+            target,
+            model,
+            rules: verdicts.clone(),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    let failed = verdicts
+        .iter()
+        .filter(|v| v.verdict == Verdict::Fail)
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} rules are not followed", verdicts.len());
+    }
+    Ok(())
+}
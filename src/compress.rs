@@ -0,0 +1,131 @@
+//! Token-efficient `<source>` elements: blank lines and comments can be left out of the full
+//! files sent with a review, with an offset table mapping the remaining lines to the original ones
+
+use crate::config::SourceCompression;
+use crate::language::Language;
+
+/// Added to the system prompt when sources are compressed, so that findings keep the original line numbers
+pub const SOURCE_COMPRESSION_PROMPT: &str = r#"
+Some <source> elements are compressed: their blank lines (and comments, with
+compression="strip-comments") were removed. Their line_offsets attribute lists
+"line:+offset" pairs: from that line of the element on, add the offset to get
+the line number of the original file. Always report original line numbers.
+"#;
+
+/// Source with lines left out, and the offset table mapping its lines to the original ones
+#[derive(Debug, Clone, Default)]
+pub struct CompressedSource {
+    pub text: String,
+    /// `(line, offset)`: from `line` (1-based, in `text`) on, the original line is `line + offset`
+    pub line_offsets: Vec<(usize, usize)>,
+}
+
+impl CompressedSource {
+    /// Offset table as an attribute value (ex. `12:+3,40:+7`)
+    pub fn format_offsets(&self) -> String {
+        self.line_offsets
+            .iter()
+            .map(|(line, offset)| format!("{line}:+{offset}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Line comment prefixes and block comment delimiters of a language
+pub type CommentSyntax = (
+    &'static [&'static str],
+    Option<(&'static str, &'static str)>,
+);
+
+pub fn comment_syntax(language: Language) -> CommentSyntax {
+    match language {
+        Language::Rust
+        | Language::JavaScript
+        | Language::TypeScript
+        | Language::Go
+        | Language::C
+        | Language::Cpp
+        | Language::CSharp
+        | Language::Java
+        | Language::Kotlin
+        | Language::Swift => (&["//"], Some(("/*", "*/"))),
+        Language::Php => (&["//", "#"], Some(("/*", "*/"))),
+        Language::Css => (&[], Some(("/*", "*/"))),
+        Language::Python
+        | Language::Ruby
+        | Language::Shell
+        | Language::Toml
+        | Language::Yaml
+        | Language::Dockerfile => (&["#"], None),
+        Language::Sql => (&["--"], Some(("/*", "*/"))),
+        Language::Html | Language::Markdown => (&[], Some(("<!--", "-->"))),
+        Language::Json | Language::Other => (&[], None),
+    }
+}
+
+/// Remove the blank lines and, with `StripComments`, the lines holding only a comment.
+/// Comments sharing a line with code are kept, as telling them apart from strings needs a parser.
+pub fn compress_source(
+    text: &str,
+    language: Language,
+    compression: SourceCompression,
+) -> CompressedSource {
+    let (line_comments, block_comment) = match compression {
+        SourceCompression::None => {
+            return CompressedSource {
+                text: text.to_string(),
+                line_offsets: Vec::new(),
+            };
+        }
+        SourceCompression::StripBlank => (&[][..], None),
+        SourceCompression::StripComments => comment_syntax(language),
+    };
+
+    let mut compressed = CompressedSource::default();
+    let mut kept = 0;
+    let mut removed = 0;
+    let mut in_block = false;
+    for (idx, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        let is_comment = if in_block {
+            // The line closing the block is kept when code follows the end of the comment
+            match block_comment.and_then(|(_, end)| trimmed.find(end).map(|pos| pos + end.len())) {
+                Some(comment_end) => {
+                    in_block = false;
+                    comment_end == trimmed.len()
+                }
+                None => true,
+            }
+        } else if let Some((start, end)) = block_comment
+            && trimmed.starts_with(start)
+        {
+            // A block comment opened and closed on a line with code after it isn't a comment-only line
+            match trimmed[start.len()..].find(end) {
+                Some(pos) => pos + start.len() + end.len() == trimmed.len(),
+                None => {
+                    in_block = true;
+                    true
+                }
+            }
+        } else {
+            // The shebang is a comment for the language, but tells how the file runs
+            !(idx == 0 && trimmed.starts_with("#!"))
+                && line_comments
+                    .iter()
+                    .any(|comment| trimmed.starts_with(comment))
+        };
+
+        if trimmed.is_empty() || is_comment {
+            removed += 1;
+            continue;
+        }
+        kept += 1;
+        if compressed.line_offsets.last().map(|(_, offset)| *offset) != Some(removed) && removed > 0
+        {
+            compressed.line_offsets.push((kept, removed));
+        }
+        compressed.text.push_str(line);
+        compressed.text.push('\n');
+    }
+    compressed
+}
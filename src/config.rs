@@ -1,38 +1,16 @@
-use clap::ValueEnum;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{self, ErrorKind, Read};
+use std::io::{self, Read};
 use std::path::PathBuf;
 
-pub fn default_config_path() -> io::Result<PathBuf> {
-    // Build the path
-    let path: PathBuf = if let Some(mut dir) = dirs::config_dir() {
-        dir.push("rv");
-        dir.push("config.toml");
-        dir
-    } else {
-        // Fallback: $HOME/.config/rv/config.toml
-        let home = std::env::var_os("HOME").ok_or_else(|| {
-            io::Error::new(
-                ErrorKind::NotFound,
-                "could not determine config directory (no XDG config dir and HOME not set)",
-            )
-        })?;
-        let mut p = PathBuf::from(home);
-        p.push(".config");
-        p.push("rv");
-        p.push("config.toml");
-        p
-    };
-
-    // Ensure parent directory exists
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
-    } else {
-        return Err(io::Error::other("config path has no parent directory"));
-    }
+/// Name of the configuration file in the configuration directory
+pub const CONFIG_FILE: &str = "config.toml";
 
+pub fn default_config_path() -> io::Result<PathBuf> {
+    let mut path = crate::paths::ensure_dir(crate::paths::Dir::Config)?;
+    path.push(CONFIG_FILE);
     Ok(path)
 }
 
@@ -74,8 +52,26 @@ fn default_llm_configs() -> Vec<LLMConfig> {
             model_id: String::from("qwen/qwen3-235b-a22b-2507"),
             api_key: default_api_key(),
             allow_reasoning: true,
+            reasoning_effort: None,
             custom_prompt: None,
             actions_menu: true,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+            capabilities: CapabilityOverrides::default(),
+            sampling: SamplingParams::default(),
+            retry: RetryConfig::default(),
+            first_token_timeout_secs: default_first_token_timeout_secs(),
+            total_timeout_secs: default_total_timeout_secs(),
+            context_window: None,
+            context_overflow: ContextOverflow::default(),
+            quality_tier: None,
+            host: None,
+            port: None,
+            endpoint: None,
+            deployment_name: None,
+            api_version: None,
+            base_url: None,
+            headers: HashMap::new(),
         },
         LLMConfig {
             configuration_name: String::from("free"),
@@ -83,8 +79,26 @@ fn default_llm_configs() -> Vec<LLMConfig> {
             model_id: String::from("mistralai/devstral-2512:free"),
             api_key: default_api_key(),
             allow_reasoning: true,
+            reasoning_effort: None,
             custom_prompt: None,
             actions_menu: true,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+            capabilities: CapabilityOverrides::default(),
+            sampling: SamplingParams::default(),
+            retry: RetryConfig::default(),
+            first_token_timeout_secs: default_first_token_timeout_secs(),
+            total_timeout_secs: default_total_timeout_secs(),
+            context_window: None,
+            context_overflow: ContextOverflow::default(),
+            quality_tier: None,
+            host: None,
+            port: None,
+            endpoint: None,
+            deployment_name: None,
+            api_version: None,
+            base_url: None,
+            headers: HashMap::new(),
         },
         LLMConfig {
             configuration_name: String::from("think"),
@@ -92,8 +106,26 @@ fn default_llm_configs() -> Vec<LLMConfig> {
             model_id: String::from("deepseek/deepseek-v3.2:online"),
             api_key: default_api_key(),
             allow_reasoning: true,
+            reasoning_effort: None,
             custom_prompt: None,
             actions_menu: true,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+            capabilities: CapabilityOverrides::default(),
+            sampling: SamplingParams::default(),
+            retry: RetryConfig::default(),
+            first_token_timeout_secs: default_first_token_timeout_secs(),
+            total_timeout_secs: default_total_timeout_secs(),
+            context_window: None,
+            context_overflow: ContextOverflow::default(),
+            quality_tier: None,
+            host: None,
+            port: None,
+            endpoint: None,
+            deployment_name: None,
+            api_version: None,
+            base_url: None,
+            headers: HashMap::new(),
         },
     ]
 }
@@ -105,8 +137,227 @@ fn default_default_llm_config() -> String {
 fn default_branch_mode() -> BranchAgainst {
     BranchAgainst::Main
 }
+
+fn default_base_branches() -> Vec<String> {
+    vec![String::from("main"), String::from("master")]
+}
+
+fn default_review_cache() -> bool {
+    true
+}
+
+fn default_next_steps() -> bool {
+    true
+}
+
+fn default_small_max_tokens() -> usize {
+    4_000
+}
+
+fn default_medium_max_tokens() -> usize {
+    30_000
+}
+
+fn default_prompt_injection_guard() -> bool {
+    true
+}
+
+fn default_reformat_invalid_reviews() -> bool {
+    true
+}
+
+fn default_normalize_output() -> bool {
+    true
+}
+
+fn default_max_added_file_bytes() -> u64 {
+    1024 * 1024
+}
+
+fn default_binary_files_check() -> bool {
+    true
+}
+
+fn default_duplicate_code_check() -> bool {
+    true
+}
+
+fn default_duplicate_min_tokens() -> usize {
+    50
+}
+
+fn default_formats_enabled() -> bool {
+    true
+}
+
+fn default_migration_safety() -> bool {
+    true
+}
+
+fn default_infra_checks() -> bool {
+    true
+}
+
+fn default_semver_command() -> String {
+    String::from("cargo semver-checks check-release --color never")
+}
+
+fn default_complexity_enabled() -> bool {
+    true
+}
+
+fn default_complexity_max_functions() -> usize {
+    15
+}
+
+fn default_embedding_model() -> String {
+    String::from("text-embedding-3-small")
+}
+
+fn default_chunk_lines() -> usize {
+    60
+}
+
+fn default_top_k() -> usize {
+    5
+}
+
+fn default_ticket_max_tokens() -> usize {
+    1000
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_retry_jitter() -> bool {
+    true
+}
+
+fn default_first_token_timeout_secs() -> u64 {
+    120
+}
+
+fn default_total_timeout_secs() -> u64 {
+    900
+}
+
+fn default_notify_after_secs() -> u64 {
+    30
+}
+
+fn default_notify_sound() -> Vec<String> {
+    vec!["CRITICAL".to_string(), "HIGH".to_string()]
+}
+
+fn default_sandbox_timeout_secs() -> u64 {
+    300
+}
+
+fn default_max_output_bytes() -> usize {
+    1_000_000
+}
+
+fn default_env_allowlist() -> Vec<String> {
+    [
+        "PATH",
+        "HOME",
+        "USER",
+        "LANG",
+        "LC_ALL",
+        "TERM",
+        "TMPDIR",
+        "CARGO_HOME",
+        "RUSTUP_HOME",
+        "GOPATH",
+        "GOCACHE",
+        "GOMODCACHE",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_container_runtime() -> String {
+    String::from("docker")
+}
+
+fn default_max_file_bytes() -> u64 {
+    512 * 1024
+}
+
+fn default_max_file_tokens() -> usize {
+    20_000
+}
+
+fn default_raw_exclude() -> Vec<String> {
+    [
+        ".git/",
+        ".hg/",
+        ".svn/",
+        "target/",
+        "node_modules/",
+        "dist/",
+        "__pycache__/",
+        ".venv/",
+        "venv/",
+        ".tox/",
+        ".mypy_cache/",
+        ".pytest_cache/",
+        ".next/",
+        ".gradle/",
+        "*.lock",
+        "package-lock.json",
+        "*.min.js",
+        "*.min.css",
+        "*.map",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
+}
+
+fn default_readme_enabled() -> bool {
+    true
+}
+
+fn default_readme_max_tokens() -> usize {
+    2000
+}
+
+fn default_readme_files() -> Vec<String> {
+    vec![String::from("README.md"), String::from("docs/OVERVIEW.md")]
+}
 // ----------------------------------------------------------------------------
 
+/// What is left out of the full sources sent with a review, to save tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceCompression {
+    #[default]
+    None,
+    StripBlank,
+    /// Blank lines and comment-only lines
+    StripComments,
+}
+
+impl SourceCompression {
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceCompression::None => "none",
+            SourceCompression::StripBlank => "strip-blank",
+            SourceCompression::StripComments => "strip-comments",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(default)]
 /// How the LLM context gets produced
@@ -115,6 +366,8 @@ pub struct DiffProfile {
     pub report_diffs: bool,
     #[serde(default = "default_report_sources")]
     pub report_sources: bool,
+    #[serde(default)]
+    pub source_compression: SourceCompression,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -136,14 +389,194 @@ pub struct LLMConfig {
     #[serde(default = "default_api_key")]
     pub api_key: String,
 
-    // TODO: Implement optional reasioning
+    /// Let the model reason before answering, when it can do without (Ollama `think`, OpenRouter
+    /// `reasoning.enabled`)
     #[serde(default = "default_allow_reasoning")]
     pub allow_reasoning: bool,
+    /// Reasoning effort requested from the models that reason (OpenAI, OpenRouter, Azure OpenAI and
+    /// the OpenAI-compatible APIs)
+    #[serde(default)]
+    pub reasoning_effort: Option<ReasoningEffort>,
 
     #[serde(default)]
     pub custom_prompt: Option<CustomPrompt>,
     #[serde(default)]
     pub actions_menu: bool,
+
+    /// Price in USD per million input tokens, used for cost estimates
+    #[serde(default)]
+    pub input_price_per_mtok: Option<f64>,
+    /// Price in USD per million output tokens, used for cost estimates
+    #[serde(default)]
+    pub output_price_per_mtok: Option<f64>,
+
+    /// Corrections to the capabilities rv assumes for the model
+    #[serde(default)]
+    pub capabilities: CapabilityOverrides,
+    /// Sampling parameters sent with the requests, unset ones keep the default of the provider
+    #[serde(default)]
+    pub sampling: SamplingParams,
+
+    /// Retries of the requests failing with a transient error
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Longest wait for the first token of a streamed response, retries included (0 disables it)
+    #[serde(default = "default_first_token_timeout_secs")]
+    pub first_token_timeout_secs: u64,
+    /// Longest duration of a request, from sending it to the end of the response (0 disables it)
+    #[serde(default = "default_total_timeout_secs")]
+    pub total_timeout_secs: u64,
+
+    /// Context window of the model in tokens, for the models rv doesn't know (or to lower it)
+    #[serde(default)]
+    pub context_window: Option<usize>,
+    /// What happens to a review prompt exceeding the context window
+    #[serde(default)]
+    pub context_overflow: ContextOverflow,
+
+    /// Quality of the model, required for the configuration to be picked by `model_strategy = "auto"`
+    #[serde(default)]
+    pub quality_tier: Option<QualityTier>,
+
+    /// Host of the Ollama server (`localhost` when unset)
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Port of the Ollama server (11434 when unset)
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Endpoint of the Azure OpenAI resource (ex. `https://my-resource.openai.azure.com`)
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Azure OpenAI deployment receiving the requests (`model_id` when unset)
+    #[serde(default)]
+    pub deployment_name: Option<String>,
+    /// Azure OpenAI API version (ex. `2024-10-21`)
+    #[serde(default)]
+    pub api_version: Option<String>,
+
+    /// Base URL of the OpenAI-compatible API of the Custom provider (ex. `http://localhost:8000/v1`)
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Extra HTTP headers sent with every request to an OpenAI-compatible API (ex. gateway tokens)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// How long a model reasons before answering
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReasoningEffort::Minimal => "minimal",
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+/// What happens to a review prompt exceeding the context window of the model
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextOverflow {
+    /// Fail before sending anything
+    #[default]
+    Refuse,
+    /// Leave files out of the prompt, sources first, until it fits
+    Truncate,
+}
+
+/// Quality annotation of an LLM configuration, from the cheapest to the strongest model
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityTier {
+    Fast,
+    Balanced,
+    Strong,
+}
+
+impl QualityTier {
+    pub fn name(self) -> &'static str {
+        match self {
+            QualityTier::Fast => "fast",
+            QualityTier::Balanced => "balanced",
+            QualityTier::Strong => "strong",
+        }
+    }
+}
+
+/// What `--notes` does when the reviewed commit already has a review note
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotesMerge {
+    /// Add the review after the existing ones
+    #[default]
+    Append,
+    /// Replace the existing note
+    Overwrite,
+    /// Leave the existing note untouched
+    Keep,
+}
+
+/// How the LLM configuration is picked when `--llm` isn't used
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelStrategy {
+    /// Always use `default_llm_config`
+    #[default]
+    Default,
+    /// Pick among the configurations with a `quality_tier` from the size of the review and `max_review_cost`
+    Auto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+/// Capabilities of a model that differ from the ones rv knows about; unset fields keep the known value
+pub struct CapabilityOverrides {
+    pub streaming: Option<bool>,
+    pub system_messages: Option<bool>,
+    pub json_mode: Option<bool>,
+    pub reasoning: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+/// Sampling parameters of the requests, for the models that need other values than the defaults of
+/// their provider (or reject some of them, like the penalties)
+pub struct SamplingParams {
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Longest response, in tokens
+    pub max_tokens: Option<u64>,
+    pub frequency_penalty: Option<f64>,
+    pub presence_penalty: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+/// Retries of the requests failing with a transient error (HTTP 408, 429, 5xx, connection errors)
+pub struct RetryConfig {
+    /// Attempts of every request, the first one included (1 disables the retries)
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled at every attempt
+    #[serde(default = "default_retry_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Longest delay between two attempts, `Retry-After` included
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Randomize the delays, so that parallel runs don't retry at the same time
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -156,6 +589,310 @@ pub struct ProjectGuidelinesFiles {
     pub files: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// How the project README gets included as context
+pub struct ReadmeConfig {
+    #[serde(default = "default_readme_enabled")]
+    pub enabled: bool,
+    /// Maximum estimated tokens of README content; 0 disables the cap
+    #[serde(default = "default_readme_max_tokens")]
+    pub max_tokens: usize,
+    /// Headings of the sections to include; empty includes every section
+    #[serde(default)]
+    pub sections: Vec<String>,
+    /// Candidate files, the first one found in the repository root is used
+    #[serde(default = "default_readme_files")]
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Automatic switching of the review profile depending on the size of the diffs
+pub struct AutoProfileConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Diffs up to this many estimated tokens get full sources and a deep review
+    #[serde(default = "default_small_max_tokens")]
+    pub small_max_tokens: usize,
+    /// Diffs up to this many estimated tokens get hunks only; bigger ones are summarized first
+    #[serde(default = "default_medium_max_tokens")]
+    pub medium_max_tokens: usize,
+}
+
+/// Where the embeddings of the repository index come from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingBackend {
+    /// Hashed identifier vectors computed locally, nothing leaves the machine
+    #[default]
+    Local,
+    /// Embedding API of the provider of an LLM configuration
+    Api,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Embedding index of the repository (`rv index`), used to retrieve related code for reviews
+pub struct IndexConfig {
+    #[serde(default)]
+    pub embeddings: EmbeddingBackend,
+    /// Embedding model, with `embeddings = "api"`
+    #[serde(default = "default_embedding_model")]
+    pub model: String,
+    /// LLM configuration providing the provider and API key, with `embeddings = "api"` (the default one when unset)
+    #[serde(default)]
+    pub llm_config: Option<String>,
+    /// Lines of every indexed chunk
+    #[serde(default = "default_chunk_lines")]
+    pub chunk_lines: usize,
+    /// Chunks retrieved for a review; 0 disables the retrieval
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+}
+
+/// Issue tracker the tickets referenced by the reviewed changes are fetched from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackerKind {
+    /// Tickets aren't fetched
+    #[default]
+    None,
+    Jira,
+    Linear,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Ticket referenced by the branch or the commit messages, sent with the review as context
+pub struct TicketsConfig {
+    #[serde(default)]
+    pub tracker: TrackerKind,
+    /// Base URL of the Jira instance (ex. `https://acme.atlassian.net`)
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Jira account email the API token belongs to
+    #[serde(default)]
+    pub user: Option<String>,
+    /// API token; `JIRA_API_TOKEN` or `LINEAR_API_KEY` is used when unset
+    #[serde(default)]
+    pub api_token: Option<String>,
+    /// Maximum estimated tokens of the ticket description
+    #[serde(default = "default_ticket_max_tokens")]
+    pub max_tokens: usize,
+}
+
+/// Isolation of the project commands run by rv
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxIsolation {
+    /// Plain child process (timeout, output cap and environment scrubbing still apply)
+    #[default]
+    None,
+    /// `bwrap`: read-only system, private /tmp, only the checked directory writable
+    Bwrap,
+    /// `docker run` (or `podman run`) of `container_image`
+    Container,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Desktop notifications of the reviews, for runs long enough to be left in the background
+pub struct NotifyConfig {
+    /// Notify every review (`--notify` enables it for one run)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only notify the runs lasting at least this many seconds
+    #[serde(default = "default_notify_after_secs")]
+    pub after_secs: u64,
+    /// Severities of the reviews that are notified (ex. `["CRITICAL", "HIGH"]`); all when empty
+    #[serde(default)]
+    pub severities: Vec<String>,
+    /// Severities of the reviews whose notification also plays a sound
+    #[serde(default = "default_notify_sound")]
+    pub sound: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+/// Directories of rv overriding the platform ones (the configuration file is moved with
+/// `RV_CONFIG_DIR`), printed by `rv paths`
+pub struct PathsConfig {
+    /// Cached data that can be rebuilt
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Data kept between runs (review history)
+    #[serde(default)]
+    pub state_dir: Option<PathBuf>,
+    /// Logs of the runs
+    #[serde(default)]
+    pub log_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+/// Prices of a model in USD per million tokens, used by the LLM configurations without their own
+pub struct ModelPrice {
+    #[serde(default)]
+    pub input_per_mtok: f64,
+    #[serde(default)]
+    pub output_per_mtok: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+/// Consensus reviews: the same prompt sent to several LLM configurations, their findings merged
+pub struct ConsensusConfig {
+    /// Names of the LLM configurations reviewing every change (at least two to take effect)
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Guardrails of the project commands run by rv (ex. `verify_command`)
+pub struct SandboxConfig {
+    #[serde(default)]
+    pub isolation: SandboxIsolation,
+    /// Commands are killed after this many seconds
+    #[serde(default = "default_sandbox_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Output kept of each stream of a command, the rest is dropped
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    /// Environment variables passed to the commands; every other one is removed
+    #[serde(default = "default_env_allowlist")]
+    pub env_allowlist: Vec<String>,
+    /// Paths the isolated commands can write to (ex. `~/.cargo` for its registry cache)
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// Let the isolated commands reach the network
+    #[serde(default)]
+    pub network: bool,
+    /// Image of the containers, with `isolation = "container"`
+    #[serde(default)]
+    pub container_image: Option<String>,
+    #[serde(default = "default_container_runtime")]
+    pub container_runtime: String,
+}
+
+/// When the reviewed commits must carry a `Signed-off-by` line (Developer Certificate of Origin)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignoffRequirement {
+    /// Only in repositories with a DCO file (`DCO`, `DCO.md`, `.github/dco.yml`)
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Breaking changes of the public API of Rust crates (requires the `rust` feature)
+pub struct SemverConfig {
+    /// Run the check when the changes touch public items
+    #[serde(default)]
+    pub enabled: bool,
+    /// Revision compared with (the parent of the reviewed commits, or `HEAD`, when unset)
+    #[serde(default)]
+    pub baseline: Option<String>,
+    /// Command run in the sandbox, followed by `--baseline-rev <revision>`
+    #[serde(default = "default_semver_command")]
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Structured formats of the changed files (OpenAPI, Protocol Buffers, SQL migrations,
+/// Dockerfiles, CI configurations, Kubernetes manifests)
+pub struct FormatsConfig {
+    /// Detect the formats and send their review guidelines to the model
+    #[serde(default = "default_formats_enabled")]
+    pub enabled: bool,
+    /// Run the linters of the detected files in the sandbox and send their output to the model
+    #[serde(default)]
+    pub lint: bool,
+    /// Flag the risky statements of the migrations and review them for safety (destructive
+    /// operations, locks, reversibility)
+    #[serde(default = "default_migration_safety")]
+    pub migration_safety: bool,
+    /// Flag the risky lines of the Dockerfiles and CI configurations (unpinned images and
+    /// actions, secrets, cache busting, privileged steps) and review them
+    #[serde(default = "default_infra_checks")]
+    pub infra_checks: bool,
+    /// Linter commands by format (`openapi`, `protobuf`, `sql-migration`, `dockerfile`,
+    /// `github-actions`, `gitlab-ci`, `kubernetes`), `{file}` being replaced by the path of the
+    /// file; an empty command skips the format
+    #[serde(default)]
+    pub linters: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Complexity of the changed functions, reported along with the review
+pub struct ComplexityConfig {
+    /// Measure the changed functions and add a COMPLEXITY appendix to the report
+    #[serde(default = "default_complexity_enabled")]
+    pub enabled: bool,
+    /// Also send the measures to the model, asking it to review the most complex functions first
+    #[serde(default)]
+    pub prioritize: bool,
+    /// Functions listed at most, the most complex first
+    #[serde(default = "default_complexity_max_functions")]
+    pub max_functions: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Deterministic checks of the reviewed changes, reported along with the review
+pub struct ChecksConfig {
+    /// Report the reviewed commits without a GPG, SSH or X.509 signature
+    #[serde(default)]
+    pub signatures: bool,
+    #[serde(default)]
+    pub signoff: SignoffRequirement,
+    /// Report the added or modified files bigger than this; 0 disables the check
+    #[serde(default = "default_max_added_file_bytes")]
+    pub max_added_file_bytes: u64,
+    /// Report the binary files added or modified by the changes
+    #[serde(default = "default_binary_files_check")]
+    pub binary_files: bool,
+    /// Report the code copy-pasted across the changes, and tell the model about it
+    #[serde(default = "default_duplicate_code_check")]
+    pub duplicate_code: bool,
+    /// Tokens of the smallest copy reported
+    #[serde(default = "default_duplicate_min_tokens")]
+    pub duplicate_min_tokens: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// How files get collected in raw mode
+pub struct RawConfig {
+    /// Files bigger than this are skipped in directory reviews; 0 disables the limit
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Files with more estimated tokens than this are skipped in directory reviews; 0 disables the limit
+    #[serde(default = "default_max_file_tokens")]
+    pub max_file_tokens: usize,
+    /// Follow symlinks that stay inside of the reviewed directory
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Include dotfiles and dot-directories
+    #[serde(default)]
+    pub hidden: bool,
+    /// Review the subdirectories too (`--recursive`, `--no-recursive`)
+    #[serde(default)]
+    pub recursive: bool,
+    /// Review `--file` and `--dir` without Git (`--no-raw` reviews their diffs)
+    #[serde(default)]
+    pub by_default: bool,
+    /// Gitignore-like patterns excluded from directory reviews, on top of `.rvignore`
+    #[serde(default = "default_raw_exclude")]
+    pub exclude: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 /// Main configuration structure, used in `~/.config/rv/config.toml`
@@ -168,10 +905,136 @@ pub struct RvConfig {
     pub default_llm_config: String,
     #[serde(default = "default_branch_mode")]
     pub default_branch_mode: BranchAgainst,
+    /// Candidate names of the main branch, checked in order when comparing against `main`
+    #[serde(default = "default_base_branches")]
+    pub base_branches: Vec<String>,
     #[serde(default)]
     pub project_context_files: ProjectContextFiles,
     #[serde(default)]
     pub project_guidelines_files: ProjectGuidelinesFiles,
+    #[serde(default)]
+    pub readme: ReadmeConfig,
+    #[serde(default)]
+    pub raw: RawConfig,
+    #[serde(default)]
+    pub auto_profile: AutoProfileConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub checks: ChecksConfig,
+    #[serde(default)]
+    pub complexity: ComplexityConfig,
+    #[serde(default)]
+    pub semver: SemverConfig,
+    #[serde(default)]
+    pub formats: FormatsConfig,
+    #[serde(default)]
+    pub tickets: TicketsConfig,
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub consensus: ConsensusConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    /// Redact secrets (API keys, passwords, tokens) before sending anything
+    #[serde(default)]
+    pub redact_secrets: bool,
+    /// Send stable pseudonyms (ex. `file_01.rs`) in place of the paths of the reviewed files
+    #[serde(default)]
+    pub redact_paths: bool,
+    /// Neutralize instruction-like text in the reviewed code and delimit it with random boundaries
+    #[serde(default = "default_prompt_injection_guard")]
+    pub prompt_injection_guard: bool,
+    /// Ask once for a reformatted review when the reply doesn't follow the required structure
+    #[serde(default = "default_reformat_invalid_reviews")]
+    pub reformat_invalid_reviews: bool,
+    /// Normalize the model output to ASCII and soft-wrap it at 80 columns; escape sequences and
+    /// control characters are always removed
+    #[serde(default = "default_normalize_output")]
+    pub normalize_output: bool,
+    /// Print out the XML structure of every Git review (`--log-xml`, `--no-log-xml`)
+    #[serde(default)]
+    pub log_xml_structure: bool,
+    /// Record token usage and costs in the local review history, shown by `rv stats`
+    #[serde(default)]
+    pub usage_stats: bool,
+    /// Print the commands to run next at the end of every review (ex. `--verify-fixes`, `--comment`)
+    #[serde(default = "default_next_steps")]
+    pub next_steps: bool,
+    /// Return the cached review when the prompts and the model didn't change (`--no-cache` bypasses it)
+    #[serde(default = "default_review_cache")]
+    pub review_cache: bool,
+    /// Print the estimated token usage and cost after every review, and record them
+    #[serde(default)]
+    pub show_usage: bool,
+    /// Print the reasoning of the models as it streams, before the response (`--show-reasoning`)
+    #[serde(default)]
+    pub show_reasoning: bool,
+    /// Ask the model for a JSON review (findings with file, line, severity and fix), parsed and
+    /// added to the JSON outputs (`--json-review`)
+    #[serde(default)]
+    pub json_review: bool,
+    /// Prices of the models, by model ID (ex. `[prices."gpt-5"]`), for the LLM configurations
+    /// without `input_price_per_mtok` and `output_price_per_mtok`
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    /// Distill durable facts of every review into `.rv/knowledge.md`, after confirmation
+    #[serde(default)]
+    pub learn_knowledge: bool,
+    /// Fetch refs passed to `--branch` from `origin` when they don't exist locally (off by
+    /// default, a review doesn't touch the network besides the model unless asked to)
+    #[serde(default)]
+    pub auto_fetch: bool,
+    /// Keep the refs fetched for pull request reviews instead of deleting them after the review
+    #[serde(default)]
+    pub keep_pr_refs: bool,
+    /// OTLP/HTTP collector receiving the review pipeline traces (requires the `otel` feature)
+    #[serde(default)]
+    pub otel_endpoint: Option<String>,
+    /// URL receiving every review as JSON, on top of the terminal output
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Editor opening the findings of `--open` (`$VISUAL` or `$EDITOR` when unset)
+    #[serde(default)]
+    pub editor: Option<String>,
+    /// Merge strategy of `--notes` with the existing review note of the commit
+    #[serde(default)]
+    pub notes_merge: NotesMerge,
+    /// Tags added to every review (ex. the service of the repository), on top of `--tag`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Values of the `{{name}}` variables of the guidelines and custom prompts, on top of the
+    /// variables read from git (`branch`, `author`, `repo_name`, `ticket`)
+    #[serde(default)]
+    pub prompt_variables: HashMap<String, String>,
+    /// Project keys of the `{{ticket}}` variable (ex. `["PAY"]` for `PAY-123`); any key when empty
+    #[serde(default)]
+    pub ticket_prefixes: Vec<String>,
+    #[serde(default)]
+    pub model_strategy: ModelStrategy,
+    /// Cost ceiling in USD of a review, used by `model_strategy = "auto"`
+    #[serde(default)]
+    pub max_review_cost: Option<f64>,
+    /// Fail the review when a smaller share (0.0 to 1.0) of the diff reaches the prompt
+    #[serde(default)]
+    pub min_coverage: Option<f64>,
+    /// Context lines kept around the changes of every diff hunk (git's 3 when unset)
+    #[serde(default)]
+    pub diff_context_lines: Option<usize>,
+    /// Leave the hunks that only change whitespace out of the diffs
+    #[serde(default)]
+    pub ignore_whitespace: bool,
+    /// Apply every suggested fix to a temporary worktree and check that it builds
+    #[serde(default)]
+    pub verify_fixes: bool,
+    /// Command checking a suggested fix (`cargo check` or `go build ./...` when unset)
+    #[serde(default)]
+    pub verify_command: Option<String>,
+    /// Re-print the reviewed diff with the findings under the lines they are about
+    #[serde(default)]
+    pub inline: bool,
 }
 
 // -----------------------------------
@@ -181,7 +1044,6 @@ impl Default for ProjectContextFiles {
         ProjectContextFiles {
             files: vec![
                 String::from(".rv_context"),
-                String::from("README.md"),
                 String::from("Cargo.toml"),
                 String::from("package.json"),
                 String::from("pyproject.toml"),
@@ -205,11 +1067,153 @@ impl Default for ProjectGuidelinesFiles {
     }
 }
 
+impl Default for ReadmeConfig {
+    fn default() -> Self {
+        ReadmeConfig {
+            enabled: true,
+            max_tokens: default_readme_max_tokens(),
+            sections: Vec::new(),
+            files: default_readme_files(),
+        }
+    }
+}
+
+impl Default for AutoProfileConfig {
+    fn default() -> Self {
+        AutoProfileConfig {
+            enabled: false,
+            small_max_tokens: default_small_max_tokens(),
+            medium_max_tokens: default_medium_max_tokens(),
+        }
+    }
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            embeddings: EmbeddingBackend::default(),
+            model: default_embedding_model(),
+            llm_config: None,
+            chunk_lines: default_chunk_lines(),
+            top_k: default_top_k(),
+        }
+    }
+}
+
+impl Default for TicketsConfig {
+    fn default() -> Self {
+        TicketsConfig {
+            tracker: TrackerKind::default(),
+            url: None,
+            user: None,
+            api_token: None,
+            max_tokens: default_ticket_max_tokens(),
+        }
+    }
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            isolation: SandboxIsolation::default(),
+            timeout_secs: default_sandbox_timeout_secs(),
+            max_output_bytes: default_max_output_bytes(),
+            env_allowlist: default_env_allowlist(),
+            writable_paths: Vec::new(),
+            network: false,
+            container_image: None,
+            container_runtime: default_container_runtime(),
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_ms: default_retry_initial_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            jitter: default_retry_jitter(),
+        }
+    }
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            enabled: false,
+            after_secs: default_notify_after_secs(),
+            severities: Vec::new(),
+            sound: default_notify_sound(),
+        }
+    }
+}
+
+impl Default for SemverConfig {
+    fn default() -> Self {
+        SemverConfig {
+            enabled: false,
+            baseline: None,
+            command: default_semver_command(),
+        }
+    }
+}
+
+impl Default for FormatsConfig {
+    fn default() -> Self {
+        FormatsConfig {
+            enabled: default_formats_enabled(),
+            lint: false,
+            migration_safety: default_migration_safety(),
+            infra_checks: default_infra_checks(),
+            linters: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ComplexityConfig {
+    fn default() -> Self {
+        ComplexityConfig {
+            enabled: default_complexity_enabled(),
+            prioritize: false,
+            max_functions: default_complexity_max_functions(),
+        }
+    }
+}
+
+impl Default for ChecksConfig {
+    fn default() -> Self {
+        ChecksConfig {
+            signatures: false,
+            signoff: SignoffRequirement::default(),
+            max_added_file_bytes: default_max_added_file_bytes(),
+            binary_files: default_binary_files_check(),
+            duplicate_code: default_duplicate_code_check(),
+            duplicate_min_tokens: default_duplicate_min_tokens(),
+        }
+    }
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        RawConfig {
+            max_file_bytes: default_max_file_bytes(),
+            max_file_tokens: default_max_file_tokens(),
+            follow_symlinks: false,
+            hidden: false,
+            recursive: false,
+            by_default: false,
+            exclude: default_raw_exclude(),
+        }
+    }
+}
+
 impl Default for DiffProfile {
     fn default() -> Self {
         DiffProfile {
             report_diffs: true,
             report_sources: true,
+            source_compression: SourceCompression::None,
         }
     }
 }
@@ -222,8 +1226,26 @@ impl Default for LLMConfig {
             model_id: String::from("deepseek/deepseek-v3.2"),
             api_key: String::from("[insert api key here]"),
             allow_reasoning: true,
+            reasoning_effort: None,
             custom_prompt: None,
             actions_menu: true,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+            capabilities: CapabilityOverrides::default(),
+            sampling: SamplingParams::default(),
+            retry: RetryConfig::default(),
+            first_token_timeout_secs: default_first_token_timeout_secs(),
+            total_timeout_secs: default_total_timeout_secs(),
+            context_window: None,
+            context_overflow: ContextOverflow::default(),
+            quality_tier: None,
+            host: None,
+            port: None,
+            endpoint: None,
+            deployment_name: None,
+            api_version: None,
+            base_url: None,
+            headers: HashMap::new(),
         }
     }
 }
@@ -238,31 +1260,144 @@ impl Default for RvConfig {
             llm_configs,
             default_llm_config: String::from("default"),
             default_branch_mode: BranchAgainst::Main,
+            base_branches: default_base_branches(),
             project_context_files: ProjectContextFiles::default(),
             project_guidelines_files: ProjectGuidelinesFiles::default(),
+            readme: ReadmeConfig::default(),
+            raw: RawConfig::default(),
+            auto_profile: AutoProfileConfig::default(),
+            index: IndexConfig::default(),
+            checks: ChecksConfig::default(),
+            complexity: ComplexityConfig::default(),
+            semver: SemverConfig::default(),
+            formats: FormatsConfig::default(),
+            tickets: TicketsConfig::default(),
+            sandbox: SandboxConfig::default(),
+            consensus: ConsensusConfig::default(),
+            notify: NotifyConfig::default(),
+            paths: PathsConfig::default(),
+            redact_secrets: false,
+            redact_paths: false,
+            prompt_injection_guard: default_prompt_injection_guard(),
+            reformat_invalid_reviews: default_reformat_invalid_reviews(),
+            normalize_output: default_normalize_output(),
+            log_xml_structure: false,
+            review_cache: default_review_cache(),
+            next_steps: default_next_steps(),
+            usage_stats: false,
+            show_usage: false,
+            show_reasoning: false,
+            json_review: false,
+            prices: HashMap::new(),
+            learn_knowledge: false,
+            auto_fetch: false,
+            keep_pr_refs: false,
+            otel_endpoint: None,
+            webhook_url: None,
+            editor: None,
+            notes_merge: NotesMerge::default(),
+            tags: Vec::new(),
+            prompt_variables: HashMap::new(),
+            ticket_prefixes: Vec::new(),
+            model_strategy: ModelStrategy::default(),
+            max_review_cost: None,
+            min_coverage: None,
+            diff_context_lines: None,
+            ignore_whitespace: false,
+            verify_fixes: false,
+            verify_command: None,
+            inline: false,
         }
     }
 }
 
+/// Minimum similarity for a valid key to be suggested in place of an unknown one
+const KEY_SUGGESTION_THRESHOLD: f64 = 0.8;
+
+/// Collect the keys of `user` missing from `known` (the serialized default configuration), prefixed with `path`
+fn collect_unknown_keys(
+    user: &toml::Value,
+    known: &serde_json::Value,
+    path: &str,
+    warnings: &mut Vec<String>,
+) {
+    match (user, known) {
+        // Free-form maps (ex. `prompt_variables`, the `headers` of an LLM configuration)
+        (toml::Value::Table(_), serde_json::Value::Object(known_keys)) if known_keys.is_empty() => {
+        }
+        (toml::Value::Table(table), serde_json::Value::Object(known_keys)) => {
+            for (key, value) in table {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match known_keys.get(key) {
+                    Some(known_value) => {
+                        collect_unknown_keys(value, known_value, &key_path, warnings)
+                    }
+                    None => {
+                        let suggestion = known_keys
+                            .keys()
+                            .map(|k| (k, strsim::jaro_winkler(k, key)))
+                            .filter(|(_, score)| *score >= KEY_SUGGESTION_THRESHOLD)
+                            .max_by(|a, b| a.1.total_cmp(&b.1));
+                        match suggestion {
+                            Some((k, _)) => warnings
+                                .push(format!("unknown key `{key_path}`, did you mean `{k}`?")),
+                            None => warnings.push(format!("unknown key `{key_path}`")),
+                        }
+                    }
+                }
+            }
+        }
+        // Every element of an array of tables (ex. `[[llm_configs]]`) is checked against the first default one
+        (toml::Value::Array(items), serde_json::Value::Array(known_items)) => {
+            if let Some(known_item) = known_items.first() {
+                for (idx, item) in items.iter().enumerate() {
+                    collect_unknown_keys(item, known_item, &format!("{path}[{idx}]"), warnings);
+                }
+            }
+        }
+        // Scalars, and values without a default to compare with (ex. unset options)
+        _ => {}
+    }
+}
+
+/// Warnings about the keys of a configuration file that rv doesn't recognize
+pub fn unknown_keys_warnings(contents: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Ok(user) = toml::from_str::<toml::Value>(contents)
+        && let Ok(known) = serde_json::to_value(RvConfig::default())
+    {
+        collect_unknown_keys(&user, &known, "", &mut warnings);
+    }
+    warnings
+}
+
 impl RvConfig {
     pub fn load_from_path(path: String) -> anyhow::Result<RvConfig> {
         let mut file = File::open(&path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
+        // The typos are reported first, they are often what the parser fails on
+        for warning in unknown_keys_warnings(&contents) {
+            eprintln!("[WARN] {path}: {warning}");
+        }
         let config: RvConfig = toml::from_str(&contents)?;
 
         Ok(config)
     }
 
+    /// Load config.toml, creating it with the defaults when it doesn't exist yet; a config.toml
+    /// that fails to parse is an error, it's never overwritten
     pub fn load_default() -> anyhow::Result<RvConfig> {
         let config_path = default_config_path()?;
-        let loaded_config: anyhow::Result<RvConfig> =
-            RvConfig::load_from_path(config_path.display().to_string());
 
-        if let Ok(config) = loaded_config {
-            // Return successfully loaded config
-            Ok(config)
+        let config = if config_path.exists() {
+            RvConfig::load_from_path(config_path.display().to_string())
+                .with_context(|| format!("Failed to load {}", config_path.display()))?
         } else {
             // Create new config
             let new_config: RvConfig = Default::default();
@@ -270,9 +1405,38 @@ impl RvConfig {
             // Save to disk as config.toml
             let toml_string = toml::to_string_pretty(&new_config)?;
             fs::write(config_path, toml_string)?;
+            new_config
+        };
+        // The `RV_*` environment overrides go on top
+        crate::settings::apply_env_overrides(config).map(RvConfig::apply_prices)
+    }
 
-            Ok(new_config)
+    /// Give the LLM configurations without prices those of their model in `[prices]`, matched
+    /// by model ID with or without its vendor (ex. `anthropic/`)
+    pub fn apply_prices(mut self) -> RvConfig {
+        for llm_config in &mut self.llm_configs {
+            if llm_config.input_price_per_mtok.is_some()
+                && llm_config.output_price_per_mtok.is_some()
+            {
+                continue;
+            }
+            let model = llm_config.model_id.as_str();
+            let unvendored = model.rsplit('/').next().unwrap_or(model);
+            let Some(price) = self
+                .prices
+                .get(model)
+                .or_else(|| self.prices.get(unvendored))
+            else {
+                continue;
+            };
+            llm_config
+                .input_price_per_mtok
+                .get_or_insert(price.input_per_mtok);
+            llm_config
+                .output_price_per_mtok
+                .get_or_insert(price.output_per_mtok);
         }
+        self
     }
 
     pub fn get_llm_configs(self) -> HashMap<String, LLMConfig> {
@@ -286,24 +1450,112 @@ impl RvConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum OpenAIProvider {
     OpenAI,
     #[default]
     OpenRouter,
+    /// Local Ollama server, no API key needed
+    Ollama,
+    /// Google Gemini (Generative Language API)
+    Gemini,
+    /// Deployment of a model on an Azure OpenAI resource
+    AzureOpenAI,
+    /// Any OpenAI-compatible API (vLLM, LM Studio, llama.cpp server, internal gateways)
+    Custom,
+}
+
+impl OpenAIProvider {
+    /// Human readable provider name
+    pub fn name(self) -> &'static str {
+        match self {
+            OpenAIProvider::OpenAI => "OpenAI",
+            OpenAIProvider::OpenRouter => "OpenRouter",
+            OpenAIProvider::Ollama => "Ollama",
+            OpenAIProvider::Gemini => "Gemini",
+            OpenAIProvider::AzureOpenAI => "Azure OpenAI",
+            OpenAIProvider::Custom => "Custom",
+        }
+    }
+
+    /// API endpoint the requests are sent to
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            OpenAIProvider::OpenAI => "https://api.openai.com/v1",
+            OpenAIProvider::OpenRouter => "https://openrouter.ai/api/v1",
+            OpenAIProvider::Ollama => "http://localhost:11434",
+            OpenAIProvider::Gemini => "https://generativelanguage.googleapis.com",
+            // Every resource has its own endpoint, see `LLMConfig::endpoint`
+            OpenAIProvider::AzureOpenAI => "https://<resource>.openai.azure.com",
+            // Set by `base_url`, see `LLMConfig::endpoint`
+            OpenAIProvider::Custom => "<base_url>",
+        }
+    }
 }
 
 /// Enum to control what to compare a branch against
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, ValueEnum, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub enum BranchAgainst {
     /// Compare branch against the current HEAD
     Current,
     /// Compare branch against the repository's `main`
     #[default]
     Main,
+    /// Compare branch against an arbitrary branch, tag or revision
+    Ref(String),
+}
+
+impl std::str::FromStr for BranchAgainst {
+    type Err = String;
+
+    /// Parse `current`, `main` or any other reference name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(String::from("expected `current`, `main` or a reference")),
+            "current" => Ok(BranchAgainst::Current),
+            "main" => Ok(BranchAgainst::Main),
+            reference => Ok(BranchAgainst::Ref(reference.to_string())),
+        }
+    }
 }
 
 impl LLMConfig {
+    /// Estimate the cost in USD of a request, if the model prices are configured
+    pub fn estimate_cost(&self, input_tokens: usize, output_tokens: usize) -> Option<f64> {
+        let input_price = self.input_price_per_mtok?;
+        let output_price = self.output_price_per_mtok?;
+        Some(
+            (input_tokens as f64 * input_price + output_tokens as f64 * output_price) / 1_000_000.0,
+        )
+    }
+
+    /// Endpoint the requests are sent to, with the configured host and port for Ollama, the
+    /// resource endpoint and deployment for Azure OpenAI and the base URL for Custom
+    pub fn endpoint(&self) -> String {
+        match self.provider {
+            OpenAIProvider::Custom => self
+                .base_url
+                .as_deref()
+                .unwrap_or(OpenAIProvider::Custom.endpoint())
+                .trim_end_matches('/')
+                .to_string(),
+            OpenAIProvider::AzureOpenAI => format!(
+                "{}/openai/deployments/{}",
+                self.endpoint
+                    .as_deref()
+                    .unwrap_or(OpenAIProvider::AzureOpenAI.endpoint())
+                    .trim_end_matches('/'),
+                self.deployment_name.as_deref().unwrap_or(&self.model_id)
+            ),
+            OpenAIProvider::Ollama => format!(
+                "http://{}:{}",
+                self.host.as_deref().unwrap_or("localhost"),
+                self.port.unwrap_or(11434)
+            ),
+            provider => provider.endpoint().to_string(),
+        }
+    }
+
     pub fn resolve_api_key(&self) -> anyhow::Result<String> {
         if self.api_key == "[insert api key here]" || self.api_key.is_empty() {
             match self.provider {
@@ -321,9 +1573,69 @@ impl LLMConfig {
                         )
                     })
                 }
+                OpenAIProvider::Gemini => {
+                    std::env::var("GEMINI_API_KEY").map_err(|_| {
+                        anyhow::anyhow!(
+                            "[ERROR] Insert compatible API key inside `~/.config/rv/config.toml` or set GEMINI_API_KEY environment variable"
+                        )
+                    })
+                }
+                OpenAIProvider::AzureOpenAI => {
+                    std::env::var("AZURE_API_KEY").map_err(|_| {
+                        anyhow::anyhow!(
+                            "[ERROR] Insert compatible API key inside `~/.config/rv/config.toml` or set AZURE_API_KEY environment variable"
+                        )
+                    })
+                }
+                // Ollama servers don't authenticate the requests, local OpenAI-compatible servers
+                // often don't either
+                OpenAIProvider::Ollama | OpenAIProvider::Custom => Ok(String::new()),
             }
         } else {
             Ok(self.api_key.clone())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_an_unknown_top_level_key() {
+        let warnings = unknown_keys_warnings(
+            "defualt_llm_config = \"fast\"\nnot_an_option = true\ndefault_branch_mode = \"main\"\n",
+        );
+        assert_eq!(
+            warnings,
+            [
+                "unknown key `defualt_llm_config`, did you mean `default_llm_config`?",
+                "unknown key `not_an_option`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_unknown_nested_keys_with_their_path() {
+        let warnings = unknown_keys_warnings(
+            "[raw]\nmax_file_byte = 10\nrecursive = true\n\n\
+             [[llm_configs]]\nconfiguration_name = \"a\"\n\n[[llm_configs]]\nconfiguration_name = \"b\"\nmodle_id = \"m\"\n",
+        );
+        assert_eq!(
+            warnings,
+            [
+                "unknown key `llm_configs[1].modle_id`, did you mean `model_id`?",
+                "unknown key `raw.max_file_byte`, did you mean `max_file_bytes`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_any_key_of_the_free_form_maps() {
+        let warnings = unknown_keys_warnings(
+            "[prompt_variables]\nteam = \"core\"\n\n\
+             [[llm_configs]]\nconfiguration_name = \"a\"\n[llm_configs.headers]\nX-Trace = \"1\"\n",
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+}
@@ -0,0 +1,245 @@
+//! `rv conflicts`: resolution guidance for the conflicts of a merge or rebase in progress
+
+use crate::config::RvConfig;
+use crate::context::estimate_tokens;
+use crate::encoding::Decoded;
+use crate::git_helpers::{self, Conflict};
+use crate::history::{self, Usage};
+use crate::llm::create_llm_provider;
+use crate::markup;
+use crate::request::{self, RequestPipeline};
+use crate::review::ESTIMATED_REVIEW_OUTPUT_TOKENS;
+use crate::sanitize::sanitize_output;
+use crate::term_helpers;
+
+use anyhow::{Context, Result};
+use rig::message::Message;
+
+/// Maximum number of conflicts sent at the same time
+const MAX_PARALLEL_CONFLICTS: usize = 4;
+
+const CONFLICT_SYSTEM_PROMPT: &str = r#"
+You are a senior software engineer helping to resolve a Git conflict.
+You will receive one conflicted file: the common ancestor <base>, our
+side <ours>, their side <theirs> and the working tree file <working>
+with the conflict markers. A missing side means that the file didn't
+exist there (added on both sides) or was deleted on that side.
+Follow these rules exactly.
+
+OUTPUT FORMAT & STYLE
+- ASCII only. No emojis, no markdown, no color codes.
+- Soft-wrap the guidance at ~80 columns.
+
+STRICT STRUCTURE (in this exact order)
+1) INTENT: one line per side, describing what each side changed.
+2) GUIDANCE: how to resolve every conflict hunk, keeping the intent of
+   both sides; say when the two intents can't be combined and which side
+   should win.
+3) RISK: one line about what to test after the resolution.
+4) RESOLUTION: the complete resolved file, without conflict markers,
+   between a <resolved> line and a </resolved> line. If the file should
+   be deleted, write <resolved deleted/> instead.
+
+=============================
+"#;
+
+/// Text of one side of a conflict, as sent to the LLM
+fn side_text(side: &Option<Decoded>) -> &str {
+    match side {
+        Some(Decoded::Text(text)) => text,
+        Some(Decoded::Binary) => "[binary content]",
+        None => "[missing]",
+    }
+}
+
+fn is_binary(conflict: &Conflict) -> bool {
+    [
+        &conflict.base,
+        &conflict.ours,
+        &conflict.theirs,
+        &conflict.working,
+    ]
+    .into_iter()
+    .any(|side| matches!(side, Some(Decoded::Binary)))
+}
+
+/// Prompt describing a conflicted file
+fn conflict_prompt(conflict: &Conflict) -> String {
+    let path = markup::path_text(&conflict.path);
+    let attributes = [("path", path.as_str())];
+    format!(
+        "{}{}{}{}",
+        markup::element("base", &attributes, side_text(&conflict.base)),
+        markup::element("ours", &attributes, side_text(&conflict.ours)),
+        markup::element("theirs", &attributes, side_text(&conflict.theirs)),
+        markup::element("working", &attributes, side_text(&conflict.working))
+    )
+}
+
+/// Split the answer into the guidance and the proposed resolution (`None` for a deletion)
+fn split_resolution(answer: &str) -> (&str, Option<Option<String>>) {
+    if let Some(start) = answer.find("<resolved deleted/>") {
+        return (answer[..start].trim_end(), Some(None));
+    }
+    let Some(start) = answer.find("<resolved>") else {
+        return (answer.trim_end(), None);
+    };
+    let content_start = start + "<resolved>".len();
+    let Some(length) = answer[content_start..].find("</resolved>") else {
+        return (answer.trim_end(), None);
+    };
+    let resolved = answer[content_start..content_start + length]
+        .trim_start_matches(['\r', '\n'])
+        .to_string();
+    (answer[..start].trim_end(), Some(Some(resolved)))
+}
+
+/// Guidance followed by the proposed resolution, as a diff against the working tree file
+fn render(conflict: &Conflict, answer: &str) -> Result<String> {
+    let (guidance, resolution) = split_resolution(answer);
+    let mut text = format!("{guidance}\n\n");
+    let working = side_text(&conflict.working);
+    match resolution {
+        Some(Some(resolved)) => {
+            text.push_str("PROPOSED RESOLUTION (diff against the working tree file)\n");
+            text.push_str(&git_helpers::diff_texts(
+                working,
+                &resolved,
+                &conflict.path,
+            )?);
+        }
+        Some(None) => text.push_str(&format!(
+            "PROPOSED RESOLUTION: delete the file (git rm {})\n",
+            conflict.path.display()
+        )),
+        None => text.push_str("[WARN] The answer contains no resolution\n"),
+    }
+    Ok(text)
+}
+
+/// Ask for resolution guidance for every conflicted file of the merge or rebase in progress
+pub async fn resolve_conflicts(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    pipe: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let pipeline = RequestPipeline::new(rvconfig)?;
+
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let operation = git_helpers::operation_in_progress()?;
+    let conflicts =
+        git_helpers::conflicts().context("Failed to read the conflicts of the index")?;
+    if conflicts.is_empty() {
+        match operation {
+            Some(operation) => {
+                println!("[NO CONFLICTS] The {operation} in progress has no conflicts left.")
+            }
+            None => println!("[NO CONFLICTS] There is no merge or rebase in progress."),
+        }
+        return Ok(());
+    }
+
+    // Binary conflicts can't be resolved from text
+    let (binary, conflicts): (Vec<Conflict>, Vec<Conflict>) =
+        conflicts.into_iter().partition(is_binary);
+    for conflict in &binary {
+        println!(
+            "[SKIPPED] {}: binary content, resolve it with `git checkout --ours/--theirs`",
+            conflict.path.display()
+        );
+    }
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    let mut prompts: Vec<String> = conflicts
+        .iter()
+        .map(|conflict| pipeline.redact(conflict_prompt(conflict)))
+        .collect();
+    let paths: Vec<String> = conflicts
+        .iter()
+        .map(|conflict| conflict.path.to_string_lossy().to_string())
+        .collect();
+
+    let prompt_tokens: usize = prompts.iter().map(|prompt| estimate_tokens(prompt)).sum();
+    let (llm_configuration_key, llm_configuration) =
+        pipeline.select_llm(llm_selection, prompt_tokens, &paths, pipe)?;
+    let system_prompt = pipeline.system_prompt(CONFLICT_SYSTEM_PROMPT.to_string());
+    // Both sides of a conflict are untrusted
+    pipeline.prepare(&llm_configuration, &system_prompt, prompts.iter_mut())?;
+
+    let input_tokens: usize = prompts
+        .iter()
+        .map(|prompt| estimate_tokens(&system_prompt) + estimate_tokens(prompt))
+        .sum();
+    let requests = format!(
+        "{} conflicted files (~{} more output tokens each, the resolved files)",
+        conflicts.len(),
+        ESTIMATED_REVIEW_OUTPUT_TOKENS
+    );
+    if !request::confirm_requests(
+        &llm_configuration,
+        conflicts.len(),
+        input_tokens,
+        Some(requests),
+        assume_yes,
+    )? {
+        return Ok(());
+    }
+
+    if !pipe {
+        term_helpers::clear_term();
+        let operation = operation.unwrap_or("merge");
+        println!(
+            "[{} conflicted files in the {operation} in progress]",
+            conflicts.len()
+        );
+    }
+
+    let mut usage = Usage::ZERO;
+    let mut guidance = String::new();
+    for (chunk, chunk_prompts) in conflicts
+        .chunks(MAX_PARALLEL_CONFLICTS)
+        .zip(prompts.chunks(MAX_PARALLEL_CONFLICTS))
+    {
+        let requests: Vec<_> = chunk_prompts
+            .iter()
+            .map(|prompt| {
+                let client = create_llm_provider(llm_configuration.clone());
+                client.request(system_prompt.clone(), vec![Message::user(prompt.clone())])
+            })
+            .collect();
+        let answers = futures::future::join_all(requests).await;
+        for ((conflict, prompt), answer) in chunk.iter().zip(chunk_prompts).zip(answers) {
+            println!("\n=== CONFLICT {} ===\n", conflict.path.display());
+            match answer {
+                Ok(answer) => {
+                    usage += Usage::estimate(
+                        &llm_configuration,
+                        estimate_tokens(&system_prompt) + estimate_tokens(prompt),
+                        estimate_tokens(&answer),
+                    );
+                    // The resolution is code, so only the escape sequences are removed
+                    let rendered = render(conflict, &sanitize_output(&answer, false))?;
+                    println!("{rendered}");
+                    guidance.push_str(&format!(
+                        "=== CONFLICT {} ===\n\n{rendered}\n\n",
+                        conflict.path.display()
+                    ));
+                }
+                Err(e) => eprintln!("[ERROR] Failed to get guidance: {e:#}"),
+            }
+        }
+    }
+    history::save_command_usage(
+        &pipeline.rvconfig,
+        format!("conflicts of the {}", operation.unwrap_or("merge")),
+        &llm_configuration_key,
+        &llm_configuration,
+        paths,
+        guidance,
+        usage,
+    );
+    Ok(())
+}
@@ -0,0 +1,440 @@
+//! Consensus reviews (`--models a,b,c` or `[consensus]`): the same prompt is sent to several LLM
+//! configurations at once and their findings are merged into one review, the findings reported
+//! by more than one model being marked as higher confidence
+
+use crate::checks;
+use crate::complexity::{self, COMPLEXITY_PROMPT};
+use crate::config::{CustomPrompt, LLMConfig, RvConfig};
+use crate::context::estimate_tokens;
+use crate::coverage::{Coverage, check_min_coverage};
+use crate::findings::{SEVERITIES, finding_anchors, finding_items, review_severity, same_pattern};
+use crate::formats;
+use crate::git_helpers::ExpandedCommit;
+use crate::history::{ReviewRecord, Usage};
+use crate::injection::{PromptGuard, warn_neutralized};
+use crate::inline::INLINE_ANCHORS_PROMPT;
+use crate::llm::create_llm_provider;
+use crate::policy::Policy;
+use crate::pseudonyms::{PathMap, redact_paths};
+use crate::redact::redact_secrets;
+use crate::report::{self, PartialFailure, Report, Reporter, Verdict};
+use crate::review::{
+    ESTIMATED_REVIEW_OUTPUT_TOKENS, SYSTEM_PROMPT, pack_prompt, print_request_summary,
+    request_structured_review, select_llm_config,
+};
+use crate::sanitize::sanitize_output;
+use crate::semver;
+use crate::structure::section_of_heading;
+use crate::suppress::Suppressions;
+use crate::term_helpers;
+use crate::tokens;
+
+use anyhow::{Result, bail};
+use rig::message::Message;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Cited lines at most this far apart are taken as the same place
+const SAME_PLACE_MAX_LINES: u32 = 3;
+
+/// Review of one of the models
+struct ModelReview {
+    /// Name of the LLM configuration
+    key: String,
+    review: String,
+}
+
+/// Finding of one or more models
+struct MergedFinding {
+    title: String,
+    /// Lines of the finding, as written by the first model reporting it
+    lines: Vec<String>,
+    anchors: Vec<(String, Option<u32>)>,
+    /// LLM configurations reporting it
+    models: Vec<String>,
+}
+
+/// Whether two files named by findings are the same (ex. `a.rs` and `src/a.rs`)
+fn same_file(file: &str, other: &str) -> bool {
+    file == other || file.ends_with(&format!("/{other}")) || other.ends_with(&format!("/{file}"))
+}
+
+impl MergedFinding {
+    /// Whether a finding with `title` at `anchors` is about the same issue: it cites the same
+    /// place, or it describes the same pattern in the same files
+    fn is_same_issue(&self, title: &str, anchors: &[(String, Option<u32>)]) -> bool {
+        let same_place = self.anchors.iter().any(|(file, line)| {
+            anchors.iter().any(|(other_file, other_line)| {
+                same_file(file, other_file)
+                    && matches!((line, other_line), (Some(line), Some(other))
+                        if line.abs_diff(*other) <= SAME_PLACE_MAX_LINES)
+            })
+        });
+        let shared_files = self.anchors.is_empty()
+            || anchors.is_empty()
+            || self.anchors.iter().any(|(file, _)| {
+                anchors
+                    .iter()
+                    .any(|(other_file, _)| same_file(file, other_file))
+            });
+        same_place || (shared_files && same_pattern(&self.title, title))
+    }
+}
+
+/// Findings of every review, the same issue reported by several models merged into one; the
+/// findings with the most models come first, then in the order of the models
+fn merge_findings(reviews: &[ModelReview]) -> Vec<MergedFinding> {
+    let mut merged: Vec<MergedFinding> = Vec::new();
+    for model_review in reviews {
+        let lines: Vec<&str> = model_review.review.lines().collect();
+        for item in finding_items(&model_review.review) {
+            let anchors = finding_anchors(&model_review.review, &item);
+            let existing = merged.iter_mut().find(|finding| {
+                !finding.models.contains(&model_review.key)
+                    && finding.is_same_issue(&item.title, &anchors)
+            });
+            match existing {
+                Some(finding) => finding.models.push(model_review.key.clone()),
+                None => merged.push(MergedFinding {
+                    title: item.title.clone(),
+                    lines: lines[item.lines.clone()]
+                        .iter()
+                        .map(|line| line.to_string())
+                        .collect(),
+                    anchors,
+                    models: vec![model_review.key.clone()],
+                }),
+            }
+        }
+    }
+    merged.sort_by_key(|finding| std::cmp::Reverse(finding.models.len()));
+    merged
+}
+
+/// FINDINGS items of the merged findings, each one followed by the models reporting it
+fn format_findings(merged: &[MergedFinding], models: usize) -> String {
+    let mut text = String::new();
+    for (idx, finding) in merged.iter().enumerate() {
+        text.push_str(&format!("{}) {}\n", idx + 1, finding.title));
+        for line in finding.lines.iter().skip(1) {
+            text.push_str(&format!("{}\n", line.trim_end()));
+        }
+        let confidence = if finding.models.len() > 1 {
+            ", higher confidence"
+        } else {
+            ""
+        };
+        text.push_str(&format!(
+            "   Consensus: {}/{models} models ({}){confidence}\n",
+            finding.models.len(),
+            finding.models.join(", ")
+        ));
+    }
+    text
+}
+
+/// The review of the first model, with the merged findings of every model and the highest
+/// severity among them
+fn merged_review(reviews: &[ModelReview]) -> String {
+    let merged = merge_findings(reviews);
+    let findings = format_findings(&merged, reviews.len());
+    let severity = reviews
+        .iter()
+        .filter_map(|model_review| review_severity(&model_review.review))
+        .min_by_key(|severity| SEVERITIES.iter().position(|s| s == severity));
+
+    let base = &reviews[0].review;
+    let mut text = String::new();
+    let mut skipping = false;
+    let mut has_findings = false;
+    for line in base.lines() {
+        match section_of_heading(line) {
+            Some("FINDINGS") => {
+                text.push_str(&format!("{line}\n{findings}\n"));
+                has_findings = true;
+                skipping = true;
+            }
+            Some("SEVERITY")
+                if severity.is_some_and(|severity| review_severity(base) != Some(severity)) =>
+            {
+                let prefix = line.split("SEVERITY").next().unwrap_or_default();
+                text.push_str(&format!(
+                    "{prefix}SEVERITY: {}\n",
+                    severity.unwrap_or_default()
+                ));
+                skipping = true;
+            }
+            Some(_) => {
+                text.push_str(&format!("{line}\n"));
+                skipping = false;
+            }
+            None if !skipping => text.push_str(&format!("{line}\n")),
+            None => {}
+        }
+    }
+    if !has_findings && !merged.is_empty() {
+        text.push_str(&format!("\nFINDINGS\n{findings}"));
+    }
+    text
+}
+
+/// Review `expcommit` with every model of `models` at once, then report their merged review
+#[allow(clippy::too_many_arguments)]
+pub async fn consensus_review(
+    rvconfig: &RvConfig,
+    models: &[String],
+    expcommit: ExpandedCommit,
+    current_commit_oid: Option<git2::Oid>,
+    load_context: Option<PathBuf>,
+    target: String,
+    pipe: bool,
+    assume_yes: bool,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<()> {
+    let policy = Policy::load()?;
+    let mut rvconfig = rvconfig.clone();
+    if let Some(policy) = &policy {
+        policy.enforce_config(&mut rvconfig);
+    }
+    let rvconfig = &rvconfig;
+
+    let reviewed_files: Vec<String> = expcommit
+        .sources
+        .iter()
+        .flatten()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    let mut skipped = expcommit.skipped.clone();
+    let coverage = Coverage::of(&expcommit);
+    let mut check_findings = checks::run_checks(&rvconfig.checks, &expcommit);
+    check_findings.extend(semver::semver_findings(rvconfig, &expcommit));
+    let structured_files = if rvconfig.formats.enabled {
+        formats::detect_formats(&expcommit)
+    } else {
+        Vec::new()
+    };
+    check_findings.extend(formats::lint_findings(
+        rvconfig,
+        &expcommit,
+        &structured_files,
+    ));
+    check_findings.extend(formats::flag_findings(
+        rvconfig,
+        &expcommit,
+        &structured_files,
+    ));
+    let complexity = complexity::changed_functions(&rvconfig.complexity, &expcommit);
+    let reviewed_commit = expcommit.commits.last().map(|oid| oid.to_string());
+    let changed_lines = expcommit.changed_lines();
+    let inline_patches = if rvconfig.inline {
+        expcommit.patches.clone()
+    } else {
+        BTreeMap::new()
+    };
+    let path_map = rvconfig.redact_paths.then(|| {
+        Arc::new(PathMap::new(
+            expcommit
+                .sources
+                .iter()
+                .flatten()
+                .chain(expcommit.patches.keys()),
+        ))
+    });
+
+    let mut review_prompt = expcommit.get_xml_structure(rvconfig.diff_profile);
+    if rvconfig.redact_secrets {
+        review_prompt = redact_secrets(&review_prompt);
+    }
+    // The reviewed code is untrusted: it must not be able to steer the reviewers
+    let guard = PromptGuard::new(rvconfig.prompt_injection_guard);
+    let (review_prompt, neutralized_instructions) = guard.untrusted(&review_prompt);
+    warn_neutralized(neutralized_instructions);
+
+    // Every model gets its own system prompt (custom prompts, guidelines of its configuration)
+    let mut configurations: Vec<(String, LLMConfig, String)> = Vec::new();
+    for model in models {
+        let (key, llm_configuration) =
+            select_llm_config(rvconfig, Some(model.clone()), policy.as_ref())?;
+        let system_prompt = pack_prompt(
+            SYSTEM_PROMPT,
+            rvconfig,
+            Some(&llm_configuration),
+            load_context.as_ref(),
+            None,
+            &mut skipped,
+        )? + &checks::format_duplicates(&check_findings)
+            + &semver::format_breakages(&check_findings)
+            + &formats::format_guidelines(&structured_files)
+            + &formats::format_lints(&check_findings)
+            + &formats::format_flags(rvconfig, &structured_files, &check_findings);
+        let mut system_prompt = if rvconfig.complexity.prioritize && !complexity.is_empty() {
+            system_prompt + COMPLEXITY_PROMPT + &complexity::format_complexity_context(&complexity)
+        } else {
+            system_prompt
+        };
+        if rvconfig.redact_secrets {
+            system_prompt = redact_secrets(&system_prompt);
+        }
+        if rvconfig.inline {
+            system_prompt.push_str(INLINE_ANCHORS_PROMPT);
+        }
+        guard.harden(&mut system_prompt);
+        configurations.push((key, llm_configuration, system_prompt));
+    }
+    // The models share the prompt, so none of them can truncate it: oversized prompts are refused
+    for (_, llm_configuration, system_prompt) in &configurations {
+        tokens::ensure_fits(
+            llm_configuration,
+            tokens::count_tokens(system_prompt) + tokens::count_tokens(&review_prompt),
+        )?;
+    }
+    // Several files of the guidelines may be skipped once per model
+    skipped.dedup_by(|a, b| a.path == b.path && a.reason == b.reason);
+
+    if !assume_yes && std::env::var_os("CI").is_none() {
+        for (_, llm_configuration, system_prompt) in &configurations {
+            print_request_summary(
+                llm_configuration,
+                reviewed_files.len(),
+                estimate_tokens(system_prompt) + estimate_tokens(&review_prompt),
+            );
+        }
+        eprintln!(
+            "  Requests : {} reviews, one per model (~{} output tokens)",
+            configurations.len(),
+            ESTIMATED_REVIEW_OUTPUT_TOKENS * configurations.len()
+        );
+        if !term_helpers::confirm("Send these requests?")? {
+            eprintln!("Review cancelled, nothing was sent.");
+            return Ok(());
+        }
+    }
+    if !pipe {
+        term_helpers::clear_term();
+        println!("Reviewing with {} models...", configurations.len());
+    }
+
+    let requests: Vec<_> = configurations
+        .iter()
+        .map(|(_, llm_configuration, system_prompt)| {
+            let client = redact_paths(
+                create_llm_provider(llm_configuration.clone()),
+                path_map.as_ref(),
+            );
+            let reformat = rvconfig.reformat_invalid_reviews
+                && !matches!(
+                    llm_configuration.custom_prompt,
+                    Some(CustomPrompt::Replace(_))
+                );
+            let system_prompt = system_prompt.clone();
+            let messages = vec![Message::user(review_prompt.clone())];
+            async move {
+                request_structured_review(client.as_ref(), system_prompt, messages, reformat).await
+            }
+        })
+        .collect();
+    let responses = futures::future::join_all(requests).await;
+
+    // A failed model doesn't stop the run, the others still reach a consensus
+    let mut reviews: Vec<ModelReview> = Vec::new();
+    let mut usage = Usage::ZERO;
+    for ((key, llm_configuration, system_prompt), response) in configurations.iter().zip(responses)
+    {
+        match response {
+            Ok(response) => {
+                let review = sanitize_output(&response, rvconfig.normalize_output);
+                let input_tokens = estimate_tokens(system_prompt) + estimate_tokens(&review_prompt);
+                let output_tokens = estimate_tokens(&review);
+                usage += Usage::estimate(llm_configuration, input_tokens, output_tokens);
+                reviews.push(ModelReview {
+                    key: key.clone(),
+                    review,
+                });
+            }
+            Err(e) => eprintln!("[ERROR] The review of {key} failed: {e:#}"),
+        }
+    }
+    if reviews.is_empty() {
+        bail!("Every model failed to review the changes");
+    }
+    let failed = configurations.len() - reviews.len();
+
+    let response = merged_review(&reviews);
+    println!(
+        "[Consensus of {} models: {}]\n\n{response}",
+        reviews.len(),
+        reviews
+            .iter()
+            .map(|model_review| model_review.key.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let (reported_review, suppressed) = Suppressions::load().apply(&response);
+
+    let keys: Vec<&str> = configurations
+        .iter()
+        .map(|(key, _, _)| key.as_str())
+        .collect();
+    let model_ids: Vec<&str> = configurations
+        .iter()
+        .map(|(_, llm_configuration, _)| llm_configuration.model_id.as_str())
+        .collect();
+    let mut record = ReviewRecord::new(
+        target.clone(),
+        current_commit_oid.map(|oid| oid.to_string()),
+        keys.join("+"),
+        model_ids.join("+"),
+        reviewed_files.clone(),
+        skipped.clone(),
+        reported_review.clone(),
+    );
+    record.tags = rvconfig.tags.clone();
+    record.changed_lines = changed_lines;
+    record.set_usage(rvconfig, usage);
+    let review_id = match record.save() {
+        Ok(_) => Some(record.id.clone()),
+        Err(e) => {
+            eprintln!("[WARN] Failed to save review to history: {e}");
+            None
+        }
+    };
+
+    let prompt = configurations
+        .iter()
+        .map(|(key, _, system_prompt)| format!("[{key}]\n{system_prompt}"))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    report::dispatch(
+        reporters,
+        &Report {
+            id: review_id,
+            target,
+            verdict: Verdict::Reviewed,
+            commit: reviewed_commit,
+            model: model_ids.join("+"),
+            tags: rvconfig.tags.clone(),
+            review: reported_review,
+            reviewed_files,
+            not_reviewed: skipped,
+            suppressed,
+            checks: check_findings,
+            fixes: Vec::new(),
+            complexity,
+            coverage,
+            structured: None,
+            prompt: format!("{prompt}\n\n{review_prompt}"),
+            patches: inline_patches,
+        },
+    )
+    .await;
+
+    if failed > 0 {
+        return Err(PartialFailure {
+            failed,
+            total: configurations.len(),
+        }
+        .into());
+    }
+    check_min_coverage(coverage.as_ref(), rvconfig.min_coverage)
+}
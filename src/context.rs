@@ -0,0 +1,254 @@
+use crate::config::ReadmeConfig;
+use crate::git_helpers;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Token estimation of `text`, good enough for budgeting context (see `tokens::count_tokens`)
+pub fn estimate_tokens(text: &str) -> usize {
+    crate::tokens::count_tokens(text)
+}
+
+/// Process /load and /read-only commands within context files
+pub fn process_context_commands(content: &str) -> String {
+    let mut result = String::new();
+
+    // Find all /load and /read-only commands in the content
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("/load ") || trimmed.starts_with("/read-only ") {
+            // Extract file path from command
+            let parts: Vec<&str> = trimmed.splitn(2, ' ').collect();
+            if parts.len() >= 2 {
+                let file_path = parts[1].trim();
+
+                // Try to load the referenced file
+                if let Ok(loaded_content) = load_context_file(file_path) {
+                    result.push_str(&format!(
+                        "\n[Content loaded from '{}':]\n{}\n",
+                        file_path, loaded_content
+                    ));
+                } else if let Ok(loaded_content) = std::fs::read_to_string(file_path) {
+                    result.push_str(&format!(
+                        "\n[Content loaded from '{}':]\n{}\n",
+                        file_path, loaded_content
+                    ));
+                } else {
+                    result.push_str(&format!(
+                        "\n[Failed to load content from '{}']\n",
+                        file_path
+                    ));
+                }
+            }
+        } else {
+            // Regular line, add as-is
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+
+    result
+}
+
+/// Resolve a project file relative to the repository root.
+/// Outside of a Git repository (raw mode), search upward from the current directory instead.
+pub fn resolve_project_file(filename: &str) -> Option<PathBuf> {
+    if let Ok(repo) = git_helpers::open_repo()
+        && let Some(workdir) = repo.workdir()
+    {
+        let full_path = workdir.join(filename);
+        return full_path.is_file().then_some(full_path);
+    }
+
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(filename))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn read_file(filename: &str) -> Option<String> {
+    // Load files from project's root directory (where .git is) not current working directory
+    let full_path = resolve_project_file(filename)?;
+    std::fs::read_to_string(&full_path).ok()
+}
+
+pub fn load_context_file(file_path: &str) -> Result<String> {
+    // Try to read the file relative to current directory first
+    if let Ok(content) = std::fs::read_to_string(file_path) {
+        return Ok(content);
+    }
+
+    // If not found, try relative to git repository root (or the closest parent directory)
+    let full_path = resolve_project_file(file_path)
+        .ok_or_else(|| anyhow::anyhow!("Failed to find context file '{}'", file_path))?;
+
+    std::fs::read_to_string(&full_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read context file '{}': {}", file_path, e))
+}
+
+/// Section of a Markdown document, delimited by headings
+struct MarkdownSection {
+    /// Heading text without the leading `#`s; empty for the text before the first heading
+    title: String,
+    body: String,
+}
+
+fn split_markdown_sections(content: &str) -> Vec<MarkdownSection> {
+    let mut sections: Vec<MarkdownSection> = vec![MarkdownSection {
+        title: String::new(),
+        body: String::new(),
+    }];
+    let mut in_code_block = false;
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if !in_code_block && line.starts_with('#') {
+            let title = line.trim_start_matches('#').trim().to_string();
+            sections.push(MarkdownSection {
+                title,
+                body: String::new(),
+            });
+        }
+
+        // Safe: `sections` always holds at least the preamble
+        let current = sections.last_mut().unwrap();
+        current.body.push_str(line);
+        current.body.push('\n');
+    }
+
+    sections
+}
+
+/// README content after applying the `readme` configuration
+pub struct ReadmeContent {
+    pub file: String,
+    pub content: String,
+    /// Sections left out because of the token budget
+    pub truncated: bool,
+}
+
+/// Reduce a README to the configured sections and token budget.
+/// Whole sections are kept until the budget is reached, so the output is truncated by heading.
+pub fn shrink_readme(content: &str, readme_config: &ReadmeConfig) -> (String, bool) {
+    let sections: Vec<MarkdownSection> = split_markdown_sections(content)
+        .into_iter()
+        .filter(|section| !section.body.trim().is_empty())
+        .filter(|section| {
+            readme_config.sections.is_empty()
+                || readme_config
+                    .sections
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(&section.title))
+        })
+        .collect();
+
+    let mut result = String::new();
+    let mut used_tokens: usize = 0;
+    let mut truncated = false;
+
+    for section in sections {
+        let section_tokens = estimate_tokens(&section.body);
+
+        if readme_config.max_tokens > 0 && used_tokens + section_tokens > readme_config.max_tokens {
+            if result.is_empty() {
+                // Not even the first section fits: hard-cut it to the budget
+                let max_chars = readme_config.max_tokens * 4;
+                result.extend(section.body.chars().take(max_chars));
+                result.push('\n');
+            }
+            truncated = true;
+            break;
+        }
+
+        used_tokens += section_tokens;
+        result.push_str(&section.body);
+    }
+
+    if truncated {
+        result.push_str("[README truncated]\n");
+    }
+
+    (result, truncated)
+}
+
+/// Load the first README candidate found in the repository root, already shrunk to the configured budget
+pub fn load_readme(readme_config: &ReadmeConfig) -> Option<ReadmeContent> {
+    if !readme_config.enabled {
+        return None;
+    }
+
+    for f in &readme_config.files {
+        if let Some(content) = read_file(f) {
+            let (content, truncated) = shrink_readme(&content, readme_config);
+            return Some(ReadmeContent {
+                file: f.clone(),
+                content,
+                truncated,
+            });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CurrentDir, repo_with};
+    use tempfile::TempDir;
+
+    #[test]
+    fn resolves_project_files_from_a_subdirectory_of_the_repository() {
+        let repo = repo_with(&[
+            ("README.md", "# Project\n"),
+            (".rv_context", "Use tabs\n"),
+            ("src/nested/lib.rs", "fn main() {}\n"),
+        ]);
+        let _cwd = CurrentDir::enter(&repo.path().join("src/nested"));
+        let root = repo.path().canonicalize().unwrap();
+
+        let readme = resolve_project_file("README.md").unwrap();
+        assert_eq!(readme.canonicalize().unwrap(), root.join("README.md"));
+        assert_eq!(read_file(".rv_context").as_deref(), Some("Use tabs\n"));
+        assert_eq!(load_context_file(".rv_context").unwrap(), "Use tabs\n");
+        assert!(resolve_project_file("missing.md").is_none());
+    }
+
+    #[test]
+    fn ignores_files_of_subdirectories_in_a_repository() {
+        let repo = repo_with(&[("src/README.md", "# Module\n")]);
+        let _cwd = CurrentDir::enter(&repo.path().join("src"));
+
+        assert!(resolve_project_file("README.md").is_none());
+    }
+
+    #[test]
+    fn searches_upward_outside_of_a_repository() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".rv_context"), "Raw context\n").unwrap();
+        let nested = dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        let _cwd = CurrentDir::enter(&nested);
+
+        assert_eq!(read_file(".rv_context").as_deref(), Some("Raw context\n"));
+        assert_eq!(
+            resolve_project_file(".rv_context")
+                .unwrap()
+                .canonicalize()
+                .unwrap(),
+            dir.path().canonicalize().unwrap().join(".rv_context")
+        );
+    }
+
+    #[test]
+    fn loads_the_readme_of_the_repository_root() {
+        let repo = repo_with(&[("README.md", "# Project\nAbout\n"), ("docs/x.md", "")]);
+        let _cwd = CurrentDir::enter(&repo.path().join("docs"));
+
+        let readme = load_readme(&ReadmeConfig::default()).unwrap();
+        assert_eq!(readme.file, "README.md");
+        assert!(readme.content.contains("About"));
+    }
+}
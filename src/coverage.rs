@@ -0,0 +1,116 @@
+//! Share of the diff (files, hunks, changed lines) that reached the prompt after exclusions
+
+use crate::git_helpers::{DiffStats, ExpandedCommit};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Reviewed part of a diff against the whole diff
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Coverage {
+    pub reviewed: DiffStats,
+    pub total: DiffStats,
+}
+
+fn ratio(reviewed: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        reviewed as f64 / total as f64
+    }
+}
+
+impl Coverage {
+    /// Coverage of the diffs of `expcommit`; `None` for reviews without diffs (raw mode)
+    pub fn of(expcommit: &ExpandedCommit) -> Option<Coverage> {
+        if expcommit.diff_stats.is_empty() {
+            return None;
+        }
+        let skipped: BTreeSet<&PathBuf> = expcommit.skipped.iter().map(|s| &s.path).collect();
+        let reviewed_paths: BTreeSet<&PathBuf> = expcommit
+            .sources
+            .iter()
+            .flatten()
+            .filter(|path| !skipped.contains(path))
+            .collect();
+
+        let mut coverage = Coverage::default();
+        for (path, stats) in &expcommit.diff_stats {
+            coverage.total += *stats;
+            if reviewed_paths.contains(path) {
+                coverage.reviewed += *stats;
+            }
+        }
+        Some(coverage)
+    }
+
+    pub fn files(&self) -> f64 {
+        ratio(self.reviewed.files, self.total.files)
+    }
+
+    pub fn hunks(&self) -> f64 {
+        ratio(self.reviewed.hunks, self.total.hunks)
+    }
+
+    pub fn lines(&self) -> f64 {
+        ratio(self.reviewed.lines, self.total.lines)
+    }
+
+    /// Lowest of the three ratios, compared against `--min-coverage`
+    pub fn min_ratio(&self) -> f64 {
+        self.files().min(self.hunks()).min(self.lines())
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "files {}/{} ({:.0}%), hunks {}/{} ({:.0}%), lines {}/{} ({:.0}%)",
+            self.reviewed.files,
+            self.total.files,
+            self.files() * 100.0,
+            self.reviewed.hunks,
+            self.total.hunks,
+            self.hunks() * 100.0,
+            self.reviewed.lines,
+            self.total.lines,
+            self.lines() * 100.0
+        )
+    }
+}
+
+/// The ratios are serialized along with the counts, so that CI scripts don't have to compute them
+impl Serialize for Coverage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Coverage", 5)?;
+        state.serialize_field("files", &self.files())?;
+        state.serialize_field("hunks", &self.hunks())?;
+        state.serialize_field("lines", &self.lines())?;
+        state.serialize_field("reviewed", &self.reviewed)?;
+        state.serialize_field("total", &self.total)?;
+        state.end()
+    }
+}
+
+impl std::ops::AddAssign for Coverage {
+    fn add_assign(&mut self, other: Coverage) {
+        self.reviewed += other.reviewed;
+        self.total += other.total;
+    }
+}
+
+/// Fail when the coverage of a review is below `min_coverage`
+pub fn check_min_coverage(
+    coverage: Option<&Coverage>,
+    min_coverage: Option<f64>,
+) -> anyhow::Result<()> {
+    if let (Some(coverage), Some(min_coverage)) = (coverage, min_coverage)
+        && coverage.min_ratio() < min_coverage
+    {
+        anyhow::bail!(
+            "[COVERAGE] Only {:.0}% of the diff reached the prompt, below the minimum of {:.0}% ({})",
+            coverage.min_ratio() * 100.0,
+            min_coverage * 100.0,
+            coverage.summary()
+        );
+    }
+    Ok(())
+}
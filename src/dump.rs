@@ -0,0 +1,171 @@
+//! Debug dumps of the prompts (`--log_xml`): written in full to a temporary file, and shown
+//! in a pager with the XML tags highlighted instead of flooding the terminal
+
+use crate::context::estimate_tokens;
+use crate::history::now_timestamp;
+use crate::sanitize::sanitize_output;
+
+use anyhow::{Context, Result};
+use std::io::{IsTerminal, Write, stdout};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Pager used when `$PAGER` isn't set: colors kept, long lines chopped to the terminal width
+const DEFAULT_PAGER: &str = "less -RS";
+
+const TAG_COLOR: &str = "\x1b[36m";
+const ATTRIBUTE_COLOR: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Size of the terminal as (columns, rows)
+#[cfg(feature = "tui")]
+fn terminal_size() -> (usize, usize) {
+    crossterm::terminal::size()
+        .map(|(columns, rows)| (usize::from(columns), usize::from(rows)))
+        .unwrap_or((80, 24))
+}
+
+/// Size of the terminal as (columns, rows), from `COLUMNS` and `LINES` (rv was built without the `tui` feature)
+#[cfg(not(feature = "tui"))]
+fn terminal_size() -> (usize, usize) {
+    let read = |name: &str, default: usize| {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    (read("COLUMNS", 80), read("LINES", 24))
+}
+
+/// Color the tags of an XML line, and the quoted attribute values inside them
+fn highlight_line(line: &str) -> String {
+    let mut highlighted = String::new();
+    let mut in_tag = false;
+    let mut in_value = false;
+    let mut previous = ' ';
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Only tags, not the `<<<` markers of the untrusted content nor comparisons
+            '<' if !in_tag
+                && previous != '<'
+                && chars
+                    .peek()
+                    .is_some_and(|n| n.is_ascii_alphabetic() || *n == '/') =>
+            {
+                in_tag = true;
+                highlighted.push_str(TAG_COLOR);
+                highlighted.push(c);
+            }
+            '>' if in_tag && !in_value => {
+                in_tag = false;
+                highlighted.push(c);
+                highlighted.push_str(RESET);
+            }
+            '"' if in_tag => {
+                in_value = !in_value;
+                if in_value {
+                    highlighted.push_str(ATTRIBUTE_COLOR);
+                    highlighted.push(c);
+                } else {
+                    highlighted.push(c);
+                    highlighted.push_str(TAG_COLOR);
+                }
+            }
+            _ => highlighted.push(c),
+        }
+        previous = c;
+    }
+    if in_tag {
+        highlighted.push_str(RESET);
+    }
+    highlighted
+}
+
+/// Cut `line` to `width` columns, marking the cut
+fn fit_line(line: &str, width: usize) -> String {
+    if line.chars().count() <= width {
+        return line.to_string();
+    }
+    let mut cut: String = line.chars().take(width.saturating_sub(1)).collect();
+    cut.push('>');
+    cut
+}
+
+/// Whether `path` is a dump written by `write_dump`
+pub fn is_dump(path: &Path) -> bool {
+    path.starts_with(std::env::temp_dir())
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("rv-") && name.ends_with(".xml"))
+}
+
+/// Write `content` to a new temporary file named after `name`
+fn write_dump(name: &str, content: &str) -> Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!(
+        "rv-{name}-{}-{}.xml",
+        now_timestamp(),
+        std::process::id()
+    ));
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Show `text` in the pager; `false` when no pager could be started
+fn page(text: &str) -> bool {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be closed before reading everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait().is_ok()
+}
+
+/// Dump `content`: the full text goes to a temporary file whose path is printed; interactive
+/// terminals also get it in a pager, or a preview fitting the screen when there is no pager
+pub fn show_dump(name: &str, content: &str) -> Result<()> {
+    let path = write_dump(name, content)?;
+    let summary = format!(
+        "[{name}: {} lines, ~{} tokens, written to {}]",
+        content.lines().count(),
+        estimate_tokens(content),
+        path.display()
+    );
+
+    if !stdout().is_terminal() {
+        println!("{summary}");
+        return Ok(());
+    }
+    // The dump holds the reviewed code, which can carry escape sequences
+    let safe = sanitize_output(content, false);
+    let highlighted: Vec<String> = safe.lines().map(highlight_line).collect();
+    if page(&highlighted.join("\n")) {
+        println!("{summary}");
+        return Ok(());
+    }
+
+    let (columns, rows) = terminal_size();
+    let lines: Vec<&str> = safe.lines().collect();
+    let shown = rows.saturating_sub(4).max(1).min(lines.len());
+    for line in &lines[..shown] {
+        println!("{}", highlight_line(&fit_line(line, columns)));
+    }
+    if shown < lines.len() {
+        println!("[... {} more lines]", lines.len() - shown);
+    }
+    println!("{summary}");
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! `--open`: after the review, the files named by the findings are opened in the editor at the
+//! lines they cite, the most severe findings first
+
+use crate::findings::{SEVERITIES, finding_anchors, finding_items, finding_severity};
+use crate::report::{Report, Reporter};
+use crate::term_helpers;
+
+use anyhow::{Context, Result, bail};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Editor used when neither the configuration nor the environment names one
+const FALLBACK_EDITOR: &str = "vi";
+
+/// Editors opening `file:line` with `-g`
+const GOTO_FLAG_EDITORS: [&str; 4] = ["code", "code-insiders", "codium", "cursor"];
+
+/// Editors opening `file:line` as is
+const FILE_LINE_EDITORS: [&str; 4] = ["subl", "zed", "hx", "helix"];
+
+/// Place of a finding to open
+#[derive(Debug, Clone)]
+struct Stop {
+    path: PathBuf,
+    line: Option<u32>,
+    title: String,
+}
+
+/// Editor command: the `editor` of the configuration, else `$VISUAL` or `$EDITOR`
+pub fn editor_command(configured: Option<&str>) -> String {
+    configured
+        .map(str::to_string)
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| FALLBACK_EDITOR.to_string())
+}
+
+/// Arguments opening `path` at `line`, in the syntax of `program` (`+line file` by default, as
+/// vim, nano, emacs and most terminal editors expect)
+fn location_args(program: &str, path: &Path, line: Option<u32>) -> Vec<String> {
+    let name = Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file = path.to_string_lossy().to_string();
+    let Some(line) = line else {
+        return vec![file];
+    };
+    if GOTO_FLAG_EDITORS.contains(&name.as_str()) {
+        vec!["-g".to_string(), format!("{file}:{line}")]
+    } else if FILE_LINE_EDITORS.contains(&name.as_str()) {
+        vec![format!("{file}:{line}")]
+    } else {
+        vec![format!("+{line}"), file]
+    }
+}
+
+/// File of the working tree named `file` by a finding: the path itself, or the reviewed file it
+/// ends (ex. `a.rs` for `src/a.rs`)
+fn resolve_file(file: &str, reviewed_files: &[String]) -> Option<PathBuf> {
+    let path = PathBuf::from(file);
+    if path.is_file() {
+        return Some(path);
+    }
+    reviewed_files
+        .iter()
+        .find(|reviewed| reviewed.ends_with(&format!("/{file}")))
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+}
+
+/// Files of the findings of `report` with their first cited line, the most severe findings
+/// first (then in the order of the review); every file is opened once
+fn stops(report: &Report) -> Vec<Stop> {
+    let mut items = finding_items(&report.review);
+    items.sort_by_key(|item| {
+        finding_severity(&report.review, item)
+            .and_then(|severity| SEVERITIES.iter().position(|s| *s == severity))
+            .unwrap_or(SEVERITIES.len())
+    });
+
+    let mut stops: Vec<Stop> = Vec::new();
+    for item in items {
+        for (file, line) in finding_anchors(&report.review, &item) {
+            let Some(path) = resolve_file(&file, &report.reviewed_files) else {
+                continue;
+            };
+            if stops.iter().any(|stop| stop.path == path) {
+                continue;
+            }
+            stops.push(Stop {
+                path,
+                line,
+                title: item.title.clone(),
+            });
+        }
+    }
+    stops
+}
+
+/// Open `path` at `line` with `editor` and wait for it to be closed
+fn open_in_editor(editor: &str, path: &Path, line: Option<u32>) -> Result<()> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(FALLBACK_EDITOR);
+    let status = Command::new(program)
+        .args(words)
+        .args(location_args(program, path, line))
+        .status()
+        .with_context(|| format!("Failed to run the editor `{program}`"))?;
+    if !status.success() {
+        bail!("`{program}` exited with {status}");
+    }
+    Ok(())
+}
+
+/// Open the files of the findings one by one, asking before each of them
+pub struct OpenReporter {
+    pub editor: String,
+}
+
+impl Reporter for OpenReporter {
+    fn name(&self) -> &'static str {
+        "editor"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let stops = stops(report);
+            if stops.is_empty() {
+                println!("\n[OPEN] No finding cites a file of the working tree.");
+                return Ok(());
+            }
+            println!("\n[OPEN] {} files with findings", stops.len());
+            for (idx, stop) in stops.iter().enumerate() {
+                let line = stop.line.map(|line| format!(":{line}")).unwrap_or_default();
+                println!(
+                    "\n[{}/{}] {}{line} - {}",
+                    idx + 1,
+                    stops.len(),
+                    stop.path.display(),
+                    stop.title
+                );
+                let answer = term_helpers::get_terminal_input(
+                    "Open it? [Enter] open, [s] skip, [q] quit: ".to_string(),
+                );
+                match answer.trim() {
+                    "q" | "quit" => break,
+                    "s" | "skip" => continue,
+                    _ => open_in_editor(&self.editor, &stop.path, stop.line)?,
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
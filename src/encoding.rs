@@ -0,0 +1,159 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::path::Path;
+
+/// Number of leading bytes inspected by the heuristics
+const SNIFF_LEN: usize = 8 * 1024;
+/// Share of NUL bytes on one parity of the sample above which the content is treated as BOM-less UTF-16
+const UTF16_NUL_RATIO: f64 = 0.3;
+/// Share of control characters above which 8-bit content is treated as binary
+const BINARY_CONTROL_RATIO: f64 = 0.1;
+
+/// Source content converted to UTF-8
+#[derive(Debug, Clone)]
+pub enum Decoded {
+    Text(String),
+    /// Content that can't be decoded as text
+    Binary,
+}
+
+/// Ratio of NUL bytes at even and odd offsets of `sample`
+fn nul_ratios(sample: &[u8]) -> (f64, f64) {
+    let half = (sample.len() / 2).max(1) as f64;
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count() as f64;
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count() as f64;
+    (even / half, odd / half)
+}
+
+fn is_control(b: u8) -> bool {
+    (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f
+}
+
+/// Decode `bytes` with `encoding`, failing on malformed sequences
+fn decode_strict(bytes: &[u8], encoding: &'static Encoding) -> Decoded {
+    match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(text) => Decoded::Text(text.into_owned()),
+        None => Decoded::Binary,
+    }
+}
+
+/// Detect the encoding of `bytes` (BOM first, then heuristics) and convert them to UTF-8
+pub fn decode_bytes(bytes: &[u8]) -> Decoded {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return decode_strict(&bytes[bom_len..], encoding);
+    }
+
+    // Mostly-ASCII UTF-16 has a NUL byte in every other position
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if sample.contains(&0) && bytes.len().is_multiple_of(2) {
+        let (even_nuls, odd_nuls) = nul_ratios(sample);
+        if odd_nuls > UTF16_NUL_RATIO && even_nuls < UTF16_NUL_RATIO / 3.0 {
+            return decode_strict(bytes, UTF_16LE);
+        }
+        if even_nuls > UTF16_NUL_RATIO && odd_nuls < UTF16_NUL_RATIO / 3.0 {
+            return decode_strict(bytes, UTF_16BE);
+        }
+    }
+
+    if !sample.contains(&0)
+        && let Ok(text) = std::str::from_utf8(bytes)
+    {
+        return Decoded::Text(text.to_string());
+    }
+
+    // Legacy 8-bit text (Latin-1 and its Windows-1252 superset), unless it looks like binary data
+    let controls = sample.iter().filter(|b| is_control(**b)).count();
+    if sample.contains(&0) || controls as f64 > sample.len() as f64 * BINARY_CONTROL_RATIO {
+        return Decoded::Binary;
+    }
+    decode_strict(bytes, WINDOWS_1252)
+}
+
+/// Decode a single line of a diff, which can't be sniffed as a whole file
+pub fn decode_line(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => WINDOWS_1252
+            .decode_without_bom_handling(bytes)
+            .0
+            .into_owned(),
+    }
+}
+
+/// Read a source file, converting it to UTF-8
+pub fn read_source(path: &Path) -> std::io::Result<Decoded> {
+    Ok(decode_bytes(&std::fs::read(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        match decode_bytes(bytes) {
+            Decoded::Text(text) => text,
+            Decoded::Binary => panic!("{bytes:?} was decoded as binary"),
+        }
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strips_the_byte_order_marks() {
+        assert_eq!(text(b"\xEF\xBB\xBFfn main() {}\n"), "fn main() {}\n");
+
+        let mut little_endian = vec![0xFF, 0xFE];
+        little_endian.extend(utf16("let café = 1;\n", false));
+        assert_eq!(text(&little_endian), "let café = 1;\n");
+
+        let mut big_endian = vec![0xFE, 0xFF];
+        big_endian.extend(utf16("let café = 1;\n", true));
+        assert_eq!(text(&big_endian), "let café = 1;\n");
+    }
+
+    #[test]
+    fn detects_utf16_without_byte_order_mark() {
+        let source = "int main(void) { return 0; }\n";
+        assert_eq!(text(&utf16(source, false)), source);
+        assert_eq!(text(&utf16(source, true)), source);
+    }
+
+    #[test]
+    fn decodes_legacy_8bit_text() {
+        // "café" and "naïve" in Latin-1, which isn't valid UTF-8
+        assert_eq!(text(b"# caf\xE9, na\xEFve\n"), "# café, naïve\n");
+        assert_eq!(text("# café\n".as_bytes()), "# café\n");
+        assert_eq!(decode_line(b"-caf\xE9\n"), "-café\n");
+    }
+
+    #[test]
+    fn treats_nul_and_control_bytes_as_binary() {
+        assert!(matches!(
+            decode_bytes(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x01"),
+            Decoded::Binary
+        ));
+        assert!(matches!(
+            decode_bytes(&[0x01, 0x02, 0x03, 0xE9, 0x04, 0x05]),
+            Decoded::Binary
+        ));
+        // A malformed sequence after a BOM isn't replaced
+        assert!(matches!(
+            decode_bytes(&[0xFF, 0xFE, 0x00, 0xD8]),
+            Decoded::Binary
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+//! Consolidation of the findings repeated across the reviews of a run (commits of a series,
+//! language groups of a directory), so that a pattern found in many places is reported once
+
+use crate::rules::{rule_ids, strip_rule_tags};
+use crate::structure::section_of_heading;
+
+/// Maximum number of consolidated findings shown, like the findings cap of a single review
+pub const MAX_CONSOLIDATED_FINDINGS: usize = 6;
+
+/// Similarity above which two finding titles are considered the same pattern
+const SAME_FINDING_SIMILARITY: f64 = 0.9;
+
+/// Finding repeated in more than one review
+#[derive(Debug, Clone)]
+pub struct ConsolidatedFinding {
+    /// Title of the first occurrence
+    pub title: String,
+    /// Locations of every occurrence (ex. commit or language group, with the file named by the finding)
+    pub locations: Vec<String>,
+}
+
+/// Number and title of a numbered item (ex. `1) Unchecked unwrap in src/a.rs`)
+fn numbered_item(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let rest = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+    let number = trimmed[..trimmed.len() - rest.len()].parse().ok()?;
+    let title = rest.strip_prefix(')').or_else(|| rest.strip_prefix('.'))?;
+    Some((number, title.trim())).filter(|(_, title)| !title.is_empty())
+}
+
+/// Item of the FINDINGS section of a review
+#[derive(Debug, Clone)]
+pub struct FindingItem {
+    pub number: usize,
+    pub title: String,
+    /// Guideline rules the finding is tagged with (ex. `RG-012`)
+    pub rules: Vec<String>,
+    /// Lines of the review covered by the item: its title and the indented lines following it
+    pub lines: std::ops::Range<usize>,
+}
+
+/// Items of the FINDINGS section of a review
+pub fn finding_items(review: &str) -> Vec<FindingItem> {
+    let mut in_findings = false;
+    let mut items: Vec<FindingItem> = Vec::new();
+    for (idx, line) in review.lines().enumerate() {
+        if let Some(section) = section_of_heading(line) {
+            in_findings = section == "FINDINGS";
+            continue;
+        }
+        if !in_findings {
+            continue;
+        }
+        if let Some((number, title)) = numbered_item(line) {
+            items.push(FindingItem {
+                number,
+                title: title.to_string(),
+                rules: rule_ids(title),
+                lines: idx..idx + 1,
+            });
+        } else if let Some(item) = items.last_mut()
+            && item.lines.end == idx
+            && (line.starts_with(char::is_whitespace) || line.trim().is_empty())
+        {
+            item.lines.end = idx + 1;
+        }
+    }
+    // Trailing blank lines belong to the section, not to its last item
+    let lines: Vec<&str> = review.lines().collect();
+    for item in &mut items {
+        while item.lines.end > item.lines.start + 1 && lines[item.lines.end - 1].trim().is_empty() {
+            item.lines.end -= 1;
+        }
+    }
+    items
+}
+
+/// Titles of the items of the FINDINGS section of a review
+pub fn finding_titles(review: &str) -> Vec<String> {
+    finding_items(review)
+        .into_iter()
+        .map(|item| item.title)
+        .collect()
+}
+
+/// Files named by a finding (its title and explanation), without line numbers nor duplicates
+pub fn finding_files(review: &str, item: &FindingItem) -> Vec<String> {
+    let text: Vec<&str> = review
+        .lines()
+        .skip(item.lines.start)
+        .take(item.lines.len())
+        .collect();
+    let mut files: Vec<String> = Vec::new();
+    for place in places(&text.join(" ")) {
+        let file = place.split(':').next().unwrap_or_default().to_string();
+        if !file.is_empty() && !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    files
+}
+
+/// Places of a finding as (file, line): the ones of its title, or else of its explanation
+pub fn finding_anchors(review: &str, item: &FindingItem) -> Vec<(String, Option<u32>)> {
+    let mut found = places(&item.title);
+    if found.is_empty() {
+        let text: Vec<&str> = review
+            .lines()
+            .skip(item.lines.start)
+            .take(item.lines.len())
+            .collect();
+        found = places(&text.join(" "));
+    }
+    found
+        .into_iter()
+        .map(|place| match place.split_once(':') {
+            Some((file, rest)) => {
+                let rest = rest.trim_start_matches(['L', 'l']);
+                let digits =
+                    rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (file.to_string(), rest[..digits].parse().ok())
+            }
+            None => (place, None),
+        })
+        .collect()
+}
+
+/// Severities of the reviews and of their findings, the most severe first
+pub const SEVERITIES: [&str; 5] = ["CRITICAL", "HIGH", "MEDIUM", "LOW", "INFO"];
+
+/// Severity of a finding, when its title or explanation names one (ex. `[HIGH]`)
+pub fn finding_severity(review: &str, item: &FindingItem) -> Option<&'static str> {
+    review
+        .lines()
+        .skip(item.lines.start)
+        .take(item.lines.len())
+        .flat_map(|line| line.split(|c: char| !c.is_ascii_alphabetic()))
+        .find_map(|word| SEVERITIES.into_iter().find(|severity| *severity == word))
+}
+
+/// Severity of a review (ex. `HIGH`), from its SEVERITY section
+pub fn review_severity(review: &str) -> Option<&'static str> {
+    let mut lines = review
+        .lines()
+        .skip_while(|line| section_of_heading(line) != Some("SEVERITY"));
+    let heading = lines.next()?;
+    // The severity follows the heading, on the same line or the next one
+    let next = lines
+        .next()
+        .filter(|line| section_of_heading(line).is_none());
+    let text = format!("{heading} {}", next.unwrap_or_default()).to_ascii_uppercase();
+    let text = text
+        .split_once("SEVERITY")
+        .map_or(text.as_str(), |(_, rest)| rest);
+    text.split(|c: char| !c.is_ascii_alphabetic())
+        .find_map(|word| SEVERITIES.into_iter().find(|severity| *severity == word))
+}
+
+/// Verdict of a review (ex. `Request changes: X`), from its FINAL VERDICT section
+pub fn review_verdict(review: &str) -> Option<String> {
+    let mut lines = review
+        .lines()
+        .skip_while(|line| section_of_heading(line) != Some("FINAL VERDICT"));
+    let heading = lines.next()?;
+    // The verdict follows the heading, on the same line or the next one
+    let verdict = match heading.split_once(':') {
+        Some((_, rest)) if !rest.trim().is_empty() => rest,
+        _ => lines.find(|line| !line.trim().is_empty())?,
+    };
+    let verdict = verdict.trim().trim_matches('"');
+    (!verdict.is_empty()).then(|| verdict.to_string())
+}
+
+/// Stable fingerprint of a finding: the first file it names and its title without places and numbers.
+/// Line numbers and other files don't change it, so it survives unrelated edits.
+pub fn fingerprint(review: &str, item: &FindingItem) -> String {
+    let text: Vec<&str> = review
+        .lines()
+        .skip(item.lines.start)
+        .take(item.lines.len())
+        .collect();
+    let file = places(&text.join(" "))
+        .first()
+        .map(|place| place.split(':').next().unwrap_or_default().to_string())
+        .unwrap_or_default();
+
+    // FNV-1a, stable across Rust versions unlike the standard hasher
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in format!("{file}\n{}", pattern_key(&item.title)).bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Quoted file path with spaces (ex. `docs/my notes.md`), unlike quoted code (ex. `a.len() > 0`)
+fn is_quoted_path(quoted: &str) -> bool {
+    let extension = quoted.rsplit_once('.').map(|(_, extension)| extension);
+    quoted.contains(char::is_whitespace)
+        && extension.is_some_and(|extension| {
+            (1..=8).contains(&extension.len())
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Words of a title; backtick-quoted paths stay in one word with what follows them, so that
+/// paths with spaces (ex. `` `docs/my notes.md`:12 ``) aren't split
+fn title_words(title: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut rest = title.trim_start();
+    while !rest.is_empty() {
+        let mut end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if let Some(open) = rest[..end].find('`')
+            && let Some(close) = rest[open + 1..].find('`')
+            && is_quoted_path(&rest[open + 1..open + 1 + close])
+        {
+            let after = open + 1 + close + 1;
+            end = after
+                + rest[after..]
+                    .find(char::is_whitespace)
+                    .unwrap_or(rest.len() - after);
+        }
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    words
+}
+
+/// Path-like words of a title (ex. `src/a.rs`, `main.rs:12`), naming where the finding is
+fn is_path_like(word: &str) -> bool {
+    // Calls (ex. `a.len()`) are code, not places
+    if word.contains("()") {
+        return false;
+    }
+    // A period ending the word ends the sentence (ex. `It panics.`)
+    let word = word
+        .trim_end_matches('.')
+        .trim_matches(|c: char| matches!(c, '`' | '(' | ')' | ',' | ':' | ';' | '"'));
+    word.contains('/') || (word.contains('.') && word.chars().any(|c| c.is_ascii_alphabetic()))
+}
+
+/// Title without the places it names and its rule tags, so that the same pattern in different
+/// files compares equal
+fn pattern_key(title: &str) -> String {
+    title_words(&strip_rule_tags(title))
+        .into_iter()
+        .filter(|word| !is_path_like(word))
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty() && !word.chars().all(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether two finding titles describe the same pattern, wherever they are
+pub fn same_pattern(title: &str, other: &str) -> bool {
+    let (key, other_key) = (pattern_key(title), pattern_key(other));
+    !key.is_empty() && strsim::normalized_levenshtein(&key, &other_key) >= SAME_FINDING_SIMILARITY
+}
+
+/// Places named by a title (ex. `src/a.rs:12`), without their quotes
+fn places(title: &str) -> Vec<String> {
+    title_words(title)
+        .into_iter()
+        .filter(|word| is_path_like(word))
+        .map(|word| {
+            word.trim_end_matches('.')
+                .trim_matches(|c: char| matches!(c, '(' | ')' | ',' | ';' | '"'))
+                .replace('`', "")
+        })
+        .collect()
+}
+
+/// Place named by a title, if any
+fn named_place(title: &str) -> Option<String> {
+    let places = places(title);
+    (!places.is_empty()).then(|| places.join(" "))
+}
+
+/// Group the findings of `reviews` (location, review text) that describe the same pattern,
+/// keeping only the ones found more than once; the most repeated come first
+pub fn consolidate(reviews: &[(String, String)]) -> Vec<ConsolidatedFinding> {
+    let mut groups: Vec<(String, ConsolidatedFinding)> = Vec::new();
+    for (location, review) in reviews {
+        for title in finding_titles(review) {
+            let key = pattern_key(&title);
+            if key.is_empty() {
+                continue;
+            }
+            let location = match named_place(&title) {
+                Some(place) => format!("{location}: {place}"),
+                None => location.clone(),
+            };
+            match groups.iter_mut().find(|(group_key, _)| {
+                strsim::normalized_levenshtein(group_key, &key) >= SAME_FINDING_SIMILARITY
+            }) {
+                Some((_, finding)) => {
+                    if !finding.locations.contains(&location) {
+                        finding.locations.push(location);
+                    }
+                }
+                None => groups.push((
+                    key,
+                    ConsolidatedFinding {
+                        title,
+                        locations: vec![location],
+                    },
+                )),
+            }
+        }
+    }
+
+    let mut repeated: Vec<ConsolidatedFinding> = groups
+        .into_iter()
+        .map(|(_, finding)| finding)
+        .filter(|finding| finding.locations.len() > 1)
+        .collect();
+    // Stable sort, findings repeated as many times keep the order of the reviews
+    repeated.sort_by_key(|finding| std::cmp::Reverse(finding.locations.len()));
+    repeated
+}
+
+/// Plain text section listing the consolidated findings, capped to `MAX_CONSOLIDATED_FINDINGS`
+pub fn format_consolidated(findings: &[ConsolidatedFinding]) -> String {
+    let mut text = String::from("CONSOLIDATED FINDINGS (same pattern in several places)\n");
+    for (idx, finding) in findings.iter().take(MAX_CONSOLIDATED_FINDINGS).enumerate() {
+        text.push_str(&format!(
+            "{}) {} [{} locations]\n",
+            idx + 1,
+            finding.title,
+            finding.locations.len()
+        ));
+        for location in &finding.locations {
+            text.push_str(&format!("   - {location}\n"));
+        }
+    }
+    if findings.len() > MAX_CONSOLIDATED_FINDINGS {
+        text.push_str(&format!(
+            "... and {} more repeated findings\n",
+            findings.len() - MAX_CONSOLIDATED_FINDINGS
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Review whose only finding names `place`
+    fn review_naming(place: &str) -> String {
+        format!("SUMMARY\nOne issue.\n\nFINDINGS\n1) Unchecked unwrap in {place}\n   It panics.\n")
+    }
+
+    #[test]
+    fn keeps_exotic_paths_whole() {
+        let cases = [
+            ("`docs/my notes.md`:12", "docs/my notes.md:12"),
+            ("`a b/c d.txt`", "a b/c d.txt"),
+            ("src/ünïcode/ファイル.rs:3", "src/ünïcode/ファイル.rs:3"),
+            (
+                "`src/ünïcode dir/ファイル.rs`:L7",
+                "src/ünïcode dir/ファイル.rs:L7",
+            ),
+            ("(src/main.rs:40).", "src/main.rs:40"),
+        ];
+        for (place, expected) in cases {
+            let title = format!("Unchecked unwrap in {place}. It panics.");
+            assert_eq!(places(&title), vec![expected.to_string()], "{place}");
+        }
+    }
+
+    #[test]
+    fn keeps_quoted_code_apart_from_paths() {
+        assert_eq!(
+            places("`a.len() > 0` of `src/my lib.rs`:2"),
+            vec![String::from("src/my lib.rs:2")]
+        );
+        assert_eq!(places("`a.len()` is never checked"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn consolidates_findings_of_exotic_paths() {
+        let reviews = [
+            (
+                String::from("abc123"),
+                review_naming("`docs/my notes.md`:12"),
+            ),
+            (
+                String::from("def456"),
+                review_naming("`other dir/x y.rs`:1"),
+            ),
+        ];
+        let consolidated = consolidate(&reviews);
+        assert_eq!(consolidated.len(), 1);
+        assert_eq!(
+            consolidated[0].locations,
+            vec!["abc123: docs/my notes.md:12", "def456: other dir/x y.rs:1"]
+        );
+
+        let items = finding_items(&reviews[0].1);
+        let other = finding_items(&reviews[1].1);
+        assert_ne!(
+            fingerprint(&reviews[0].1, &items[0]),
+            fingerprint(&reviews[1].1, &other[0])
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! `--verify-fixes`: the unified diffs of the SUGGESTED FIX section are applied one by one to a
+//! temporary worktree of the reviewed code, where a verification command (ex. `cargo check`)
+//! tells whether each suggestion builds
+
+use crate::context::resolve_project_file;
+use crate::git_helpers;
+use crate::history::now_timestamp;
+use crate::sandbox::{CommandOutput, Sandbox};
+use crate::structure::section_of_heading;
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Verification commands of the projects recognized by their manifest, when none is configured
+const DEFAULT_COMMANDS: &[(&str, &str)] =
+    &[("Cargo.toml", "cargo check"), ("go.mod", "go build ./...")];
+
+/// Fix suggested by a review for one of its findings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedFix {
+    /// Number of the finding the fix is labeled with
+    pub finding: usize,
+    /// Unified diff of the fix, when it was given as one
+    pub patch: Option<String>,
+}
+
+/// Outcome of the verification of a fix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixStatus {
+    Verified,
+    DoesNotBuild,
+    DoesNotApply,
+    /// The fix isn't a unified diff (ex. a code snippet), it can't be applied
+    NotADiff,
+}
+
+impl FixStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            FixStatus::Verified => "verified",
+            FixStatus::DoesNotBuild => "does not build",
+            FixStatus::DoesNotApply => "does not apply",
+            FixStatus::NotADiff => "not a diff",
+        }
+    }
+}
+
+/// Verification of the fix of a finding
+#[derive(Debug, Clone, Serialize)]
+pub struct FixVerification {
+    pub finding: usize,
+    pub status: FixStatus,
+    /// First error of `git apply` or of the verification command
+    pub detail: String,
+}
+
+/// Number of the finding a line of the SUGGESTED FIX section is labeled with (ex. `1)`,
+/// `Fix 2:`, `[3]`, `Finding #4`)
+fn fix_label(line: &str) -> Option<usize> {
+    let mut rest = line.trim_start().trim_start_matches(['*', '[', '#']);
+    // Labels like `Fix for finding 2`
+    while let Some(word) = ["fix", "finding", "for"].into_iter().find(|word| {
+        rest.get(..word.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(word))
+    }) {
+        rest = rest[word.len()..].trim_start().trim_start_matches('#');
+    }
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = rest[..digits].parse().ok()?;
+    let after = &rest[digits..];
+    (after.is_empty() || after.starts_with([')', '.', ':', ']', ' ', '*'])).then_some(number)
+}
+
+/// Unified diff of the text of a fix: from its first file header to the last line of its hunks
+fn extract_patch(lines: &[&str]) -> Option<String> {
+    let start = lines
+        .iter()
+        .position(|line| line.starts_with("--- ") || line.starts_with("diff --git "))?;
+    let mut patch: Vec<String> = Vec::new();
+    for line in &lines[start..] {
+        match line.chars().next() {
+            Some(' ' | '+' | '-' | '@' | '\\') => patch.push(line.to_string()),
+            _ if line.starts_with("diff --git ") || line.starts_with("index ") => {
+                patch.push(line.to_string())
+            }
+            // Models drop the space of blank context lines
+            None => patch.push(String::from(" ")),
+            Some(_) => break,
+        }
+    }
+    while patch.last().is_some_and(|line| line.trim().is_empty()) {
+        patch.pop();
+    }
+    let has_hunks = patch.iter().any(|line| line.starts_with("@@"));
+    let has_target = patch.iter().any(|line| line.starts_with("+++ "));
+    (has_hunks && has_target).then(|| patch.join("\n") + "\n")
+}
+
+/// Fixes of the SUGGESTED FIX section of a review, in order
+pub fn parse_fixes(review: &str) -> Vec<SuggestedFix> {
+    let mut in_section = false;
+    let mut in_fence = false;
+    let mut fixes: Vec<(usize, Vec<&str>)> = Vec::new();
+    for line in review.lines() {
+        if !in_fence && let Some(section) = section_of_heading(line) {
+            in_section = section == "SUGGESTED FIX";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        let is_diff_line = line.starts_with([' ', '+', '-', '@']);
+        if !in_fence
+            && !is_diff_line
+            && let Some(number) = fix_label(line)
+        {
+            fixes.push((number, Vec::new()));
+            continue;
+        }
+        if let Some((_, lines)) = fixes.last_mut() {
+            lines.push(line);
+        }
+    }
+    fixes
+        .into_iter()
+        .map(|(finding, lines)| SuggestedFix {
+            finding,
+            patch: extract_patch(&lines),
+        })
+        .collect()
+}
+
+/// Verification command of the configuration, or the default one of the project
+pub fn verify_command(configured: Option<&str>) -> Result<String> {
+    if let Some(command) = configured {
+        return Ok(command.to_string());
+    }
+    DEFAULT_COMMANDS
+        .iter()
+        .find(|(manifest, _)| resolve_project_file(manifest).is_some())
+        .map(|(_, command)| command.to_string())
+        .context("Set `verify_command` in config.toml to verify the suggested fixes")
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .context("Failed to invoke git")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
+
+/// First error line of the output of a command, or its last line
+fn first_error(stderr: &str, stdout: &str) -> String {
+    let text = format!("{stderr}\n{stdout}");
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    lines
+        .iter()
+        .find(|line| line.to_ascii_lowercase().contains("error"))
+        .or(lines.last())
+        .map(|line| line.to_string())
+        .unwrap_or_default()
+}
+
+/// Temporary worktree of the reviewed code, removed when dropped
+pub struct TempWorktree {
+    repo_dir: PathBuf,
+    pub dir: PathBuf,
+}
+
+impl TempWorktree {
+    /// Worktree at `commit`, or at the uncommitted changes of the working tree when `None`
+    pub fn create(repo_dir: &Path, commit: Option<&str>) -> Result<TempWorktree> {
+        let base = match commit {
+            Some(commit) => commit.to_string(),
+            None => {
+                // A commit of the working tree changes, without touching the working tree
+                let stash = git(repo_dir, &["stash", "create"])?;
+                let stash = String::from_utf8_lossy(&stash.stdout).trim().to_string();
+                if stash.is_empty() {
+                    String::from("HEAD")
+                } else {
+                    stash
+                }
+            }
+        };
+        let dir = std::env::temp_dir().join(format!(
+            "rv-verify-{}-{}",
+            now_timestamp(),
+            std::process::id()
+        ));
+        let dir_text = dir.to_string_lossy().to_string();
+        git(
+            repo_dir,
+            &["worktree", "add", "--detach", "--quiet", &dir_text, &base],
+        )?;
+        Ok(TempWorktree {
+            repo_dir: repo_dir.to_path_buf(),
+            dir,
+        })
+    }
+
+    /// Back to the reviewed code, keeping the ignored build outputs for the next build
+    fn reset(&self) -> Result<()> {
+        git(&self.dir, &["reset", "--quiet", "--hard"])?;
+        git(&self.dir, &["clean", "--quiet", "-fd"])?;
+        Ok(())
+    }
+
+    /// Apply `patch`, with `-p0` when its paths don't have the `a/` and `b/` prefixes
+    fn apply(&self, patch: &str) -> Result<(), String> {
+        let prefixed = patch
+            .lines()
+            .filter_map(|line| line.strip_prefix("--- ").or(line.strip_prefix("+++ ")))
+            .filter(|path| *path != "/dev/null")
+            .all(|path| path.starts_with("a/") || path.starts_with("b/"));
+        let mut child = Command::new("git")
+            .current_dir(&self.dir)
+            // Suggested fixes often have wrong hunk counts and no context lines
+            .args([
+                "apply",
+                "--recount",
+                "--unidiff-zero",
+                "--whitespace=nowarn",
+            ])
+            .arg(if prefixed { "-p1" } else { "-p0" })
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to invoke `git apply`: {e}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(patch.as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("`git apply` failed: {e}"))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(first_error(
+                &String::from_utf8_lossy(&output.stderr),
+                &String::from_utf8_lossy(&output.stdout),
+            ))
+        }
+    }
+}
+
+/// Error of a failed command, for the report
+fn command_error(output: &CommandOutput, sandbox: &Sandbox) -> String {
+    if output.timed_out {
+        format!("timed out after {}s", sandbox.timeout_secs())
+    } else {
+        first_error(&output.stderr, &output.stdout)
+    }
+}
+
+impl Drop for TempWorktree {
+    fn drop(&mut self) {
+        let dir = self.dir.to_string_lossy().to_string();
+        if git(&self.repo_dir, &["worktree", "remove", "--force", &dir]).is_err() {
+            eprintln!("[WARN] Failed to remove the temporary worktree {dir}");
+        }
+    }
+}
+
+/// Apply every suggested fix of `review` to a worktree of `commit` (the working tree changes
+/// when `None`) and run `command` in `sandbox` after each of them
+pub fn verify_fixes(
+    review: &str,
+    commit: Option<&str>,
+    command: &str,
+    sandbox: &Sandbox,
+) -> Result<Vec<FixVerification>> {
+    let fixes = parse_fixes(review);
+    if fixes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let repo = git_helpers::open_repo()?;
+    let repo_dir = repo
+        .workdir()
+        .context("The repository has no working tree")?
+        .to_path_buf();
+    let worktree = TempWorktree::create(&repo_dir, commit)?;
+
+    // A fix can only be judged on code that builds without it
+    let baseline = sandbox.run(command, &worktree.dir)?;
+    if !baseline.success {
+        bail!(
+            "`{command}` fails on the reviewed code itself: {}",
+            command_error(&baseline, sandbox)
+        );
+    }
+
+    let mut verifications = Vec::new();
+    for fix in fixes {
+        let Some(patch) = &fix.patch else {
+            verifications.push(FixVerification {
+                finding: fix.finding,
+                status: FixStatus::NotADiff,
+                detail: String::new(),
+            });
+            continue;
+        };
+        worktree.reset()?;
+        let (status, detail) = match worktree.apply(patch) {
+            Err(error) => (FixStatus::DoesNotApply, error),
+            Ok(()) => {
+                let output = sandbox.run(command, &worktree.dir)?;
+                if output.success {
+                    (FixStatus::Verified, String::new())
+                } else {
+                    (FixStatus::DoesNotBuild, command_error(&output, sandbox))
+                }
+            }
+        };
+        verifications.push(FixVerification {
+            finding: fix.finding,
+            status,
+            detail,
+        });
+    }
+    Ok(verifications)
+}
+
+/// FIX VERIFICATION appendix of the report
+pub fn format_fixes(verifications: &[FixVerification]) -> String {
+    let verified = verifications
+        .iter()
+        .filter(|v| v.status == FixStatus::Verified)
+        .count();
+    let mut appendix = format!(
+        "FIX VERIFICATION ({verified} of {} suggested fixes build)\n",
+        verifications.len()
+    );
+    for verification in verifications {
+        appendix.push_str(&format!(
+            "- Fix {}: {}",
+            verification.finding,
+            verification.status.name()
+        ));
+        if !verification.detail.is_empty() {
+            appendix.push_str(&format!(" ({})", verification.detail));
+        }
+        appendix.push('\n');
+    }
+    appendix
+}
@@ -0,0 +1,194 @@
+//! Dockerfiles (and Containerfiles)
+
+use super::infra;
+use super::{FlagRule, FormatFlag};
+use crate::redact;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Linter run on the changed Dockerfiles
+pub const LINTER: &str = "hadolint --no-color {file}";
+
+pub const GUIDELINES: &str = "\
+- Base images are pinned to a version, or better a digest; never `latest`.
+- Secrets never go through ENV, ARG or COPY, they stay in the image history; builds use
+  `RUN --mount=type=secret` and runtimes inject them.
+- Layers are ordered for the cache: the dependency manifests are copied and installed before
+  the sources; `apt-get update` and `apt-get install` share one RUN, which cleans the lists.
+- The final stage runs as a non-root USER, with only the runtime files (multi-stage builds).
+- Downloads are verified (checksum, signature) instead of piped into a shell.";
+
+/// Risky instructions, with their rule ID, severity and description
+const RULES: [FlagRule; 6] = [
+    (
+        "DKR-001",
+        "MEDIUM",
+        "uses a base image not pinned to a version or a digest",
+    ),
+    (
+        "DKR-002",
+        "HIGH",
+        "passes a secret through ENV or ARG, kept in the image history; use a build secret",
+    ),
+    (
+        "DKR-003",
+        "MEDIUM",
+        "runs `apt-get update` without installing in the same layer, leaving a stale cached index",
+    ),
+    (
+        "DKR-004",
+        "LOW",
+        "copies the whole build context before installing the dependencies, busting their cached layer on every change",
+    ),
+    ("DKR-005", "MEDIUM", "runs the container as root"),
+    (
+        "DKR-006",
+        "HIGH",
+        "runs a download without verifying it (piped into a shell, ADD of a URL without --checksum)",
+    ),
+];
+
+/// Commands installing the dependencies of a project
+const INSTALL_COMMANDS: [&str; 9] = [
+    "npm ci",
+    "npm install",
+    "yarn install",
+    "pnpm install",
+    "pip install -r",
+    "bundle install",
+    "go mod download",
+    "cargo fetch",
+    "composer install",
+];
+
+/// Whether `path` is a Dockerfile (ex. `Dockerfile`, `Dockerfile.dev`, `api.dockerfile`)
+pub fn detect(path: &Path, _content: &str) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    name == "dockerfile"
+        || name == "containerfile"
+        || name.starts_with("dockerfile.")
+        || name.ends_with(".dockerfile")
+}
+
+/// Instructions of a Dockerfile, as their first line, last line and text, continuation lines
+/// joined
+fn instructions(content: &str) -> Vec<(usize, usize, String)> {
+    let mut instructions = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if current.is_none() && (trimmed.is_empty() || trimmed.starts_with('#')) {
+            continue;
+        }
+        let (start, mut text) = current.take().unwrap_or((idx + 1, String::new()));
+        match trimmed.strip_suffix('\\') {
+            Some(continued) => {
+                text.push_str(continued.trim_end());
+                text.push(' ');
+                current = Some((start, text));
+            }
+            None => {
+                text.push_str(trimmed);
+                instructions.push((start, idx + 1, text));
+            }
+        }
+    }
+    if let Some((start, text)) = current {
+        instructions.push((start, content.lines().count(), text));
+    }
+    instructions
+}
+
+/// Arguments of an instruction without its flags (ex. `--platform=...`, `--chown=...`)
+fn arguments(args: &str) -> Vec<&str> {
+    args.split_whitespace()
+        .filter(|word| !word.starts_with("--"))
+        .collect()
+}
+
+/// Whether ENV or ARG arguments name a secret (ex. `ARG NPM_TOKEN`, `ENV DB_PASSWORD=...`)
+fn names_secret(args: &str) -> bool {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    // `ENV KEY value` sets one variable, `ENV A=1 B=2` several
+    let keys: Vec<&str> = match words.first() {
+        Some(first) if !first.contains('=') => vec![first],
+        _ => words
+            .iter()
+            .filter_map(|word| word.split_once('=').map(|(key, _)| key))
+            .collect(),
+    };
+    keys.iter().any(|key| redact::is_secret_key(key))
+}
+
+/// Risky instructions added to the Dockerfile with `content`, `lines` being its added lines
+pub fn scan(content: &str, lines: &[(usize, &str)]) -> Vec<FormatFlag> {
+    let added: BTreeSet<usize> = lines.iter().map(|(number, _)| *number).collect();
+    let mut stages: Vec<String> = Vec::new();
+    let mut copied_context = false;
+    let mut flags = Vec::new();
+    for (start, end, instruction) in instructions(content) {
+        let (keyword, args) = instruction
+            .split_once(char::is_whitespace)
+            .unwrap_or((instruction.as_str(), ""));
+        let keyword = keyword.to_ascii_uppercase();
+        let args = args.trim();
+        let lower = args.to_ascii_lowercase();
+        let mut rules = Vec::new();
+        match keyword.as_str() {
+            "FROM" => {
+                let words = arguments(args);
+                // Later stages start from the earlier ones by name
+                if let Some(image) = words.first()
+                    && !stages.iter().any(|stage| stage.eq_ignore_ascii_case(image))
+                    && infra::image_unpinned(image)
+                {
+                    rules.push("DKR-001");
+                }
+                if words.len() >= 3 && words[1].eq_ignore_ascii_case("as") {
+                    stages.push(words[2].to_string());
+                }
+            }
+            "ENV" | "ARG" if names_secret(args) => rules.push("DKR-002"),
+            "RUN" => {
+                if lower.contains("apt-get update") && !lower.contains("install") {
+                    rules.push("DKR-003");
+                }
+                if copied_context
+                    && INSTALL_COMMANDS
+                        .iter()
+                        .any(|command| lower.contains(command))
+                {
+                    rules.push("DKR-004");
+                }
+                if infra::pipes_to_shell(args) {
+                    rules.push("DKR-006");
+                }
+            }
+            "COPY" | "ADD" => {
+                let words = arguments(args);
+                if words.first() == Some(&".") {
+                    copied_context = true;
+                }
+                if keyword == "ADD"
+                    && words.first().is_some_and(|src| {
+                        src.starts_with("http://") || src.starts_with("https://")
+                    })
+                    && !args.contains("--checksum")
+                {
+                    rules.push("DKR-006");
+                }
+            }
+            "USER" if matches!(args, "root" | "0" | "root:root" | "0:0") => rules.push("DKR-005"),
+            _ => {}
+        }
+        if (start..=end).any(|number| added.contains(&number)) {
+            for rule in rules {
+                flags.push(FormatFlag::new(&RULES, rule, start, &instruction));
+            }
+        }
+    }
+    flags
+}
@@ -0,0 +1,195 @@
+//! GitHub Actions workflows and composite actions
+
+use super::infra;
+use super::{FlagRule, FormatFlag};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Linter run on the changed workflows
+pub const LINTER: &str = "actionlint -no-color {file}";
+
+pub const GUIDELINES: &str = "\
+- Third-party actions are pinned to a full commit SHA, with the version in a comment.
+- The GITHUB_TOKEN gets the least `permissions` the jobs need, set per workflow or job.
+- Secrets come from `${{ secrets.* }}`, are passed to the steps needing them only, and never
+  printed; untrusted event data (titles, bodies, branch names) reaches scripts through `env:`,
+  never through `${{ }}` interpolation.
+- `pull_request_target` and `workflow_run` never check out or run the code of the fork.
+- Caches are keyed on the hash of the lock files, so that they are reused and refreshed.
+- Steps don't run privileged containers or `sudo` without a reason.";
+
+/// Risky lines, with their rule ID, severity and description
+const RULES: [FlagRule; 8] = [
+    (
+        "GHA-001",
+        "MEDIUM",
+        "uses an action or an image not pinned to a commit SHA or a digest",
+    ),
+    (
+        "GHA-002",
+        "HIGH",
+        "writes a secret in the workflow instead of using `${{ secrets.* }}`",
+    ),
+    (
+        "GHA-003",
+        "HIGH",
+        "interpolates untrusted event data into a script; pass it through `env:`",
+    ),
+    (
+        "GHA-004",
+        "MEDIUM",
+        "runs on pull_request_target, with the secrets of the base repository, for pull requests of forks",
+    ),
+    (
+        "GHA-005",
+        "MEDIUM",
+        "grants every permission to the token (`write-all`)",
+    ),
+    ("GHA-006", "MEDIUM", "runs a privileged container or step"),
+    (
+        "GHA-007",
+        "HIGH",
+        "runs a download without verifying it (piped into a shell)",
+    ),
+    (
+        "GHA-008",
+        "LOW",
+        "keys a cache on the run instead of the hash of the lock files, the cache is never reused or refreshed",
+    ),
+];
+
+/// Fields of the event payload written by whoever opens the issue, the pull request or the
+/// branch
+const UNTRUSTED_FIELDS: [&str; 9] = [
+    ".title",
+    ".body",
+    ".message",
+    ".head.ref",
+    ".head.label",
+    ".head_branch",
+    ".author.name",
+    ".author.email",
+    ".page_name",
+];
+
+/// Whether `path` is a workflow (`.github/workflows/*.yml`) or a composite action (`action.yml`)
+pub fn detect(path: &Path, _content: &str) -> bool {
+    if path
+        .extension()
+        .is_none_or(|ext| ext != "yml" && ext != "yaml")
+    {
+        return false;
+    }
+    let in_workflows = path
+        .parent()
+        .is_some_and(|dir| dir.ends_with(".github/workflows"));
+    let action = path.file_stem().is_some_and(|stem| stem == "action");
+    in_workflows || action
+}
+
+/// Key and value of a YAML line, list marker removed (ex. `- uses: a/b@v1`)
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim().trim_start_matches("- ");
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim(), value.trim().trim_matches(['"', '\''])))
+}
+
+/// Whether the value of `uses:` floats: actions not pinned to a 40 characters commit SHA,
+/// images without a digest; local actions are part of the change
+fn unpinned_uses(uses: &str) -> bool {
+    let uses = uses.split(" #").next().unwrap_or(uses).trim();
+    if uses.starts_with("./") {
+        return false;
+    }
+    if let Some(image) = uses.strip_prefix("docker://") {
+        return !image.contains("@sha256:");
+    }
+    uses.rsplit_once('@').is_none_or(|(_, reference)| {
+        reference.len() != 40 || !reference.bytes().all(|b| b.is_ascii_hexdigit())
+    })
+}
+
+/// Whether a line interpolates data of the event controlled by its author
+fn interpolates_untrusted(line: &str) -> bool {
+    line.split("${{").skip(1).any(|expression| {
+        let expression = expression.split("}}").next().unwrap_or_default();
+        expression.contains("github.head_ref")
+            || (expression.contains("github.event.")
+                && UNTRUSTED_FIELDS
+                    .iter()
+                    .any(|field| expression.contains(field)))
+    })
+}
+
+/// Lines of the `run:` and `script:` blocks of `content`, shell and JavaScript code
+fn script_lines(content: &str) -> BTreeSet<usize> {
+    let mut lines = BTreeSet::new();
+    let mut block_indent: Option<usize> = None;
+    for (idx, line) in content.lines().enumerate() {
+        let indent = line.len() - line.trim_start().len();
+        if let Some(block) = block_indent {
+            if line.trim().is_empty() || indent > block {
+                lines.insert(idx + 1);
+                continue;
+            }
+            block_indent = None;
+        }
+        let item = line.trim_start().trim_start_matches("- ");
+        if item.starts_with("run:") || item.starts_with("script:") {
+            lines.insert(idx + 1);
+            // The key is indented past the list marker
+            block_indent = Some(indent + (line.trim_start().len() - item.len()));
+        }
+    }
+    lines
+}
+
+/// Risky lines added to the workflow with `content`, `lines` being its added lines
+pub fn scan(content: &str, lines: &[(usize, &str)]) -> Vec<FormatFlag> {
+    let scripts = script_lines(content);
+    let caches = content.contains("actions/cache");
+    let mut flags = Vec::new();
+    for (number, line) in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        let mut rules = Vec::new();
+        let (key, value) = key_value(line).unwrap_or_default();
+        if key == "uses" && unpinned_uses(value) {
+            rules.push("GHA-001");
+        }
+        if infra::has_secret(line) {
+            rules.push("GHA-002");
+        }
+        if scripts.contains(number) && interpolates_untrusted(line) {
+            rules.push("GHA-003");
+        }
+        if trimmed.starts_with("pull_request_target")
+            || (key == "on" && value.contains("pull_request_target"))
+        {
+            rules.push("GHA-004");
+        }
+        if key == "permissions" && value == "write-all" {
+            rules.push("GHA-005");
+        }
+        if infra::is_privileged(line) {
+            rules.push("GHA-006");
+        }
+        if scripts.contains(number) && infra::pipes_to_shell(line) {
+            rules.push("GHA-007");
+        }
+        if caches
+            && key == "key"
+            && (!value.contains("hashFiles")
+                || value.contains("github.sha")
+                || value.contains("github.run_id"))
+        {
+            rules.push("GHA-008");
+        }
+        for rule in rules {
+            flags.push(FormatFlag::new(&RULES, rule, *number, trimmed));
+        }
+    }
+    flags
+}
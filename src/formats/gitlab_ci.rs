@@ -0,0 +1,104 @@
+//! GitLab CI configurations
+
+use super::infra;
+use super::{FlagRule, FormatFlag};
+use std::path::Path;
+
+/// Linter run on the changed configurations: none by default, `glab ci lint` needs the GitLab
+/// API
+pub const LINTER: &str = "";
+
+pub const GUIDELINES: &str = "\
+- Job images and services are pinned to a version or a digest, never `latest`.
+- Secrets are masked and protected CI/CD variables, never written in the configuration nor
+  echoed in the job logs.
+- Jobs don't need privileged runners: images are built with Kaniko or Buildah rather than
+  Docker in Docker.
+- Caches are keyed on the lock files (`cache:key:files`), so that they are reused and
+  refreshed; artifacts expire.
+- `rules` and `needs` run the jobs only where needed, deployment jobs only on protected
+  branches.";
+
+/// Risky lines, with their rule ID, severity and description
+const RULES: [FlagRule; 5] = [
+    (
+        "GLC-001",
+        "MEDIUM",
+        "uses an image not pinned to a version or a digest",
+    ),
+    (
+        "GLC-002",
+        "HIGH",
+        "writes a secret in the configuration instead of a masked CI/CD variable",
+    ),
+    (
+        "GLC-003",
+        "MEDIUM",
+        "needs a privileged runner (privileged container, Docker in Docker)",
+    ),
+    (
+        "GLC-004",
+        "HIGH",
+        "runs a download without verifying it (piped into a shell)",
+    ),
+    (
+        "GLC-005",
+        "LOW",
+        "keys a cache on the commit or the pipeline, the cache is never reused",
+    ),
+];
+
+/// Predefined variables changing on every commit or pipeline
+const VOLATILE_VARIABLES: [&str; 4] = [
+    "CI_COMMIT_SHA",
+    "CI_COMMIT_SHORT_SHA",
+    "CI_PIPELINE_ID",
+    "CI_JOB_ID",
+];
+
+/// Whether `path` is a GitLab CI configuration (ex. `.gitlab-ci.yml`, `deploy.gitlab-ci.yml`)
+pub fn detect(path: &Path, _content: &str) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.ends_with(".gitlab-ci.yml") || name.ends_with(".gitlab-ci.yaml"))
+}
+
+/// Risky lines added to the configuration, `lines` being its added lines
+pub fn scan(lines: &[(usize, &str)]) -> Vec<FormatFlag> {
+    let mut flags = Vec::new();
+    for (number, line) in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        let item = trimmed.trim_start_matches("- ");
+        let (key, value) = item
+            .split_once(':')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .unwrap_or_default();
+        let mut rules = Vec::new();
+        if key == "image" && infra::image_unpinned(value) {
+            rules.push("GLC-001");
+        }
+        if infra::has_secret(line) {
+            rules.push("GLC-002");
+        }
+        if infra::is_privileged(line) || trimmed.contains("dind") {
+            rules.push("GLC-003");
+        }
+        if infra::pipes_to_shell(line) {
+            rules.push("GLC-004");
+        }
+        if key == "key"
+            && VOLATILE_VARIABLES
+                .iter()
+                .any(|variable| value.contains(variable))
+        {
+            rules.push("GLC-005");
+        }
+        for rule in rules {
+            flags.push(FormatFlag::new(&RULES, rule, *number, trimmed));
+        }
+    }
+    flags
+}
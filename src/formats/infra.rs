@@ -0,0 +1,75 @@
+//! Checks shared by the infrastructure formats (Dockerfiles, GitHub Actions, GitLab CI)
+
+use crate::redact::redact_secrets;
+
+/// Infrastructure review, added to the system prompt when the change has a Dockerfile or a CI
+/// configuration
+pub const INFRA_PROMPT: &str = r#"
+INFRASTRUCTURE
+- The change touches Dockerfiles or CI configurations. Check the pinning
+  of images and actions, secrets leaking into env values, image layers or
+  logs, the layer and CI caches (busted or never refreshed), and steps
+  running privileged or with more permissions than they need.
+- <info checks="infra-config"> lists the risky lines flagged by rule.
+  Report each one as a finding with at least the severity given, ending
+  its title with the rule ID (ex. `[GHA-001]`), unless the context makes
+  it safe; say so then.
+"#;
+
+/// Shells a download shouldn't be piped into
+const SHELLS: [&str; 6] = ["sh", "bash", "zsh", "sudo sh", "sudo bash", "python"];
+
+/// Whether `image` (ex. `rust:1.80`, `node`, `ghcr.io/a/b@sha256:...`) floats: without a tag,
+/// or tagged `latest`; images set by a variable can't be told
+pub fn image_unpinned(image: &str) -> bool {
+    let image = image.trim().trim_matches(['"', '\'']);
+    if image.is_empty() || image == "scratch" || image.contains('$') || image.contains('@') {
+        return false;
+    }
+    let name = image.rsplit('/').next().unwrap_or(image);
+    match name.split_once(':') {
+        Some((_, tag)) => tag == "latest",
+        None => true,
+    }
+}
+
+/// Whether a shell line pipes a download into a shell (ex. `curl -sSL URL | sh`)
+pub fn pipes_to_shell(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    let mut downloaded = false;
+    for segment in line.split('|').map(str::trim) {
+        if downloaded
+            && SHELLS.iter().any(|shell| {
+                segment
+                    .strip_prefix(shell)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+            })
+        {
+            return true;
+        }
+        downloaded |= segment.contains("curl ") || segment.contains("wget ");
+    }
+    false
+}
+
+/// Whether a line runs a privileged container or step
+pub fn is_privileged(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    line.contains("--privileged") || line.contains("privileged: true")
+}
+
+/// Whether a line writes a secret in clear (ex. `API_TOKEN: "abc..."`), unlike the references
+/// to the secrets of the CI (ex. `${{ secrets.TOKEN }}`, `$TOKEN`) and `secrets: inherit`
+pub fn has_secret(line: &str) -> bool {
+    let line = line.trim();
+    if line.starts_with('#') || line.contains('$') {
+        return false;
+    }
+    let value = line
+        .split_once([':', '='])
+        .map_or("", |(_, value)| value.trim().trim_matches(['"', '\'']));
+    if matches!(value, "inherit" | "true" | "false") {
+        return false;
+    }
+    redact_secrets(line) != line
+}
@@ -0,0 +1,24 @@
+//! Kubernetes manifests (Deployments, Services, CronJobs...) in YAML
+
+use std::path::Path;
+
+/// Linter run on the changed manifests
+pub const LINTER: &str = "kube-linter lint --format plain {file}";
+
+pub const GUIDELINES: &str = "\
+- Containers set resource requests and limits, and readiness and liveness probes.
+- Images are pinned to a version or a digest, never `latest`.
+- Pods run as non-root, without privileged mode, host namespaces or hostPath volumes, with a
+  read-only root filesystem and the capabilities dropped, unless the manifest explains why.
+- Secrets are referenced from Secret objects, never written in the manifests or the env values.
+- Labels and selectors of Deployments and Services match; the selector of an existing
+  Deployment can't be changed.
+- Rollouts don't cause downtime (replicas, rollout strategy, PodDisruptionBudget).";
+
+/// Whether `content` is a manifest: YAML with top-level `apiVersion` and `kind` fields
+pub fn detect(path: &Path, content: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        && content.lines().any(|line| line.starts_with("apiVersion:"))
+        && content.lines().any(|line| line.starts_with("kind:"))
+}
@@ -0,0 +1,545 @@
+//! Structured text formats of the changed files (API descriptions, schemas, migrations,
+//! Dockerfiles, CI configurations, manifests): they are detected from their path and content, their review guidelines are sent
+//! to the model, and their linters run in the sandbox with `[formats] lint`, the output feeding
+//! the prompt
+
+pub mod dockerfile;
+pub mod github_actions;
+pub mod gitlab_ci;
+pub mod infra;
+pub mod kubernetes;
+pub mod openapi;
+pub mod protobuf;
+pub mod sql;
+
+use crate::checks::CheckFinding;
+use crate::config::RvConfig;
+use crate::fixes::TempWorktree;
+use crate::git_helpers::{self, ExpandedCommit, FilePatch};
+use crate::markup;
+use crate::sandbox::Sandbox;
+
+use anyhow::{Context, Result};
+use git2::{Delta, Repository};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the check of the linter output
+pub const FORMAT_LINT_CHECK: &str = "format-lint";
+
+/// Name of the check of the risky statements of the migrations
+pub const MIGRATION_CHECK: &str = "migration-safety";
+
+/// Name of the check of the risky lines of the Dockerfiles and CI configurations
+pub const INFRA_CHECK: &str = "infra-config";
+
+/// Lines of linter output kept for every file
+const MAX_LINT_LINES: usize = 20;
+
+/// Files larger than this aren't inspected
+const MAX_DETECTED_BYTES: usize = 1_048_576;
+
+/// Characters of a flagged line or statement kept in its message
+const MAX_STATEMENT_CHARS: usize = 80;
+
+/// Rule of the deterministic checks of a format: its ID, severity and description
+pub type FlagRule = (&'static str, &'static str, &'static str);
+
+/// Risky line or statement of a file, flagged by a rule of its format
+#[derive(Debug, Clone)]
+pub struct FormatFlag {
+    /// Rule ID (ex. `MIG-001`)
+    pub rule: &'static str,
+    pub severity: &'static str,
+    pub description: &'static str,
+    /// Line of the statement in the new version of the file (0 for the whole file)
+    pub line: usize,
+    /// Statement, shortened
+    pub statement: String,
+}
+
+impl FormatFlag {
+    /// Flag of `rule`, one of `rules`, raised by `statement` at `line`
+    pub fn new(rules: &[FlagRule], rule: &'static str, line: usize, statement: &str) -> FormatFlag {
+        let (_, severity, description) = rules
+            .iter()
+            .find(|(id, _, _)| *id == rule)
+            .copied()
+            .unwrap_or((rule, "MEDIUM", ""));
+        let mut statement = statement.to_string();
+        if statement.chars().count() > MAX_STATEMENT_CHARS {
+            statement = statement
+                .chars()
+                .take(MAX_STATEMENT_CHARS)
+                .collect::<String>()
+                + "...";
+        }
+        FormatFlag {
+            rule,
+            severity,
+            description,
+            line,
+            statement,
+        }
+    }
+}
+
+/// Structured format of a file, reviewed with its own guidelines and linter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Format {
+    OpenApi,
+    Protobuf,
+    SqlMigration,
+    Dockerfile,
+    GitHubActions,
+    GitLabCi,
+    Kubernetes,
+}
+
+impl Format {
+    /// Every format, Kubernetes last as the other YAML formats are told by their path
+    pub const ALL: [Format; 7] = [
+        Format::OpenApi,
+        Format::Protobuf,
+        Format::SqlMigration,
+        Format::Dockerfile,
+        Format::GitHubActions,
+        Format::GitLabCi,
+        Format::Kubernetes,
+    ];
+
+    /// Identifier of the format, the key of its linter in `[formats.linters]`
+    pub fn id(self) -> &'static str {
+        match self {
+            Format::OpenApi => "openapi",
+            Format::Protobuf => "protobuf",
+            Format::SqlMigration => "sql-migration",
+            Format::Dockerfile => "dockerfile",
+            Format::GitHubActions => "github-actions",
+            Format::GitLabCi => "gitlab-ci",
+            Format::Kubernetes => "kubernetes",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::OpenApi => "OpenAPI",
+            Format::Protobuf => "Protocol Buffers",
+            Format::SqlMigration => "SQL migration",
+            Format::Dockerfile => "Dockerfile",
+            Format::GitHubActions => "GitHub Actions workflow",
+            Format::GitLabCi => "GitLab CI configuration",
+            Format::Kubernetes => "Kubernetes manifest",
+        }
+    }
+
+    fn guidelines(self) -> &'static str {
+        match self {
+            Format::OpenApi => openapi::GUIDELINES,
+            Format::Protobuf => protobuf::GUIDELINES,
+            Format::SqlMigration => sql::GUIDELINES,
+            Format::Dockerfile => dockerfile::GUIDELINES,
+            Format::GitHubActions => github_actions::GUIDELINES,
+            Format::GitLabCi => gitlab_ci::GUIDELINES,
+            Format::Kubernetes => kubernetes::GUIDELINES,
+        }
+    }
+
+    /// Linter of the format when `[formats.linters]` doesn't set one, `{file}` being the path
+    pub fn default_linter(self) -> &'static str {
+        match self {
+            Format::OpenApi => openapi::LINTER,
+            Format::Protobuf => protobuf::LINTER,
+            Format::SqlMigration => sql::LINTER,
+            Format::Dockerfile => dockerfile::LINTER,
+            Format::GitHubActions => github_actions::LINTER,
+            Format::GitLabCi => gitlab_ci::LINTER,
+            Format::Kubernetes => kubernetes::LINTER,
+        }
+    }
+
+    fn matches(self, path: &Path, content: &str) -> bool {
+        match self {
+            Format::OpenApi => openapi::detect(path, content),
+            Format::Protobuf => protobuf::detect(path, content),
+            Format::SqlMigration => sql::detect(path, content),
+            Format::Dockerfile => dockerfile::detect(path, content),
+            Format::GitHubActions => github_actions::detect(path, content),
+            Format::GitLabCi => gitlab_ci::detect(path, content),
+            Format::Kubernetes => kubernetes::detect(path, content),
+        }
+    }
+
+    /// Check of the deterministic flags of the format, if it has some
+    fn check(self) -> Option<&'static str> {
+        match self {
+            Format::SqlMigration => Some(MIGRATION_CHECK),
+            Format::Dockerfile | Format::GitHubActions | Format::GitLabCi => Some(INFRA_CHECK),
+            Format::OpenApi | Format::Protobuf | Format::Kubernetes => None,
+        }
+    }
+
+    fn flags_enabled(self, rvconfig: &RvConfig) -> bool {
+        match self.check() {
+            Some(MIGRATION_CHECK) => rvconfig.formats.migration_safety,
+            Some(_) => rvconfig.formats.infra_checks,
+            None => false,
+        }
+    }
+
+    /// Risky lines of the file at `path` with `content`, `lines` being its added lines
+    fn scan(self, path: &Path, content: &str, lines: &[(usize, &str)]) -> Vec<FormatFlag> {
+        match self {
+            Format::SqlMigration => sql::scan(path, lines),
+            Format::Dockerfile => dockerfile::scan(content, lines),
+            Format::GitHubActions => github_actions::scan(content, lines),
+            Format::GitLabCi => gitlab_ci::scan(lines),
+            Format::OpenApi | Format::Protobuf | Format::Kubernetes => Vec::new(),
+        }
+    }
+
+    /// Format of the file at `path` with `content`, if it has one
+    pub fn detect(path: &Path, content: &str) -> Option<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.matches(path, content))
+    }
+}
+
+/// New content of a changed file: its blob, else the file of the working tree
+fn new_content(
+    repo: Option<&Repository>,
+    workdir: &Path,
+    path: &Path,
+    oid: Option<git2::Oid>,
+) -> Option<String> {
+    let bytes = match (repo, oid) {
+        (Some(repo), Some(oid)) if !oid.is_zero() => repo.find_blob(oid).ok()?.content().to_vec(),
+        _ => fs::read(workdir.join(path)).ok()?,
+    };
+    (bytes.len() <= MAX_DETECTED_BYTES).then(|| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Files of the review in a structured format: the added and modified files of Git reviews, the
+/// sources of raw reviews
+pub fn detect_formats(expcommit: &ExpandedCommit) -> Vec<(PathBuf, Format)> {
+    let repo = git_helpers::open_repo().ok();
+    let workdir = repo
+        .as_ref()
+        .and_then(|repo| repo.workdir().map(Path::to_path_buf))
+        .unwrap_or_default();
+
+    let mut detected = Vec::new();
+    if expcommit.patches.is_empty() {
+        for path in expcommit.sources.iter().flatten() {
+            if let Some(format) = fs::read(path)
+                .ok()
+                .filter(|bytes| bytes.len() <= MAX_DETECTED_BYTES)
+                .and_then(|bytes| Format::detect(path, &String::from_utf8_lossy(&bytes)))
+            {
+                detected.push((path.clone(), format));
+            }
+        }
+        return detected;
+    }
+    for (path, patch) in &expcommit.patches {
+        // Files left out of the scope of the review (ex. `--file`) and deleted files are skipped
+        if !expcommit.diff_stats.contains_key(path)
+            || patch.binary
+            || patch.status == Some(Delta::Deleted)
+        {
+            continue;
+        }
+        if let Some(format) = new_content(repo.as_ref(), &workdir, path, patch.new_oid)
+            .and_then(|content| Format::detect(path, &content))
+        {
+            detected.push((path.clone(), format));
+        }
+    }
+    detected
+}
+
+/// Review guidelines of the detected formats, for the system prompt
+pub fn format_guidelines(detected: &[(PathBuf, Format)]) -> String {
+    let formats: BTreeSet<Format> = detected.iter().map(|(_, format)| *format).collect();
+    formats
+        .into_iter()
+        .map(|format| {
+            markup::element(
+                "guideline",
+                &[("format", format.name())],
+                format.guidelines(),
+            )
+        })
+        .collect()
+}
+
+/// `path` quoted for `sh`
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Run the linters of the detected files in `dir`, the reviewed code
+fn run_linters(
+    rvconfig: &RvConfig,
+    dir: &Path,
+    detected: &[(PathBuf, Format)],
+) -> Vec<CheckFinding> {
+    let sandbox = Sandbox::new(&rvconfig.sandbox);
+    let mut missing: BTreeSet<Format> = BTreeSet::new();
+    let mut findings = Vec::new();
+    for (path, format) in detected {
+        let linter = rvconfig
+            .formats
+            .linters
+            .get(format.id())
+            .map_or(format.default_linter(), String::as_str);
+        // Rails migrations are Ruby, the SQL linter can't read them
+        if linter.trim().is_empty()
+            || missing.contains(format)
+            || (*format == Format::SqlMigration && !sql::is_sql(path))
+        {
+            continue;
+        }
+        let command = linter.replace("{file}", &shell_quote(path));
+        let output = match sandbox.run(&command, dir) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("[WARN] Failed to lint {}: {e:#}", path.display());
+                continue;
+            }
+        };
+        if output.timed_out {
+            eprintln!(
+                "[WARN] `{command}` timed out after {}s",
+                sandbox.timeout_secs()
+            );
+            continue;
+        }
+        // `sh` prints `<program>: not found` (or `command not found`) and exits with 127
+        if !output.success && output.stderr.contains("not found") && output.stdout.trim().is_empty()
+        {
+            eprintln!(
+                "[WARN] The {} linter isn't installed (`{linter}`), set another one in [formats.linters]",
+                format.name()
+            );
+            missing.insert(*format);
+            continue;
+        }
+        if output.success {
+            continue;
+        }
+        let location = path.to_string_lossy().to_string();
+        for line in format!("{}\n{}", output.stdout, output.stderr)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take(MAX_LINT_LINES)
+        {
+            findings.push(CheckFinding::new(
+                FORMAT_LINT_CHECK,
+                location.clone(),
+                format!("{}: {line}", format.name()),
+            ));
+        }
+    }
+    findings
+}
+
+/// Directory of the reviewed code: a worktree of the last reviewed commit, else the working tree
+fn lint_in_reviewed_code(
+    rvconfig: &RvConfig,
+    expcommit: &ExpandedCommit,
+    detected: &[(PathBuf, Format)],
+) -> Result<Vec<CheckFinding>> {
+    if expcommit.patches.is_empty() {
+        return Ok(run_linters(rvconfig, &std::env::current_dir()?, detected));
+    }
+    let repo = git_helpers::open_repo()?;
+    let repo_dir = repo
+        .workdir()
+        .context("The repository has no working tree")?
+        .to_path_buf();
+    match expcommit.commits.last() {
+        Some(commit) => {
+            let worktree = TempWorktree::create(&repo_dir, Some(&commit.to_string()))?;
+            Ok(run_linters(rvconfig, &worktree.dir, detected))
+        }
+        // The linters only read the files, the working tree has the uncommitted changes
+        None => Ok(run_linters(rvconfig, &repo_dir, detected)),
+    }
+}
+
+/// Problems the linters of the detected formats report, when enabled with `[formats] lint`
+pub fn lint_findings(
+    rvconfig: &RvConfig,
+    expcommit: &ExpandedCommit,
+    detected: &[(PathBuf, Format)],
+) -> Vec<CheckFinding> {
+    if !rvconfig.formats.lint || detected.is_empty() {
+        return Vec::new();
+    }
+    match lint_in_reviewed_code(rvconfig, expcommit, detected) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("[WARN] Failed to lint the structured files: {e:#}");
+            Vec::new()
+        }
+    }
+}
+
+/// Linter output for the system prompt, so that the review confirms or explains it
+pub fn format_lints(findings: &[CheckFinding]) -> String {
+    let lints: Vec<String> = findings
+        .iter()
+        .filter(|finding| finding.check == FORMAT_LINT_CHECK)
+        .map(|finding| format!("{}: {}", finding.location, finding.message))
+        .collect();
+    if lints.is_empty() {
+        return String::new();
+    }
+    markup::element(
+        "info",
+        &[("checks", FORMAT_LINT_CHECK)],
+        &format!("- {}", lints.join("\n- ")),
+    )
+}
+
+/// Added lines of a changed file, with their number in its new version
+fn added_lines(patch: &FilePatch) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    for hunk in &patch.hunks {
+        let mut new_line = hunk.new_start as usize;
+        for line in &hunk.lines {
+            match line.origin {
+                '+' => {
+                    lines.push((new_line, line.content.trim_end_matches(['\n', '\r'])));
+                    new_line += 1;
+                }
+                ' ' => new_line += 1,
+                _ => {}
+            }
+        }
+    }
+    lines
+}
+
+/// Risky lines added to the detected files: migration statements (drops, NOT NULL columns
+/// without a default, missing down migrations...) with `[formats] migration_safety`, Dockerfile
+/// and CI lines (unpinned images, secrets, privileged steps...) with `[formats] infra_checks`
+pub fn flag_findings(
+    rvconfig: &RvConfig,
+    expcommit: &ExpandedCommit,
+    detected: &[(PathBuf, Format)],
+) -> Vec<CheckFinding> {
+    let repo = git_helpers::open_repo().ok();
+    let workdir = repo
+        .as_ref()
+        .and_then(|repo| repo.workdir().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let mut flags = Vec::new();
+    for (path, format) in detected {
+        let Some(check) = format.check().filter(|_| format.flags_enabled(rvconfig)) else {
+            continue;
+        };
+        match expcommit.patches.get(path) {
+            Some(patch) => {
+                let content =
+                    new_content(repo.as_ref(), &workdir, path, patch.new_oid).unwrap_or_default();
+                flags.extend(
+                    format
+                        .scan(path, &content, &added_lines(patch))
+                        .into_iter()
+                        .map(|flag| (path, check, flag)),
+                );
+                if *format == Format::SqlMigration
+                    && patch.status == Some(Delta::Added)
+                    && let Some(down) = sql::down_migration(path)
+                    && !expcommit.patches.contains_key(&down)
+                    && !workdir.join(&down).exists()
+                {
+                    flags.push((path, check, sql::missing_down()));
+                }
+            }
+            // Raw reviews: the whole file is new
+            None => {
+                let Ok(content) = fs::read_to_string(path) else {
+                    continue;
+                };
+                let lines: Vec<(usize, &str)> = content
+                    .lines()
+                    .enumerate()
+                    .map(|(idx, line)| (idx + 1, line))
+                    .collect();
+                flags.extend(
+                    format
+                        .scan(path, &content, &lines)
+                        .into_iter()
+                        .map(|flag| (path, check, flag)),
+                );
+                if *format == Format::SqlMigration
+                    && let Some(down) = sql::down_migration(path)
+                    && !down.exists()
+                {
+                    flags.push((path, check, sql::missing_down()));
+                }
+            }
+        }
+    }
+    flags
+        .into_iter()
+        .map(|(path, check, flag)| {
+            let location = match flag.line {
+                0 => path.to_string_lossy().to_string(),
+                line => format!("{}:{line}", path.display()),
+            };
+            CheckFinding::new(
+                check,
+                location,
+                match flag.statement.as_str() {
+                    "" => format!("[{}] {} ({})", flag.rule, flag.description, flag.severity),
+                    statement => format!(
+                        "[{}] {} ({}): {statement}",
+                        flag.rule, flag.description, flag.severity
+                    ),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Reviews of the migrations and of the infrastructure files for the system prompt, with their
+/// flagged lines
+pub fn format_flags(
+    rvconfig: &RvConfig,
+    detected: &[(PathBuf, Format)],
+    findings: &[CheckFinding],
+) -> String {
+    let mut text = String::new();
+    for (check, prompt) in [
+        (MIGRATION_CHECK, sql::SAFETY_PROMPT),
+        (INFRA_CHECK, infra::INFRA_PROMPT),
+    ] {
+        if !detected
+            .iter()
+            .any(|(_, format)| format.check() == Some(check) && format.flags_enabled(rvconfig))
+        {
+            continue;
+        }
+        text.push_str(prompt);
+        let flags: Vec<String> = findings
+            .iter()
+            .filter(|finding| finding.check == check)
+            .map(|finding| format!("{}: {}", finding.location, finding.message))
+            .collect();
+        if !flags.is_empty() {
+            text.push_str(&markup::element(
+                "info",
+                &[("checks", check)],
+                &format!("- {}", flags.join("\n- ")),
+            ));
+        }
+    }
+    text
+}
@@ -0,0 +1,30 @@
+//! OpenAPI (and Swagger) descriptions of HTTP APIs, in YAML or JSON
+
+use std::path::Path;
+
+/// Linter run on the changed descriptions
+pub const LINTER: &str = "redocly lint --format stylish {file}";
+
+pub const GUIDELINES: &str = "\
+- Removing or renaming a path, an operation, a parameter, a property or an enum value, making
+  an optional field required and changing a type or a format break the existing clients; they
+  need a new version of the API or a deprecation period.
+- Every operation has a unique operationId and documents its error responses (4xx, 5xx).
+- New operations are covered by a security scheme, unless they are meant to be public.
+- Every $ref resolves; shared schemas are referenced instead of copied.
+- Examples and defaults match their schemas.";
+
+/// Whether `content` is an OpenAPI or Swagger description: its `openapi` or `swagger` version
+/// field comes first, or at the top level
+pub fn detect(path: &Path, content: &str) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => content
+            .lines()
+            .any(|line| line.starts_with("openapi:") || line.starts_with("swagger:")),
+        Some("json") => {
+            let head: String = content.chars().take(512).collect();
+            head.contains("\"openapi\"") || head.contains("\"swagger\"")
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,19 @@
+//! Protocol Buffers definitions (messages, enums, gRPC services)
+
+use std::path::Path;
+
+/// Linter run on the changed definitions
+pub const LINTER: &str = "buf lint --error-format text {file}";
+
+pub const GUIDELINES: &str = "\
+- Field numbers are never reused or renumbered; removed fields and enum values are reserved,
+  by number and by name.
+- The type and the cardinality of an existing field don't change, and fields don't move in or
+  out of a oneof.
+- The first value of an enum is `<ENUM>_UNSPECIFIED = 0`.
+- Renaming a package, a message, a service or a method breaks the generated code and the gRPC
+  paths of the existing clients.";
+
+pub fn detect(path: &Path, _content: &str) -> bool {
+    path.extension().is_some_and(|ext| ext == "proto")
+}
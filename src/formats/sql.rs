@@ -0,0 +1,263 @@
+//! SQL migrations (Flyway, Rails, Diesel, sqlx, golang-migrate and the like), and the
+//! deterministic flags of their risky statements
+
+use super::{FlagRule, FormatFlag};
+use std::path::Path;
+
+/// Linter run on the changed migrations
+pub const LINTER: &str = "sqlfluff lint --nocolor --dialect ansi {file}";
+
+/// Directories holding migrations
+const MIGRATION_DIRS: [&str; 3] = ["migrations", "migration", "migrate"];
+
+pub const GUIDELINES: &str = "\
+- A migration that may already be applied is never edited; changes go in a new migration.
+- Statements locking large tables are flagged: rewriting ALTER TABLE, NOT NULL columns added
+  without a default, indexes created without CONCURRENTLY (PostgreSQL).
+- Destructive statements (DROP, TRUNCATE, DELETE or UPDATE without WHERE, narrowing a column
+  type) lose data; they need a backup or an expand and contract rollout.
+- The down migration, when there is one, undoes exactly the up migration.
+- The code deployed before and after the migration keeps working with the new schema.";
+
+/// Safety review of the migrations, added to the system prompt when the change has one
+pub const SAFETY_PROMPT: &str = r#"
+MIGRATION SAFETY
+- The change contains database migrations. Review them first and most
+  thoroughly for destructive operations (data loss), locks held on large
+  tables while they run, and reversibility (down migration, rollback plan).
+- <info checks="migration-safety"> lists the risky statements flagged by
+  rule. Report each one as a finding with at least the severity given,
+  ending its title with the rule ID (ex. `[MIG-001]`), unless the change
+  makes it safe (ex. a table created by the same change); say so then.
+"#;
+
+/// Risky statements, with their rule ID, severity and description
+const RULES: [FlagRule; 7] = [
+    (
+        "MIG-001",
+        "HIGH",
+        "drops a table or a column, its data is lost",
+    ),
+    ("MIG-002", "HIGH", "deletes or changes every row of a table"),
+    (
+        "MIG-003",
+        "HIGH",
+        "makes a column NOT NULL without a default, failing on existing rows or locking the table",
+    ),
+    (
+        "MIG-004",
+        "MEDIUM",
+        "creates an index without CONCURRENTLY, blocking writes while it builds",
+    ),
+    (
+        "MIG-005",
+        "MEDIUM",
+        "changes the type of a column, rewriting the table under lock",
+    ),
+    (
+        "MIG-006",
+        "MEDIUM",
+        "renames a table or a column, breaking the code deployed before the migration",
+    ),
+    ("MIG-007", "MEDIUM", "has no down migration to revert it"),
+];
+
+/// Whether `path` is a Rails migration (ex. `db/migrate/20240101120000_add_users.rb`)
+fn is_rails(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "rb")
+        && path.parent().is_some_and(|dir| dir.ends_with("db/migrate"))
+        && path.file_name().is_some_and(|name| {
+            name.to_string_lossy()
+                .starts_with(|c: char| c.is_ascii_digit())
+        })
+}
+
+/// Whether `path` is an SQL migration, the only ones the SQL linter reads
+pub fn is_sql(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sql")
+}
+
+/// Whether `path` reverts another migration (ex. Diesel `down.sql`, sqlx `1_users.down.sql`)
+fn is_down(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name == "down.sql" || name.ends_with(".down.sql"))
+}
+
+/// Down migration expected next to the up migration at `path`, for Diesel and sqlx
+pub fn down_migration(path: &Path) -> Option<std::path::PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    if name == "up.sql" {
+        return Some(path.with_file_name("down.sql"));
+    }
+    let stem = name.strip_suffix(".up.sql")?;
+    Some(path.with_file_name(format!("{stem}.down.sql")))
+}
+
+/// Rules an SQL statement breaks, `statement` being uppercase with single spaces
+fn sql_rules(statement: &str) -> Vec<&'static str> {
+    let mut rules = Vec::new();
+    let has = |word: &str| statement.contains(word);
+    if statement.starts_with("DROP TABLE")
+        || statement.starts_with("DROP SCHEMA")
+        || statement.starts_with("DROP DATABASE")
+        || has("DROP COLUMN")
+    {
+        rules.push("MIG-001");
+    }
+    if statement.starts_with("TRUNCATE")
+        || ((statement.starts_with("DELETE FROM") || statement.starts_with("UPDATE "))
+            && !has(" WHERE "))
+    {
+        rules.push("MIG-002");
+    }
+    if has("SET NOT NULL")
+        || (statement.starts_with("ALTER TABLE")
+            && has(" ADD ")
+            && has("NOT NULL")
+            && !has("DEFAULT"))
+    {
+        rules.push("MIG-003");
+    }
+    if (statement.starts_with("CREATE INDEX") || statement.starts_with("CREATE UNIQUE INDEX"))
+        && !has("CONCURRENTLY")
+    {
+        rules.push("MIG-004");
+    }
+    if (has("ALTER COLUMN") && has(" TYPE ")) || has("MODIFY COLUMN") {
+        rules.push("MIG-005");
+    }
+    if statement.starts_with("ALTER TABLE") && has(" RENAME ") {
+        rules.push("MIG-006");
+    }
+    rules
+}
+
+/// Rules a line of a Rails migration breaks
+fn rails_rules(line: &str) -> Vec<&'static str> {
+    let line = line.trim();
+    let call = |name: &str| {
+        line.strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with([' ', '(']))
+    };
+    let mut rules = Vec::new();
+    if call("drop_table") || call("remove_column") || call("remove_columns") {
+        rules.push("MIG-001");
+    }
+    if (call("add_column") && line.contains("null: false") && !line.contains("default:"))
+        || (call("change_column_null") && line.contains("false"))
+    {
+        rules.push("MIG-003");
+    }
+    if call("add_index") && !line.contains("algorithm: :concurrently") {
+        rules.push("MIG-004");
+    }
+    if call("change_column") {
+        rules.push("MIG-005");
+    }
+    if call("rename_column") || call("rename_table") {
+        rules.push("MIG-006");
+    }
+    // SQL run by the migration
+    if call("execute")
+        && let Some(sql) = line
+            .split_once(['"', '\''])
+            .and_then(|(_, rest)| rest.rsplit_once(['"', '\'']))
+            .map(|(sql, _)| sql)
+    {
+        rules.extend(sql_rules(&normalize(sql)));
+    }
+    rules
+}
+
+/// Statement uppercase with single spaces
+fn normalize(statement: &str) -> String {
+    statement
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+/// Risky statements of the migration at `path`, `lines` being its added lines with their
+/// number; down migrations are expected to be destructive and aren't scanned
+pub fn scan(path: &Path, lines: &[(usize, &str)]) -> Vec<FormatFlag> {
+    let mut flags = Vec::new();
+    if is_down(path) {
+        return flags;
+    }
+    if is_rails(path) {
+        for (number, line) in lines {
+            for rule in rails_rules(line) {
+                flags.push(FormatFlag::new(&RULES, rule, *number, line.trim()));
+            }
+        }
+        return flags;
+    }
+    // Statements end with `;`, or where the added lines stop
+    let mut statement = String::new();
+    let mut start = 0;
+    let mut previous = 0;
+    let flush = |statement: &mut String, start: usize, flags: &mut Vec<FormatFlag>| {
+        let collapsed = statement.split_whitespace().collect::<Vec<_>>().join(" ");
+        for rule in sql_rules(&collapsed.to_uppercase()) {
+            flags.push(FormatFlag::new(&RULES, rule, start, &collapsed));
+        }
+        statement.clear();
+    };
+    for (number, line) in lines {
+        if *number != previous + 1 && !statement.trim().is_empty() {
+            flush(&mut statement, start, &mut flags);
+        }
+        previous = *number;
+        let line = line.split_once("--").map_or(*line, |(code, _)| code);
+        let mut parts = line.split(';').peekable();
+        while let Some(part) = parts.next() {
+            if statement.trim().is_empty() && !part.trim().is_empty() {
+                start = *number;
+            }
+            statement.push_str(part);
+            statement.push(' ');
+            if parts.peek().is_some() {
+                flush(&mut statement, start, &mut flags);
+            }
+        }
+    }
+    if !statement.trim().is_empty() {
+        flush(&mut statement, start, &mut flags);
+    }
+    flags
+}
+
+/// Flag of an up migration added without its down migration
+pub fn missing_down() -> FormatFlag {
+    FormatFlag::new(&RULES, "MIG-007", 0, "")
+}
+
+/// Whether `path` is a migration: an SQL file in a migrations directory, named after its
+/// version (ex. `V2__add_users.sql`, `20240101120000_add_users.sql`), or a Rails migration
+pub fn detect(path: &Path, _content: &str) -> bool {
+    if is_rails(path) {
+        return true;
+    }
+    if !is_sql(path) {
+        return false;
+    }
+    let in_migrations = path.parent().is_some_and(|dir| {
+        dir.components().any(|component| {
+            MIGRATION_DIRS.contains(&component.as_os_str().to_string_lossy().as_ref())
+        })
+    });
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let versioned = name
+        .strip_prefix('V')
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()) && rest.contains("__"))
+        || name.starts_with("R__")
+        || name.split_once(['_', '-']).is_some_and(|(version, _)| {
+            version.len() >= 3 && version.bytes().all(|b| b.is_ascii_digit())
+        });
+    in_migrations || versioned
+}
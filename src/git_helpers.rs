@@ -1,7 +1,306 @@
-use crate::config::{BranchAgainst, DiffProfile};
+use crate::compress::{CompressedSource, compress_source};
+use crate::config::{BranchAgainst, DiffProfile, SourceCompression};
+use crate::encoding::{self, Decoded};
+use crate::language::detect_language;
+use crate::markup;
+use crate::network;
 use git2::Object;
-use git2::{BranchType, Commit, DiffFormat, DiffOptions, Error, Oid, Repository, Tree};
-use std::{collections::BTreeSet, env, fs, path::Path, path::PathBuf, str};
+use git2::{
+    BranchType, DiffFormat, DiffOptions, Error, ErrorClass, ErrorCode, Oid, Repository,
+    RepositoryOpenFlags, Tree,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    path::Path,
+    path::PathBuf,
+    process::Command,
+    str,
+    sync::OnceLock,
+};
+
+/// Selection of paths used to scope a Git review down to a subset of the diff
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+    pub files: Vec<PathBuf>,
+    pub dirs: Vec<PathBuf>,
+    pub globs: Vec<glob::Pattern>,
+    /// Also match files inside subdirectories of `dirs`
+    pub recursive: bool,
+}
+
+impl PathFilter {
+    /// Build a filter from CLI inputs; paths are converted to be relative to the repository root
+    pub fn new(
+        files: Vec<PathBuf>,
+        dirs: Vec<PathBuf>,
+        globs: &[String],
+        recursive: bool,
+    ) -> Result<PathFilter, glob::PatternError> {
+        let globs = globs
+            .iter()
+            .map(|g| glob::Pattern::new(g))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(PathFilter {
+            files: files.iter().map(|f| repo_relative_path(f)).collect(),
+            dirs: dirs.iter().map(|d| repo_relative_path(d)).collect(),
+            globs,
+            recursive,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty() && self.globs.is_empty()
+    }
+
+    /// Check if a repository-relative path is selected by the filter
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let file_match = self.files.iter().any(|f| f == path);
+        let dir_match = self.dirs.iter().any(|d| {
+            if self.recursive {
+                path.starts_with(d)
+            } else {
+                path.parent() == Some(d.as_path())
+            }
+        });
+        let glob_match = self.globs.iter().any(|g| g.matches_path(path));
+
+        file_match || dir_match || glob_match
+    }
+}
+
+/// Repository of the run, set once by `-C`, `GIT_DIR` and `GIT_WORK_TREE`
+#[derive(Debug, Clone)]
+pub struct RepoLocation {
+    /// Directory the repository is searched from
+    pub start: PathBuf,
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>,
+}
+
+static REPO: OnceLock<RepoLocation> = OnceLock::new();
+
+/// Use the repository found from `dir` (the cwd when `None`) for the rest of the run. Like with
+/// `git -C`, relative `GIT_DIR` and `GIT_WORK_TREE` are relative to `dir`; every path is made
+/// absolute, so that the location holds once rv moves to the root of the working tree
+pub fn configure_repo(dir: Option<&Path>) -> std::io::Result<()> {
+    let cwd = env::current_dir()?;
+    let start = match dir {
+        Some(dir) => cwd.join(dir),
+        None => cwd,
+    };
+    if !start.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is not a directory", start.display()),
+        ));
+    }
+    let from_env = |var: &str| env::var_os(var).map(|value| start.join(value));
+    let _ = REPO.set(RepoLocation {
+        git_dir: from_env("GIT_DIR"),
+        work_tree: from_env("GIT_WORK_TREE"),
+        start,
+    });
+    Ok(())
+}
+
+/// Directory the repository of the run is searched from
+pub fn repo_start_dir() -> std::io::Result<PathBuf> {
+    match REPO.get() {
+        Some(location) => Ok(location.start.clone()),
+        None => env::current_dir(),
+    }
+}
+
+/// Open the repository rv runs in: the one set by `GIT_DIR`/`GIT_WORK_TREE`, otherwise the one
+/// containing the directory of `-C` (the cwd by default)
+pub fn open_repo() -> Result<Repository, Error> {
+    let Some(location) = REPO.get() else {
+        let repo = Repository::open_from_env()?;
+        if let Some(work_tree) = env::var_os("GIT_WORK_TREE") {
+            repo.set_workdir(Path::new(&work_tree), false)?;
+        }
+        return Ok(repo);
+    };
+    let repo = match &location.git_dir {
+        Some(git_dir) => Repository::open(git_dir)?,
+        None => Repository::open_ext(
+            &location.start,
+            RepositoryOpenFlags::empty(),
+            env::var_os("GIT_CEILING_DIRECTORIES")
+                .map(|dirs| env::split_paths(&dirs).collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )?,
+    };
+    if let Some(work_tree) = &location.work_tree {
+        repo.set_workdir(work_tree, false)?;
+    }
+    Ok(repo)
+}
+
+/// `program` (`git`, `gh`), run at the root of the working tree of the repository of the run
+pub fn repo_command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    if let Some(location) = REPO.get() {
+        let workdir = open_repo()
+            .ok()
+            .and_then(|repo| repo.workdir().map(Path::to_path_buf));
+        command.current_dir(workdir.unwrap_or_else(|| location.start.clone()));
+        if let Some(git_dir) = &location.git_dir {
+            command.env("GIT_DIR", git_dir);
+        }
+        if let Some(work_tree) = &location.work_tree {
+            command.env("GIT_WORK_TREE", work_tree);
+        }
+    }
+    command
+}
+
+/// Convert a user-provided path (relative to the directory of `-C`, the cwd by default) into a path
+/// relative to the repository root.
+/// Paths that can't be resolved are returned unchanged.
+pub fn repo_relative_path(path: &Path) -> PathBuf {
+    let Ok(repo) = open_repo() else {
+        return path.to_path_buf();
+    };
+    let Some(workdir) = repo.workdir().and_then(|w| w.canonicalize().ok()) else {
+        return path.to_path_buf();
+    };
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match repo_start_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => return path.to_path_buf(),
+        }
+    };
+    // Canonicalize when possible (the path could be deleted in the reviewed diff)
+    let absolute = absolute.canonicalize().unwrap_or(absolute);
+
+    match absolute.strip_prefix(&workdir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Move to the root of the working tree (the linked worktree, when run from one),
+/// so that the repository-relative paths of the diffs can be read from disk
+pub fn enter_workdir() -> Result<PathBuf, Error> {
+    let repo = open_repo()?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::from_str("Bare repository has no working directory"))?
+        .to_path_buf();
+    env::set_current_dir(&workdir).map_err(|e| Error::from_str(&e.to_string()))?;
+    Ok(workdir)
+}
+
+/// Content left out of a review, with the reason why
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Produce the deterministic "NOT REVIEWED" appendix of a report
+pub fn format_not_reviewed(skipped: &[SkippedFile]) -> String {
+    let mut sorted: Vec<&SkippedFile> = skipped.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path).then(a.reason.cmp(&b.reason)));
+    sorted.dedup_by(|a, b| a.path == b.path && a.reason == b.reason);
+
+    let mut appendix = String::from("NOT REVIEWED\n");
+    for skipped_file in sorted {
+        appendix.push_str(&format!(
+            "- {}: {}\n",
+            skipped_file.path.display(),
+            skipped_file.reason
+        ));
+    }
+    appendix
+}
+
+/// Size of a diff, used to measure how much of it reaches the prompt
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub files: usize,
+    pub hunks: usize,
+    /// Added and removed lines
+    pub lines: usize,
+}
+
+impl std::ops::AddAssign for DiffStats {
+    fn add_assign(&mut self, other: DiffStats) {
+        self.files += other.files;
+        self.hunks += other.hunks;
+        self.lines += other.lines;
+    }
+}
+
+/// Line of a hunk with its git origin: `' '` (context), `'+'`, `'-'`, or `'='`, `'>'`, `'<'` for
+/// the "No newline at end of file" markers
+#[derive(Clone, Debug)]
+pub struct DiffLine {
+    pub origin: char,
+    pub content: String,
+}
+
+impl DiffLine {
+    pub fn is_change(&self) -> bool {
+        matches!(self.origin, '+' | '-')
+    }
+}
+
+/// Hunk of a file patch, with the line ranges of its `@@ -old_start,old_lines +new_start,new_lines @@` header
+#[derive(Clone, Debug)]
+pub struct Hunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    /// Text following the line ranges in the header (ex. the enclosing function)
+    pub section: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Typed patch of a single file: its `diff --git` header lines followed by its hunks
+#[derive(Clone, Debug, Default)]
+pub struct FilePatch {
+    pub header: String,
+    pub hunks: Vec<Hunk>,
+    /// Kind of change of the file (ex. added, modified, deleted)
+    pub status: Option<git2::Delta>,
+    pub binary: bool,
+    /// Blob of the new version of the file, and its size in bytes (0 when git didn't load it)
+    pub new_oid: Option<Oid>,
+    pub new_size: u64,
+}
+
+impl FilePatch {
+    /// Render the patch as a unified diff
+    pub fn render(&self) -> String {
+        let mut text = self.header.clone();
+        for hunk in &self.hunks {
+            text.push_str(&format!(
+                "@@ -{},{} +{},{} @@{}",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines, hunk.section
+            ));
+            for line in &hunk.lines {
+                if matches!(line.origin, ' ' | '+' | '-') {
+                    text.push(line.origin);
+                }
+                text.push_str(&line.content);
+            }
+        }
+        text
+    }
+}
 
 /// Structure that allow to contain both the diff and the edited source file for commits or for staged edits
 #[derive(Clone, Debug)]
@@ -9,6 +308,16 @@ pub struct ExpandedCommit {
     //pub workdir: String,
     pub diffs: Option<Vec<String>>,
     pub sources: Option<Vec<PathBuf>>,
+    /// Content excluded from the review, reported in the NOT REVIEWED appendix
+    pub skipped: Vec<SkippedFile>,
+    /// The reviewed revision is not available locally, so only the diffs can be reported
+    pub diff_only: bool,
+    /// Size of the diff of every changed file, including the excluded ones
+    pub diff_stats: BTreeMap<PathBuf, DiffStats>,
+    /// Typed patches the diffs were rendered from, used to minimize them
+    pub patches: BTreeMap<PathBuf, FilePatch>,
+    /// Commits whose changes are reviewed, oldest first (none for staged or raw reviews)
+    pub commits: Vec<Oid>,
 }
 impl Default for ExpandedCommit {
     fn default() -> Self {
@@ -21,15 +330,78 @@ impl ExpandedCommit {
         ExpandedCommit {
             diffs: None,
             sources: None,
+            skipped: Vec::new(),
+            diff_only: false,
+            diff_stats: BTreeMap::new(),
+            patches: BTreeMap::new(),
+            commits: Vec::new(),
         }
     }
 
-    pub fn is_empty(self) -> bool {
-        // Sources must exist and have at least 1 element
-        if self.sources.is_some() && self.sources.unwrap().len() > 0 {
-            return false;
+    /// Keep only the diffs and sources selected by `filter`
+    pub fn retain_matching(self, filter: &PathFilter) -> ExpandedCommit {
+        self.retain(|path| filter.matches(path), None)
+    }
+
+    /// Drop the diffs and sources matched by `is_excluded`, reporting them as skipped
+    pub fn exclude(self, is_excluded: impl Fn(&Path) -> bool, reason: &str) -> ExpandedCommit {
+        self.retain(|path| !is_excluded(path), Some(reason))
+    }
+
+    /// Keep only the diffs and sources whose path satisfies `keep`.
+    /// When `skip_reason` is set, dropped paths are reported as skipped.
+    fn retain(mut self, keep: impl Fn(&Path) -> bool, skip_reason: Option<&str>) -> ExpandedCommit {
+        let Some(sources) = self.sources else {
+            return self;
+        };
+
+        let mut kept_diffs: Vec<String> = Vec::new();
+        let mut kept_sources: Vec<PathBuf> = Vec::new();
+        for (idx, source) in sources.into_iter().enumerate() {
+            if !keep(&source) {
+                match skip_reason {
+                    Some(reason) => self.skipped.push(SkippedFile {
+                        path: source,
+                        reason: reason.to_string(),
+                    }),
+                    // Paths left out of the scope of the review don't count against its coverage
+                    None => {
+                        self.diff_stats.remove(&source);
+                    }
+                }
+                continue;
+            }
+            if let Some(diff) = self.diffs.as_ref().and_then(|d| d.get(idx)) {
+                kept_diffs.push(diff.clone());
+            }
+            kept_sources.push(source);
+        }
+
+        ExpandedCommit {
+            diffs: self.diffs.map(|_| kept_diffs),
+            sources: if kept_sources.is_empty() {
+                None
+            } else {
+                Some(kept_sources)
+            },
+            skipped: self.skipped,
+            diff_only: self.diff_only,
+            diff_stats: self.diff_stats,
+            patches: self.patches,
+            commits: self.commits,
         }
-        true
+    }
+
+    /// Added and removed lines of the changed files, `None` without diffs (ex. raw reviews)
+    pub fn changed_lines(&self) -> Option<usize> {
+        (!self.diff_stats.is_empty()).then(|| self.diff_stats.values().map(|s| s.lines).sum())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // Sources must exist and have at least 1 element
+        self.sources
+            .as_ref()
+            .is_none_or(|sources| sources.is_empty())
     }
 
     /// Produce XML-like output useful for LLM prompting
@@ -41,44 +413,45 @@ impl ExpandedCommit {
         if diff_profile.report_diffs {
             let diffs = self.diffs.as_ref().ok_or("Diffs are missing").unwrap();
             for (diff_counter, diff_val) in diffs.iter().enumerate() {
-                // Open <diff NAME> tag
-                xml_string.push_str("<diff ");
-                let diff_source_path = sources[diff_counter].to_string_lossy();
-                xml_string.push_str(&diff_source_path);
-                xml_string.push_str(" >\n");
-
-                // Add diff
-                xml_string.push_str(diff_val);
-
-                // Close </diff> tag
-                xml_string.push_str("\n</diff>\n");
+                let diff_source_path = markup::path_text(&sources[diff_counter]);
+                xml_string.push_str(&markup::element(
+                    "diff",
+                    &[("path", &diff_source_path)],
+                    diff_val,
+                ));
             }
         }
-        if diff_profile.report_diffs && diff_profile.report_sources {
-            println!("\n\n---SPLIT----\n--Do not confuse text before and after the SPLIT--\n\n");
+        if diff_profile.report_diffs && diff_profile.report_sources && !self.diff_only {
+            xml_string.push_str(
+                "\n\n---SPLIT----\n--Do not confuse text before and after the SPLIT--\n\n",
+            );
         }
-        if diff_profile.report_sources {
+        if diff_profile.report_sources && !self.diff_only {
             for source_val in sources {
-                // Open <source NAME> tag
-                xml_string.push_str("<source ");
-                // [review] Ignore this line, .to_string_lossy is the correct choice
-                let source_path = source_val.to_string_lossy();
-                xml_string.push_str(&source_path);
-                xml_string.push_str(" >\n");
-
-                // Add source
-                match fs::read(source_val) {
-                    Ok(source_bytes) => {
-                        let source_text = String::from_utf8_lossy(&source_bytes);
-                        xml_string.push_str(&source_text);
+                let source_path = markup::path_text(source_val);
+                let compression = diff_profile.source_compression;
+                let source = match encoding::read_source(source_val) {
+                    Ok(Decoded::Text(source_text)) => {
+                        compress_source(&source_text, detect_language(source_val), compression)
                     }
-                    Err(err) => {
-                        xml_string.push_str(&format!("[source unavailable: {err}]"));
+                    Ok(Decoded::Binary) => CompressedSource {
+                        text: String::from("[binary file, content not included]"),
+                        ..Default::default()
+                    },
+                    Err(err) => CompressedSource {
+                        text: format!("[source unavailable: {err}]"),
+                        ..Default::default()
+                    },
+                };
+                let offsets = source.format_offsets();
+                let mut attributes = vec![("path", source_path.as_str())];
+                if compression != SourceCompression::None {
+                    attributes.push(("compression", compression.name()));
+                    if !offsets.is_empty() {
+                        attributes.push(("line_offsets", &offsets));
                     }
                 }
-
-                // Close </source> tag
-                xml_string.push_str("\n</source>\n");
+                xml_string.push_str(&markup::element("source", &attributes, &source.text));
             }
         }
 
@@ -88,15 +461,12 @@ impl ExpandedCommit {
 
 /// Get an ExpandedCommit representing staged edits
 pub fn staged_diffs(diff_profile: DiffProfile) -> Result<ExpandedCommit, git2::Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
+    // In a linked worktree, this is the index of the worktree
     let index = repo.index()?;
 
     // Set cwd to repository main directory
-    let workdir: &Path = repo
-        .workdir()
-        .ok_or("Bare repository has no working directory")
-        .unwrap();
-    env::set_current_dir(workdir).unwrap();
+    enter_workdir()?;
 
     // Try to get HEAD tree. If repo has no commits yet, treat HEAD tree as None.
     let head_tree = match repo.head() {
@@ -108,70 +478,17 @@ pub fn staged_diffs(diff_profile: DiffProfile) -> Result<ExpandedCommit, git2::E
     // Customize diff_opts if you want (context lines, pathspecs, etc.)
     let diff = repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), Some(&mut diff_opts))?;
 
-    // Use the same logic as diff_trees_to_expanded to collect patches and touched files
-    let mut patches: Vec<String> = Vec::new();
-    let mut current_patch = String::new();
-    let mut last_file: Option<PathBuf> = None;
-    let mut touched: BTreeSet<PathBuf> = BTreeSet::new();
-
-    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
-        // Determine the file path for this delta: prefer the new file path, else old file path
-        let maybe_path = delta
-            .new_file()
-            .path()
-            .or(delta.old_file().path())
-            .map(|p| p.to_path_buf());
-        // If the delta changed (a new file's patch started), flush the previous patch
-        if last_file.as_ref() != maybe_path.as_ref() {
-            if !current_patch.is_empty() {
-                patches.push(std::mem::take(&mut current_patch));
-            }
-            last_file = maybe_path.clone();
-        }
-
-        // Append the line content (may be binary; try to decode as UTF-8)
-        let content = line.content();
-        match std::str::from_utf8(content) {
-            Ok(s) => current_patch.push_str(s),
-            Err(_) => current_patch.push_str(&format!("<non-utf8 {} bytes>", content.len())),
-        }
-
-        if let Some(p) = maybe_path {
-            touched.insert(p);
-        }
-        // return true to continue processing
-        true
-    })?;
-
-    // push the last accumulated patch if any
-    if !current_patch.is_empty() {
-        patches.push(current_patch);
-    }
-
     // Filter out Cargo.lock from both patches and touched files
-    let mut filtered_patches = Vec::new();
-    let mut filtered_touched = BTreeSet::new();
-    for (patch, path) in patches.into_iter().zip(touched.into_iter()) {
-        if let Some(path_str) = path.to_str()
-            && path_str.contains("Cargo.lock")
-        {
-            continue;
-        }
-        filtered_patches.push(patch);
-        filtered_touched.insert(path);
-    }
+    let mut expcommit = diff_to_expanded(&diff)?.exclude(
+        |path| path.to_string_lossy().contains("Cargo.lock"),
+        "lockfile",
+    );
 
-    let mut expcommit = ExpandedCommit::new();
-    if diff_profile.report_diffs && !filtered_patches.is_empty() {
-        expcommit.diffs = Some(filtered_patches);
-    }
     // Keep the sources in order to allow ExpandedCommit::get_xml_structure to find the namefile of diffs
     // Don't worry, the report_sources variable will be considered in the get_xml_structure in order to allow source-less reports
-    expcommit.sources = if filtered_touched.is_empty() {
-        None
-    } else {
-        Some(filtered_touched.into_iter().collect())
-    };
+    if !diff_profile.report_diffs {
+        expcommit.diffs = None;
+    }
 
     Ok(expcommit)
 }
@@ -182,63 +499,116 @@ fn diff_trees_to_expanded(
     new_tree: Option<&Tree>,
 ) -> Result<ExpandedCommit, git2::Error> {
     let diff = repo.diff_tree_to_tree(old_tree, new_tree, None)?;
-    // Collect patches (one string per file) and touched files
-    let mut patches: Vec<String> = Vec::new();
-    let mut current_patch = String::new();
-    let mut last_file: Option<PathBuf> = None;
+    diff_to_expanded(&diff)
+}
+
+fn diff_to_expanded(diff: &git2::Diff) -> Result<ExpandedCommit, git2::Error> {
+    // Collect patches (one per file) and touched files
+    let mut patches: Vec<(PathBuf, FilePatch)> = Vec::new();
     let mut touched: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut binary: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut diff_stats: BTreeMap<PathBuf, DiffStats> = BTreeMap::new();
 
-    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+    diff.print(DiffFormat::Patch, |delta, hunk, line| {
         // Determine the file path for this delta: prefer the new file path, else old file path
-        let maybe_path = delta
+        let Some(path) = delta
             .new_file()
             .path()
             .or(delta.old_file().path())
-            .map(|p| p.to_path_buf());
-        // If the delta changed (a new file's patch started), flush the previous patch
-        if last_file.as_ref() != maybe_path.as_ref() {
-            if !current_patch.is_empty() {
-                patches.push(std::mem::take(&mut current_patch));
-            }
-            last_file = maybe_path.clone();
+            .map(|p| p.to_path_buf())
+        else {
+            return true;
+        };
+        // If the delta changed (a new file's patch started), start a new patch
+        if patches.last().is_none_or(|(last, _)| *last != path) {
+            let new_file = delta.new_file();
+            patches.push((
+                path.clone(),
+                FilePatch {
+                    status: Some(delta.status()),
+                    binary: delta.flags().is_binary(),
+                    new_oid: (!new_file.id().is_zero()).then(|| new_file.id()),
+                    new_size: new_file.size(),
+                    ..FilePatch::default()
+                },
+            ));
         }
+        let Some((_, patch)) = patches.last_mut() else {
+            return true;
+        };
 
-        // Append the line content (may be binary; try to decode as UTF-8)
-        let content = line.content();
-        match std::str::from_utf8(content) {
-            Ok(s) => current_patch.push_str(s),
-            Err(_) => current_patch.push_str(&format!("<non-utf8 {} bytes>", content.len())),
+        // Non UTF-8 lines are decoded as legacy 8-bit text
+        let content = encoding::decode_line(line.content());
+        match (line.origin(), hunk) {
+            ('H', Some(hunk)) => {
+                let header = encoding::decode_line(hunk.header());
+                // The section follows the second `@@` of the header
+                let section = header.splitn(3, "@@").nth(2).unwrap_or("\n").to_string();
+                patch.hunks.push(Hunk {
+                    old_start: hunk.old_start(),
+                    old_lines: hunk.old_lines(),
+                    new_start: hunk.new_start(),
+                    new_lines: hunk.new_lines(),
+                    section,
+                    lines: Vec::new(),
+                });
+            }
+            (origin @ (' ' | '+' | '-' | '=' | '>' | '<'), Some(_)) if !patch.hunks.is_empty() => {
+                if let Some(current) = patch.hunks.last_mut() {
+                    current.lines.push(DiffLine { origin, content });
+                }
+            }
+            // File headers and binary notices
+            _ => patch.header.push_str(&content),
         }
 
-        if let Some(p) = maybe_path {
-            touched.insert(p);
+        if delta.flags().is_binary() {
+            patch.binary = true;
+            binary.insert(path.clone());
+        }
+        let stats = diff_stats.entry(path.clone()).or_insert(DiffStats {
+            files: 1,
+            ..Default::default()
+        });
+        match line.origin() {
+            'H' => stats.hunks += 1,
+            '+' | '-' => stats.lines += 1,
+            _ => {}
         }
+        touched.insert(path);
         // return true to continue processing
         true
     })?;
 
-    // push the last accumulated patch if any
-    if !current_patch.is_empty() {
-        patches.push(current_patch);
-    }
-
-    Ok(ExpandedCommit {
-        diffs: if patches.is_empty() {
-            None
-        } else {
-            Some(patches)
-        },
+    let diffs: Vec<String> = patches.iter().map(|(_, patch)| patch.render()).collect();
+    let expcommit = ExpandedCommit {
+        diffs: if diffs.is_empty() { None } else { Some(diffs) },
         sources: if touched.is_empty() {
             None
         } else {
             Some(touched.into_iter().collect())
         },
-    })
+        skipped: Vec::new(),
+        diff_only: false,
+        diff_stats,
+        patches: patches.into_iter().collect(),
+        commits: Vec::new(),
+    };
+    // Binary files are reported as not reviewed, so they must not reach the prompt either
+    Ok(expcommit.exclude(|path| binary.contains(path), "binary content"))
+}
+
+/// Build an ExpandedCommit from a unified diff whose revisions are not available locally
+pub fn expanded_from_patch(patch: &[u8]) -> Result<ExpandedCommit, git2::Error> {
+    let diff = git2::Diff::from_buffer(patch)?;
+    let mut expanded = diff_to_expanded(&diff)?;
+    expanded.diff_only = true;
+    Ok(expanded)
 }
 
 /// Build an ExpandedCommit for a given commit OID.
 pub fn expanded_from_commit(oid: Oid) -> Result<ExpandedCommit, git2::Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
     let commit = repo.find_commit(oid)?;
     let new_tree = commit.tree().ok();
     // parent tree (if any)
@@ -249,67 +619,187 @@ pub fn expanded_from_commit(oid: Oid) -> Result<ExpandedCommit, git2::Error> {
     };
     let old_tree_ref = old_tree.as_ref();
     let new_tree_ref = new_tree.as_ref();
-    diff_trees_to_expanded(&repo, old_tree_ref, new_tree_ref)
+    let mut expcommit = diff_trees_to_expanded(&repo, old_tree_ref, new_tree_ref)?;
+    expcommit.commits = vec![oid];
+    Ok(expcommit)
 }
 
 /// Build an ExpandedCommit for HEAD (last commit on current branch).
 pub fn expanded_from_head() -> Result<ExpandedCommit, git2::Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
     let head_ref = repo.head()?;
     let head_commit = head_ref.peel_to_commit()?;
     expanded_from_commit(head_commit.id())
 }
 
-/// Compare the tip of `branch_name` against either the current HEAD or `main`/`master`.
-/// Returns the diff between `base` (the `against` target) and the branch tip:
-/// i.e., diff(base_tree, branch_tree) so the produced patches reflect changes from base -> branch.
-pub fn expanded_from_branch(
+/// Resolve a local or remote-tracking branch, a tag (annotated tags are peeled) or any revision to a commit
+fn resolve_branch_ref(repo: &Repository, name: &str) -> Result<Oid, Error> {
+    for branch_type in [BranchType::Local, BranchType::Remote] {
+        if let Ok(branch) = repo.find_branch(name, branch_type) {
+            return Ok(branch.into_reference().peel_to_commit()?.id());
+        }
+    }
+    Ok(repo.revparse_single(name)?.peel_to_commit()?.id())
+}
+
+/// Fetch `name` from `origin`, so that refs that only exist on the remote can be reviewed
+fn fetch_from_origin(name: &str) -> bool {
+    if network::is_disabled() {
+        return false;
+    }
+    let name = name.strip_prefix("origin/").unwrap_or(name);
+    // Names come from the command line and the target files, they must not be read as options
+    repo_command("git")
+        .args(["fetch", "origin", "--end-of-options", name])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Resolve the commit of the reference passed to `--branch`, fetching it from `origin` if it's missing
+pub fn resolve_branch(name: &str, auto_fetch: bool) -> Result<Oid, Error> {
+    let repo = open_repo()?;
+    let not_found = match resolve_branch_ref(&repo, name) {
+        Ok(oid) => return Ok(oid),
+        Err(e) => e,
+    };
+    if !auto_fetch {
+        eprintln!(
+            "[INFO] {name} not found locally; fetch it, or set `auto_fetch = true` to fetch missing refs from origin"
+        );
+        return Err(not_found);
+    }
+
+    eprintln!("[INFO] {name} not found locally, fetching origin/{name}");
+    if !fetch_from_origin(name) {
+        return Err(not_found);
+    }
+    // Branches get a remote-tracking ref, everything else is only available in FETCH_HEAD
+    resolve_branch_ref(&repo, name)
+        .or_else(|_| resolve_branch_ref(&repo, &format!("origin/{name}")))
+        .or_else(|_| resolve_branch_ref(&repo, "FETCH_HEAD"))
+}
+
+/// Find the main branch of the repository: the first of `base_branches` that exists
+/// (locally or on `origin`), falling back to the branch the remote HEAD points to
+pub fn find_base_branch(repo: &Repository, base_branches: &[String]) -> Result<Oid, Error> {
+    for name in base_branches {
+        if let Ok(branch) = repo.find_branch(name, BranchType::Local) {
+            return Ok(branch.into_reference().peel_to_commit()?.id());
+        }
+        if let Ok(branch) = repo.find_branch(&format!("origin/{name}"), BranchType::Remote) {
+            return Ok(branch.into_reference().peel_to_commit()?.id());
+        }
+    }
+
+    // `refs/remotes/origin/HEAD` is a symbolic ref to the default branch of the remote
+    if let Ok(remote_head) = repo.find_reference("refs/remotes/origin/HEAD")
+        && let Ok(resolved) = remote_head.resolve()
+    {
+        return Ok(resolved.peel_to_commit()?.id());
+    }
+
+    Err(Error::new(
+        ErrorCode::NotFound,
+        ErrorClass::Reference,
+        format!(
+            "Tried to compare against the main branch, but none of {base_branches:?} exist and the remote HEAD is not set; configure `base_branches` in config.toml"
+        ),
+    ))
+}
+
+/// Resolve the commit a branch gets compared against (the current HEAD or the main branch), and the tip of the branch
+pub fn branch_base_and_tip(
     branch_name: &str,
     against: BranchAgainst,
-) -> Result<ExpandedCommit, git2::Error> {
-    let search_repo = Repository::discover(".")?;
-    let repo = Repository::discover(".")?;
+    base_branches: &[String],
+    auto_fetch: bool,
+) -> Result<(Oid, Oid), git2::Error> {
+    let repo = open_repo()?;
     // Find branch commit
-    let branch = repo.find_branch(branch_name, BranchType::Local)?;
-    let branch_commit = branch.into_reference().peel_to_commit()?;
+    let branch_oid = resolve_branch(branch_name, auto_fetch)?;
 
     // Determine base commit to compare against
-    let base_commit: Option<Commit> = match against {
+    let base_oid = match against {
+        BranchAgainst::Ref(reference) => resolve_branch(&reference, auto_fetch)?,
         BranchAgainst::Current => {
             // If HEAD is unborn (no commits), repo.head() may fail; handle by returning an error
             let head_ref = repo.head()?;
-            Some(head_ref.peel_to_commit()?)
-        }
-        BranchAgainst::Main => {
-            if let Ok(branch) = search_repo.find_branch("main", BranchType::Local) {
-                let commit = branch.into_reference().peel_to_commit()?;
-                Some(commit)
-            } else if let Ok(branch) = search_repo.find_branch("master", BranchType::Local) {
-                let commit = branch.into_reference().peel_to_commit()?;
-                Some(commit)
-            } else {
-                panic!(
-                    "[ERR] Tried to compare against the main branch, but there are no branches named 'main' or 'master'."
-                );
-            }
+            head_ref.peel_to_commit()?.id()
         }
+        BranchAgainst::Main => find_base_branch(&repo, base_branches)?,
     };
 
-    // get trees (Option<&Tree>)
-    let new_tree = branch_commit.tree().ok();
-    let old_tree = base_commit.as_ref().and_then(|c| c.tree().ok());
+    Ok((base_oid, branch_oid))
+}
 
-    let old_tree_ref = old_tree.as_ref();
-    let new_tree_ref = new_tree.as_ref();
+/// Build the net diff of `head` against its merge-base with `base` (like `git diff base...head`).
+/// Files touched by the intermediate commits but without a net change are reported as skipped.
+pub fn expanded_net_diff(base_oid: Oid, head_oid: Oid) -> Result<ExpandedCommit, git2::Error> {
+    let repo = open_repo()?;
+    let merge_base = repo.merge_base(base_oid, head_oid)?;
 
-    diff_trees_to_expanded(&repo, old_tree_ref, new_tree_ref)
+    let base_tree = repo.find_commit(merge_base)?.tree()?;
+    let head_tree = repo.find_commit(head_oid)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), None)?;
+
+    // Drop the entries without added or removed lines, but keep the mode changes (ex. the exec bit)
+    let mut empty_patches: BTreeSet<PathBuf> = BTreeSet::new();
+    for idx in 0..diff.deltas().len() {
+        if let Some(patch) = git2::Patch::from_diff(&diff, idx)?
+            && let (_, 0, 0) = patch.line_stats()?
+            && !patch.delta().flags().is_binary()
+            && patch.delta().old_file().mode() == patch.delta().new_file().mode()
+            && let Some(path) = patch
+                .delta()
+                .new_file()
+                .path()
+                .or(patch.delta().old_file().path())
+        {
+            empty_patches.insert(path.to_path_buf());
+        }
+    }
+    let mut expcommit =
+        diff_to_expanded(&diff)?.exclude(|path| empty_patches.contains(path), "no net change");
+
+    // Report the churn: files edited within the series but restored by its end
+    let net_files: BTreeSet<PathBuf> = expcommit.sources.iter().flatten().cloned().collect();
+    let mut churned: BTreeSet<PathBuf> = BTreeSet::new();
+    expcommit.commits = commits_between(merge_base, head_oid)?;
+    for oid in expcommit.commits.clone() {
+        let commit_files = expanded_from_commit(oid)?.sources.unwrap_or_default();
+        churned.extend(commit_files.into_iter().filter(|p| !net_files.contains(p)));
+    }
+    for path in churned {
+        if !empty_patches.contains(&path) {
+            expcommit.skipped.push(SkippedFile {
+                path,
+                reason: String::from("changed within the series, but no net change"),
+            });
+        }
+    }
+
+    Ok(expcommit)
+}
+
+/// Build an ExpandedCommit with every change (committed, staged or not) made after the given commit
+pub fn expanded_since_commit(oid: Oid) -> Result<ExpandedCommit, git2::Error> {
+    let repo = open_repo()?;
+    let commit = repo.find_commit(oid)?;
+    let old_tree = commit.tree()?;
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&old_tree), None)?;
+    let mut expcommit = diff_to_expanded(&diff)?;
+    if let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) {
+        expcommit.commits = commits_between(oid, head.id())?;
+    }
+    Ok(expcommit)
 }
 
 pub fn expanded_between_commits(
     base_oid: Oid,
     head_oid: Oid,
 ) -> Result<ExpandedCommit, git2::Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
     let base_commit = repo.find_commit(base_oid)?;
     let head_commit = repo.find_commit(head_oid)?;
     let base_tree = base_commit.tree().ok();
@@ -318,11 +808,13 @@ pub fn expanded_between_commits(
     let base_tree_ref = base_tree.as_ref();
     let head_tree_ref = head_tree.as_ref();
 
-    diff_trees_to_expanded(&repo, base_tree_ref, head_tree_ref)
+    let mut expcommit = diff_trees_to_expanded(&repo, base_tree_ref, head_tree_ref)?;
+    expcommit.commits = commits_between(base_oid, head_oid)?;
+    Ok(expcommit)
 }
 
 pub fn get_oid(rev: &str) -> Result<Oid, Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
     // If the input parses as an Oid, try that first (fast path).
     if let Ok(oid) = Oid::from_str(rev) {
         if let Ok(obj) = repo.find_object(oid, None) {
@@ -344,7 +836,7 @@ pub fn get_oid(rev: &str) -> Result<Oid, Error> {
 
 /// Get the OID of the parent commit of a given commit
 pub fn get_parent_oid(commit_oid: Oid) -> Result<Oid, Error> {
-    let repo = Repository::discover(".")?;
+    let repo = open_repo()?;
     let commit = repo.find_commit(commit_oid)?;
     if commit.parent_count() > 0 {
         Ok(commit.parent(0)?.id())
@@ -352,3 +844,395 @@ pub fn get_parent_oid(commit_oid: Oid) -> Result<Oid, Error> {
         Err(Error::from_str("Commit has no parent"))
     }
 }
+
+/// Resolve a `base..head` range into the OIDs of its two ends
+pub fn resolve_range(range: &str) -> Result<(Oid, Oid), Error> {
+    let repo = open_repo()?;
+    let revspec = repo.revparse(range)?;
+    let (Some(from), Some(to)) = (revspec.from(), revspec.to()) else {
+        return Err(Error::from_str(
+            "Expected a range in the `base..head` format",
+        ));
+    };
+    Ok((from.peel_to_commit()?.id(), to.peel_to_commit()?.id()))
+}
+
+/// List the commits reachable from `head` but not from `base`, oldest first
+pub fn commits_between(base_oid: Oid, head_oid: Oid) -> Result<Vec<Oid>, Error> {
+    let repo = open_repo()?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push(head_oid)?;
+    revwalk.hide(base_oid)?;
+    revwalk.collect()
+}
+
+/// List the merge commits of the first-parent history of `tip` committed at or after `since`
+/// (seconds since the epoch), oldest first
+pub fn merges_since(tip: Oid, since: i64) -> Result<Vec<Oid>, Error> {
+    let repo = open_repo()?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TIME)?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(tip)?;
+
+    let mut merges = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.time().seconds() < since {
+            break;
+        }
+        if commit.parent_count() > 1 {
+            merges.push(commit.id());
+        }
+    }
+    merges.reverse();
+    Ok(merges)
+}
+
+/// Get the full message of a commit
+pub fn commit_message(oid: Oid) -> Result<String, Error> {
+    let repo = open_repo()?;
+    let commit = repo.find_commit(oid)?;
+    Ok(String::from_utf8_lossy(commit.message_bytes()).to_string())
+}
+
+/// Range of the commits of the current branch not pushed yet (`upstream..HEAD`).
+/// Branches without an upstream are compared against the main branch instead.
+pub fn unpushed_range(base_branches: &[String]) -> Result<String, Error> {
+    let repo = open_repo()?;
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(Error::from_str(
+            "HEAD is detached, there is no upstream branch to compare against",
+        ));
+    }
+    let branch = git2::Branch::wrap(head);
+    match branch.upstream() {
+        Ok(upstream) => {
+            let name = upstream.name()?.ok_or_else(|| {
+                Error::from_str("The name of the upstream branch is not valid UTF-8")
+            })?;
+            Ok(format!("{name}..HEAD"))
+        }
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            let branch_name = branch.name()?.unwrap_or("HEAD");
+            eprintln!("[INFO] {branch_name} has no upstream, comparing it against the main branch");
+            let base_oid = find_base_branch(&repo, base_branches)?;
+            Ok(format!("{base_oid}..HEAD"))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Merge-like operation in progress (ex. `merge`, `rebase`), if any
+pub fn operation_in_progress() -> Result<Option<&'static str>, Error> {
+    use git2::RepositoryState;
+    let repo = open_repo()?;
+    Ok(match repo.state() {
+        RepositoryState::Clean => None,
+        RepositoryState::Merge => Some("merge"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert"),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("cherry-pick"),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => Some("rebase"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => Some("am"),
+        RepositoryState::Bisect => Some("bisect"),
+    })
+}
+
+/// Conflicted file of the index, with the three sides of the conflict (stages 1, 2 and 3)
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub path: PathBuf,
+    /// Common ancestor (stage 1), missing when both sides added the file
+    pub base: Option<Decoded>,
+    /// Our side (stage 2), missing when we deleted the file
+    pub ours: Option<Decoded>,
+    /// Their side (stage 3), missing when they deleted the file
+    pub theirs: Option<Decoded>,
+    /// Working tree file, with the conflict markers
+    pub working: Option<Decoded>,
+}
+
+/// Read the conflicted files of the index
+pub fn conflicts() -> Result<Vec<Conflict>, Error> {
+    let repo = open_repo()?;
+    let index = repo.index()?;
+    let workdir = repo.workdir().map(Path::to_path_buf);
+
+    let read_blob = |entry: &Option<git2::IndexEntry>| -> Result<Option<Decoded>, Error> {
+        match entry {
+            Some(entry) => Ok(Some(encoding::decode_bytes(
+                repo.find_blob(entry.id)?.content(),
+            ))),
+            None => Ok(None),
+        }
+    };
+
+    let mut conflicts = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let Some(entry) = conflict
+            .our
+            .as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref())
+        else {
+            continue;
+        };
+        let path = PathBuf::from(String::from_utf8_lossy(&entry.path).to_string());
+        let working = workdir
+            .as_ref()
+            .and_then(|workdir| std::fs::read(workdir.join(&path)).ok())
+            .map(|content| encoding::decode_bytes(&content));
+        conflicts.push(Conflict {
+            base: read_blob(&conflict.ancestor)?,
+            ours: read_blob(&conflict.our)?,
+            theirs: read_blob(&conflict.their)?,
+            working,
+            path,
+        });
+    }
+    Ok(conflicts)
+}
+
+/// Unified diff between two versions of the file at `path`
+pub fn diff_texts(old: &str, new: &str, path: &Path) -> Result<String, Error> {
+    let mut patch =
+        git2::Patch::from_buffers(old.as_bytes(), Some(path), new.as_bytes(), Some(path), None)?;
+    let buf = patch.to_buf()?;
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CurrentDir, Offline, git, repo_with};
+
+    #[test]
+    fn reviews_the_staged_edits_of_a_linked_worktree() {
+        let main = repo_with(&[("lib.rs", "fn main() {}\n"), ("src/mod.rs", "")]);
+        let linked = tempfile::TempDir::new().unwrap();
+        let worktree = linked.path().join("feature");
+        git(
+            main.path(),
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "feature",
+                worktree.to_str().unwrap(),
+            ],
+        );
+        // Each checkout stages its own edit, in its own index
+        std::fs::write(main.path().join("lib.rs"), "fn main() { main_edit(); }\n").unwrap();
+        git(main.path(), &["add", "lib.rs"]);
+        std::fs::write(worktree.join("lib.rs"), "fn main() { feature(); }\n").unwrap();
+        git(&worktree, &["add", "lib.rs"]);
+        assert!(worktree.join(".git").is_file());
+
+        let _cwd = CurrentDir::enter(&worktree.join("src"));
+        let worktree = worktree.canonicalize().unwrap();
+        assert_eq!(enter_workdir().unwrap().canonicalize().unwrap(), worktree);
+        assert_eq!(
+            std::env::current_dir().unwrap().canonicalize().unwrap(),
+            worktree
+        );
+
+        let repo = Repository::discover(".").unwrap();
+        assert_eq!(repo.workdir().unwrap().canonicalize().unwrap(), worktree);
+        let staged_blob = repo
+            .index()
+            .unwrap()
+            .get_path(Path::new("lib.rs"), 0)
+            .unwrap()
+            .id;
+        assert_eq!(
+            repo.find_blob(staged_blob).unwrap().content(),
+            b"fn main() { feature(); }\n"
+        );
+
+        let staged = staged_diffs(DiffProfile::default()).unwrap();
+        assert_eq!(staged.sources, Some(vec![PathBuf::from("lib.rs")]));
+        let diffs = staged.diffs.clone().unwrap();
+        assert!(diffs[0].contains("fn main() { feature(); }"));
+        assert!(!diffs[0].contains("main_edit"));
+
+        let xml = staged.get_xml_structure(DiffProfile::default());
+        assert!(xml.contains("fn main() { feature(); }\n"));
+        assert!(!xml.contains("main_edit"));
+    }
+
+    #[test]
+    fn fetches_missing_refs_only_with_the_network() {
+        let origin = repo_with(&[("lib.rs", "fn main() {}\n")]);
+        git(origin.path(), &["branch", "feature"]);
+        let local = repo_with(&[]);
+        let origin_path = origin.path().to_string_lossy().to_string();
+        git(local.path(), &["remote", "add", "origin", &origin_path]);
+        let _cwd = CurrentDir::enter(local.path());
+
+        {
+            let _offline = Offline::enter();
+            assert!(!fetch_from_origin("feature"));
+            assert!(resolve_branch("feature", true).is_err());
+        }
+        let feature = git(origin.path(), &["rev-parse", "feature"]);
+        assert_eq!(
+            resolve_branch("feature", true).unwrap().to_string(),
+            feature.trim()
+        );
+
+        let marker = local.path().join("injected");
+        let option = format!("--upload-pack=touch {}", marker.display());
+        assert!(!fetch_from_origin(&option));
+        assert!(!marker.exists());
+    }
+
+    fn filter(files: &[&str], dirs: &[&str], globs: &[&str], recursive: bool) -> PathFilter {
+        PathFilter {
+            files: files.iter().map(PathBuf::from).collect(),
+            dirs: dirs.iter().map(PathBuf::from).collect(),
+            globs: globs
+                .iter()
+                .map(|g| glob::Pattern::new(g).unwrap())
+                .collect(),
+            recursive,
+        }
+    }
+
+    #[test]
+    fn selects_files_and_directories() {
+        assert!(filter(&[], &[], &[], false).matches(Path::new("any/file.rs")));
+
+        let files = filter(&["src/lib.rs"], &[], &[], false);
+        assert!(files.matches(Path::new("src/lib.rs")));
+        assert!(!files.matches(Path::new("src/lib.rs.orig")));
+        assert!(!files.matches(Path::new("lib.rs")));
+
+        let shallow = filter(&[], &["src"], &[], false);
+        assert!(shallow.matches(Path::new("src/main.rs")));
+        assert!(!shallow.matches(Path::new("src/llm/mod.rs")));
+        // Directories match whole components, not name prefixes
+        assert!(!shallow.matches(Path::new("srcs/main.rs")));
+
+        let recursive = filter(&[], &["src"], &[], true);
+        assert!(recursive.matches(Path::new("src/llm/mod.rs")));
+        assert!(!recursive.matches(Path::new("srcs/llm/mod.rs")));
+    }
+
+    #[test]
+    fn selects_globs_and_the_union_of_the_criteria() {
+        let globs = filter(&[], &[], &["src/**/*.rs", "docs/?.md", "[ab].toml"], false);
+        assert!(globs.matches(Path::new("src/llm/openai.rs")));
+        assert!(globs.matches(Path::new("docs/a.md")));
+        assert!(!globs.matches(Path::new("docs/ab.md")));
+        assert!(globs.matches(Path::new("b.toml")));
+        assert!(!globs.matches(Path::new("c.toml")));
+
+        let union = filter(&["README.md"], &["tests"], &["*.toml"], false);
+        assert!(union.matches(Path::new("README.md")));
+        assert!(union.matches(Path::new("tests/cli.rs")));
+        assert!(union.matches(Path::new("Cargo.toml")));
+        assert!(!union.matches(Path::new("src/main.rs")));
+
+        assert!(PathFilter::new(Vec::new(), Vec::new(), &[String::from("[")], false).is_err());
+    }
+
+    #[test]
+    fn keeps_the_diffs_aligned_with_the_retained_sources() {
+        let mut expcommit = ExpandedCommit::new();
+        expcommit.sources = Some(vec![
+            PathBuf::from("src/lib.rs"),
+            PathBuf::from("docs/intro.md"),
+            PathBuf::from("src/main.rs"),
+        ]);
+        expcommit.diffs = Some(vec![
+            String::from("lib diff"),
+            String::from("intro diff"),
+            String::from("main diff"),
+        ]);
+        for source in expcommit.sources.clone().unwrap() {
+            expcommit.diff_stats.insert(source, DiffStats::default());
+        }
+
+        let retained = expcommit
+            .clone()
+            .retain_matching(&filter(&[], &["src"], &[], false));
+        assert_eq!(
+            retained.sources.unwrap(),
+            [PathBuf::from("src/lib.rs"), PathBuf::from("src/main.rs")]
+        );
+        assert_eq!(retained.diffs.unwrap(), ["lib diff", "main diff"]);
+        // Paths out of scope are neither reported as skipped nor counted in the coverage
+        assert!(retained.skipped.is_empty());
+        assert!(!retained.diff_stats.contains_key(Path::new("docs/intro.md")));
+
+        let nothing = expcommit.retain_matching(&filter(&["missing.rs"], &[], &[], false));
+        assert!(nothing.sources.is_none());
+        assert!(nothing.is_empty());
+    }
+
+    #[test]
+    fn leaves_the_binary_files_out_of_the_prompt() {
+        let repo = repo_with(&[("lib.rs", "fn main() {}\n")]);
+        std::fs::write(repo.path().join("lib.rs"), "fn main() { run(); }\n").unwrap();
+        std::fs::write(repo.path().join("logo.png"), b"\x89PNG\r\n\x00\x00\x01").unwrap();
+        git(repo.path(), &["add", "-A"]);
+        git(repo.path(), &["commit", "-q", "-m", "logo"]);
+        let _cwd = CurrentDir::enter(repo.path());
+
+        let head = open_repo().unwrap().head().unwrap().target().unwrap();
+        let expcommit = expanded_from_commit(head).unwrap();
+        assert_eq!(
+            expcommit.sources.as_deref(),
+            Some(&[PathBuf::from("lib.rs")][..])
+        );
+        assert_eq!(expcommit.diffs.as_ref().map(Vec::len), Some(1));
+        assert!(!expcommit.diffs.as_ref().unwrap()[0].contains("logo.png"));
+        assert_eq!(expcommit.skipped.len(), 1);
+        assert_eq!(expcommit.skipped[0].path, Path::new("logo.png"));
+        assert_eq!(expcommit.skipped[0].reason, "binary content");
+        // The binary file still counts against the coverage of the review
+        assert!(expcommit.diff_stats.contains_key(Path::new("logo.png")));
+    }
+
+    #[test]
+    fn keeps_the_mode_changes_in_the_net_diff() {
+        let repo = repo_with(&[("run.sh", "echo run\n"), ("lib.rs", "fn main() {}\n")]);
+        let base = git(repo.path(), &["rev-parse", "HEAD"]);
+        git(repo.path(), &["update-index", "--chmod=+x", "run.sh"]);
+        git(repo.path(), &["commit", "-q", "-m", "exec"]);
+        // Edited then restored within the series
+        for (content, message) in [
+            ("fn main() { run(); }\n", "edit"),
+            ("fn main() {}\n", "revert"),
+        ] {
+            std::fs::write(repo.path().join("lib.rs"), content).unwrap();
+            git(repo.path(), &["add", "lib.rs"]);
+            git(repo.path(), &["commit", "-q", "-m", message]);
+        }
+        let head = git(repo.path(), &["rev-parse", "HEAD"]);
+        let _cwd = CurrentDir::enter(repo.path());
+
+        let expcommit = expanded_net_diff(
+            Oid::from_str(base.trim()).unwrap(),
+            Oid::from_str(head.trim()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            expcommit.sources.as_deref(),
+            Some(&[PathBuf::from("run.sh")][..])
+        );
+        assert!(expcommit.diffs.as_ref().unwrap()[0].contains("new mode 100755"));
+        assert!(
+            expcommit
+                .skipped
+                .iter()
+                .all(|skipped| skipped.path != Path::new("run.sh"))
+        );
+    }
+}
@@ -1,9 +1,36 @@
+//! Pull request reviews through the GitHub CLI (`gh`)
+
 use crate::git_helpers::{self, ExpandedCommit};
+use crate::network;
 use anyhow::{Context, Result, bail};
 use git2::Oid;
 use serde::Deserialize;
-use serde_json;
-use std::process::Command;
+
+/// Namespace of the temporary refs holding the heads of the reviewed pull requests
+pub const PR_REFS_NAMESPACE: &str = "refs/rv/tmp/pr";
+
+/// Delete the temporary pull request refs, unless `keep` is set
+pub fn cleanup_pr_refs(keep: bool) {
+    if keep {
+        return;
+    }
+    let Ok(repo) = git_helpers::open_repo() else {
+        return;
+    };
+    let Ok(references) = repo.references_glob(&format!("{PR_REFS_NAMESPACE}/*")) else {
+        return;
+    };
+
+    let names: Vec<String> = references
+        .flatten()
+        .filter_map(|reference| reference.name().map(str::to_string))
+        .collect();
+    for name in names {
+        if let Err(e) = repo.find_reference(&name).and_then(|mut r| r.delete()) {
+            eprintln!("[WARN] Failed to delete {name}: {e}");
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct PrViewMetadata {
@@ -17,6 +44,40 @@ struct PrViewMetadata {
 }
 
 pub fn expanded_commit_from_pr(pr: &str) -> Result<ExpandedCommit> {
+    network::ensure_allowed("The pull request review")?;
+    let (base_oid, head_oid) = match pr_base_and_head(pr) {
+        Ok(oids) => oids,
+        Err(e) => {
+            // Fetching fails when the remote requires credentials git doesn't have, but `gh` may
+            eprintln!("[WARN] {e:#}");
+            eprintln!("[WARN] Falling back to the diff provided by the GitHub API");
+            return expanded_commit_from_pr_diff(pr);
+        }
+    };
+
+    git_helpers::expanded_between_commits(base_oid, head_oid)
+        .context("Failed to compute diff between PR base and head commits")
+}
+
+/// Build the diff of a pull request with `gh pr diff`, without fetching its commits
+fn expanded_commit_from_pr_diff(pr: &str) -> Result<ExpandedCommit> {
+    let output = git_helpers::repo_command("gh")
+        .args(["pr", "diff", pr, "--color", "never"])
+        .output()
+        .context("Failed to invoke `gh pr diff`")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("`gh pr diff` failed: {stderr}");
+    }
+
+    git_helpers::expanded_from_patch(&output.stdout)
+        .context("Unable to parse the diff returned by `gh pr diff`")
+}
+
+/// Resolve the base and head commits of a pull request, fetching them if needed
+pub fn pr_base_and_head(pr: &str) -> Result<(Oid, Oid)> {
+    network::ensure_allowed("The pull request review")?;
     ensure_gh_available()?;
     let metadata = fetch_pr_metadata(pr)?;
 
@@ -28,12 +89,11 @@ pub fn expanded_commit_from_pr(pr: &str) -> Result<ExpandedCommit> {
     let head_oid = Oid::from_str(metadata.head_ref_oid.trim())
         .context("Invalid head commit SHA returned by gh")?;
 
-    git_helpers::expanded_between_commits(base_oid, head_oid)
-        .context("Failed to compute diff between PR base and head commits")
+    Ok((base_oid, head_oid))
 }
 
 fn ensure_gh_available() -> Result<()> {
-    let status = Command::new("gh")
+    let status = git_helpers::repo_command("gh")
         .arg("--version")
         .status()
         .context("Failed to invoke `gh --version`")?;
@@ -46,7 +106,7 @@ fn ensure_gh_available() -> Result<()> {
 }
 
 fn fetch_pr_metadata(pr: &str) -> Result<PrViewMetadata> {
-    let output = Command::new("gh")
+    let output = git_helpers::repo_command("gh")
         .args([
             "pr",
             "view",
@@ -71,9 +131,10 @@ fn ensure_base_available(reference: &str, sha: &str) -> Result<()> {
         return Ok(());
     }
 
-    let status = Command::new("git")
+    let status = git_helpers::repo_command("git")
         .arg("fetch")
         .arg("origin")
+        .arg("--end-of-options")
         .arg(reference)
         .status()
         .context("Failed to invoke `git fetch` for PR base reference")?;
@@ -94,10 +155,11 @@ fn ensure_pr_head_available(pr_number: u64, sha: &str) -> Result<()> {
         return Ok(());
     }
 
-    let refspec = format!("pull/{pr_number}/head:refs/rv/pr/{pr_number}");
-    let status = Command::new("git")
+    let refspec = format!("pull/{pr_number}/head:{PR_REFS_NAMESPACE}/{pr_number}");
+    let status = git_helpers::repo_command("git")
         .arg("fetch")
         .arg("origin")
+        .arg("--end-of-options")
         .arg(&refspec)
         .status()
         .context("Failed to invoke `git fetch` for PR head reference")?;
@@ -114,7 +176,7 @@ fn ensure_pr_head_available(pr_number: u64, sha: &str) -> Result<()> {
 }
 
 fn commit_exists_locally(sha: &str) -> bool {
-    Command::new("git")
+    git_helpers::repo_command("git")
         .args(["cat-file", "-e", &format!("{sha}^{{commit}}")])
         .status()
         .map(|status| status.success())
@@ -0,0 +1,344 @@
+use crate::config::{LLMConfig, RvConfig};
+use crate::findings::finding_items;
+use crate::git_helpers::{self, SkippedFile};
+use crate::paths;
+use crate::stats::civil_from_days;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Estimated token usage of a review, only recorded when `usage_stats` or `show_usage` is enabled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Usage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cost_usd: Option<f64>,
+}
+
+impl Usage {
+    /// Usage of nothing sent, costing nothing (ex. a cached review)
+    pub const ZERO: Usage = Usage {
+        input_tokens: 0,
+        output_tokens: 0,
+        cost_usd: Some(0.0),
+    };
+
+    /// Estimated usage of requests of `input_tokens` to `llm_config`, answered with `output_tokens`
+    pub fn estimate(llm_config: &LLMConfig, input_tokens: usize, output_tokens: usize) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            cost_usd: llm_config.estimate_cost(input_tokens, output_tokens),
+        }
+    }
+
+    /// Print the usage of a review by `model`, as `--show-usage` does
+    pub fn print(&self, model: &str) {
+        let cost = match self.cost_usd {
+            Some(cost) => format!("~${cost:.4}"),
+            None => format!("cost unknown, add the prices of `{model}` to `[prices]`"),
+        };
+        eprintln!(
+            "[USAGE] ~{} input tokens, ~{} output tokens, {cost} ({model})",
+            self.input_tokens, self.output_tokens
+        );
+    }
+}
+
+/// The cost is unknown as soon as the price of one of the requests is
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd = self.cost_usd.zip(other.cost_usd).map(|(a, b)| a + b);
+    }
+}
+
+/// Single review stored in the history
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewRecord {
+    pub id: String,
+    /// Seconds since UNIX epoch
+    pub timestamp: u64,
+    /// Working directory of the reviewed repository, if any
+    pub repo: Option<String>,
+    /// Commit the review was based on, if any
+    pub commit: Option<String>,
+    /// Human readable description of what was reviewed (ex. `commit abc123`)
+    pub target: String,
+    pub llm_config: String,
+    pub model_id: String,
+    pub files: Vec<String>,
+    /// Content left out of the review
+    #[serde(default)]
+    pub not_reviewed: Vec<SkippedFile>,
+    pub response: String,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    /// Tags of the run (`--tag` and the `tags` of the configuration)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Added and removed lines of the reviewed change, if it was a diff
+    #[serde(default)]
+    pub changed_lines: Option<usize>,
+}
+
+/// Directory of the review history, in the state directory
+pub const HISTORY_DIR: &str = "history";
+
+pub fn history_dir() -> io::Result<PathBuf> {
+    let mut dir = paths::dir(paths::Dir::State)?;
+    dir.push(HISTORY_DIR);
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Last review ID handed out by this process, in milliseconds
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Generate a short, sortable review ID: the current time in milliseconds, moved past the last ID
+/// of the process so that reviews saved back to back (ex. batch runs) don't share one
+fn new_review_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let previous = LAST_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(millis.max(last + 1))
+        })
+        .unwrap_or_default();
+    format!("{:x}", millis.max(previous + 1))
+}
+
+/// Whether `id` can be a review ID, and so a file name of the history
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+impl ReviewRecord {
+    pub fn new(
+        target: String,
+        commit: Option<String>,
+        llm_config: String,
+        model_id: String,
+        files: Vec<String>,
+        not_reviewed: Vec<SkippedFile>,
+        response: String,
+    ) -> ReviewRecord {
+        let repo = git_helpers::open_repo()
+            .ok()
+            .and_then(|r| r.workdir().map(|w| w.display().to_string()));
+
+        ReviewRecord {
+            id: new_review_id(),
+            timestamp: now_timestamp(),
+            repo,
+            commit,
+            target,
+            llm_config,
+            model_id,
+            files,
+            not_reviewed,
+            response,
+            usage: None,
+            tags: Vec::new(),
+            changed_lines: None,
+        }
+    }
+
+    /// Keep `usage` when `usage_stats` or `show_usage` is enabled, printing it with `show_usage`
+    pub fn set_usage(&mut self, rvconfig: &RvConfig, usage: Usage) {
+        if rvconfig.show_usage {
+            usage.print(&self.model_id);
+        }
+        if rvconfig.usage_stats || rvconfig.show_usage {
+            self.usage = Some(usage);
+        }
+    }
+
+    /// Store the review in the history; it gets a new ID when another process took its own
+    pub fn save(&mut self) -> Result<PathBuf> {
+        loop {
+            let path = record_path(&self.id)?;
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    self.id = new_review_id();
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            return Ok(path);
+        }
+    }
+}
+
+/// Keep the usage of a command other than a review (ex. `rv narrate`) in the history, so that
+/// `rv stats` counts it; nothing is stored unless `usage_stats` or `show_usage` is enabled
+pub fn save_command_usage(
+    rvconfig: &RvConfig,
+    target: String,
+    llm_config_key: &str,
+    llm_config: &LLMConfig,
+    files: Vec<String>,
+    response: String,
+    usage: Usage,
+) {
+    let mut record = ReviewRecord::new(
+        target,
+        None,
+        llm_config_key.to_string(),
+        llm_config.model_id.clone(),
+        files,
+        Vec::new(),
+        response,
+    );
+    record.tags = rvconfig.tags.clone();
+    record.set_usage(rvconfig, usage);
+    if record.usage.is_some()
+        && let Err(e) = record.save()
+    {
+        eprintln!("[WARN] Failed to save the usage to history: {e}");
+    }
+}
+
+/// Path of the history file of the review `id`
+pub fn record_path(id: &str) -> io::Result<PathBuf> {
+    let mut path = history_dir()?;
+    path.push(format!("{id}.json"));
+    Ok(path)
+}
+
+pub fn load_record(id: &str) -> Result<ReviewRecord> {
+    // The ID names a file of the history directory, it must not reach outside of it
+    if !is_valid_id(id) {
+        bail!("'{id}' is not a review ID");
+    }
+    let path = record_path(id)?;
+
+    if !path.exists() {
+        bail!("No review with ID '{id}' found in the history");
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read review history file {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse review history file {}", path.display()))
+}
+
+/// Load every stored review, oldest first; unreadable entries are skipped
+pub fn load_all_records() -> Result<Vec<ReviewRecord>> {
+    let mut records: Vec<ReviewRecord> = Vec::new();
+
+    for entry in fs::read_dir(history_dir()?)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json")
+            && let Ok(content) = fs::read_to_string(&path)
+            && let Ok(record) = serde_json::from_str::<ReviewRecord>(&content)
+        {
+            records.push(record);
+        }
+    }
+
+    records.sort_by_key(|r| r.timestamp);
+    Ok(records)
+}
+
+/// Date and time of a timestamp as `YYYY-MM-DD HH:MM`, in UTC
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / 86_400) as i64);
+    let minutes = timestamp % 86_400 / 60;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Print the last `limit` reviews of the history, newest first; with `tags`, only the reviews
+/// carrying all of them
+pub fn list_records(tags: &[String], limit: usize) -> Result<()> {
+    let records: Vec<ReviewRecord> = load_all_records()?
+        .into_iter()
+        .rev()
+        .filter(|record| tags.iter().all(|tag| record.tags.contains(tag)))
+        .take(limit)
+        .collect();
+    if records.is_empty() {
+        if tags.is_empty() {
+            println!("No reviews in the history yet.");
+        } else {
+            println!("No reviews tagged {} in the history.", tags.join(", "));
+        }
+        return Ok(());
+    }
+
+    for record in &records {
+        let tags = if record.tags.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", record.tags.join(", "))
+        };
+        println!(
+            "{}  {}  {:>3} findings  {}{tags}",
+            record.id,
+            format_timestamp(record.timestamp),
+            finding_items(&record.response).len(),
+            record.target
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_out_distinct_ids_back_to_back() {
+        let mut ids: Vec<String> = (0..100).map(|_| new_review_id()).collect();
+        assert!(ids.iter().all(|id| is_valid_id(id)));
+        ids.dedup();
+        assert_eq!(ids.len(), 100);
+    }
+
+    #[test]
+    fn refuses_ids_reaching_outside_of_the_history() {
+        for id in ["../x", "a/b", "", "..", "/etc/passwd"] {
+            assert!(load_record(id).is_err(), "{id}");
+        }
+    }
+
+    #[test]
+    fn sums_the_usage_of_several_requests() {
+        let mut usage = Usage::ZERO;
+        usage += Usage {
+            input_tokens: 10,
+            output_tokens: 2,
+            cost_usd: Some(0.5),
+        };
+        assert_eq!(usage.input_tokens, 10);
+        assert_eq!(usage.cost_usd, Some(0.5));
+        usage += Usage {
+            input_tokens: 5,
+            output_tokens: 1,
+            cost_usd: None,
+        };
+        assert_eq!((usage.input_tokens, usage.output_tokens), (15, 3));
+        assert_eq!(usage.cost_usd, None);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::context::resolve_project_file;
+use std::path::Path;
+
+/// Name of the project file listing paths that should never be reviewed
+pub const RVIGNORE_FILE: &str = ".rvignore";
+
+/// Like in `.gitignore`, wildcards in anchored patterns don't cross directories
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Single gitignore-like rule
+#[derive(Clone, Debug)]
+struct IgnoreRule {
+    pattern: glob::Pattern,
+    /// Rule only applies to directories (pattern ending with `/`)
+    dir_only: bool,
+    /// Rule matches against the whole relative path instead of the file name
+    anchored: bool,
+    /// Rule re-includes previously ignored paths (pattern starting with `!`)
+    negated: bool,
+}
+
+/// Set of gitignore-like rules, used by raw mode defaults and `.rvignore` files.
+/// Later rules take precedence over earlier ones, like in `.gitignore`.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> IgnoreRules {
+        IgnoreRules { rules: Vec::new() }
+    }
+
+    /// Add a single pattern; invalid patterns are reported and ignored
+    pub fn add_pattern(&mut self, line: &str) {
+        let mut pattern = line.trim();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return;
+        }
+
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        match glob::Pattern::new(pattern) {
+            Ok(pattern) => self.rules.push(IgnoreRule {
+                pattern,
+                dir_only,
+                anchored,
+                negated,
+            }),
+            Err(e) => eprintln!("[WARN] Invalid ignore pattern '{line}': {e}"),
+        }
+    }
+
+    pub fn add_patterns<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>) {
+        for line in lines {
+            self.add_pattern(line);
+        }
+    }
+
+    /// Load the rules of the project's `.rvignore` file, if any
+    pub fn from_rvignore() -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        rules.add_rvignore();
+        rules
+    }
+
+    pub fn add_rvignore(&mut self) {
+        if let Some(path) = resolve_project_file(RVIGNORE_FILE)
+            && let Ok(content) = std::fs::read_to_string(path)
+        {
+            self.add_patterns(content.lines());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check if `path` (relative to the reviewed root) is ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let matched = if rule.anchored {
+                rule.pattern.matches_path_with(path, MATCH_OPTIONS)
+            } else {
+                rule.pattern.matches(&file_name)
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Check if `path` or any of its parent directories is ignored, used for diffs where only file paths are known
+    pub fn is_path_ignored(&self, path: &Path) -> bool {
+        let parent_ignored = path
+            .ancestors()
+            .skip(1)
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|dir| self.is_ignored(dir, true));
+        parent_ignored || self.is_ignored(path, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(patterns: &str) -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        rules.add_patterns(patterns.lines());
+        rules
+    }
+
+    #[test]
+    fn matches_unanchored_patterns_on_the_file_name() {
+        let rules = rules("# generated\n*.min.js\n\nsecrets.env");
+        assert!(rules.is_ignored(Path::new("app.min.js"), false));
+        assert!(rules.is_ignored(Path::new("web/static/app.min.js"), false));
+        assert!(rules.is_ignored(Path::new("config/secrets.env"), false));
+        assert!(!rules.is_ignored(Path::new("app.js"), false));
+    }
+
+    #[test]
+    fn matches_anchored_patterns_on_the_whole_path() {
+        let rules = rules("/schema.sql\ndocs/*.md\nassets/**/*.png");
+        assert!(rules.is_ignored(Path::new("schema.sql"), false));
+        assert!(!rules.is_ignored(Path::new("db/schema.sql"), false));
+        assert!(rules.is_ignored(Path::new("docs/intro.md"), false));
+        assert!(!rules.is_ignored(Path::new("docs/api/intro.md"), false));
+        assert!(rules.is_ignored(Path::new("assets/icons/dark/logo.png"), false));
+    }
+
+    #[test]
+    fn matches_directory_patterns_only_on_directories() {
+        let rules = rules("generated/");
+        assert!(rules.is_ignored(Path::new("src/generated"), true));
+        assert!(!rules.is_ignored(Path::new("src/generated"), false));
+        assert!(rules.is_path_ignored(Path::new("src/generated/parser.rs")));
+        assert!(!rules.is_path_ignored(Path::new("src/generated.rs")));
+    }
+
+    #[test]
+    fn lets_later_negations_include_paths_back() {
+        let negated = rules("*.json\n!package.json\nfixtures/\n!fixtures/keep.json");
+        assert!(negated.is_ignored(Path::new("data.json"), false));
+        assert!(!negated.is_ignored(Path::new("package.json"), false));
+        // Like git, a file can't be included back when its directory is excluded
+        assert!(negated.is_path_ignored(Path::new("fixtures/keep.json")));
+
+        let reversed = rules("!package.json\n*.json");
+        assert!(reversed.is_ignored(Path::new("package.json"), false));
+    }
+
+    #[test]
+    fn keeps_the_source_trees_by_default() {
+        let mut rules = IgnoreRules::new();
+        rules.add_patterns(
+            crate::config::RawConfig::default()
+                .exclude
+                .iter()
+                .map(String::as_str),
+        );
+        for dir in ["vendor", "build", "out"] {
+            assert!(!rules.is_path_ignored(&Path::new(dir).join("main.go")));
+        }
+        assert!(rules.is_path_ignored(Path::new("node_modules/left-pad/index.js")));
+        assert!(rules.is_path_ignored(Path::new("target/debug/build.rs")));
+    }
+}
@@ -0,0 +1,164 @@
+//! Sibling files referenced by the imports of a single file, sent as read-only context with
+//! raw single-file reviews (scripts, gists) so that the LLM sees the helpers they rely on
+
+use crate::language::Language;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of imported files added to a review
+pub const MAX_IMPORTED_FILES: usize = 5;
+
+/// First quoted string of `text` (single, double or back quotes)
+fn quoted(text: &str) -> Option<&str> {
+    let start = text.find(['"', '\'', '`'])?;
+    let quote = text[start..].chars().next()?;
+    let rest = &text[start + 1..];
+    let end = rest.find(quote)?;
+    Some(&rest[..end])
+}
+
+/// Suffixes tried to resolve a JavaScript or TypeScript module to a file
+const JS_SUFFIXES: &[&str] = &[
+    "",
+    ".js",
+    ".ts",
+    ".mjs",
+    ".jsx",
+    ".tsx",
+    "/index.js",
+    "/index.ts",
+];
+
+/// Modules imported by a line of `language`, as the paths (relative to the importing file) each
+/// one can be stored at. Only local imports are resolved: packages can't be mapped to files.
+fn imported_paths(language: Language, line: &str) -> Vec<Vec<PathBuf>> {
+    let line = line.trim();
+    match language {
+        Language::Python => python_imports(line),
+        Language::JavaScript | Language::TypeScript => {
+            let is_import = line.starts_with("import ")
+                || line.starts_with("export ")
+                || line.contains("require(")
+                || line.contains("import(");
+            let Some(module) =
+                is_import.then(|| line.rsplit_once(" from ").map_or(line, |(_, m)| m))
+            else {
+                return Vec::new();
+            };
+            quoted(module)
+                .filter(|module| module.starts_with("./") || module.starts_with("../"))
+                .map(|module| vec![candidates(module, JS_SUFFIXES)])
+                .unwrap_or_default()
+        }
+        Language::Shell => {
+            let Some(rest) = line
+                .strip_prefix("source ")
+                .or_else(|| line.strip_prefix(". "))
+            else {
+                return Vec::new();
+            };
+            let file = quoted(rest).unwrap_or_else(|| rest.split_whitespace().next().unwrap_or(""));
+            // Variables (ex. `$DIR/lib.sh`) can't be resolved without running the script
+            if file.is_empty() || file.contains('$') {
+                return Vec::new();
+            }
+            vec![vec![PathBuf::from(file)]]
+        }
+        Language::Ruby => line
+            .strip_prefix("require_relative")
+            .and_then(quoted)
+            .map(|module| vec![candidates(module, &["", ".rb"])])
+            .unwrap_or_default(),
+        Language::C | Language::Cpp => line
+            .strip_prefix("#include")
+            .map(str::trim_start)
+            .filter(|rest| rest.starts_with('"'))
+            .and_then(quoted)
+            .map(|header| vec![vec![PathBuf::from(header)]])
+            .unwrap_or_default(),
+        Language::Php => {
+            let is_include = ["require", "include"]
+                .iter()
+                .any(|keyword| line.starts_with(keyword));
+            is_include
+                .then(|| quoted(line))
+                .flatten()
+                .map(|file| vec![vec![PathBuf::from(file)]])
+                .unwrap_or_default()
+        }
+        Language::Rust => {
+            let line = line.strip_prefix("pub ").unwrap_or(line);
+            line.strip_prefix("mod ")
+                .and_then(|rest| rest.strip_suffix(';'))
+                .map(|module| vec![candidates(module.trim(), &[".rs", "/mod.rs"])])
+                .unwrap_or_default()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// `import a.b`, `from .a import b` and `from a import b`
+fn python_imports(line: &str) -> Vec<Vec<PathBuf>> {
+    let modules: Vec<&str> = if let Some(rest) = line.strip_prefix("from ") {
+        rest.split_whitespace().next().into_iter().collect()
+    } else if let Some(rest) = line.strip_prefix("import ") {
+        rest.split(',')
+            .filter_map(|module| module.split_whitespace().next())
+            .collect()
+    } else {
+        return Vec::new();
+    };
+
+    let mut paths = Vec::new();
+    for module in modules {
+        // Every leading dot after the first one goes up a directory
+        let dots = module.chars().take_while(|c| *c == '.').count();
+        let mut path = PathBuf::new();
+        for _ in 1..dots {
+            path.push("..");
+        }
+        let name = &module[dots..];
+        if name.is_empty() {
+            continue;
+        }
+        path.push(name.replace('.', "/"));
+        paths.push(candidates(
+            &path.to_string_lossy(),
+            &[".py", "/__init__.py"],
+        ));
+    }
+    paths
+}
+
+/// Paths a module can be stored at, trying every suffix in order
+fn candidates(module: &str, suffixes: &[&str]) -> Vec<PathBuf> {
+    suffixes
+        .iter()
+        .map(|suffix| PathBuf::from(format!("{module}{suffix}")))
+        .collect()
+}
+
+/// Existing files imported by `content` (the content of `path`), resolved next to `path`
+pub fn referenced_siblings(path: &Path, language: Language, content: &str) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut siblings: Vec<PathBuf> = Vec::new();
+    for module in content
+        .lines()
+        .flat_map(|line| imported_paths(language, line))
+    {
+        // The first candidate that exists is the imported file
+        let Some(found) = module
+            .into_iter()
+            .map(|candidate| dir.join(candidate.strip_prefix(".").unwrap_or(&candidate)))
+            .find(|candidate| candidate.is_file())
+        else {
+            continue;
+        };
+        if found != path && !siblings.contains(&found) {
+            siblings.push(found);
+        }
+        if siblings.len() == MAX_IMPORTED_FILES {
+            break;
+        }
+    }
+    siblings
+}
@@ -0,0 +1,359 @@
+//! Embedding index of the repository (`rv index`), and the retrieval of the code chunks most
+//! related to a diff, so that reviews of big repositories see the code their changes interact with
+
+use crate::collect::FileCollector;
+use crate::config::{EmbeddingBackend, IndexConfig, RvConfig};
+use crate::context::{estimate_tokens, resolve_project_file};
+use crate::encoding::{self, Decoded};
+use crate::git_helpers;
+use crate::ignore::IgnoreRules;
+use crate::llm::embeddings::embed_texts;
+use crate::markup;
+use crate::policy::Policy;
+use crate::redact::redact_secrets;
+use crate::review::select_llm_config;
+use crate::term_helpers;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Index file, relative to the root of the working tree
+pub const INDEX_FILE: &str = ".rv/index.json";
+
+/// Dimensions of the local embeddings
+const LOCAL_DIMENSIONS: usize = 512;
+
+/// Estimated tokens of the diff used as the retrieval query; embedding models have small inputs
+const MAX_QUERY_TOKENS: usize = 6_000;
+
+/// Indexed chunk of a file; its text is read back from the working tree when retrieved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    pub path: PathBuf,
+    /// First and last line of the chunk, 1-based
+    pub start_line: usize,
+    pub end_line: usize,
+    pub hash: String,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoIndex {
+    /// Embeddings the vectors come from (`local`, or `api:[model]`); queries must use the same
+    pub embeddings: String,
+    pub chunk_lines: usize,
+    pub chunks: Vec<IndexedChunk>,
+}
+
+/// Chunk of a file retrieved for a review
+#[derive(Debug, Clone)]
+pub struct RelatedChunk {
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+}
+
+fn embeddings_id(config: &IndexConfig) -> String {
+    match config.embeddings {
+        EmbeddingBackend::Local => String::from("local"),
+        EmbeddingBackend::Api => format!("api:{}", config.model),
+    }
+}
+
+/// FNV-1a hash, stable across Rust versions unlike the standard hasher
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Hash of a chunk, to reuse its vector while it doesn't change
+fn content_hash(text: &str) -> String {
+    format!("{:016x}", fnv1a(text))
+}
+
+/// Words of an identifier, split on `snake_case` and `camelCase` boundaries
+fn identifier_words(identifier: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in identifier.chars() {
+        if (c == '_' || (c.is_uppercase() && previous_lower)) && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        if c != '_' {
+            current.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Local embedding: the identifiers of `text` and their words, hashed into a normalized vector
+pub fn local_embedding(text: &str) -> Vec<f32> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for identifier in text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|identifier| identifier.chars().count() >= 3)
+    {
+        *counts.entry(identifier.to_lowercase()).or_default() += 1;
+        let words = identifier_words(identifier);
+        if words.len() > 1 {
+            for word in words.into_iter().filter(|word| word.len() >= 3) {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+
+    let mut vector = vec![0.0f32; LOCAL_DIMENSIONS];
+    for (term, count) in counts {
+        let bucket = fnv1a(&term);
+        let sign = if bucket >> 63 == 0 { 1.0 } else { -1.0 };
+        // Sublinear term frequency, so that repeated identifiers don't dominate
+        vector[(bucket % LOCAL_DIMENSIONS as u64) as usize] += sign * (1.0 + (count as f32).ln());
+    }
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Embed `texts` with the configured backend
+async fn embed(
+    rvconfig: &RvConfig,
+    policy: Option<&Policy>,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>> {
+    match rvconfig.index.embeddings {
+        EmbeddingBackend::Local => Ok(texts.iter().map(|text| local_embedding(text)).collect()),
+        EmbeddingBackend::Api => {
+            let (_, llm_configuration) =
+                select_llm_config(rvconfig, rvconfig.index.llm_config.clone(), policy)?;
+            let texts = if rvconfig.redact_secrets {
+                texts.iter().map(|text| redact_secrets(text)).collect()
+            } else {
+                texts
+            };
+            embed_texts(&llm_configuration, &rvconfig.index.model, texts).await
+        }
+    }
+}
+
+/// Load the index of the repository, if it was built
+pub fn load_index() -> Option<RepoIndex> {
+    let path = resolve_project_file(INDEX_FILE)?;
+    let content = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            eprintln!("[WARN] Failed to parse {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Build or update the index of the repository; unchanged chunks keep their vectors
+pub async fn build_index(rvconfig: RvConfig, assume_yes: bool) -> Result<()> {
+    let policy = Policy::load()?;
+    let mut rvconfig = rvconfig;
+    if let Some(policy) = &policy {
+        policy.enforce_config(&mut rvconfig);
+    }
+    let chunk_lines = rvconfig.index.chunk_lines.max(1);
+    let embeddings = embeddings_id(&rvconfig.index);
+
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let mut ignore = IgnoreRules::new();
+    ignore.add_patterns(rvconfig.raw.exclude.iter().map(String::as_str));
+    ignore.add_rvignore();
+    let root = Path::new(".");
+    let mut collector = FileCollector::new(root, true, rvconfig.raw.clone(), ignore)?;
+    collector
+        .collect(root)
+        .context("Failed to collect the files of the repository")?;
+
+    // Vectors of the previous index, reused for the chunks that didn't change
+    let previous: HashMap<(PathBuf, String), Vec<f32>> = load_index()
+        .filter(|index| index.embeddings == embeddings && index.chunk_lines == chunk_lines)
+        .map(|index| {
+            index
+                .chunks
+                .into_iter()
+                .map(|chunk| ((chunk.path, chunk.hash), chunk.vector))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut chunks: Vec<IndexedChunk> = Vec::new();
+    let mut pending: Vec<(usize, String)> = Vec::new();
+    for file in &collector.files {
+        let Ok(Decoded::Text(content)) = encoding::read_source(file) else {
+            continue;
+        };
+        let path = file.strip_prefix(root).unwrap_or(file).to_path_buf();
+        let lines: Vec<&str> = content.lines().collect();
+        for (idx, chunk) in lines.chunks(chunk_lines).enumerate() {
+            let text = chunk.join("\n");
+            if text.trim().is_empty() {
+                continue;
+            }
+            let hash = content_hash(&text);
+            let vector = previous
+                .get(&(path.clone(), hash.clone()))
+                .cloned()
+                .unwrap_or_default();
+            if vector.is_empty() {
+                // The path gives the embedding a hint of what the chunk is about
+                pending.push((chunks.len(), format!("{}\n{text}", path.display())));
+            }
+            chunks.push(IndexedChunk {
+                path: path.clone(),
+                start_line: idx * chunk_lines + 1,
+                end_line: idx * chunk_lines + chunk.len(),
+                hash,
+                vector,
+            });
+        }
+    }
+
+    // The API backend sends the code of the whole repository, so it's confirmed first
+    if rvconfig.index.embeddings == EmbeddingBackend::Api
+        && !pending.is_empty()
+        && !assume_yes
+        && std::env::var_os("CI").is_none()
+    {
+        let tokens: usize = pending.iter().map(|(_, text)| estimate_tokens(text)).sum();
+        eprintln!(
+            "About to send {} chunks (~{tokens} tokens) to the {} embedding model",
+            pending.len(),
+            rvconfig.index.model
+        );
+        if !term_helpers::confirm("Send these requests?")? {
+            eprintln!("Indexing cancelled, nothing was sent.");
+            return Ok(());
+        }
+    }
+
+    let embedded = pending.len();
+    let (positions, texts): (Vec<usize>, Vec<String>) = pending.into_iter().unzip();
+    let vectors = embed(&rvconfig, policy.as_ref(), texts)
+        .await
+        .context("Failed to embed the chunks of the repository")?;
+    for (position, vector) in positions.into_iter().zip(vectors) {
+        chunks[position].vector = vector;
+    }
+
+    let index = RepoIndex {
+        embeddings,
+        chunk_lines,
+        chunks,
+    };
+    std::fs::create_dir_all(".rv")?;
+    std::fs::write(INDEX_FILE, serde_json::to_string(&index)?)
+        .with_context(|| format!("Failed to write {INDEX_FILE}"))?;
+    println!(
+        "[INDEX] {} chunks from {} files ({embedded} embedded, {} unchanged), saved to {INDEX_FILE}",
+        index.chunks.len(),
+        collector.files.len(),
+        index.chunks.len() - embedded
+    );
+    Ok(())
+}
+
+/// Retrieve the `top_k` chunks of the index closest to `query` (ex. the diffs of a review),
+/// leaving out the files already in the review
+pub async fn retrieve(
+    rvconfig: &RvConfig,
+    policy: Option<&Policy>,
+    query: &str,
+    reviewed: &[String],
+) -> Result<Vec<RelatedChunk>> {
+    if rvconfig.index.top_k == 0 || policy.is_some_and(|policy| policy.forbid_sources) {
+        return Ok(Vec::new());
+    }
+    let Some(index) = load_index() else {
+        return Ok(Vec::new());
+    };
+    if index.embeddings != embeddings_id(&rvconfig.index) {
+        eprintln!(
+            "[WARN] {INDEX_FILE} was built with other embeddings ({}), run `rv index` to rebuild it",
+            index.embeddings
+        );
+        return Ok(Vec::new());
+    }
+
+    // ~4 characters per token, like `estimate_tokens`
+    let query: String = query.chars().take(MAX_QUERY_TOKENS * 4).collect();
+    let Some(query_vector) = embed(rvconfig, policy, vec![query]).await?.pop() else {
+        return Ok(Vec::new());
+    };
+
+    let mut scored: Vec<(f32, &IndexedChunk)> = index
+        .chunks
+        .iter()
+        .filter(|chunk| !reviewed.iter().any(|path| Path::new(path) == chunk.path))
+        .map(|chunk| (cosine(&query_vector, &chunk.vector), chunk))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut related = Vec::new();
+    for (_, chunk) in scored.into_iter().take(rvconfig.index.top_k) {
+        // The working tree may have changed since the index was built, the current lines are sent
+        let Some(full_path) = resolve_project_file(&chunk.path.to_string_lossy()) else {
+            continue;
+        };
+        let Ok(Decoded::Text(content)) = encoding::read_source(&full_path) else {
+            continue;
+        };
+        let text: Vec<&str> = content
+            .lines()
+            .skip(chunk.start_line - 1)
+            .take(chunk.end_line + 1 - chunk.start_line)
+            .collect();
+        if text.is_empty() {
+            continue;
+        }
+        related.push(RelatedChunk {
+            path: chunk.path.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            text: text.join("\n"),
+        });
+    }
+    Ok(related)
+}
+
+/// Related chunks as read-only context elements of the system prompt
+pub fn format_related(related: &[RelatedChunk]) -> String {
+    related
+        .iter()
+        .map(|chunk| {
+            markup::element(
+                "related_code",
+                &[
+                    ("path", &markup::path_text(&chunk.path)),
+                    ("lines", &format!("{}-{}", chunk.start_line, chunk.end_line)),
+                ],
+                &chunk.text,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,167 @@
+//! `--inline`: the reviewed diff is printed again with the findings interleaved under the lines
+//! they are about, like the comments of an inline code review
+
+use crate::findings::{FindingItem, finding_anchors, finding_items};
+use crate::git_helpers::{FilePatch, Hunk};
+use crate::report::{Report, Reporter};
+
+use anyhow::Result;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Instructions added to the system prompt, so that every finding can be placed in the diff
+pub const INLINE_ANCHORS_PROMPT: &str = r#"
+LINE ANCHORS
+- Every finding title must name the file and the line of the NEW version
+  of the file it is about, as `path:line` (ex. `src/a.rs:12`).
+"#;
+
+/// Prefix of the comment lines, set apart from the diff lines
+const COMMENT_PREFIX: &str = "    >> ";
+
+/// Finding placed in the diff
+struct Placement {
+    /// Index of the hunk, and of the line of the hunk the comment follows; the comment goes
+    /// after the hunks of the file without a hunk
+    position: Option<(usize, usize)>,
+    finding: usize,
+}
+
+/// Patch of `file`, as named by a finding (ex. `a.rs` for `src/a.rs`)
+fn find_patch<'a>(patches: &'a BTreeMap<PathBuf, FilePatch>, file: &str) -> Option<&'a PathBuf> {
+    patches.keys().find(|path| {
+        let path = path.to_string_lossy();
+        path == file || path.ends_with(&format!("/{file}"))
+    })
+}
+
+/// Line of `hunk` after which a comment about the new line `line` goes: the line itself, or
+/// the last line before it (ex. a removed line)
+fn line_in_hunk(hunk: &Hunk, line: u32) -> Option<usize> {
+    if line < hunk.new_start || line >= hunk.new_start + hunk.new_lines.max(1) {
+        return None;
+    }
+    let mut new_line = hunk.new_start;
+    let mut position = 0;
+    for (idx, diff_line) in hunk.lines.iter().enumerate() {
+        if new_line > line {
+            break;
+        }
+        position = idx;
+        if matches!(diff_line.origin, ' ' | '+') {
+            new_line += 1;
+        }
+    }
+    Some(position)
+}
+
+/// Comment lines of a finding
+fn comment(review_lines: &[&str], item: &FindingItem) -> String {
+    review_lines[item.lines.clone()]
+        .iter()
+        .map(|line| format!("{COMMENT_PREFIX}{}\n", line.trim_end()))
+        .collect()
+}
+
+/// The diff of `patches` with the findings of `review` under the lines they are about; the
+/// findings that can't be placed are listed at the end
+pub fn format_inline(review: &str, patches: &BTreeMap<PathBuf, FilePatch>) -> String {
+    let items = finding_items(review);
+    let review_lines: Vec<&str> = review.lines().collect();
+
+    let mut placements: BTreeMap<&Path, Vec<Placement>> = BTreeMap::new();
+    let mut unplaced: Vec<usize> = Vec::new();
+    for (idx, item) in items.iter().enumerate() {
+        let placed = finding_anchors(review, item)
+            .into_iter()
+            .find_map(|(file, line)| {
+                let path = find_patch(patches, &file)?;
+                let position = line.and_then(|line| {
+                    patches[path]
+                        .hunks
+                        .iter()
+                        .enumerate()
+                        .find_map(|(h, hunk)| Some((h, line_in_hunk(hunk, line)?)))
+                });
+                Some((path.as_path(), position))
+            });
+        match placed {
+            Some((path, position)) => placements.entry(path).or_default().push(Placement {
+                position,
+                finding: idx,
+            }),
+            None => unplaced.push(idx),
+        }
+    }
+
+    let mut text = format!(
+        "INLINE REVIEW ({} of {} findings placed in the diff)\n",
+        items.len() - unplaced.len(),
+        items.len()
+    );
+    for (path, patch) in patches {
+        if patch.hunks.is_empty() {
+            continue;
+        }
+        let file_placements = placements.get(path.as_path());
+        let at = |position: Option<(usize, usize)>| -> String {
+            file_placements
+                .into_iter()
+                .flatten()
+                .filter(|placement| placement.position == position)
+                .map(|placement| comment(&review_lines, &items[placement.finding]))
+                .collect()
+        };
+
+        text.push_str(&format!("\n=== {}\n", path.display()));
+        for (h, hunk) in patch.hunks.iter().enumerate() {
+            text.push_str(&format!(
+                "@@ -{},{} +{},{} @@{}",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines, hunk.section
+            ));
+            if !hunk.section.ends_with('\n') {
+                text.push('\n');
+            }
+            for (l, line) in hunk.lines.iter().enumerate() {
+                if matches!(line.origin, ' ' | '+' | '-') {
+                    text.push(line.origin);
+                    text.push_str(&line.content);
+                    if !line.content.ends_with('\n') {
+                        text.push('\n');
+                    }
+                }
+                text.push_str(&at(Some((h, l))));
+            }
+        }
+        text.push_str(&at(None));
+    }
+
+    if !unplaced.is_empty() {
+        text.push_str("\n=== (not tied to a changed line)\n");
+        for idx in unplaced {
+            text.push_str(&comment(&review_lines, &items[idx]));
+        }
+    }
+    text
+}
+
+/// Print the diff of every report with its findings inline
+pub struct InlineReporter;
+
+impl Reporter for InlineReporter {
+    fn name(&self) -> &'static str {
+        "inline review"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if !report.patches.is_empty() {
+                println!("\n\n{}", format_inline(&report.review, &report.patches));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,188 @@
+//! Project knowledge base: durable facts about the project (ex. "module X owns auth") distilled
+//! from the reviews into `.rv/knowledge.md`, and sent with every later review
+
+use crate::config::{LLMConfig, RvConfig};
+use crate::context::{estimate_tokens, read_file, resolve_project_file};
+use crate::git_helpers;
+use crate::history::{self, Usage};
+use crate::llm::defs::LLMProvider;
+use crate::markup;
+use crate::request::RequestPipeline;
+use crate::sanitize::sanitize_output;
+use crate::term_helpers;
+use anyhow::{Context, Result};
+use rig::message::Message;
+use std::path::PathBuf;
+
+pub const KNOWLEDGE_FILE: &str = ".rv/knowledge.md";
+
+/// Facts at least this similar (normalized Levenshtein) to a known one are duplicates
+const SAME_FACT_SIMILARITY: f64 = 0.85;
+
+const DISTILL_SYSTEM_PROMPT: &str = r#"
+You maintain the knowledge base of a software project, used as context for
+future code reviews. You will receive the facts already known and a code
+review. Extract the DURABLE facts the review teaches about the project:
+ownership and responsibilities of modules, architectural decisions, project
+conventions and rules (ex. "don't use unwrap in request handlers").
+
+RULES
+- Only facts that stay true after the reviewed change is merged; never
+  findings about the reviewed code itself, line numbers or temporary state.
+- Skip facts already known, even when worded differently.
+- One fact per line, starting with "- ", at most 20 words each.
+- At most 5 facts. If there is nothing durable to learn, reply NONE.
+- ASCII only, no other text.
+"#;
+
+const KNOWLEDGE_HEADER: &str = "# Project knowledge\n\nFacts learned from the reviews of rv, sent with every review. Edit freely.\n\n";
+
+/// Facts of a knowledge text: its `- ` bullet lines
+fn parse_facts(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix("- "))
+        .map(|fact| fact.trim().to_string())
+        .filter(|fact| !fact.is_empty())
+        .collect()
+}
+
+/// Comparison key of a fact: lowercase words, without punctuation
+fn fact_key(fact: &str) -> String {
+    fact.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Facts of `candidates` that aren't already in `known`, nor repeated among themselves
+fn new_facts(known: &[String], candidates: Vec<String>) -> Vec<String> {
+    let mut keys: Vec<String> = known.iter().map(|fact| fact_key(fact)).collect();
+    let mut facts = Vec::new();
+    for fact in candidates {
+        let key = fact_key(&fact);
+        if key.is_empty()
+            || keys
+                .iter()
+                .any(|known| strsim::normalized_levenshtein(known, &key) >= SAME_FACT_SIMILARITY)
+        {
+            continue;
+        }
+        keys.push(key);
+        facts.push(fact);
+    }
+    facts
+}
+
+/// Known facts of the project
+pub fn load_facts() -> Vec<String> {
+    read_file(KNOWLEDGE_FILE)
+        .map(|text| parse_facts(&text))
+        .unwrap_or_default()
+}
+
+/// Knowledge base as a prompt element, empty when there are no facts
+pub fn format_knowledge() -> String {
+    let facts = load_facts();
+    if facts.is_empty() {
+        return String::new();
+    }
+    let content: String = facts.iter().map(|fact| format!("- {fact}\n")).collect();
+    markup::element("project_knowledge", &[("path", KNOWLEDGE_FILE)], &content)
+}
+
+/// Path of the knowledge base, at the root of the working tree
+fn knowledge_path() -> PathBuf {
+    if let Some(path) = resolve_project_file(KNOWLEDGE_FILE) {
+        return path;
+    }
+    match git_helpers::open_repo()
+        .ok()
+        .and_then(|repo| repo.workdir().map(|workdir| workdir.join(KNOWLEDGE_FILE)))
+    {
+        Some(path) => path,
+        None => PathBuf::from(KNOWLEDGE_FILE),
+    }
+}
+
+/// Append `facts` to the knowledge base, creating it if needed; manual edits are kept
+fn append_facts(facts: &[String]) -> Result<PathBuf> {
+    let path = knowledge_path();
+    let mut text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            String::from(KNOWLEDGE_HEADER)
+        }
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    for fact in facts {
+        text.push_str(&format!("- {fact}\n"));
+    }
+    std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Distill the durable facts of `review` and, once confirmed, add the new ones to the knowledge
+/// base. The review is built from untrusted diffs, so the facts always need a human to confirm
+/// them, even with `--yes`; without a terminal, nothing is learned
+pub async fn learn_from_review(
+    rvconfig: &RvConfig,
+    llm_configuration_key: &str,
+    llm_config: &LLMConfig,
+    client: &dyn LLMProvider,
+    target: &str,
+    review: &str,
+) -> Result<()> {
+    if !term_helpers::is_interactive() {
+        eprintln!("[INFO] The knowledge base is only updated after confirmation, in a terminal");
+        return Ok(());
+    }
+    let pipeline = RequestPipeline::new(rvconfig.clone())?;
+    let known = load_facts();
+    let known_text: String = known.iter().map(|fact| format!("- {fact}\n")).collect();
+    let mut prompt = pipeline.redact(
+        markup::element("known_facts", &[], &known_text) + &markup::element("review", &[], review),
+    );
+    let system_prompt = pipeline.system_prompt(DISTILL_SYSTEM_PROMPT.to_string());
+    // The known facts and the review may carry injected instructions
+    pipeline.prepare(llm_config, &system_prompt, std::iter::once(&mut prompt))?;
+
+    let input_tokens = estimate_tokens(&system_prompt) + estimate_tokens(&prompt);
+    let reply = client
+        .request(system_prompt, vec![Message::user(prompt)])
+        .await?;
+    let usage = Usage::estimate(llm_config, input_tokens, estimate_tokens(&reply));
+    history::save_command_usage(
+        &pipeline.rvconfig,
+        format!("knowledge of {target}"),
+        llm_configuration_key,
+        llm_config,
+        Vec::new(),
+        reply.clone(),
+        usage,
+    );
+
+    let facts = new_facts(&known, parse_facts(&sanitize_output(&reply, false)));
+    if facts.is_empty() {
+        return Ok(());
+    }
+    println!("\n[KNOWLEDGE] Durable facts learned from this review:");
+    for fact in &facts {
+        println!("  - {fact}");
+    }
+    if !term_helpers::confirm(&format!("Add them to {KNOWLEDGE_FILE}?"))? {
+        return Ok(());
+    }
+    let path = append_facts(&facts)?;
+    println!(
+        "[KNOWLEDGE] {} facts added to {}",
+        facts.len(),
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Programming language of a source file, used to group and contextualize reviews
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Go,
+    C,
+    Cpp,
+    CSharp,
+    Java,
+    Kotlin,
+    Swift,
+    Ruby,
+    Php,
+    Shell,
+    Sql,
+    Html,
+    Css,
+    Markdown,
+    Toml,
+    Yaml,
+    Json,
+    Dockerfile,
+    Other,
+}
+
+impl Language {
+    /// Every language, in the order of the enum
+    pub const ALL: [Language; 23] = [
+        Language::Rust,
+        Language::Python,
+        Language::JavaScript,
+        Language::TypeScript,
+        Language::Go,
+        Language::C,
+        Language::Cpp,
+        Language::CSharp,
+        Language::Java,
+        Language::Kotlin,
+        Language::Swift,
+        Language::Ruby,
+        Language::Php,
+        Language::Shell,
+        Language::Sql,
+        Language::Html,
+        Language::Css,
+        Language::Markdown,
+        Language::Toml,
+        Language::Yaml,
+        Language::Json,
+        Language::Dockerfile,
+        Language::Other,
+    ];
+
+    /// Parse a language from its identifier, name or usual extension (ex. `rust`, `C++`, `py`)
+    pub fn parse(value: &str) -> Option<Language> {
+        let value = value.trim();
+        Language::ALL
+            .into_iter()
+            .find(|language| {
+                language.id().eq_ignore_ascii_case(value)
+                    || language.name().eq_ignore_ascii_case(value)
+            })
+            .or_else(|| language_from_extension(value))
+    }
+
+    /// Human readable name, used in prompts and headers
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Python => "Python",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::Go => "Go",
+            Language::C => "C",
+            Language::Cpp => "C++",
+            Language::CSharp => "C#",
+            Language::Java => "Java",
+            Language::Kotlin => "Kotlin",
+            Language::Swift => "Swift",
+            Language::Ruby => "Ruby",
+            Language::Php => "PHP",
+            Language::Shell => "Shell",
+            Language::Sql => "SQL",
+            Language::Html => "HTML",
+            Language::Css => "CSS",
+            Language::Markdown => "Markdown",
+            Language::Toml => "TOML",
+            Language::Yaml => "YAML",
+            Language::Json => "JSON",
+            Language::Dockerfile => "Dockerfile",
+            Language::Other => "Other",
+        }
+    }
+
+    /// Lowercase identifier, used for per-language files such as `.rv_guidelines.rust`
+    pub fn id(self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::Go => "go",
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::CSharp => "csharp",
+            Language::Java => "java",
+            Language::Kotlin => "kotlin",
+            Language::Swift => "swift",
+            Language::Ruby => "ruby",
+            Language::Php => "php",
+            Language::Shell => "shell",
+            Language::Sql => "sql",
+            Language::Html => "html",
+            Language::Css => "css",
+            Language::Markdown => "markdown",
+            Language::Toml => "toml",
+            Language::Yaml => "yaml",
+            Language::Json => "json",
+            Language::Dockerfile => "dockerfile",
+            Language::Other => "other",
+        }
+    }
+}
+
+fn language_from_extension(ext: &str) -> Option<Language> {
+    let language = match ext.to_ascii_lowercase().as_str() {
+        "rs" => Language::Rust,
+        "py" | "pyi" => Language::Python,
+        "js" | "mjs" | "cjs" | "jsx" => Language::JavaScript,
+        "ts" | "mts" | "cts" | "tsx" => Language::TypeScript,
+        "go" => Language::Go,
+        "c" | "h" => Language::C,
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Language::Cpp,
+        "cs" => Language::CSharp,
+        "java" => Language::Java,
+        "kt" | "kts" => Language::Kotlin,
+        "swift" => Language::Swift,
+        "rb" => Language::Ruby,
+        "php" => Language::Php,
+        "sh" | "bash" | "zsh" | "fish" => Language::Shell,
+        "sql" => Language::Sql,
+        "html" | "htm" => Language::Html,
+        "css" | "scss" | "sass" | "less" => Language::Css,
+        "md" | "markdown" => Language::Markdown,
+        "toml" => Language::Toml,
+        "yml" | "yaml" => Language::Yaml,
+        "json" => Language::Json,
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Shebang line of a file (ex. `#!/usr/bin/env python3`), if any
+pub fn shebang(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut first_line = String::new();
+    BufReader::new(file).read_line(&mut first_line).ok()?;
+    first_line
+        .starts_with("#!")
+        .then(|| first_line.trim_end().to_string())
+}
+
+fn language_from_shebang(path: &Path) -> Option<Language> {
+    shebang_language(&shebang(path)?)
+}
+
+/// Language run by the interpreter of a shebang line
+fn shebang_language(first_line: &str) -> Option<Language> {
+    let interpreter = first_line.strip_prefix("#!")?;
+    // Handle both `#!/usr/bin/python3` and `#!/usr/bin/env python3`
+    let program = interpreter
+        .split_whitespace()
+        .find(|part| !part.ends_with("/env"))?
+        .rsplit('/')
+        .next()?;
+
+    let language = if program.starts_with("python") {
+        Language::Python
+    } else if program.starts_with("node") || program.starts_with("deno") {
+        Language::JavaScript
+    } else if program.starts_with("ruby") {
+        Language::Ruby
+    } else if program.starts_with("php") {
+        Language::Php
+    } else if matches!(program, "sh" | "bash" | "zsh" | "dash" | "ksh" | "fish") {
+        Language::Shell
+    } else {
+        return None;
+    };
+    Some(language)
+}
+
+/// Detect the language of a file using its name and extension, falling back to its shebang
+pub fn detect_language(path: &Path) -> Language {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if file_name == "Dockerfile" || file_name.starts_with("Dockerfile.") {
+        return Language::Dockerfile;
+    }
+
+    if let Some(language) = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(language_from_extension)
+    {
+        return language;
+    }
+
+    language_from_shebang(path).unwrap_or(Language::Other)
+}
+
+/// Language of a file with what it was detected from, shown to the LLM with single-file reviews
+pub fn language_hint(path: &Path) -> String {
+    let language = detect_language(path);
+    let mut hint = format!("Language: {}", language.name());
+    if let Some(ext) = path.extension() {
+        hint.push_str(&format!(", extension .{}", ext.to_string_lossy()));
+    }
+    if let Some(shebang) = shebang(path) {
+        hint.push_str(&format!(", shebang `{shebang}`"));
+    }
+    hint
+}
+
+/// Guess the language of a snippet without a file name from its first lines; `Other` when unsure
+pub fn guess_language(code: &str) -> Language {
+    if let Some(first_line) = code.lines().next()
+        && first_line.starts_with("#!")
+    {
+        return shebang_language(first_line).unwrap_or(Language::Other);
+    }
+
+    // Markers that are (almost) specific to a single language
+    const MARKERS: &[(&str, Language)] = &[
+        ("<?php", Language::Php),
+        ("package main", Language::Go),
+        ("fn main(", Language::Rust),
+        ("let mut ", Language::Rust),
+        ("impl ", Language::Rust),
+        ("pub fn ", Language::Rust),
+        ("#include <iostream>", Language::Cpp),
+        ("std::", Language::Cpp),
+        ("#include", Language::C),
+        ("public static void main", Language::Java),
+        ("using System", Language::CSharp),
+        ("fun main(", Language::Kotlin),
+        ("def __init__(self", Language::Python),
+        ("from __future__", Language::Python),
+        ("interface ", Language::TypeScript),
+        (": string", Language::TypeScript),
+        ("console.log(", Language::JavaScript),
+        ("function ", Language::JavaScript),
+        ("=> {", Language::JavaScript),
+        ("<!DOCTYPE html", Language::Html),
+        ("<html", Language::Html),
+        ("FROM ", Language::Dockerfile),
+    ];
+    let head: String = code.lines().take(50).collect::<Vec<_>>().join("\n");
+    if let Some((_, language)) = MARKERS.iter().find(|(marker, _)| head.contains(marker)) {
+        return *language;
+    }
+
+    let trimmed = head.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return Language::Json;
+    }
+    let keyword_lines = |keywords: &[&str]| {
+        head.lines()
+            .filter(|line| keywords.iter().any(|k| line.trim_start().starts_with(k)))
+            .count()
+    };
+    if keyword_lines(&["def ", "import ", "from ", "class "]) > 0 && head.contains(":\n") {
+        return Language::Python;
+    }
+    if keyword_lines(&[
+        "SELECT ",
+        "INSERT ",
+        "UPDATE ",
+        "CREATE TABLE",
+        "select ",
+        "insert ",
+    ]) > 0
+    {
+        return Language::Sql;
+    }
+    Language::Other
+}
@@ -0,0 +1,87 @@
+//! Review engine of *rv*: configuration, Git extraction, prompt packing, LLM providers and reporting.
+//!
+//! The `rv` binary is a thin CLI over this crate; other tools (bots, IDE plugins, CI wrappers)
+//! can embed the same engine without shelling out. The main entry points are:
+//!
+//! - [`config::RvConfig`], loaded with [`config::RvConfig::load_default`] and
+//!   [`settings::apply_env_overrides`]
+//! - [`review::git_review`] and [`review::raw_review`], running a whole review
+//! - [`git_helpers::ExpandedCommit`] and its constructors, extracting the diffs to review
+//! - [`review::pack_prompt`] and [`git_helpers::ExpandedCommit::get_xml_structure`], building the prompt
+//! - [`llm::create_llm_provider`] and [`llm::defs::LLMProvider`], talking to the models
+//! - [`report::Reporter`], receiving the results
+//!
+//! Fallible functions return [`anyhow::Result`], or [`git2::Error`] for the Git layer.
+
+pub mod artifact;
+pub mod audit;
+pub mod batch;
+pub mod budget;
+pub mod cache;
+pub mod checks;
+pub mod clean;
+pub mod clipboard;
+pub mod collect;
+pub mod complexity;
+pub mod compliance;
+pub mod compress;
+pub mod config;
+pub mod conflicts;
+pub mod consensus;
+pub mod context;
+pub mod coverage;
+pub mod dump;
+pub mod editor;
+pub mod encoding;
+pub mod findings;
+pub mod fixes;
+pub mod formats;
+pub mod git_helpers;
+pub mod github;
+pub mod history;
+pub mod ignore;
+pub mod imports;
+pub mod index;
+pub mod injection;
+pub mod inline;
+pub mod knowledge;
+pub mod language;
+pub mod llm;
+pub mod markup;
+pub mod minimize;
+pub mod narrate;
+pub mod network;
+pub mod next_steps;
+pub mod notes;
+pub mod notify;
+pub mod options;
+pub mod paths;
+pub mod policy;
+pub mod presets;
+pub mod profile;
+pub mod pseudonyms;
+pub mod redact;
+pub mod report;
+pub mod request;
+pub mod review;
+pub mod review_model;
+pub mod rules;
+pub mod sandbox;
+pub mod sanitize;
+pub mod selection;
+pub mod semver;
+pub mod series;
+pub mod settings;
+pub mod snippet;
+pub mod stats;
+pub mod structure;
+pub mod suppress;
+pub mod telemetry;
+pub mod term_helpers;
+pub mod tickets;
+pub mod tokens;
+pub mod triage;
+pub mod variables;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,120 @@
+use crate::config::LLMConfig;
+use crate::llm::defs::{LLMProvider, RequestParams, TokenStream, build_agent, text_stream};
+use crate::llm::split_prompt;
+use crate::telemetry;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use rig::client::CompletionClient;
+use rig::completion::Chat;
+use rig::message::Message;
+use rig::providers::azure;
+use rig::streaming::StreamingChat;
+use std::future::IntoFuture;
+
+/// API version used when the configuration doesn't set one
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Azure OpenAI: requests go to a deployment of a model on the resource endpoint
+pub struct AzureOpenAIClient {
+    pub api_key: String,
+    /// Endpoint of the resource (ex. `https://my-resource.openai.azure.com`)
+    pub endpoint: Option<String>,
+    pub deployment: String,
+    pub api_version: String,
+    pub params: RequestParams,
+}
+
+/// Client of the resource, authenticated with its API key
+fn client(endpoint: Option<&str>, api_key: &str, api_version: &str) -> Result<azure::Client> {
+    let endpoint = endpoint.context("Set `endpoint` in the Azure OpenAI configuration")?;
+    Ok(azure::Client::builder()
+        .api_key(azure::AzureOpenAIAuth::ApiKey(api_key.to_string()))
+        .azure_endpoint(endpoint.trim_end_matches('/').to_string())
+        .api_version(api_version)
+        .build()?)
+}
+
+impl AzureOpenAIClient {
+    pub fn from_config(llmconfig: LLMConfig) -> AzureOpenAIClient {
+        AzureOpenAIClient {
+            params: RequestParams::from_config(&llmconfig),
+            api_key: llmconfig.api_key,
+            endpoint: llmconfig.endpoint,
+            // Deployments are often named after their model
+            deployment: llmconfig.deployment_name.unwrap_or(llmconfig.model_id),
+            api_version: llmconfig
+                .api_version
+                .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+        }
+    }
+
+    pub fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<TokenStream> {
+        let client = client(self.endpoint.as_deref(), &self.api_key, &self.api_version)?;
+
+        let model = client.completion_model(&self.deployment);
+
+        let agent = build_agent(model, sys_prompt, &self.params);
+
+        let request = agent.stream_chat("", messages);
+        Ok(futures::stream::once(request.into_future())
+            .flat_map(text_stream)
+            .boxed())
+    }
+
+    pub fn chat(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        let api_key = self.api_key.clone();
+        let endpoint = self.endpoint.clone();
+        let deployment = self.deployment.clone();
+        let api_version = self.api_version.clone();
+        let params = self.params;
+
+        async move {
+            let client = client(endpoint.as_deref(), &api_key, &api_version)?;
+
+            let model = client.completion_model(&deployment);
+
+            let agent = build_agent(model, &sys_prompt, &params);
+
+            let (prompt, history) = split_prompt(messages);
+            let response = agent.chat(prompt, history).await?;
+
+            Ok(response)
+        }
+    }
+}
+
+impl LLMProvider for AzureOpenAIClient {
+    fn get_provider_name(&self) -> String {
+        "Azure OpenAI".to_string()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let attributes = [
+            ("llm.provider", "Azure OpenAI"),
+            ("llm.model", self.deployment.as_str()),
+        ];
+        let stream = self.stream_chat(&sys_prompt, messages)?;
+        Ok(telemetry::in_span_stream(
+            "llm_request",
+            &attributes,
+            stream,
+        ))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let attributes = [
+            ("llm.provider", "Azure OpenAI"),
+            ("llm.model", self.deployment.as_str()),
+        ];
+        telemetry::in_span_future("llm_request", &attributes, self.chat(sys_prompt, messages))
+    }
+}
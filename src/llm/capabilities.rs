@@ -0,0 +1,188 @@
+//! What a provider/model pair supports, so that rv can warn about it or adapt its requests
+
+use crate::config::{LLMConfig, OpenAIProvider};
+use crate::llm::defs::{LLMProvider, TokenStream};
+use anyhow::Result;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use rig::message::{Message, UserContent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Responses can be streamed token by token
+    pub streaming: bool,
+    /// The system role is accepted; otherwise the system prompt is folded into the first user message
+    pub system_messages: bool,
+    /// Structured JSON output can be requested
+    pub json_mode: bool,
+    /// The model reasons before answering, whatever `allow_reasoning` says
+    pub reasoning: bool,
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        ModelCapabilities {
+            streaming: true,
+            system_messages: true,
+            json_mode: false,
+            reasoning: false,
+        }
+    }
+}
+
+/// Models without support for the system role (matched against the model ID without its vendor)
+const NO_SYSTEM_ROLE: [&str; 3] = ["o1-mini", "o1-preview", "gemma"];
+
+/// Models that always reason before answering
+const REASONING: [&str; 8] = [
+    "o1",
+    "o3",
+    "o4-mini",
+    "gpt-5",
+    "deepseek-r1",
+    "qwq",
+    "thinking",
+    "magistral",
+];
+
+/// Models accepting `response_format` JSON requests
+const JSON_MODE: [&str; 7] = [
+    "gpt-4o",
+    "gpt-4.1",
+    "gpt-5",
+    "o1",
+    "o3",
+    "o4-mini",
+    "gpt-3.5-turbo",
+];
+
+fn matches_any(model: &str, patterns: &[&str]) -> bool {
+    patterns.iter().any(|pattern| {
+        model == *pattern
+            || model.starts_with(&format!("{pattern}-"))
+            || model.starts_with(&format!("{pattern}:"))
+            || model.contains(&format!("-{pattern}"))
+            || model.contains(&format!(":{pattern}"))
+    })
+}
+
+impl ModelCapabilities {
+    /// Capabilities known for `model_id`, falling back to the ones of a plain chat model
+    pub fn known(provider: &OpenAIProvider, model_id: &str) -> ModelCapabilities {
+        let model_id = model_id.to_ascii_lowercase();
+        // OpenRouter IDs start with the vendor (ex. `openai/o1-mini`)
+        let model = model_id.rsplit('/').next().unwrap_or(&model_id);
+        let mut capabilities = ModelCapabilities {
+            system_messages: !matches_any(model, &NO_SYSTEM_ROLE),
+            reasoning: matches_any(model, &REASONING),
+            ..Default::default()
+        };
+        capabilities.json_mode = match provider {
+            OpenAIProvider::OpenAI | OpenAIProvider::AzureOpenAI => matches_any(model, &JSON_MODE),
+            // OpenRouter only forwards `response_format` to some of the providers of a model
+            OpenAIProvider::OpenRouter => {
+                model_id.starts_with("openai/") && matches_any(model, &JSON_MODE)
+            }
+            OpenAIProvider::Ollama | OpenAIProvider::Gemini | OpenAIProvider::Custom => false,
+        };
+        capabilities
+    }
+
+    /// Known capabilities of the configured model, corrected by its `capabilities` table
+    pub fn from_config(config: &LLMConfig) -> ModelCapabilities {
+        let known = ModelCapabilities::known(&config.provider, &config.model_id);
+        let overrides = &config.capabilities;
+        ModelCapabilities {
+            streaming: overrides.streaming.unwrap_or(known.streaming),
+            system_messages: overrides.system_messages.unwrap_or(known.system_messages),
+            json_mode: overrides.json_mode.unwrap_or(known.json_mode),
+            reasoning: overrides.reasoning.unwrap_or(known.reasoning),
+        }
+    }
+
+    /// Whether requests have to be changed before reaching the provider
+    pub fn needs_adapter(&self) -> bool {
+        !self.streaming || !self.system_messages
+    }
+
+    /// Ways the requests to `config` differ from what rv would normally send
+    pub fn warnings(&self, config: &LLMConfig) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.system_messages {
+            warnings.push(format!(
+                "{} doesn't support system messages, the instructions are sent with the first message",
+                config.model_id
+            ));
+        }
+        if !self.streaming {
+            warnings.push(format!(
+                "{} doesn't support streaming, the response is shown once it's complete",
+                config.model_id
+            ));
+        }
+        if self.reasoning && !config.allow_reasoning {
+            warnings.push(format!(
+                "{} always reasons before answering, `allow_reasoning = false` has no effect",
+                config.model_id
+            ));
+        }
+        warnings
+    }
+}
+
+/// Move the system prompt to the start of the first user message
+pub fn fold_system_prompt(sys_prompt: String, mut messages: Vec<Message>) -> Vec<Message> {
+    if sys_prompt.is_empty() {
+        return messages;
+    }
+    let first_user = messages.iter_mut().find_map(|message| match message {
+        Message::User { content } => Some(content),
+        Message::Assistant { .. } => None,
+    });
+    match first_user {
+        Some(content) => content.insert(0, UserContent::text(sys_prompt)),
+        None => messages.insert(0, Message::user(sys_prompt)),
+    }
+    messages
+}
+
+/// Provider wrapper adapting the requests to a model with missing capabilities
+pub struct AdaptedProvider {
+    pub inner: Box<dyn LLMProvider>,
+    pub capabilities: ModelCapabilities,
+}
+
+impl AdaptedProvider {
+    fn adapt(&self, sys_prompt: String, messages: Vec<Message>) -> (String, Vec<Message>) {
+        if self.capabilities.system_messages {
+            (sys_prompt, messages)
+        } else {
+            (String::new(), fold_system_prompt(sys_prompt, messages))
+        }
+    }
+}
+
+impl LLMProvider for AdaptedProvider {
+    fn get_provider_name(&self) -> String {
+        self.inner.get_provider_name()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let (sys_prompt, messages) = self.adapt(sys_prompt, messages);
+        if self.capabilities.streaming {
+            self.inner.stream_request(sys_prompt, messages)
+        } else {
+            // The whole response arrives as a single chunk
+            Ok(futures::stream::once(self.inner.request(sys_prompt, messages)).boxed())
+        }
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let (sys_prompt, messages) = self.adapt(sys_prompt, messages);
+        self.inner.request(sys_prompt, messages)
+    }
+}
@@ -1,7 +1,276 @@
+use crate::config::{LLMConfig, OpenAIProvider, ReasoningEffort, SamplingParams};
+use crate::llm::capabilities::ModelCapabilities;
 use anyhow::Result;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use rig::agent::{Agent, AgentBuilder, MultiTurnStreamItem, StreamingResult};
+use rig::completion::CompletionModel;
 use rig::message::Message;
+use rig::streaming::StreamedAssistantContent;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub trait LLMProvider {
+/// Text of a response, chunk by chunk, as it gets generated
+pub type TokenStream = BoxStream<'static, Result<String>>;
+
+pub trait LLMProvider: Send + Sync {
     fn get_provider_name(&self) -> String;
-    fn stream_request_stdout(&self, sys_prompt: String, messages: Vec<Message>) -> Result<String>;
+    /// Send a request and stream the text of the response; presenting it is up to the caller
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream>;
+    /// Send a request and return the full response, without printing it
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>>;
+}
+
+/// Print the reasoning of the streamed responses (`--show-reasoning`)
+static SHOW_REASONING: AtomicBool = AtomicBool::new(false);
+
+/// Print the reasoning of the streamed responses for the rest of the run, or hide it
+pub fn show_reasoning(show: bool) {
+    SHOW_REASONING.store(show, Ordering::SeqCst);
+}
+
+/// Reasoning of a streamed response, printed to stderr as it arrives
+#[derive(Default)]
+struct ReasoningPrinter {
+    /// Reasoning was printed since the last text chunk
+    open: bool,
+    /// The reasoning arrived in deltas, so the complete reasoning that may follow is a repeat
+    streamed: bool,
+}
+
+impl ReasoningPrinter {
+    fn print(&mut self, reasoning: &str, delta: bool) {
+        if !delta && self.streamed {
+            return;
+        }
+        if !self.open {
+            eprintln!("[REASONING]");
+            self.open = true;
+        }
+        self.streamed |= delta;
+        eprint!("{reasoning}");
+        let _ = std::io::stderr().flush();
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            eprintln!("\n[/REASONING]\n");
+        }
+        *self = ReasoningPrinter::default();
+    }
+}
+
+/// Keep only the text chunks of a rig stream (tool calls and the final response are dropped). The
+/// reasoning is never part of the text: it's dropped, or printed to stderr with `--show-reasoning`
+pub fn text_stream<R: Send + 'static>(stream: StreamingResult<R>) -> TokenStream {
+    let show_reasoning = SHOW_REASONING.load(Ordering::SeqCst);
+    let mut printer = ReasoningPrinter::default();
+    stream
+        .map(move |item| match item {
+            Ok(MultiTurnStreamItem::StreamAssistantItem(content)) => match content {
+                StreamedAssistantContent::Text(text) => {
+                    printer.close();
+                    Some(Ok(text.text))
+                }
+                StreamedAssistantContent::ReasoningDelta { reasoning, .. } if show_reasoning => {
+                    printer.print(&reasoning, true);
+                    None
+                }
+                StreamedAssistantContent::Reasoning(reasoning) if show_reasoning => {
+                    printer.print(&reasoning.reasoning.join("\n"), false);
+                    None
+                }
+                _ => None,
+            },
+            Ok(_) => None,
+            // The message of rig errors already includes their source
+            Err(e) => Some(Err(anyhow::anyhow!("{e}"))),
+        })
+        .filter_map(futures::future::ready)
+        .boxed()
+}
+
+/// Request format of a provider, which decides where the sampling and reasoning parameters go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestApi {
+    /// OpenAI Chat Completions and the compatible APIs (Azure OpenAI, Custom)
+    ChatCompletions,
+    /// OpenAI Responses API, without penalties
+    Responses,
+    /// Chat Completions with the `reasoning` object of OpenRouter
+    OpenRouter,
+    Gemini,
+    Ollama,
+}
+
+/// Parameters of the requests set by the LLM configuration, in the format of their API
+#[derive(Debug, Clone, Copy)]
+pub struct RequestParams {
+    pub api: RequestApi,
+    pub sampling: SamplingParams,
+    pub allow_reasoning: bool,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// The model reasons whatever `allow_reasoning` says, the other ones may reject reasoning
+    /// parameters
+    pub reasoning_model: bool,
+}
+
+impl RequestApi {
+    pub fn of(provider: &OpenAIProvider) -> RequestApi {
+        match provider {
+            OpenAIProvider::OpenAI => RequestApi::Responses,
+            OpenAIProvider::OpenRouter => RequestApi::OpenRouter,
+            OpenAIProvider::AzureOpenAI | OpenAIProvider::Custom => RequestApi::ChatCompletions,
+            OpenAIProvider::Gemini => RequestApi::Gemini,
+            OpenAIProvider::Ollama => RequestApi::Ollama,
+        }
+    }
+}
+
+impl RequestParams {
+    pub fn from_config(config: &LLMConfig) -> RequestParams {
+        RequestParams {
+            api: RequestApi::of(&config.provider),
+            sampling: config.sampling,
+            allow_reasoning: config.allow_reasoning,
+            reasoning_effort: config.reasoning_effort,
+            reasoning_model: ModelCapabilities::from_config(config).reasoning,
+        }
+    }
+
+    /// Warn about the parameters the API doesn't support, which are left out of the requests
+    pub fn warn_unsupported(&self, model: &str) {
+        let sampling = &self.sampling;
+        if self.api == RequestApi::Responses
+            && (sampling.frequency_penalty.is_some() || sampling.presence_penalty.is_some())
+        {
+            eprintln!(
+                "[WARN] The OpenAI Responses API doesn't take penalties, `frequency_penalty` and `presence_penalty` are ignored for {model}"
+            );
+        }
+        if self.reasoning_effort.is_some()
+            && matches!(self.api, RequestApi::Gemini | RequestApi::Ollama)
+        {
+            eprintln!(
+                "[WARN] `reasoning_effort` is ignored for {model}, its provider doesn't take it"
+            );
+        } else if self.reasoning_effort.is_some()
+            && self.api == RequestApi::Responses
+            && !self.reasoning_model
+        {
+            eprintln!(
+                "[WARN] {model} isn't known to reason, `reasoning_effort` is ignored (set `reasoning = true` in its `capabilities` if it does)"
+            );
+        }
+    }
+
+    /// Request fields rig has no setting for, in the format of the API
+    fn additional_params(&self) -> Option<serde_json::Value> {
+        let sampling = &self.sampling;
+        let effort = self.reasoning_effort.map(|effort| effort.name());
+        let mut params = serde_json::Map::new();
+        let mut set = |name: &str, value: Option<serde_json::Value>| {
+            if let Some(value) = value {
+                params.insert(name.to_string(), value);
+            }
+        };
+        match self.api {
+            // rig doesn't send `max_tokens` to these APIs
+            RequestApi::ChatCompletions | RequestApi::OpenRouter => {
+                set("top_p", sampling.top_p.map(Into::into));
+                set("max_tokens", sampling.max_tokens.map(Into::into));
+                set(
+                    "frequency_penalty",
+                    sampling.frequency_penalty.map(Into::into),
+                );
+                set(
+                    "presence_penalty",
+                    sampling.presence_penalty.map(Into::into),
+                );
+                if self.api == RequestApi::ChatCompletions {
+                    set("reasoning_effort", effort.map(Into::into));
+                } else if let Some(effort) = effort {
+                    set("reasoning", Some(serde_json::json!({ "effort": effort })));
+                } else if !self.allow_reasoning && !self.reasoning_model {
+                    // Models that always reason reject disabling it
+                    set("reasoning", Some(serde_json::json!({ "enabled": false })));
+                }
+            }
+            RequestApi::Responses => {
+                set("top_p", sampling.top_p.map(Into::into));
+                // Other models reject the reasoning parameters
+                if self.reasoning_model {
+                    let summary = SHOW_REASONING.load(Ordering::SeqCst).then_some("auto");
+                    if effort.is_some() || summary.is_some() {
+                        set(
+                            "reasoning",
+                            Some(serde_json::json!({ "effort": effort, "summary": summary })),
+                        );
+                    }
+                }
+            }
+            // rig only applies the temperature and `max_tokens` to an existing generation config
+            RequestApi::Gemini => {
+                if *sampling != SamplingParams::default() {
+                    set(
+                        "generationConfig",
+                        Some(serde_json::json!({
+                            "temperature": sampling.temperature,
+                            "topP": sampling.top_p,
+                            "maxOutputTokens": sampling.max_tokens,
+                            "frequencyPenalty": sampling.frequency_penalty,
+                            "presencePenalty": sampling.presence_penalty,
+                        })),
+                    );
+                }
+            }
+            // Merged into the `options` of the request, except `think`
+            RequestApi::Ollama => {
+                set("top_p", sampling.top_p.map(Into::into));
+                set("num_predict", sampling.max_tokens.map(Into::into));
+                set(
+                    "frequency_penalty",
+                    sampling.frequency_penalty.map(Into::into),
+                );
+                set(
+                    "presence_penalty",
+                    sampling.presence_penalty.map(Into::into),
+                );
+                // rig asks Ollama not to think unless told otherwise
+                set(
+                    "think",
+                    (self.allow_reasoning && self.reasoning_model).then_some(true.into()),
+                );
+            }
+        }
+        (!params.is_empty()).then_some(serde_json::Value::Object(params))
+    }
+}
+
+/// Agent answering with `model` and the request parameters of the configuration; an empty
+/// system prompt sends no system message at all
+pub fn build_agent<M: CompletionModel>(
+    model: M,
+    sys_prompt: &str,
+    params: &RequestParams,
+) -> Agent<M> {
+    let mut builder = AgentBuilder::new(model);
+    if !sys_prompt.is_empty() {
+        builder = builder.preamble(sys_prompt);
+    }
+    if let Some(temperature) = params.sampling.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(max_tokens) = params.sampling.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(additional_params) = params.additional_params() {
+        builder = builder.additional_params(additional_params);
+    }
+    builder.build()
 }
@@ -0,0 +1,84 @@
+use crate::config::{LLMConfig, OpenAIProvider};
+use crate::llm::azure::DEFAULT_AZURE_API_VERSION;
+use crate::llm::openai::header_map;
+use crate::network;
+use anyhow::{Context, Result};
+use rig::client::{EmbeddingsClient, Nothing};
+use rig::embeddings::EmbeddingModel;
+use rig::providers::{azure, gemini, ollama, openai};
+
+/// Documents sent in a single embedding request
+const EMBEDDING_BATCH: usize = 64;
+
+async fn embed_with<M: EmbeddingModel>(model: M, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBEDDING_BATCH.min(M::MAX_DOCUMENTS)) {
+        let embeddings = model.embed_texts(batch.to_vec()).await?;
+        vectors.extend(
+            embeddings
+                .into_iter()
+                .map(|embedding| embedding.vec.into_iter().map(|v| v as f32).collect()),
+        );
+    }
+    Ok(vectors)
+}
+
+/// Embed `texts` with `model`, through the embedding API of the provider of `llmconfig`
+pub async fn embed_texts(
+    llmconfig: &LLMConfig,
+    model: &str,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>> {
+    network::ensure_allowed("The embedding API")?;
+    match llmconfig.provider {
+        OpenAIProvider::OpenAI => {
+            let client: openai::Client = openai::Client::new(&llmconfig.api_key)?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+        // OpenRouter only exposes embeddings through its OpenAI-compatible API
+        OpenAIProvider::OpenRouter => {
+            let api_key = std::env::var("OPENROUTER_API_KEY").unwrap_or(llmconfig.api_key.clone());
+            let client: openai::Client = openai::Client::builder()
+                .api_key(&api_key)
+                .base_url(llmconfig.provider.endpoint())
+                .build()?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+        OpenAIProvider::Custom => {
+            let client: openai::Client = openai::Client::builder()
+                .api_key(&llmconfig.api_key)
+                .base_url(llmconfig.endpoint())
+                .http_headers(header_map(&llmconfig.headers)?)
+                .build()?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+        OpenAIProvider::AzureOpenAI => {
+            let endpoint = llmconfig
+                .endpoint
+                .as_deref()
+                .context("Set `endpoint` in the Azure OpenAI configuration")?;
+            let client: azure::Client = azure::Client::builder()
+                .api_key(azure::AzureOpenAIAuth::ApiKey(llmconfig.api_key.clone()))
+                .azure_endpoint(endpoint.trim_end_matches('/').to_string())
+                .api_version(
+                    llmconfig
+                        .api_version
+                        .as_deref()
+                        .unwrap_or(DEFAULT_AZURE_API_VERSION),
+                )
+                .build()?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+        OpenAIProvider::Gemini => {
+            let client: gemini::Client = gemini::Client::new(&llmconfig.api_key)?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+        OpenAIProvider::Ollama => {
+            let client: ollama::Client = ollama::Client::builder()
+                .api_key(Nothing)
+                .base_url(llmconfig.endpoint())
+                .build()?;
+            embed_with(client.embedding_model(model), texts).await
+        }
+    }
+}
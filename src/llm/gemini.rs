@@ -0,0 +1,97 @@
+use crate::config::LLMConfig;
+use crate::llm::defs::{LLMProvider, RequestParams, TokenStream, build_agent, text_stream};
+use crate::llm::split_prompt;
+use crate::telemetry;
+use anyhow::Result;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use rig::client::CompletionClient;
+use rig::completion::Chat;
+use rig::message::Message;
+use rig::providers::gemini;
+use rig::streaming::StreamingChat;
+use std::future::IntoFuture;
+
+/// Google Gemini, through the Generative Language API
+pub struct GeminiClient {
+    pub api_key: String,
+    pub model: String,
+    pub params: RequestParams,
+}
+
+impl GeminiClient {
+    pub fn from_config(llmconfig: LLMConfig) -> GeminiClient {
+        GeminiClient {
+            params: RequestParams::from_config(&llmconfig),
+            api_key: llmconfig.api_key,
+            model: llmconfig.model_id,
+        }
+    }
+
+    pub fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<TokenStream> {
+        let client: gemini::Client = gemini::Client::new(&self.api_key)?;
+
+        let model = client.completion_model(&self.model);
+
+        let agent = build_agent(model, sys_prompt, &self.params);
+
+        let request = agent.stream_chat("", messages);
+        Ok(futures::stream::once(request.into_future())
+            .flat_map(text_stream)
+            .boxed())
+    }
+
+    pub fn chat(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        let api_key = self.api_key.clone();
+        let model_id = self.model.clone();
+        let params = self.params;
+
+        async move {
+            let client: gemini::Client = gemini::Client::new(&api_key)?;
+
+            let model = client.completion_model(&model_id);
+
+            let agent = build_agent(model, &sys_prompt, &params);
+
+            let (prompt, history) = split_prompt(messages);
+            let response = agent.chat(prompt, history).await?;
+
+            Ok(response)
+        }
+    }
+}
+
+impl LLMProvider for GeminiClient {
+    fn get_provider_name(&self) -> String {
+        "Gemini".to_string()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let attributes = [
+            ("llm.provider", "Gemini"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let stream = self.stream_chat(&sys_prompt, messages)?;
+        Ok(telemetry::in_span_stream(
+            "llm_request",
+            &attributes,
+            stream,
+        ))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let attributes = [
+            ("llm.provider", "Gemini"),
+            ("llm.model", self.model.as_str()),
+        ];
+        telemetry::in_span_future("llm_request", &attributes, self.chat(sys_prompt, messages))
+    }
+}
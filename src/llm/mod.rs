@@ -1,13 +1,61 @@
+pub mod azure;
+pub mod capabilities;
 pub mod defs;
+pub mod embeddings;
+pub mod gemini;
+pub mod ollama;
 pub mod openai;
 pub mod openrouter;
+pub mod retry;
+pub mod timeout;
 
 use crate::config::{LLMConfig, OpenAIProvider};
-use crate::llm::defs::LLMProvider;
+use crate::llm::capabilities::{AdaptedProvider, ModelCapabilities};
+use crate::llm::defs::{LLMProvider, RequestParams};
+use crate::llm::timeout::TimedProvider;
+use crate::network::{self, ExportProvider};
 
+use rig::message::Message;
+
+/// Split the messages of a chat into its prompt, the last message, and the chat history before it
+pub(crate) fn split_prompt(mut messages: Vec<Message>) -> (Message, Vec<Message>) {
+    let prompt = messages.pop().unwrap_or_else(|| Message::user(""));
+    (prompt, messages)
+}
+
+/// Create the provider of `config`, adapting the requests to the capabilities of its model and
+/// enforcing its timeouts
 pub fn create_llm_provider(config: LLMConfig) -> Box<dyn LLMProvider> {
-    match config.provider {
+    if network::is_disabled() {
+        return Box::new(ExportProvider {
+            provider_name: config.provider.name().to_string(),
+        });
+    }
+    let capabilities = ModelCapabilities::from_config(&config);
+    RequestParams::from_config(&config).warn_unsupported(&config.model_id);
+    let timed_config = config.clone();
+    let provider: Box<dyn LLMProvider> = match config.provider {
         OpenAIProvider::OpenAI => Box::new(openai::OpenAIClient::from_config(config)),
         OpenAIProvider::OpenRouter => Box::new(openrouter::OpenRouterClient::from_config(config)),
+        OpenAIProvider::Ollama => Box::new(ollama::OllamaClient::from_config(config)),
+        OpenAIProvider::Gemini => Box::new(gemini::GeminiClient::from_config(config)),
+        OpenAIProvider::AzureOpenAI => Box::new(azure::AzureOpenAIClient::from_config(config)),
+        OpenAIProvider::Custom => Box::new(openai::OpenAIClient::custom_from_config(config)),
+    };
+
+    let provider: Box<dyn LLMProvider> = if capabilities.needs_adapter() {
+        Box::new(AdaptedProvider {
+            inner: provider,
+            capabilities,
+        })
+    } else {
+        provider
+    };
+
+    let timed = TimedProvider::from_config(provider, &timed_config);
+    if timed.is_needed() {
+        Box::new(timed)
+    } else {
+        timed.inner
     }
 }
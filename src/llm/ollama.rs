@@ -0,0 +1,105 @@
+use crate::config::LLMConfig;
+use crate::llm::defs::{LLMProvider, RequestParams, TokenStream, build_agent, text_stream};
+use crate::llm::split_prompt;
+use crate::telemetry;
+use anyhow::Result;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use rig::client::{CompletionClient, Nothing};
+use rig::completion::Chat;
+use rig::message::Message;
+use rig::providers::ollama;
+use rig::streaming::StreamingChat;
+use std::future::IntoFuture;
+
+/// Local Ollama server: the diffs never leave the machine (or the network of the server)
+pub struct OllamaClient {
+    /// Base URL of the server (ex. `http://localhost:11434`)
+    pub base_url: String,
+    pub model: String,
+    pub params: RequestParams,
+}
+
+fn client(base_url: &str) -> Result<ollama::Client> {
+    Ok(ollama::Client::builder()
+        .api_key(Nothing)
+        .base_url(base_url)
+        .build()?)
+}
+
+impl OllamaClient {
+    pub fn from_config(llmconfig: LLMConfig) -> OllamaClient {
+        OllamaClient {
+            params: RequestParams::from_config(&llmconfig),
+            base_url: llmconfig.endpoint(),
+            model: llmconfig.model_id,
+        }
+    }
+
+    pub fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<TokenStream> {
+        let client = client(&self.base_url)?;
+
+        let model = client.completion_model(&self.model);
+
+        let agent = build_agent(model, sys_prompt, &self.params);
+
+        let request = agent.stream_chat("", messages);
+        Ok(futures::stream::once(request.into_future())
+            .flat_map(text_stream)
+            .boxed())
+    }
+
+    pub fn chat(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        let base_url = self.base_url.clone();
+        let model_id = self.model.clone();
+        let params = self.params;
+
+        async move {
+            let client = client(&base_url)?;
+
+            let model = client.completion_model(&model_id);
+
+            let agent = build_agent(model, &sys_prompt, &params);
+
+            let (prompt, history) = split_prompt(messages);
+            let response = agent.chat(prompt, history).await?;
+
+            Ok(response)
+        }
+    }
+}
+
+impl LLMProvider for OllamaClient {
+    fn get_provider_name(&self) -> String {
+        "Ollama".to_string()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let attributes = [
+            ("llm.provider", "Ollama"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let stream = self.stream_chat(&sys_prompt, messages)?;
+        Ok(telemetry::in_span_stream(
+            "llm_request",
+            &attributes,
+            stream,
+        ))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let attributes = [
+            ("llm.provider", "Ollama"),
+            ("llm.model", self.model.as_str()),
+        ];
+        telemetry::in_span_future("llm_request", &attributes, self.chat(sys_prompt, messages))
+    }
+}
@@ -1,37 +1,143 @@
-use crate::config::LLMConfig;
-use crate::llm::defs::LLMProvider;
-use anyhow::Result;
-use rig::agent::AgentBuilder;
+use crate::config::{LLMConfig, RetryConfig};
+use crate::llm::defs::{LLMProvider, RequestParams, TokenStream, build_agent, text_stream};
+use crate::llm::retry::{RetryAfterHttp, retry_request, retry_stream};
+use crate::llm::split_prompt;
+use crate::telemetry;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use rig::client::CompletionClient;
+use rig::completion::Chat;
 use rig::message::Message;
 use rig::providers::openai;
 use rig::streaming::StreamingChat;
+use std::collections::HashMap;
+use std::future::IntoFuture;
 
+/// Extra HTTP headers of the configuration, checked before the first request
+pub fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        map.insert(
+            HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid HTTP header name `{name}`"))?,
+            HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value of the HTTP header `{name}`"))?,
+        );
+    }
+    Ok(map)
+}
+
+/// Client of an OpenAI-compatible API, through the Chat Completions API
+fn compatible_client(
+    api_key: &str,
+    base_url: &str,
+    headers: &HashMap<String, String>,
+) -> Result<openai::CompletionsClient<RetryAfterHttp>> {
+    Ok(openai::CompletionsClient::<RetryAfterHttp>::builder()
+        .api_key(api_key)
+        .base_url(base_url)
+        .http_headers(header_map(headers)?)
+        .build()?)
+}
+
+#[derive(Clone)]
 pub struct OpenAIClient {
     pub api_key: String,
     pub model: String,
+    /// OpenAI-compatible endpoint, reached through the Chat Completions API instead of the Responses API
+    pub compatible_base_url: Option<String>,
+    /// Extra HTTP headers of the OpenAI-compatible endpoint
+    pub headers: HashMap<String, String>,
+    pub retry: RetryConfig,
+    pub params: RequestParams,
 }
 
 impl OpenAIClient {
     pub fn from_config(llmconfig: LLMConfig) -> OpenAIClient {
         OpenAIClient {
+            params: RequestParams::from_config(&llmconfig),
+            api_key: llmconfig.api_key,
+            model: llmconfig.model_id,
+            compatible_base_url: None,
+            headers: HashMap::new(),
+            retry: llmconfig.retry,
+        }
+    }
+
+    /// Reach the OpenAI-compatible API at the `base_url` of the configuration
+    pub fn custom_from_config(llmconfig: LLMConfig) -> OpenAIClient {
+        OpenAIClient {
+            params: RequestParams::from_config(&llmconfig),
+            compatible_base_url: Some(llmconfig.endpoint()),
+            retry: llmconfig.retry,
             api_key: llmconfig.api_key,
             model: llmconfig.model_id,
+            headers: llmconfig.headers,
         }
     }
 
-    pub async fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<String> {
-        let client: openai::Client = openai::Client::new(&self.api_key)?;
+    pub fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<TokenStream> {
+        if let Some(base_url) = &self.compatible_base_url {
+            let client = compatible_client(&self.api_key, base_url, &self.headers)?;
+            let agent = build_agent(
+                client.completion_model(&self.model),
+                sys_prompt,
+                &self.params,
+            );
+            let request = agent.stream_chat("", messages);
+            return Ok(futures::stream::once(request.into_future())
+                .flat_map(text_stream)
+                .boxed());
+        }
+
+        let client = openai::Client::<RetryAfterHttp>::builder()
+            .api_key(&self.api_key)
+            .build()?;
 
         let model = client.completion_model(&self.model);
 
-        let agent = AgentBuilder::new(model).preamble(sys_prompt).build();
+        let agent = build_agent(model, sys_prompt, &self.params);
+
+        let request = agent.stream_chat("", messages);
+        Ok(futures::stream::once(request.into_future())
+            .flat_map(text_stream)
+            .boxed())
+    }
+
+    pub fn chat(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        let api_key = self.api_key.clone();
+        let model_id = self.model.clone();
+        let compatible_base_url = self.compatible_base_url.clone();
+        let headers = self.headers.clone();
+        let params = self.params;
+
+        async move {
+            let (prompt, history) = split_prompt(messages);
+
+            if let Some(base_url) = compatible_base_url {
+                let client = compatible_client(&api_key, &base_url, &headers)?;
+                let agent = build_agent(client.completion_model(&model_id), &sys_prompt, &params);
+                return Ok(agent.chat(prompt, history).await?);
+            }
 
-        let mut stream = agent.stream_chat("", messages).await;
-        let res = rig::agent::stream_to_stdout(&mut stream).await?;
-        let full_text = res.response().to_string();
+            let client = openai::Client::<RetryAfterHttp>::builder()
+                .api_key(&api_key)
+                .build()?;
 
-        Ok(full_text)
+            let model = client.completion_model(&model_id);
+
+            let agent = build_agent(model, &sys_prompt, &params);
+
+            let response = agent.chat(prompt, history).await?;
+
+            Ok(response)
+        }
     }
 }
 
@@ -40,9 +146,38 @@ impl LLMProvider for OpenAIClient {
         "OpenAI".to_string()
     }
 
-    fn stream_request_stdout(&self, sys_prompt: String, messages: Vec<Message>) -> Result<String> {
-        tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.stream_chat(&sys_prompt, messages))
-        })
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let attributes = [
+            ("llm.provider", "OpenAI"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let client = self.clone();
+        let stream = retry_stream(self.retry, self.get_provider_name(), move || {
+            client.stream_chat(&sys_prompt, messages.clone())
+        });
+        Ok(telemetry::in_span_stream(
+            "llm_request",
+            &attributes,
+            stream,
+        ))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let attributes = [
+            ("llm.provider", "OpenAI"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let client = self.clone();
+        let request = async move {
+            retry_request(client.retry, &client.get_provider_name(), || {
+                client.chat(sys_prompt.clone(), messages.clone())
+            })
+            .await
+        };
+        telemetry::in_span_future("llm_request", &attributes, request)
     }
 }
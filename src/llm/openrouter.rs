@@ -1,40 +1,78 @@
-use crate::config::LLMConfig;
-use crate::llm::defs::LLMProvider;
+use crate::config::{LLMConfig, RetryConfig};
+use crate::llm::defs::{LLMProvider, RequestParams, TokenStream, build_agent, text_stream};
+use crate::llm::retry::{RetryAfterHttp, retry_request, retry_stream};
+use crate::llm::split_prompt;
+use crate::telemetry;
 use anyhow::Result;
-use rig::agent::AgentBuilder;
+use futures::StreamExt;
+use futures::future::BoxFuture;
 use rig::client::CompletionClient;
+use rig::completion::Chat;
 use rig::message::Message;
 use rig::providers::openrouter;
 use rig::streaming::StreamingChat;
+use std::future::IntoFuture;
 
+#[derive(Clone)]
 pub struct OpenRouterClient {
     pub api_key: String,
     pub model: String,
+    pub retry: RetryConfig,
+    pub params: RequestParams,
 }
 
 impl OpenRouterClient {
     pub fn from_config(llmconfig: LLMConfig) -> OpenRouterClient {
         OpenRouterClient {
+            params: RequestParams::from_config(&llmconfig),
             api_key: llmconfig.api_key,
             model: llmconfig.model_id,
+            retry: llmconfig.retry,
         }
     }
 
-    pub async fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<String> {
+    pub fn stream_chat(&self, sys_prompt: &str, messages: Vec<Message>) -> Result<TokenStream> {
         // Check for OPENROUTER_API_KEY environment variable
         let api_key = std::env::var("OPENROUTER_API_KEY").unwrap_or(self.api_key.clone());
 
-        let client: openrouter::Client = openrouter::Client::new(&api_key)?;
+        let client = openrouter::Client::<RetryAfterHttp>::builder()
+            .api_key(&api_key)
+            .build()?;
 
         let model = client.completion_model(&self.model);
 
-        let agent = AgentBuilder::new(model).preamble(sys_prompt).build();
+        let agent = build_agent(model, sys_prompt, &self.params);
 
-        let mut stream = agent.stream_chat("", messages).await;
-        let res = rig::agent::stream_to_stdout(&mut stream).await?;
-        let full_text = res.response().to_string();
+        let request = agent.stream_chat("", messages);
+        Ok(futures::stream::once(request.into_future())
+            .flat_map(text_stream)
+            .boxed())
+    }
+
+    pub fn chat(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        // Check for OPENROUTER_API_KEY environment variable
+        let api_key = std::env::var("OPENROUTER_API_KEY").unwrap_or(self.api_key.clone());
+        let model_id = self.model.clone();
+        let params = self.params;
+
+        async move {
+            let client = openrouter::Client::<RetryAfterHttp>::builder()
+                .api_key(&api_key)
+                .build()?;
+
+            let model = client.completion_model(&model_id);
 
-        Ok(full_text)
+            let agent = build_agent(model, &sys_prompt, &params);
+
+            let (prompt, history) = split_prompt(messages);
+            let response = agent.chat(prompt, history).await?;
+
+            Ok(response)
+        }
     }
 }
 
@@ -43,9 +81,38 @@ impl LLMProvider for OpenRouterClient {
         "OpenRouter".to_string()
     }
 
-    fn stream_request_stdout(&self, sys_prompt: String, messages: Vec<Message>) -> Result<String> {
-        tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.stream_chat(&sys_prompt, messages))
-        })
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let attributes = [
+            ("llm.provider", "OpenRouter"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let client = self.clone();
+        let stream = retry_stream(self.retry, self.get_provider_name(), move || {
+            client.stream_chat(&sys_prompt, messages.clone())
+        });
+        Ok(telemetry::in_span_stream(
+            "llm_request",
+            &attributes,
+            stream,
+        ))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let attributes = [
+            ("llm.provider", "OpenRouter"),
+            ("llm.model", self.model.as_str()),
+        ];
+        let client = self.clone();
+        let request = async move {
+            retry_request(client.retry, &client.get_provider_name(), || {
+                client.chat(sys_prompt.clone(), messages.clone())
+            })
+            .await
+        };
+        telemetry::in_span_future("llm_request", &attributes, request)
     }
 }
@@ -0,0 +1,257 @@
+//! Retries of the LLM requests failing with a transient error (ex. a 502 of OpenRouter), with an
+//! exponential backoff honoring the `Retry-After` of the HTTP 429 responses
+
+use crate::config::RetryConfig;
+use crate::llm::defs::TokenStream;
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{FutureExt, StreamExt};
+use reqwest::header::RETRY_AFTER;
+use rig::http_client::sse::BoxedStream;
+use rig::http_client::{
+    self, HttpClientExt, LazyBody, MultipartForm, Request, Response, StreamingResponse,
+};
+use rig::wasm_compat::WasmCompatSend;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+/// Written by `RetryAfterHttp` after the message of the responses with a `Retry-After` header
+const RETRY_AFTER_MARKER: &str = "[Retry-After: ";
+
+/// Statuses of the responses worth retrying
+const TRANSIENT_STATUSES: [u16; 7] = [408, 425, 429, 500, 502, 503, 504];
+
+/// Connection errors worth retrying
+const TRANSIENT_ERRORS: [&str; 4] = [
+    "error sending request",
+    "connection closed",
+    "connection reset",
+    "timed out",
+];
+
+/// Longest part of an error shown in the retry warnings
+const MAX_ERROR_CHARS: usize = 120;
+
+/// HTTP status of a failed request, from its error (ex. `Invalid status code 502 Bad Gateway`)
+fn status_code(message: &str) -> Option<u16> {
+    let rest = message.split("status code ").nth(1)?;
+    rest.get(..3)?.parse().ok()
+}
+
+/// Delay asked by the provider, from the `Retry-After` kept in the error
+fn retry_after(message: &str) -> Option<Duration> {
+    let rest = message.split(RETRY_AFTER_MARKER).nth(1)?;
+    let secs = rest.split(']').next()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// Whether `error` is transient, with the delay asked by the provider if any
+fn transient(error: &anyhow::Error) -> Option<Option<Duration>> {
+    let message = format!("{error:#}");
+    let transient = match status_code(&message) {
+        Some(status) => TRANSIENT_STATUSES.contains(&status),
+        None => TRANSIENT_ERRORS.iter().any(|e| message.contains(e)),
+    };
+    transient.then(|| retry_after(&message))
+}
+
+/// Delay before the attempt following `attempt`: the `Retry-After` of the provider, else the
+/// initial backoff doubled at every attempt (half of it random with `jitter`)
+fn backoff(config: &RetryConfig, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let max = Duration::from_millis(config.max_backoff_ms);
+    if let Some(delay) = retry_after {
+        return delay.min(max);
+    }
+    let delay = config
+        .initial_backoff_ms
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(config.max_backoff_ms);
+    let delay = if config.jitter {
+        let random = RandomState::new().hash_one(attempt);
+        delay / 2 + random % (delay / 2 + 1)
+    } else {
+        delay
+    };
+    Duration::from_millis(delay)
+}
+
+/// Wait before the next attempt, if `error` is transient and attempts remain
+async fn wait_for_retry(
+    config: &RetryConfig,
+    provider: &str,
+    attempt: u32,
+    error: &anyhow::Error,
+) -> bool {
+    if attempt >= config.max_attempts {
+        return false;
+    }
+    let Some(retry_after) = transient(error) else {
+        return false;
+    };
+    let delay = backoff(config, attempt, retry_after);
+    let message = format!("{error:#}");
+    let reason: String = message
+        .split(RETRY_AFTER_MARKER)
+        .next()
+        .and_then(|message| message.lines().next())
+        .unwrap_or_default()
+        .trim_end()
+        .chars()
+        .take(MAX_ERROR_CHARS)
+        .collect();
+    eprintln!(
+        "[WARN] The {provider} request failed ({reason}), retrying in {:.1}s (attempt {}/{})",
+        delay.as_secs_f64(),
+        attempt + 1,
+        config.max_attempts
+    );
+    tokio::time::sleep(delay).await;
+    true
+}
+
+/// Send the request made by `request` again while it fails with a transient error
+pub async fn retry_request<F, Fut>(
+    config: RetryConfig,
+    provider: &str,
+    request: F,
+) -> Result<String>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if wait_for_retry(&config, provider, attempt, &e).await => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Start the stream made by `start` again while it fails with a transient error before its
+/// first chunk; once the response is being shown, errors are returned as they are
+pub fn retry_stream<F>(config: RetryConfig, provider: String, start: F) -> TokenStream
+where
+    F: Fn() -> Result<TokenStream> + Send + 'static,
+{
+    futures::stream::once(async move {
+        let mut attempt = 1;
+        loop {
+            let error = match start() {
+                Ok(mut stream) => match stream.next().await {
+                    Some(Err(e)) => e,
+                    first => return futures::stream::iter(first).chain(stream).boxed(),
+                },
+                Err(e) => e,
+            };
+            if !wait_for_retry(&config, &provider, attempt, &error).await {
+                return futures::stream::once(async move { Err(error) }).boxed();
+            }
+            attempt += 1;
+        }
+    })
+    .flatten()
+    .boxed()
+}
+
+/// HTTP client of the providers, keeping the `Retry-After` header of the failed responses in
+/// their errors (rig drops the headers of the responses it doesn't parse)
+#[derive(Debug, Clone, Default)]
+pub struct RetryAfterHttp(reqwest::Client);
+
+/// Error of a response with a failure status
+async fn status_error(response: reqwest::Response) -> http_client::Error {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let mut message = response.text().await.unwrap_or_default();
+    if let Some(secs) = retry_after {
+        message.push_str(&format!(" {RETRY_AFTER_MARKER}{secs}]"));
+    }
+    http_client::Error::InvalidStatusCodeWithMessage(status, message)
+}
+
+fn request_error(error: reqwest::Error) -> http_client::Error {
+    http_client::Error::Instance(error.into())
+}
+
+impl RetryAfterHttp {
+    fn request<T: Into<Bytes>>(&self, req: Request<T>) -> reqwest::RequestBuilder {
+        let (parts, body) = req.into_parts();
+        self.0
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body.into())
+    }
+}
+
+impl HttpClientExt for RetryAfterHttp {
+    fn send<T, U>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        T: Into<Bytes>,
+        T: WasmCompatSend,
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        let request = self.request(req);
+        async move {
+            let response = request.send().await.map_err(request_error)?;
+            if !response.status().is_success() {
+                return Err(status_error(response).await);
+            }
+            let mut builder = Response::builder().status(response.status());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            let body: LazyBody<U> =
+                async move { response.bytes().await.map(U::from).map_err(request_error) }.boxed();
+            builder.body(body).map_err(http_client::Error::Protocol)
+        }
+    }
+
+    fn send_multipart<U>(
+        &self,
+        req: Request<MultipartForm>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        self.0.send_multipart(req)
+    }
+
+    fn send_streaming<T>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<StreamingResponse>> + WasmCompatSend
+    where
+        T: Into<Bytes>,
+    {
+        let request = self.request(req);
+        async move {
+            let response = request.send().await.map_err(request_error)?;
+            if !response.status().is_success() {
+                return Err(status_error(response).await);
+            }
+            let mut builder = Response::builder()
+                .status(response.status())
+                .version(response.version());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            let body: BoxedStream = Box::pin(
+                response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map_err(request_error)),
+            );
+            builder.body(body).map_err(http_client::Error::Protocol)
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Timeouts of the LLM requests (`first_token_timeout_secs`, `total_timeout_secs`), so that a
+//! stalled provider fails the review instead of hanging it
+
+use crate::config::LLMConfig;
+use crate::llm::defs::{LLMProvider, TokenStream};
+
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use rig::message::Message;
+use std::time::{Duration, Instant};
+
+/// Provider whose requests fail when they run out of time
+pub struct TimedProvider {
+    pub inner: Box<dyn LLMProvider>,
+    /// Name of the LLM configuration, shown in the errors
+    pub configuration_name: String,
+    pub first_token: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl TimedProvider {
+    pub fn from_config(inner: Box<dyn LLMProvider>, config: &LLMConfig) -> TimedProvider {
+        let timeout = |secs| (secs > 0).then(|| Duration::from_secs(secs));
+        TimedProvider {
+            inner,
+            configuration_name: config.configuration_name.clone(),
+            first_token: timeout(config.first_token_timeout_secs),
+            total: timeout(config.total_timeout_secs),
+        }
+    }
+
+    /// Whether any timeout is set
+    pub fn is_needed(&self) -> bool {
+        self.first_token.is_some() || self.total.is_some()
+    }
+}
+
+/// Error of a request that ran out of time, naming the setting to raise
+fn timeout_error(
+    provider: &str,
+    what: &str,
+    limit: Duration,
+    setting: &str,
+    config: &str,
+) -> anyhow::Error {
+    anyhow!(
+        "The {provider} request {what} within {}s; raise `{setting}` of the `{config}` LLM configuration for slow models",
+        limit.as_secs()
+    )
+}
+
+/// Stream being read, or ended by a timeout
+struct Timed {
+    stream: Option<TokenStream>,
+    started: Instant,
+    first: bool,
+}
+
+impl LLMProvider for TimedProvider {
+    fn get_provider_name(&self) -> String {
+        self.inner.get_provider_name()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let stream = self.inner.stream_request(sys_prompt, messages)?;
+        let provider = self.get_provider_name();
+        let config = self.configuration_name.clone();
+        let (first_token, total) = (self.first_token, self.total);
+        let state = Timed {
+            stream: Some(stream),
+            started: Instant::now(),
+            first: true,
+        };
+        Ok(futures::stream::unfold(state, move |mut state| {
+            let provider = provider.clone();
+            let config = config.clone();
+            async move {
+                let mut stream = state.stream.take()?;
+                let remaining = total.map(|total| total.saturating_sub(state.started.elapsed()));
+                // The first token is waited for until the earliest of both limits
+                let first = if state.first { first_token } else { None };
+                let (limit, error) = match (first, remaining) {
+                    (Some(first), remaining) if remaining.is_none_or(|r| first < r) => (
+                        Some(first),
+                        timeout_error(
+                            &provider,
+                            "sent no token",
+                            first,
+                            "first_token_timeout_secs",
+                            &config,
+                        ),
+                    ),
+                    (_, remaining) => (
+                        remaining,
+                        timeout_error(
+                            &provider,
+                            "didn't complete",
+                            total.unwrap_or_default(),
+                            "total_timeout_secs",
+                            &config,
+                        ),
+                    ),
+                };
+                let next = match limit {
+                    Some(limit) => match tokio::time::timeout(limit, stream.next()).await {
+                        Ok(next) => next,
+                        Err(_) => return Some((Err(error), state)),
+                    },
+                    None => stream.next().await,
+                };
+                let item = next?;
+                state.stream = Some(stream);
+                state.first = false;
+                Some((item, state))
+            }
+        })
+        .boxed())
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let request = self.inner.request(sys_prompt, messages);
+        let Some(total) = self.total else {
+            return request;
+        };
+        let error = timeout_error(
+            &self.get_provider_name(),
+            "didn't complete",
+            total,
+            "total_timeout_secs",
+            &self.configuration_name,
+        );
+        Box::pin(async move {
+            tokio::time::timeout(total, request)
+                .await
+                .map_err(|_| error)?
+        })
+    }
+}
@@ -1,122 +1,1057 @@
-pub mod config;
-pub mod git_helpers;
-pub mod github;
-pub mod llm;
-pub mod review;
-pub mod term_helpers;
-
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use rv_core::clipboard::CopyFormat;
+use rv_core::next_steps::NextStepsContext;
+use rv_core::options::CliFlags;
+use rv_core::{
+    audit, batch, budget, clean, compliance, config, conflicts, editor, git_helpers, history,
+    index, llm, narrate, network, notes, options, paths, presets, report, review, stats, telemetry,
+};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short = 'C', long, value_name = "PATH", value_hint = clap::ValueHint::DirPath, global = true)]
+    /// Run as if rv was started in this directory (like `git -C`)
+    repo: Option<PathBuf>,
+
+    #[arg(short, long, global = true)]
     /// LLM configuration to use
     llm: Option<String>,
 
-    #[arg(short, long)]
-    /// Git commit to review
+    #[arg(
+        short,
+        long,
+        hide = true,
+        group = "target",
+        conflicts_with_all = ["per_commit", "squashed", "against"]
+    )]
+    /// Git commit to review (deprecated, use `rv commit`)
     commit: Option<String>,
 
-    #[arg(short, long)]
-    /// Git branch to review
+    #[arg(
+        short,
+        long,
+        hide = true,
+        group = "target",
+        conflicts_with = "per_commit"
+    )]
+    /// Git branch to review (deprecated, use `rv branch`)
     branch: Option<String>,
 
-    #[arg(long, value_enum)]
-    /// Git branch review mode
-    branch_mode: Option<config::BranchAgainst>,
+    #[arg(
+        long,
+        alias = "branch-mode",
+        value_name = "current|main|REF",
+        global = true
+    )]
+    /// What to compare --branch against: the current HEAD, the main branch or any other reference
+    against: Option<config::BranchAgainst>,
 
-    #[arg(short, long)]
-    /// Github pull request to review
+    #[arg(short, long, hide = true, group = "target")]
+    /// Github pull request to review (deprecated, use `rv pr`)
     pr: Option<String>,
 
-    #[arg(long = "log_xml", action)]
-    /// Print out XML structure of the code review.
+    #[arg(
+        long,
+        value_name = "BASE..HEAD",
+        group = "target",
+        conflicts_with = "against"
+    )]
+    /// Git commit range to review
+    range: Option<String>,
+
+    #[arg(
+        long,
+        action,
+        global = true,
+        conflicts_with_all = ["file", "dir", "glob"]
+    )]
+    /// Review each commit of --pr or --range separately, then the whole series
+    per_commit: bool,
+
+    #[arg(long, action, global = true, conflicts_with = "per_commit")]
+    /// Review the net diff of --branch, --pr or --range from their merge-base, ignoring intra-branch churn
+    squashed: bool,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        group = "target",
+        conflicts_with_all = ["per_commit", "squashed", "against"]
+    )]
+    /// Review only the changes made after a previous review
+    since_review: Option<String>,
+
+    #[arg(
+        long = "log-xml",
+        alias = "log_xml",
+        global = true,
+        overrides_with = "no_log_xml_structure"
+    )]
+    /// Print out XML structure of the code review (`log_xml_structure` in config.toml)
     log_xml_structure: bool,
 
-    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
-    /// Specific file to review
+    #[arg(
+        long = "no-log-xml",
+        global = true,
+        overrides_with = "log_xml_structure"
+    )]
+    /// Don't print out the XML structure of the code review
+    no_log_xml_structure: bool,
+
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath, global = true)]
+    /// Specific file to review; with Git reviews, only its diff is reviewed
     file: Option<PathBuf>,
 
-    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
-    /// Specific directory to review
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath, global = true)]
+    /// Specific directory to review; with Git reviews, only diffs inside it are reviewed
     dir: Option<PathBuf>,
 
-    #[arg(short, long, action)]
-    /// Review all subfiles, used with `--dir`
+    #[arg(short, long = "glob", global = true)]
+    /// Only review the diffs of files matching this glob pattern (can be repeated)
+    glob: Vec<String>,
+
+    #[arg(short, long, global = true, overrides_with = "no_recursive")]
+    /// Review all subfiles, used with `--dir` (`recursive` of `[raw]` in config.toml)
     recursive: bool,
 
-    #[arg(short = 'R', long)]
-    /// Review source code without interfacing with Git
+    #[arg(long, global = true, overrides_with = "recursive")]
+    /// Only review the files directly in the directory
+    no_recursive: bool,
+
+    #[arg(long, action, global = true)]
+    /// Review files over the raw mode size limits anyway
+    force_include: bool,
+
+    #[arg(long, action, global = true)]
+    /// Follow symlinks in raw directory reviews (never outside of the directory)
+    follow_symlinks: bool,
+
+    #[arg(long, action, global = true)]
+    /// Include hidden files and directories in raw directory reviews
+    hidden: bool,
+
+    #[arg(long, action, global = true)]
+    /// Don't exclude build artifacts and `.rvignore` entries in raw directory reviews
+    no_ignore: bool,
+
+    #[arg(long, action, global = true)]
+    /// Also send the local files imported by a raw file review as read-only context
+    with_imports: bool,
+
+    #[arg(
+        short = 'R',
+        long,
+        hide = true,
+        conflicts_with_all = ["target", "per_commit", "squashed", "against", "glob"],
+        overrides_with = "no_raw"
+    )]
+    /// Review source code without interfacing with Git (deprecated, use `rv review <path>`)
     raw: bool,
 
-    #[arg(short = 'P', long, action)]
+    #[arg(long, hide = true, overrides_with = "raw")]
+    /// Review the diffs of `--file` and `--dir` even with `by_default` of `[raw]` in config.toml
+    no_raw: bool,
+
+    #[arg(short = 'P', long, action, global = true)]
     /// Output as raw text, allowing for stdout pipes
     pipe: bool,
 
-    #[arg(short = 'C', long, action)]
+    #[arg(long, action, global = true)]
     /// Launch chat mode, skipping review generation
     chat: bool,
 
-    #[arg(long, action)]
+    #[arg(long, global = true, overrides_with = "no_actions_menu")]
     /// Force post-review actions menu (normally defined by config.toml)
-    actions_menu: Option<bool>,
+    actions_menu: bool,
+
+    #[arg(long, global = true, overrides_with = "actions_menu")]
+    /// Skip the post-review actions menu
+    no_actions_menu: bool,
+
+    #[arg(short, long, action, global = true)]
+    /// Send requests without asking for confirmation (implied when the CI variable is set)
+    yes: bool,
+
+    #[arg(long, value_name = "DURATION", value_parser = budget::parse_duration, global = true)]
+    /// Stop starting new review requests after this time (ex. `120s`, `5m`) and report what was not reviewed
+    time_budget: Option<std::time::Duration>,
+
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath, global = true)]
+    /// Also save the review as JSON to this file
+    json_out: Option<PathBuf>,
+
+    #[arg(long, action, global = true)]
+    /// Also post the review as a comment of the reviewed pull request
+    comment: bool,
+
+    #[arg(long, value_name = "DIR", value_hint = clap::ValueHint::DirPath, global = true)]
+    /// Also write the review as a folder (report.md, findings.json, prompt.txt, metadata.toml)
+    artifact: Option<PathBuf>,
+
+    #[arg(long, action, global = true)]
+    /// Also store the review as a git note (`refs/notes/rv`) on the reviewed commit
+    notes: bool,
+
+    #[arg(long, action, global = true)]
+    /// After the review, accept, suppress or assign each finding interactively
+    triage: bool,
+
+    #[arg(long = "tag", value_name = "TAG", global = true)]
+    /// Tag the reviews of the run (can be repeated); with `history list`, only list the reviews with these tags
+    tags: Vec<String>,
+
+    #[arg(long, value_name = "URL", global = true)]
+    /// Also send the review as JSON to this webhook (overrides `webhook_url` in config.toml)
+    webhook: Option<String>,
+
+    #[arg(long, value_name = "RATIO", value_parser = parse_ratio, global = true)]
+    /// Fail when a smaller share (0.0 to 1.0) of the diff files, hunks or lines reaches the prompt
+    min_coverage: Option<f64>,
+
+    #[arg(long, value_name = "LINES", global = true)]
+    /// Keep at most this many unchanged context lines around the changes of every diff hunk
+    context_lines: Option<usize>,
+
+    #[arg(long, action, global = true)]
+    /// Leave the diff hunks that only change whitespace out of the review
+    ignore_whitespace: bool,
+
+    #[arg(long, action, global = true)]
+    /// Apply each suggested fix to a temporary worktree and check that it builds (`verify_command`)
+    verify_fixes: bool,
+
+    #[arg(long, action, global = true)]
+    /// Ask the model for a JSON review, parsed into typed findings (`json_review` in config.toml)
+    json_review: bool,
+
+    #[arg(long, action, global = true)]
+    /// After the review, print the reviewed diff again with each finding under its line
+    inline: bool,
+
+    #[arg(long, action, global = true)]
+    /// Send the request even if the same review is cached (`review_cache` in config.toml)
+    no_cache: bool,
+
+    #[arg(long, action, global = true)]
+    /// After the review, print its estimated token usage and cost, and record them in the history
+    show_usage: bool,
+
+    #[arg(long, global = true, overrides_with = "no_show_reasoning")]
+    /// Print the reasoning of the model as it streams (`show_reasoning` in config.toml)
+    show_reasoning: bool,
 
-    #[arg(short = 'L', long = "load-context", value_hint = clap::ValueHint::FilePath)]
+    #[arg(long, global = true, overrides_with = "show_reasoning")]
+    /// Hide the reasoning of the model
+    no_show_reasoning: bool,
+
+    #[arg(long, action, global = true)]
+    /// After the review, open the files of the findings in the editor at the cited lines
+    open: bool,
+
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        conflicts_with = "llm",
+        global = true
+    )]
+    /// Review with several LLM configurations at once and merge their findings (ex. `a,b,c`)
+    models: Vec<String>,
+
+    #[arg(
+        long,
+        action,
+        conflicts_with_all = ["webhook", "models", "per_commit", "notify"],
+        global = true
+    )]
+    /// Make no outbound connection: print the request the review would send instead of sending it
+    no_network: bool,
+
+    #[arg(long, action, global = true)]
+    /// Show a desktop notification when a long review is done (see `[notify]` in config.toml)
+    notify: bool,
+
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text", global = true)]
+    /// Copy the final review to the clipboard (`text` as printed, or `markdown`)
+    copy: Option<CopyFormat>,
+
+    #[arg(short = 'L', long = "load-context", value_hint = clap::ValueHint::FilePath, global = true)]
     /// Load additional read-only context file
     load_context: Option<PathBuf>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Review the staged changes (the last commit when nothing is staged), or a file or a
+    /// directory without interfacing with Git
+    Review {
+        #[arg(value_hint = clap::ValueHint::AnyPath)]
+        /// File or directory to review without Git, instead of the staged changes
+        path: Option<PathBuf>,
+    },
+    /// Review the staged changes, or the last commit when nothing is staged (same as `rv` alone)
+    Staged,
+    /// Review a Git commit
+    Commit {
+        #[arg(default_value = "HEAD")]
+        /// Commit, branch or any revision (ex. `HEAD~2`)
+        rev: String,
+    },
+    /// Review a Git branch against the main branch (see `--against`)
+    Branch {
+        /// Local, remote-tracking (ex. `origin/feature`) or any other branch
+        name: String,
+    },
+    /// Review a Github pull request
+    Pr {
+        /// Number or URL of the pull request
+        id: String,
+    },
+    /// Review a single file without interfacing with Git (see `--with-imports`)
+    File {
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        /// File to review, even outside of a Git repository
+        path: PathBuf,
+    },
+    /// Show local usage statistics aggregated from the review history
+    Stats {
+        #[arg(long, value_enum, default_value_t)]
+        /// Period used to group the statistics
+        period: stats::StatsPeriod,
+        #[arg(long = "by", value_enum, value_name = "DIMENSION")]
+        /// Show history analytics instead of the usage summary (can be repeated)
+        by: Vec<stats::StatsDimension>,
+        #[arg(long, value_name = "DIR")]
+        /// Export the analytics as CSV files in this directory (all of them without `--by`)
+        csv: Option<PathBuf>,
+    },
+    /// Tell the story of a commit series and how to clean it up before review (ordering, squashes, splits)
+    Narrate {
+        #[arg(long, value_name = "BASE..HEAD")]
+        /// Commit range to narrate
+        range: String,
+    },
+    /// Check a commit or a range strictly against the numbered rules of the guidelines (pass/fail/n-a per rule)
+    CheckGuidelines {
+        #[arg(default_value = "HEAD")]
+        /// Commit or `base..head` range to check
+        rev: String,
+
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        /// Also save the verdicts as JSON to this file
+        json: Option<PathBuf>,
+    },
+    /// Ask for resolution guidance for the conflicts of the merge or rebase in progress
+    Conflicts,
+    /// Review the combined diff of the commits not pushed to the upstream branch yet (for pre-push hooks)
+    Unpushed,
+    /// Review a code snippet read from stdin (ex. a heredoc) or from the clipboard
+    Snippet {
+        #[arg(long, action)]
+        /// Read the snippet from the clipboard instead of stdin
+        clipboard: bool,
+
+        #[arg(long, value_name = "LANGUAGE")]
+        /// Language of the snippet (ex. `rust`, `python`), guessed when missing
+        lang: Option<String>,
+    },
+    /// Review every target (commit, pull request number or URL, path) listed in a file, one per line
+    Batch {
+        /// File listing the targets; `commit:`, `pr:` and `path:` prefixes remove ambiguities
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        targets: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = batch::BatchMode::Full)]
+        /// Full reviews, or cheaper summaries of the changes
+        mode: batch::BatchMode,
+
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        /// Reviews requested at the same time
+        jobs: usize,
+    },
+    /// Review the merges into the main branch of a time window and print a quality digest
+    AuditMerges {
+        #[arg(long, value_name = "DURATION", default_value = "7d", value_parser = budget::parse_duration)]
+        /// Time window of the audit (ex. `7d`, `2w`, `24h`)
+        since: std::time::Duration,
+
+        #[arg(long, value_name = "BRANCH")]
+        /// Audited branch, instead of the main branch (`base_branches`)
+        branch: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = batch::BatchMode::Full)]
+        /// Full reviews, or cheaper summaries of the merges
+        mode: batch::BatchMode,
+
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        /// Reviews requested at the same time
+        jobs: usize,
+    },
+    /// Build or update the embedding index of the repository, used to add related code to reviews
+    Index,
+    /// Show the review stored as a git note on a commit by `--notes`
+    ShowNote {
+        /// Commit, branch or any revision (ex. `HEAD~2`)
+        rev: String,
+    },
+    /// Browse the local review history
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Print where the configuration, cache, state and logs of rv live
+    Paths,
+    /// Edit the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Remove old review history entries, cached reviews, logs and temporary pull request refs
+    Clean {
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        /// Remove history entries, cached reviews, logs and dumps older than this many days
+        older_than: u64,
+
+        #[arg(long, action)]
+        /// List what would be removed without removing anything
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// List the last reviews, newest first (filter them with `--tag`)
+    List {
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        /// Number of reviews to list
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Add an LLM configuration from a preset (ex. `openrouter:claude`, list them with `presets`)
+    AddLlm {
+        #[arg(long, value_name = "PRESET")]
+        /// Preset of the configuration, as `provider:name`
+        preset: String,
+
+        #[arg(long, value_name = "NAME")]
+        /// Name of the configuration, the preset with `-` in place of `:` by default
+        name: Option<String>,
+
+        #[arg(long, value_name = "MODEL")]
+        /// Model used instead of the recommended model of the preset
+        model: Option<String>,
+
+        #[arg(long, action)]
+        /// Send a test request before saving the configuration
+        validate: bool,
+    },
+    /// List the presets of `add-llm`
+    Presets,
+}
+
+/// Replacements of the deprecated target flags, removed in the next release
+const DEPRECATED_TARGET_FLAGS: [(&str, &str); 4] = [
+    ("--commit", "rv commit <rev>"),
+    ("--branch", "rv branch <name>"),
+    ("--pr", "rv pr <id>"),
+    ("--raw", "rv review <path>"),
+];
+
+/// Error of an invalid combination of arguments, reported like the parser errors
+fn usage_error(message: impl std::fmt::Display) -> clap::Error {
+    Args::command()
+        .name("rv")
+        .error(ErrorKind::ArgumentConflict, message)
+}
+
+/// Turn the target subcommands (`rv commit`, `rv review <path>`...) into the flags the reviews
+/// are started from, warning about the deprecated target flags
+fn resolve_target(args: &mut Args) -> Result<(), clap::Error> {
+    let pr = args.pr.is_some();
+    let used = [args.commit.is_some(), args.branch.is_some(), pr, args.raw];
+    let deprecated: Vec<_> = DEPRECATED_TARGET_FLAGS
+        .iter()
+        .zip(used)
+        .filter_map(|(flag, used)| used.then_some(flag))
+        .collect();
+
+    let target = match &args.command {
+        Some(Command::Review { .. }) => "review",
+        Some(Command::Staged) => "staged",
+        Some(Command::Commit { .. }) => "commit",
+        Some(Command::Branch { .. }) => "branch",
+        Some(Command::Pr { .. }) => "pr",
+        Some(Command::File { .. }) => "file",
+        _ => "",
+    };
+    if !target.is_empty() {
+        if let Some((flag, _)) = deprecated.first() {
+            return Err(usage_error(format!(
+                "the argument '{flag}' cannot be used with `rv {target}`"
+            )));
+        }
+        if args.range.is_some() || args.since_review.is_some() {
+            return Err(usage_error(format!(
+                "the arguments '--range' and '--since-review' cannot be used with `rv {target}`"
+            )));
+        }
+    }
+    for (flag, replacement) in deprecated {
+        eprintln!(
+            "[WARN] {flag} is deprecated and will be removed in the next release, use `{replacement}` instead"
+        );
+    }
+
+    match args.command.take() {
+        Some(Command::Review { path: None } | Command::Staged) => {}
+        Some(Command::Review { path: Some(path) } | Command::File { path }) => {
+            if args.file.is_some() || args.dir.is_some() {
+                return Err(usage_error(format!(
+                    "the arguments '--file' and '--dir' cannot be used with `rv {target}`, the reviewed path is {}",
+                    path.display()
+                )));
+            }
+            args.raw = true;
+            if path.is_dir() {
+                args.dir = Some(path);
+            } else {
+                args.file = Some(path);
+            }
+        }
+        Some(Command::Commit { rev }) => args.commit = Some(rev),
+        Some(Command::Branch { name }) => args.branch = Some(name),
+        Some(Command::Pr { id }) => args.pr = Some(id),
+        command => args.command = command,
+    }
+    Ok(())
+}
+
+/// Whether a Git target is reviewed, which `[raw] by_default` doesn't apply to
+fn has_git_target(args: &Args) -> bool {
+    let pr = args.pr.is_some();
+    args.commit.is_some()
+        || args.branch.is_some()
+        || pr
+        || args.range.is_some()
+        || args.since_review.is_some()
+}
+
+/// Reject the options that don't apply to the reviewed target, instead of ignoring them. The
+/// parser catches them with the flags, the target subcommands are only known here
+fn validate_review_args(args: &Args) -> Result<(), clap::Error> {
+    let (pr, comment) = (args.pr.is_some(), args.comment);
+    let raw_only = args.force_include || args.follow_symlinks || args.hidden || args.no_ignore;
+    let rules = [
+        (
+            args.per_commit,
+            pr || args.range.is_some(),
+            "'--per-commit' only applies to `rv pr <id>` and '--range'",
+        ),
+        (
+            args.per_commit,
+            args.file.is_none() && args.dir.is_none() && args.glob.is_empty(),
+            "'--per-commit' reviews whole commits, it cannot be used with '--file', '--dir' or '--glob'",
+        ),
+        (
+            args.squashed,
+            args.branch.is_some() || pr || args.range.is_some(),
+            "'--squashed' only applies to `rv branch <name>`, `rv pr <id>` and '--range'",
+        ),
+        (
+            args.against.is_some(),
+            args.branch.is_some(),
+            "'--against' only applies to `rv branch <name>`",
+        ),
+        (
+            args.recursive,
+            args.dir.is_some(),
+            "'--recursive' only applies to directories (`rv review <dir>` or '--dir')",
+        ),
+        (
+            args.with_imports,
+            args.raw && args.file.is_some(),
+            "'--with-imports' only applies to `rv file <path>`",
+        ),
+        (
+            raw_only,
+            args.raw,
+            "'--force-include', '--follow-symlinks', '--hidden' and '--no-ignore' only apply to `rv review <path>`",
+        ),
+        (
+            !args.glob.is_empty(),
+            !args.raw,
+            "'--glob' only applies to Git reviews, use `rv review <dir>` with '.rvignore' instead",
+        ),
+        (comment, pr, "'--comment' only applies to `rv pr <id>`"),
+    ];
+    match rules.iter().find(|(used, applies, _)| *used && !applies) {
+        Some((_, _, message)) => Err(usage_error(message)),
+        None => Ok(()),
+    }
+}
+
+/// Resolve the relative paths of `args` from the directory of `-C`, as git does
+fn from_repo_dir(args: &mut Args) {
+    let Some(repo) = args.repo.clone() else {
+        return;
+    };
+    // Absolute, so that the commands suggested with `-C` keep pointing to the same files
+    let repo = std::path::absolute(&repo).unwrap_or(repo);
+    let resolve = |path: &mut Option<PathBuf>| {
+        if let Some(path) = path {
+            *path = repo.join(&*path);
+        }
+    };
+    resolve(&mut args.file);
+    resolve(&mut args.dir);
+    resolve(&mut args.json_out);
+    resolve(&mut args.artifact);
+    resolve(&mut args.load_context);
+    match &mut args.command {
+        Some(Command::Review { path }) => resolve(path),
+        Some(Command::File { path }) | Some(Command::Batch { targets: path, .. }) => {
+            *path = repo.join(&*path)
+        }
+        Some(Command::Stats { csv, .. }) => resolve(csv),
+        Some(Command::CheckGuidelines { json, .. }) => resolve(json),
+        _ => {}
+    }
+}
+
+/// Command reviewing the target of `args` again, for the next steps; `None` for the other commands
+fn rerun_command(args: &Args, pr: Option<&str>) -> Option<String> {
+    if args.command.is_some() {
+        return None;
+    }
+    let quote = |path: &PathBuf| {
+        let path = path.display().to_string();
+        if path.contains(char::is_whitespace) {
+            format!("'{path}'")
+        } else {
+            path
+        }
+    };
+    let mut command = if let Some(pr) = pr {
+        format!("rv pr {pr}")
+    } else if let Some(commit) = &args.commit {
+        format!("rv commit {commit}")
+    } else if let Some(branch) = &args.branch {
+        format!("rv branch {branch}")
+    } else if let Some(range) = &args.range {
+        format!("rv --range {range}")
+    } else if let Some(id) = &args.since_review {
+        format!("rv --since-review {id}")
+    } else if args.raw {
+        format!(
+            "rv review {}",
+            quote(args.file.as_ref().or(args.dir.as_ref())?)
+        )
+    } else {
+        String::from("rv")
+    };
+    if let Some(against) = &args.against {
+        let against = match against {
+            config::BranchAgainst::Current => "current",
+            config::BranchAgainst::Main => "main",
+            config::BranchAgainst::Ref(reference) => reference,
+        };
+        command.push_str(&format!(" --against {against}"));
+    }
+    if args.squashed {
+        command.push_str(" --squashed");
+    }
+    if args.per_commit {
+        command.push_str(" --per-commit");
+    }
+    if !args.raw {
+        if let Some(file) = &args.file {
+            command.push_str(&format!(" -f {}", quote(file)));
+        }
+        if let Some(dir) = &args.dir {
+            command.push_str(&format!(" -d {}", quote(dir)));
+        }
+        for glob in &args.glob {
+            command.push_str(&format!(" -g '{glob}'"));
+        }
+    } else if args.recursive {
+        command.push_str(" -r");
+    }
+    if let Some(repo) = &args.repo {
+        command.push_str(&format!(" -C {}", quote(repo)));
+    }
+    Some(command)
+}
+
+/// Parse a ratio between 0.0 and 1.0 (ex. `0.9`)
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(String::from("expected a number between 0.0 and 1.0")),
+    }
+}
+
+fn main() {
+    let mut args = Args::parse();
+
+    if let Err(e) = git_helpers::configure_repo(args.repo.as_deref()) {
+        eprintln!("[ERROR] Cannot use the repository of -C: {e}");
+        std::process::exit(1);
+    }
+    // Before the target, so that the reviewed path is resolved from the repository
+    from_repo_dir(&mut args);
+    if let Err(e) = resolve_target(&mut args) {
+        e.exit();
+    }
+
+    run(args);
+}
+
 #[tokio::main]
-async fn main() {
-    let args = Args::parse();
-    let rvconfig = config::RvConfig::load_default().unwrap();
+async fn run(mut args: Args) {
+    let time_budget = budget::TimeBudget::new(args.time_budget);
+    let mut rvconfig = match config::RvConfig::load_default() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to load the configuration: {e:#}");
+            std::process::exit(1);
+        }
+    };
+    paths::configure(&rvconfig.paths);
+    if args.no_network {
+        network::disable();
+        if args.pr.is_some() {
+            eprintln!(
+                "[ERROR] --no-network can't review pull requests, they are fetched with `gh`"
+            );
+            std::process::exit(1);
+        }
+        // The paths reaching the network are skipped up front, the guards only catch the rest
+        rvconfig.webhook_url = None;
+        rvconfig.auto_fetch = false;
+        rvconfig.tickets.tracker = config::TrackerKind::None;
+        rvconfig.consensus.models.clear();
+        if rvconfig.index.embeddings == config::EmbeddingBackend::Api {
+            rvconfig.index.top_k = 0;
+        }
+        // The project commands (semver checks, linters) only stay offline in a sandbox without network
+        rvconfig.sandbox.network = false;
+        if rvconfig.sandbox.isolation == config::SandboxIsolation::None {
+            rvconfig.semver.enabled = false;
+            rvconfig.formats.lint = false;
+        }
+    } else {
+        telemetry::init(rvconfig.otel_endpoint.as_deref());
+    }
+    if args.min_coverage.is_some() {
+        rvconfig.min_coverage = args.min_coverage;
+    }
+    if args.context_lines.is_some() {
+        rvconfig.diff_context_lines = args.context_lines;
+    }
+    rvconfig.ignore_whitespace |= args.ignore_whitespace;
+    rvconfig.verify_fixes |= args.verify_fixes;
+    rvconfig.json_review |= args.json_review;
+    rvconfig.inline |= args.inline;
+    rvconfig.show_usage |= args.show_usage;
+    if args.no_cache {
+        rvconfig.review_cache = false;
+    }
+    if !args.models.is_empty() {
+        rvconfig.consensus.models = args.models.clone();
+    }
+    for tag in &args.tags {
+        if !rvconfig.tags.contains(tag) {
+            rvconfig.tags.push(tag.clone());
+        }
+    }
+
+    // `rv unpushed` is a squashed review of the `upstream..HEAD` range
+    if let Some(Command::Unpushed) = args.command {
+        args.command = None;
+        match git_helpers::unpushed_range(&rvconfig.base_branches) {
+            Ok(range) => {
+                let unpushed = git_helpers::resolve_range(&range)
+                    .and_then(|(base, head)| git_helpers::commits_between(base, head));
+                if let Ok(commits) = &unpushed
+                    && commits.is_empty()
+                {
+                    println!("[NO CHANGES] Every commit of the current branch is already pushed.");
+                    return;
+                }
+                args.range = Some(range);
+                args.squashed = true;
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Failed to find the unpushed commits: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let options = options::resolve(
+        CliFlags {
+            raw: options::flag(args.raw, args.no_raw),
+            recursive: options::flag(args.recursive, args.no_recursive),
+            log_xml_structure: options::flag(args.log_xml_structure, args.no_log_xml_structure),
+            actions_menu: options::flag(args.actions_menu, args.no_actions_menu),
+            show_reasoning: options::flag(args.show_reasoning, args.no_show_reasoning),
+        },
+        has_git_target(&args),
+        args.file.is_some() || args.dir.is_some(),
+        &rvconfig,
+    );
+    args.raw = options.raw;
+    llm::defs::show_reasoning(options.show_reasoning);
+    // Checked against the flags passed, the defaults of the configuration apply where they can
+    if args.command.is_none()
+        && let Err(e) = validate_review_args(&args)
+    {
+        e.exit();
+    }
+
+    let (pr, comment) = (args.pr.clone(), args.comment);
+
+    let next_steps = rvconfig.next_steps.then(|| NextStepsContext {
+        rerun: rerun_command(&args, pr.as_deref()),
+        llm: args.llm.clone(),
+        pr: pr.clone(),
+        commented: comment,
+        saved: args.artifact.is_some() || args.json_out.is_some(),
+        triaged: args.triage,
+        llm_configs: rvconfig
+            .llm_configs
+            .iter()
+            .map(|config| (config.configuration_name.clone(), config.model_id.clone()))
+            .collect(),
+        cached: rvconfig.review_cache,
+    });
+
+    // The reviews run from the root of the working tree, so paths are resolved beforehand
+    let sinks = report::OutputSinks {
+        json_path: args
+            .json_out
+            .map(|path| std::path::absolute(&path).unwrap_or(path)),
+        comment_pr: pr.clone().filter(|_| comment),
+        webhook_url: args.webhook.or_else(|| rvconfig.webhook_url.clone()),
+        artifact_dir: args
+            .artifact
+            .map(|path| std::path::absolute(&path).unwrap_or(path)),
+        notes: args.notes.then_some(rvconfig.notes_merge),
+        triage: args.triage,
+        inline: rvconfig.inline,
+        copy: args.copy,
+        open: args
+            .open
+            .then(|| editor::editor_command(rvconfig.editor.as_deref())),
+        notify: (args.notify || rvconfig.notify.enabled).then(|| rvconfig.notify.clone()),
+        next_steps,
+    };
+    let reporters = sinks.reporters(args.pipe);
+
+    if let Some(command) = args.command {
+        let result = match command {
+            Command::Stats { period, by, csv } => stats::print_stats(
+                period,
+                rvconfig.usage_stats || rvconfig.show_usage,
+                &by,
+                csv.as_deref(),
+            ),
+            Command::History {
+                command: HistoryCommand::List { limit },
+            } => history::list_records(&args.tags, limit),
+            Command::Batch {
+                targets,
+                mode,
+                jobs,
+            } => {
+                let result = batch::batch_review(
+                    rvconfig,
+                    args.llm,
+                    &targets,
+                    mode,
+                    jobs,
+                    args.pipe,
+                    args.yes,
+                    time_budget,
+                    &reporters,
+                )
+                .await;
+                telemetry::export().await;
+                result
+            }
+            Command::AuditMerges {
+                since,
+                branch,
+                mode,
+                jobs,
+            } => {
+                let result = audit::audit_merges(
+                    rvconfig,
+                    args.llm,
+                    since,
+                    branch,
+                    mode,
+                    jobs,
+                    args.pipe,
+                    args.yes,
+                    time_budget,
+                    &reporters,
+                )
+                .await;
+                telemetry::export().await;
+                result
+            }
+            Command::ShowNote { rev } => notes::show_note(&rev),
+            Command::Index => index::build_index(rvconfig, args.yes).await,
+            Command::Conflicts => {
+                let result =
+                    conflicts::resolve_conflicts(rvconfig, args.llm, args.pipe, args.yes).await;
+                telemetry::export().await;
+                result
+            }
+            Command::CheckGuidelines { rev, json } => {
+                let result = compliance::check_guidelines(
+                    rvconfig,
+                    args.llm,
+                    &rev,
+                    json.as_deref(),
+                    args.pipe,
+                    args.yes,
+                )
+                .await;
+                telemetry::export().await;
+                result
+            }
+            Command::Narrate { range } => {
+                let result =
+                    narrate::narrate(rvconfig, args.llm, &range, args.pipe, args.yes).await;
+                telemetry::export().await;
+                result
+            }
+            Command::Clean {
+                older_than,
+                dry_run,
+            } => clean::clean(older_than, dry_run),
+            Command::Paths => paths::print_paths(),
+            Command::Config {
+                command:
+                    ConfigCommand::AddLlm {
+                        preset,
+                        name,
+                        model,
+                        validate,
+                    },
+            } => presets::add_llm(&preset, name, model, validate).await,
+            Command::Config {
+                command: ConfigCommand::Presets,
+            } => presets::print_presets(),
+            Command::Snippet { clipboard, lang } => {
+                let result = review::snippet_review(
+                    rvconfig,
+                    args.llm,
+                    clipboard,
+                    lang,
+                    args.pipe,
+                    args.chat,
+                    options.actions_menu,
+                    args.load_context,
+                    args.yes,
+                    &reporters,
+                )
+                .await;
+                telemetry::export().await;
+                result
+            }
+            Command::Unpushed => unreachable!("handled as a range review"),
+            Command::Pr { .. } => unreachable!("handled by resolve_target"),
+            Command::Review { .. }
+            | Command::Staged
+            | Command::Commit { .. }
+            | Command::Branch { .. }
+            | Command::File { .. } => unreachable!("handled by resolve_target"),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e}");
+            std::process::exit(report::exit_code(&e));
+        }
+        return;
+    }
 
     if args.raw {
-        if let Err(e) = review::raw_review(
+        let result = review::raw_review(
             rvconfig,
             args.llm,
             args.file,
             args.dir,
-            Some(args.recursive),
+            options.recursive,
+            args.force_include,
+            args.follow_symlinks,
+            args.hidden,
+            args.no_ignore,
+            args.with_imports,
             args.pipe,
             args.chat,
-            args.actions_menu,
+            options.actions_menu,
             args.load_context,
+            args.yes,
+            time_budget,
+            &reporters,
         )
-        .await
-        {
-            eprintln!("Error during raw review: {e}");
-            std::process::exit(1);
+        .await;
+        telemetry::export().await;
+        if let Err(e) = result {
+            eprintln!("Error during raw review: {e:#}");
+            std::process::exit(report::exit_code(&e));
         }
     } else {
-        // Check that only 0 or 1 arguments between commit, branch or pr are used
-        // In order to make it smaller, it turns boolean values to u8 and sums them in order to get the number of enabled args
-        let enabled_git_args: u8 =
-            args.commit.is_some() as u8 + args.branch.is_some() as u8 + args.pr.is_some() as u8;
-
-        if enabled_git_args > 1 {
-            println!(
-                "[ERROR] You can enable only one parameter between --commit, --branch or --pr"
-            );
-        } else if let Err(e) = review::git_review(
+        let path_filter = match git_helpers::PathFilter::new(
+            args.file.into_iter().collect(),
+            args.dir.into_iter().collect(),
+            &args.glob,
+            options.recursive,
+        ) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("[ERROR] Invalid glob pattern: {e}");
+                std::process::exit(1);
+            }
+        };
+
+        let result = review::git_review(
             rvconfig,
             args.llm,
             args.commit,
             args.branch,
-            args.branch_mode,
-            args.pr,
-            Some(args.log_xml_structure),
+            args.against,
+            pr,
+            args.range,
+            args.per_commit,
+            args.squashed,
+            args.since_review,
+            path_filter,
+            options.log_xml_structure,
             args.pipe,
             args.chat,
-            args.actions_menu,
+            options.actions_menu,
             args.load_context,
+            args.yes,
+            time_budget,
+            &reporters,
         )
-        .await
-        {
-            eprintln!("Error during code review: {e}");
-            std::process::exit(1);
+        .await;
+        telemetry::export().await;
+        if let Err(e) = result {
+            eprintln!("Error during code review: {e:#}");
+            std::process::exit(report::exit_code(&e));
         }
     }
 }
@@ -0,0 +1,334 @@
+//! Prompt-size optimization of Git diffs: trims the unchanged context of every hunk and drops the
+//! whitespace-only hunks, working on the typed patches of an `ExpandedCommit`
+
+use crate::config::RvConfig;
+use crate::context::estimate_tokens;
+use crate::git_helpers::{DiffLine, ExpandedCommit, FilePatch, Hunk};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// What the minimization pass removes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimizeOptions {
+    /// Context lines kept around the changes of a hunk (git keeps 3)
+    pub context_lines: Option<usize>,
+    /// Drop the hunks whose changes only touch whitespace
+    pub ignore_whitespace: bool,
+}
+
+impl MinimizeOptions {
+    pub fn from_config(rvconfig: &RvConfig) -> MinimizeOptions {
+        MinimizeOptions {
+            context_lines: rvconfig.diff_context_lines,
+            ignore_whitespace: rvconfig.ignore_whitespace,
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.context_lines.is_none() && !self.ignore_whitespace
+    }
+}
+
+/// What the minimization pass saved
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimizeSavings {
+    pub context_lines: usize,
+    pub whitespace_hunks: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+}
+
+impl MinimizeSavings {
+    /// One-line report of the savings, `None` when nothing was removed
+    pub fn summary(&self) -> Option<String> {
+        if self.context_lines == 0 && self.whitespace_hunks == 0 {
+            return None;
+        }
+        Some(format!(
+            "[MINIMIZED] Removed {} context lines and {} whitespace-only hunks, ~{} tokens saved ({} -> {})",
+            self.context_lines,
+            self.whitespace_hunks,
+            self.tokens_before.saturating_sub(self.tokens_after),
+            self.tokens_before,
+            self.tokens_after
+        ))
+    }
+}
+
+/// Split `hunk` around the context lines further than `context` lines from any change,
+/// returning the remaining hunks and the number of removed lines
+fn trim_context(hunk: &Hunk, context: usize) -> (Vec<Hunk>, usize) {
+    let changes: Vec<usize> = (0..hunk.lines.len())
+        .filter(|idx| hunk.lines[*idx].is_change())
+        .collect();
+    let near_change = |idx: usize| changes.iter().any(|change| change.abs_diff(idx) <= context);
+
+    let mut keep = vec![false; hunk.lines.len()];
+    for (idx, line) in hunk.lines.iter().enumerate() {
+        keep[idx] = match line.origin {
+            ' ' => near_change(idx),
+            // End-of-file newline markers follow the line they describe
+            '=' | '>' | '<' => idx > 0 && keep[idx - 1],
+            _ => true,
+        };
+    }
+    let removed = keep.iter().filter(|kept| !**kept).count();
+    if removed == 0 {
+        return (vec![hunk.clone()], 0);
+    }
+
+    // Every run of kept lines becomes a hunk, with the line numbers at its start
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut old_line, mut new_line) = (hunk.old_start, hunk.new_start);
+    let mut in_run = false;
+    for (line, kept) in hunk.lines.iter().zip(keep) {
+        if kept {
+            if !in_run {
+                hunks.push(Hunk {
+                    old_start: old_line,
+                    old_lines: 0,
+                    new_start: new_line,
+                    new_lines: 0,
+                    section: if hunks.is_empty() {
+                        hunk.section.clone()
+                    } else {
+                        String::from("\n")
+                    },
+                    lines: Vec::new(),
+                });
+            }
+            if let Some(current) = hunks.last_mut() {
+                match line.origin {
+                    ' ' => {
+                        current.old_lines += 1;
+                        current.new_lines += 1;
+                    }
+                    '-' => current.old_lines += 1,
+                    '+' => current.new_lines += 1,
+                    _ => {}
+                }
+                current.lines.push(line.clone());
+            }
+        }
+        in_run = kept;
+        match line.origin {
+            ' ' => {
+                old_line += 1;
+                new_line += 1;
+            }
+            '-' => old_line += 1,
+            '+' => new_line += 1,
+            _ => {}
+        }
+    }
+    // Like git, an empty side starts at the line before the change
+    for hunk in &mut hunks {
+        if hunk.old_lines == 0 {
+            hunk.old_start = hunk.old_start.saturating_sub(1);
+        }
+        if hunk.new_lines == 0 {
+            hunk.new_start = hunk.new_start.saturating_sub(1);
+        }
+    }
+    (hunks, removed)
+}
+
+/// The removed and added lines of `hunk` only differ in whitespace, leaving the indentation
+/// of every line unchanged since it is meaningful in languages such as Python or YAML
+fn is_whitespace_only(hunk: &Hunk) -> bool {
+    let lines = |origin: char| {
+        hunk.lines
+            .iter()
+            .filter(move |line| line.origin == origin && !line.content.trim().is_empty())
+    };
+    let text = |origin: char| -> String {
+        lines(origin)
+            .flat_map(|line: &DiffLine| line.content.chars())
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    };
+    let indentation = |origin: char| -> Vec<&str> {
+        lines(origin)
+            .map(|line| {
+                let content = line.content.as_str();
+                &content[..content.len() - content.trim_start().len()]
+            })
+            .collect()
+    };
+    text('-') == text('+') && indentation('-') == indentation('+')
+}
+
+/// Minimized copy of `patch`
+fn minimize_patch(
+    patch: &FilePatch,
+    options: &MinimizeOptions,
+    savings: &mut MinimizeSavings,
+) -> FilePatch {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for hunk in &patch.hunks {
+        let trimmed = match options.context_lines {
+            Some(context) => {
+                let (trimmed, removed) = trim_context(hunk, context);
+                savings.context_lines += removed;
+                trimmed
+            }
+            None => vec![hunk.clone()],
+        };
+        for hunk in trimmed {
+            if options.ignore_whitespace && is_whitespace_only(&hunk) {
+                savings.whitespace_hunks += 1;
+            } else {
+                hunks.push(hunk);
+            }
+        }
+    }
+    FilePatch {
+        hunks,
+        ..patch.clone()
+    }
+}
+
+/// Minimize the diffs of `expcommit`; files left with only whitespace changes are reported as skipped
+pub fn minimize(
+    mut expcommit: ExpandedCommit,
+    options: &MinimizeOptions,
+) -> (ExpandedCommit, MinimizeSavings) {
+    let mut savings = MinimizeSavings::default();
+    if options.is_noop() {
+        return (expcommit, savings);
+    }
+    let (Some(diffs), Some(sources)) = (expcommit.diffs.as_mut(), expcommit.sources.as_ref())
+    else {
+        return (expcommit, savings);
+    };
+
+    let mut whitespace_only: BTreeSet<PathBuf> = BTreeSet::new();
+    for (diff, source) in diffs.iter_mut().zip(sources) {
+        let Some(patch) = expcommit.patches.get(source) else {
+            continue;
+        };
+        let minimized = minimize_patch(patch, options, &mut savings);
+        if !patch.hunks.is_empty() && minimized.hunks.is_empty() {
+            whitespace_only.insert(source.clone());
+        }
+        let rendered = minimized.render();
+        savings.tokens_before += estimate_tokens(diff);
+        savings.tokens_after += estimate_tokens(&rendered);
+        *diff = rendered;
+    }
+
+    if !whitespace_only.is_empty() {
+        expcommit = expcommit.exclude(
+            |path| whitespace_only.contains(path),
+            "whitespace-only changes",
+        );
+    }
+    (expcommit, savings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hunk of `lines`, each starting with its origin, with the line ranges computed from them
+    fn hunk(old_start: u32, new_start: u32, lines: &[&str]) -> Hunk {
+        let lines: Vec<DiffLine> = lines
+            .iter()
+            .map(|line| DiffLine {
+                origin: line.chars().next().unwrap(),
+                content: format!("{}\n", &line[1..]),
+            })
+            .collect();
+        let count = |origins: &[char]| {
+            lines
+                .iter()
+                .filter(|line| origins.contains(&line.origin))
+                .count() as u32
+        };
+        Hunk {
+            old_start,
+            old_lines: count(&[' ', '-']),
+            new_start,
+            new_lines: count(&[' ', '+']),
+            section: String::from(" fn main()\n"),
+            lines,
+        }
+    }
+
+    fn header(hunk: &Hunk) -> (u32, u32, u32, u32) {
+        (
+            hunk.old_start,
+            hunk.old_lines,
+            hunk.new_start,
+            hunk.new_lines,
+        )
+    }
+
+    #[test]
+    fn trims_the_context_far_from_the_changes() {
+        let original = hunk(10, 10, &[" a", " b", " c", "-d", "+D", " e", " f", " g"]);
+        let (hunks, removed) = trim_context(&original, 1);
+        assert_eq!(removed, 4);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(header(&hunks[0]), (12, 3, 12, 3));
+        assert_eq!(hunks[0].section, " fn main()\n");
+        let origins: String = hunks[0].lines.iter().map(|line| line.origin).collect();
+        assert_eq!(origins, " -+ ");
+    }
+
+    #[test]
+    fn splits_the_hunk_between_distant_changes() {
+        let original = hunk(1, 1, &[" a", "-b", " c", " d", " e", " f", "+g", " h"]);
+        let (hunks, removed) = trim_context(&original, 1);
+        assert_eq!(removed, 2);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(header(&hunks[0]), (1, 3, 1, 2));
+        // The second hunk starts one line earlier on the new side, which lacks the removed line
+        assert_eq!(header(&hunks[1]), (6, 2, 5, 3));
+        assert_eq!(hunks[1].section, "\n");
+    }
+
+    #[test]
+    fn starts_an_empty_side_at_the_line_before_the_change() {
+        let original = hunk(4, 4, &[" a", " b", "+c", " d", " e"]);
+        let (hunks, _) = trim_context(&original, 0);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(header(&hunks[0]), (5, 0, 6, 1));
+    }
+
+    #[test]
+    fn keeps_a_hunk_without_context_to_trim() {
+        let original = hunk(3, 3, &[" a", "-b", "+B", " c"]);
+        let (hunks, removed) = trim_context(&original, 3);
+        assert_eq!(removed, 0);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(header(&hunks[0]), (3, 3, 3, 3));
+    }
+
+    #[test]
+    fn drops_the_changes_of_inner_and_trailing_whitespace() {
+        assert!(is_whitespace_only(&hunk(
+            1,
+            1,
+            &["-    x = f(a,b)", "+    x = f(a, b)  "]
+        )));
+        assert!(is_whitespace_only(&hunk(1, 1, &["-a", "+a", "+"])));
+    }
+
+    #[test]
+    fn keeps_the_changes_of_indentation() {
+        // Dedenting moves the statement out of the `if` block in Python
+        assert!(!is_whitespace_only(&hunk(
+            1,
+            1,
+            &[" if ready:", "-    start()", "+start()"]
+        )));
+        // Nesting a key changes the document in YAML
+        assert!(!is_whitespace_only(&hunk(
+            1,
+            1,
+            &[" a:", "-b: 1", "+  b: 1"]
+        )));
+        assert!(!is_whitespace_only(&hunk(1, 1, &["-a", "+b"])));
+    }
+}
@@ -0,0 +1,160 @@
+//! `rv narrate`: reviewer-oriented narrative of a commit series, to clean up history before review
+
+use crate::config::RvConfig;
+use crate::context::estimate_tokens;
+use crate::git_helpers;
+use crate::history::{self, Usage};
+use crate::ignore::IgnoreRules;
+use crate::llm::create_llm_provider;
+use crate::markup;
+use crate::request::{self, RequestPipeline};
+use crate::term_helpers;
+
+use anyhow::{Context, Result};
+use rig::message::Message;
+
+/// Estimated tokens of a single commit diff in the prompt; longer diffs are cut
+const MAX_COMMIT_DIFF_TOKENS: usize = 4_000;
+
+const NARRATE_SYSTEM_PROMPT: &str = r#"
+You are a senior software engineer helping an author prepare a commit
+series for review. You will receive every commit of the series, oldest
+first, each one with its message and its diff. Do NOT review the code.
+Tell the story of the series as a reviewer will read it, and how the
+history could be made easier to review. Follow these rules exactly.
+
+OUTPUT FORMAT & STYLE
+- ASCII only. No emojis, no markdown, no color codes.
+- Soft-wrap at ~80 columns.
+- Refer to commits by their short oid (first 8 characters).
+
+STRICT STRUCTURE (in this exact order)
+1) STORY: a few sentences describing what the series does, step by step.
+2) GROUPS: the logical groups of commits (ex. preparation, feature,
+   tests, cleanup), one line each with the commits they contain.
+3) ORDERING: commits that depend on later ones, or that would read
+   better in another order, with the suggested order.
+4) SQUASH / SPLIT: fixups to squash into the commit they fix, and
+   commits mixing unrelated changes that should be split.
+5) MESSAGES: commit messages that don't describe their content, with a
+   better one-line summary.
+6) VERDICT: one sentence, "ready for review" or the main cleanup to do.
+
+=============================
+"#;
+
+/// Cut `diff` to about `MAX_COMMIT_DIFF_TOKENS`, on a line boundary
+fn cut_diff(diff: &str) -> String {
+    if estimate_tokens(diff) <= MAX_COMMIT_DIFF_TOKENS {
+        return diff.to_string();
+    }
+    let mut cut = String::new();
+    for line in diff.lines() {
+        if estimate_tokens(&cut) + estimate_tokens(line) > MAX_COMMIT_DIFF_TOKENS {
+            break;
+        }
+        cut.push_str(line);
+        cut.push('\n');
+    }
+    cut.push_str("[diff cut, the rest of the commit is not shown]\n");
+    cut
+}
+
+/// Narrate the commits of `range` (`base..head`)
+pub async fn narrate(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    range: &str,
+    pipe: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let pipeline = RequestPipeline::new(rvconfig)?;
+
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let (base_oid, head_oid) =
+        git_helpers::resolve_range(range).context("Failed to resolve the commit range")?;
+    let commits = git_helpers::commits_between(base_oid, head_oid)
+        .context("Failed to list the commits of the series")?;
+    if commits.is_empty() {
+        println!(
+            "[ERROR] Range {range} has no commits; check that it is written as `[base]..[head]`, with the older commit first."
+        );
+        return Ok(());
+    }
+
+    // Every commit with its message and its diff, without the files excluded by `.rvignore`
+    let ignore = IgnoreRules::from_rvignore();
+    let mut prompt = String::new();
+    let mut paths: Vec<String> = Vec::new();
+    for oid in &commits {
+        let message = git_helpers::commit_message(*oid)?;
+        let expanded = git_helpers::expanded_from_commit(*oid)?
+            .exclude(|path| ignore.is_path_ignored(path), "excluded by .rvignore");
+        let mut diffs = String::new();
+        for (diff, path) in expanded
+            .diffs
+            .iter()
+            .flatten()
+            .zip(expanded.sources.iter().flatten())
+        {
+            paths.push(path.to_string_lossy().to_string());
+            diffs.push_str(&markup::element(
+                "diff",
+                &[("path", &markup::path_text(path))],
+                &cut_diff(diff),
+            ));
+        }
+        prompt.push_str(&format!(
+            "<commit oid=\"{oid}\">\n{}{diffs}</commit>\n",
+            markup::element("commit_message", &[], &message)
+        ));
+    }
+    let mut prompt = pipeline.redact(prompt);
+
+    let (llm_configuration_key, llm_configuration) =
+        pipeline.select_llm(llm_selection, estimate_tokens(&prompt), &paths, pipe)?;
+    let system_prompt = pipeline.system_prompt(NARRATE_SYSTEM_PROMPT.to_string());
+    // The messages and the diffs are untrusted
+    pipeline.prepare(
+        &llm_configuration,
+        &system_prompt,
+        std::iter::once(&mut prompt),
+    )?;
+
+    let input_tokens = estimate_tokens(&system_prompt) + estimate_tokens(&prompt);
+    if !request::confirm_requests(
+        &llm_configuration,
+        paths.len(),
+        input_tokens,
+        None,
+        assume_yes,
+    )? {
+        return Ok(());
+    }
+
+    if !pipe {
+        term_helpers::clear_term();
+        println!("[Narrating {} commits of {range}]\n", commits.len());
+    }
+    let client = create_llm_provider(llm_configuration.clone());
+    let narration = term_helpers::print_stream(
+        client.stream_request(system_prompt, vec![Message::user(prompt)])?,
+        pipeline.rvconfig.normalize_output,
+    )
+    .await?;
+    let usage = Usage::estimate(
+        &llm_configuration,
+        input_tokens,
+        estimate_tokens(&narration),
+    );
+    history::save_command_usage(
+        &pipeline.rvconfig,
+        format!("narration of {range}"),
+        &llm_configuration_key,
+        &llm_configuration,
+        paths,
+        narration,
+        usage,
+    );
+    Ok(())
+}
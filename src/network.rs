@@ -0,0 +1,274 @@
+//! `--no-network`: a dry run making no outbound connection, the requests to the model being
+//! printed instead of sent by the `ExportProvider`; every other path reaching the network
+//! (embeddings, trackers, webhooks, `gh`, `git fetch`, OTLP) goes through `ensure_allowed` first
+
+use crate::llm::defs::{LLMProvider, TokenStream};
+
+use anyhow::Result;
+#[cfg(not(test))]
+use anyhow::bail;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use rig::message::{AssistantContent, Message, UserContent};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(not(test))]
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Each test thread has its own flag, so that the tests of `--no-network` don't cut off the others
+#[cfg(test)]
+thread_local! {
+    static DISABLED: AtomicBool = const { AtomicBool::new(false) };
+}
+
+fn set_disabled(disabled: bool) {
+    #[cfg(not(test))]
+    DISABLED.store(disabled, Ordering::SeqCst);
+    #[cfg(test)]
+    DISABLED.with(|flag| flag.store(disabled, Ordering::SeqCst));
+}
+
+/// Forbid every outbound connection for the rest of the run
+pub fn disable() {
+    set_disabled(true);
+}
+
+/// Allow the outbound connections again, at the end of a test run without network
+#[cfg(test)]
+pub(crate) fn enable() {
+    set_disabled(false);
+}
+
+/// Whether outbound connections are forbidden (`--no-network`)
+pub fn is_disabled() -> bool {
+    #[cfg(not(test))]
+    let disabled = DISABLED.load(Ordering::SeqCst);
+    #[cfg(test)]
+    let disabled = DISABLED.with(|flag| flag.load(Ordering::SeqCst));
+    disabled
+}
+
+/// Fail before `what` reaches the network when it's disabled. The run skips these paths
+/// beforehand, the callers only fall back on this error for the ones it missed; in the tests,
+/// reaching one of them is a bug and panics
+pub fn ensure_allowed(what: &str) -> Result<()> {
+    if is_disabled() {
+        #[cfg(test)]
+        panic!("{what} tried to reach the network, which is disabled by --no-network");
+        #[cfg(not(test))]
+        bail!("{what} needs the network, which is disabled by --no-network");
+    }
+    Ok(())
+}
+
+/// Response of the requests exported instead of sent
+pub const EXPORTED_RESPONSE: &str =
+    "[NO NETWORK] The request was printed instead of sent, there is no response.";
+
+/// Text of a message, without its images or tool calls
+fn message_text(message: &Message) -> String {
+    let texts: Vec<&str> = match message {
+        Message::User { content } => content
+            .iter()
+            .filter_map(|item| match item {
+                UserContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect(),
+        Message::Assistant { content, .. } => content
+            .iter()
+            .filter_map(|item| match item {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect(),
+    };
+    texts.join("\n")
+}
+
+/// Provider of the runs without network: every request is printed instead of sent, and
+/// answered with `EXPORTED_RESPONSE`
+pub struct ExportProvider {
+    pub provider_name: String,
+}
+
+impl ExportProvider {
+    fn export(&self, sys_prompt: &str, messages: &[Message]) -> String {
+        let mut request = sys_prompt.to_string();
+        for message in messages {
+            request.push_str("\n\n");
+            request.push_str(&message_text(message));
+        }
+        println!(
+            "[NO NETWORK] Nothing was sent; request prepared for {}:\n\n{request}\n",
+            self.provider_name
+        );
+        String::from(EXPORTED_RESPONSE)
+    }
+}
+
+impl LLMProvider for ExportProvider {
+    fn get_provider_name(&self) -> String {
+        self.provider_name.clone()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let response = self.export(&sys_prompt, &messages);
+        Ok(futures::stream::once(async move { Ok(response) }).boxed())
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let response = self.export(&sys_prompt, &messages);
+        async move { Ok(response) }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LLMConfig, SandboxConfig, SandboxIsolation};
+    use crate::report::{Report, Reporter, Verdict};
+    use crate::sandbox::Sandbox;
+    use crate::test_support::Offline;
+    use crate::tickets::{JiraTracker, Tracker};
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use rig::message::Message;
+    use std::collections::BTreeMap;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+    use std::path::Path;
+
+    /// Run `attempt` without network: it must panic before connecting
+    fn assert_blocked<T>(attempt: impl FnOnce() -> Result<T>) {
+        let _offline = Offline::enter();
+        let Err(panic) = catch_unwind(AssertUnwindSafe(attempt)) else {
+            panic!("the connection was allowed");
+        };
+        let message = panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or_default();
+        assert!(
+            message.contains("--no-network"),
+            "unexpected failure: {message}"
+        );
+    }
+
+    fn report() -> Report {
+        Report {
+            id: None,
+            target: String::from("HEAD"),
+            verdict: Verdict::Reviewed,
+            commit: None,
+            model: String::from("m"),
+            tags: Vec::new(),
+            review: String::from("LGTM"),
+            reviewed_files: Vec::new(),
+            not_reviewed: Vec::new(),
+            suppressed: Vec::new(),
+            checks: Vec::new(),
+            fixes: Vec::new(),
+            complexity: Vec::new(),
+            coverage: None,
+            structured: None,
+            prompt: String::new(),
+            patches: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn blocks_the_webhook() {
+        let webhook = crate::report::WebhookReporter {
+            url: String::from("http://127.0.0.1:9/hook"),
+        };
+        assert_blocked(|| block_on(webhook.report(&report())));
+    }
+
+    #[test]
+    fn blocks_the_pull_request_comment() {
+        let comment = crate::report::GitHubCommentReporter {
+            pr: String::from("1"),
+        };
+        assert_blocked(|| block_on(comment.report(&report())));
+    }
+
+    #[test]
+    fn blocks_the_embedding_api() {
+        let llm_config = LLMConfig::default();
+        assert_blocked(|| {
+            block_on(crate::llm::embeddings::embed_texts(
+                &llm_config,
+                "text-embedding-3-small",
+                vec![String::from("fn main() {}")],
+            ))
+        });
+    }
+
+    #[test]
+    fn blocks_the_issue_tracker() {
+        let jira = JiraTracker {
+            url: String::from("http://127.0.0.1:9"),
+            user: String::from("rv"),
+            token: String::from("token"),
+        };
+        assert_blocked(|| block_on(jira.fetch("RV-1")));
+    }
+
+    #[test]
+    fn exports_the_llm_requests() {
+        let provider = {
+            let _offline = Offline::enter();
+            crate::llm::create_llm_provider(LLMConfig::default())
+        };
+        let messages = vec![Message::user("Review this diff")];
+        let response = block_on(provider.request(String::from("You review code."), messages));
+        assert_eq!(response.unwrap(), EXPORTED_RESPONSE);
+
+        let stream = provider
+            .stream_request(String::new(), vec![Message::user("Hello")])
+            .unwrap();
+        let tokens: Vec<String> = block_on(stream.map(Result::unwrap).collect());
+        assert_eq!(tokens, [EXPORTED_RESPONSE]);
+    }
+
+    #[test]
+    fn renders_the_text_of_the_exported_messages() {
+        assert_eq!(message_text(&Message::user("fn main() {}")), "fn main() {}");
+        assert_eq!(message_text(&Message::assistant("LGTM")), "LGTM");
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn blocks_the_otlp_exporter() {
+        crate::telemetry::init(Some("http://127.0.0.1:9"));
+        crate::telemetry::span("step", &[]).end();
+        assert_blocked(|| block_on(crate::telemetry::otel::export()));
+    }
+
+    #[test]
+    fn blocks_the_project_commands_without_isolation() {
+        let sandbox = Sandbox::new(&SandboxConfig::default());
+        assert_blocked(|| sandbox.run("true", Path::new(".")));
+
+        let networked = Sandbox::new(&SandboxConfig {
+            isolation: SandboxIsolation::Bwrap,
+            network: true,
+            ..SandboxConfig::default()
+        });
+        assert_blocked(|| networked.run("true", Path::new(".")));
+    }
+
+    #[test]
+    fn reenables_the_network_after_the_guard() {
+        {
+            let _offline = Offline::enter();
+            assert!(is_disabled());
+        }
+        assert!(!is_disabled());
+        assert!(ensure_allowed("The test").is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+//! Next steps printed at the end of a review: commands picked from its outcome (verdict,
+//! severity, findings) and from what the run can reach (GitHub CLI, other LLM configurations)
+
+use crate::findings::{finding_items, review_severity, review_verdict};
+use crate::report::{Report, Reporter};
+
+use anyhow::Result;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::path::Path;
+
+/// Folder suggested for saving the report with `--artifact`
+const ARTIFACT_DIR: &str = "rv-review";
+
+/// Verdict of a review, from its FINAL VERDICT section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Approve,
+    RequestChanges,
+    Block,
+    Unknown,
+}
+
+impl Verdict {
+    fn parse(verdict: &str) -> Verdict {
+        let verdict = verdict.trim().to_ascii_lowercase();
+        if verdict.starts_with("approve") {
+            Verdict::Approve
+        } else if verdict.starts_with("block") {
+            Verdict::Block
+        } else if verdict.starts_with("request") {
+            Verdict::RequestChanges
+        } else {
+            Verdict::Unknown
+        }
+    }
+}
+
+/// Outcome of a complete review, the next steps are picked from
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub verdict: Verdict,
+    /// Verdict as written by the model (ex. `Request changes: validate the input`)
+    pub verdict_text: Option<String>,
+    pub severity: Option<&'static str>,
+    pub findings: usize,
+    /// Whether the suggested fixes were applied and built (`--verify-fixes`)
+    pub fixes_verified: bool,
+    pub model: String,
+}
+
+impl Completion {
+    pub fn of(report: &Report) -> Completion {
+        let verdict_text = review_verdict(&report.review);
+        Completion {
+            verdict: verdict_text
+                .as_deref()
+                .map_or(Verdict::Unknown, Verdict::parse),
+            verdict_text,
+            severity: review_severity(&report.review),
+            findings: finding_items(&report.review).len(),
+            fixes_verified: !report.fixes.is_empty(),
+            model: report.model.clone(),
+        }
+    }
+
+    /// Whether the review asks for changes before merging
+    fn needs_changes(&self) -> bool {
+        match self.verdict {
+            Verdict::RequestChanges | Verdict::Block => true,
+            Verdict::Approve => false,
+            Verdict::Unknown => self.findings > 0,
+        }
+    }
+
+    fn is_serious(&self) -> bool {
+        self.verdict == Verdict::Block || matches!(self.severity, Some("CRITICAL" | "HIGH"))
+    }
+}
+
+/// What the run can do after the review, besides what it already did
+#[derive(Debug, Clone, Default)]
+pub struct NextStepsContext {
+    /// Command reviewing the same target again (ex. `rv commit abc123`), when there is one
+    pub rerun: Option<String>,
+    /// LLM configuration chosen with `-l`, kept by the re-runs
+    pub llm: Option<String>,
+    /// Pull request of the review
+    pub pr: Option<String>,
+    /// The review was already posted to the pull request (`--comment`)
+    pub commented: bool,
+    /// The review was already saved (`--artifact`, `--json-out`)
+    pub saved: bool,
+    /// The findings were already walked through (`--triage`)
+    pub triaged: bool,
+    /// Names and models of the LLM configurations, for a second opinion
+    pub llm_configs: Vec<(String, String)>,
+    /// Re-runs with the same model get the cached review, without a request
+    pub cached: bool,
+}
+
+/// Whether `program` is an executable of `PATH`
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            let path = dir.join(program);
+            path.is_file() || Path::new(&format!("{}.exe", path.display())).is_file()
+        })
+    })
+}
+
+/// Commands to run next and what they do, the most useful first
+pub fn next_steps(completion: &Completion, context: &NextStepsContext) -> Vec<(String, String)> {
+    let mut steps = Vec::new();
+    if let Some(target) = &context.rerun {
+        let rerun = match &context.llm {
+            Some(llm) => format!("{target} -l {llm}"),
+            None => target.clone(),
+        };
+        if completion.findings > 0 && !completion.fixes_verified {
+            steps.push((
+                format!("{rerun} --verify-fixes"),
+                String::from("apply the suggested fixes to a worktree and build them"),
+            ));
+        }
+        if completion.is_serious()
+            && let Some((name, model)) = context
+                .llm_configs
+                .iter()
+                .find(|(_, model)| *model != completion.model)
+        {
+            steps.push((
+                format!("{target} -l {name}"),
+                format!("get a second opinion from {model}"),
+            ));
+        }
+        if completion.findings > 0 && !context.triaged {
+            steps.push((
+                format!("{rerun} --triage"),
+                String::from("accept, dismiss or turn the findings into issues"),
+            ));
+        }
+        if !context.saved {
+            steps.push((
+                format!("{rerun} --artifact {ARTIFACT_DIR}"),
+                String::from("save the report, the findings and the prompt"),
+            ));
+        }
+    }
+    if let Some(pr) = &context.pr
+        && !context.commented
+        && on_path("gh")
+    {
+        let what = if completion.needs_changes() {
+            "post the findings to the pull request"
+        } else {
+            "post the approval to the pull request"
+        };
+        steps.push((format!("rv pr {pr} --comment"), String::from(what)));
+    }
+    steps
+}
+
+/// Next steps of a review, as shown in the terminal
+pub fn format_next_steps(completion: &Completion, context: &NextStepsContext) -> Option<String> {
+    let steps = next_steps(completion, context);
+    if steps.is_empty() {
+        return None;
+    }
+    let width = steps
+        .iter()
+        .map(|(command, _)| command.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut text = match &completion.verdict_text {
+        Some(verdict) => format!("NEXT STEPS ({verdict})\n"),
+        None => String::from("NEXT STEPS\n"),
+    };
+    for (command, what) in steps {
+        text.push_str(&format!("  {command:<width$}  {what}\n"));
+    }
+    if context.cached {
+        text.push_str(
+            "  (re-runs with the same model show the cached review, without a request)\n",
+        );
+    }
+    Some(text)
+}
+
+/// Print the next steps once the other reporters are done
+pub struct NextStepsReporter {
+    pub context: NextStepsContext,
+}
+
+impl Reporter for NextStepsReporter {
+    fn name(&self) -> &'static str {
+        "next steps"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if let Some(text) = format_next_steps(&Completion::of(report), &self.context) {
+                println!("\n{}", text.trim_end());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,52 @@
+//! Reviews stored as git notes (`refs/notes/rv`) on the reviewed commits, so that they are
+//! versioned with the repository (`git push origin refs/notes/rv`) without external storage
+
+use crate::config::NotesMerge;
+use crate::git_helpers;
+use anyhow::{Context, Result};
+use git2::{Oid, Signature};
+
+pub const NOTES_REF: &str = "refs/notes/rv";
+
+/// Separates the reviews appended to the same note
+const NOTE_SEPARATOR: &str = "\n\n----------\n\n";
+
+/// Attach `text` to `commit`, merging it with the existing note as configured.
+/// Returns `false` when the existing note was kept.
+pub fn write_note(commit: &str, text: &str, merge: NotesMerge) -> Result<bool> {
+    let repo = git_helpers::open_repo()?;
+    let oid = Oid::from_str(commit)?;
+    let existing = repo
+        .find_note(Some(NOTES_REF), oid)
+        .ok()
+        .and_then(|note| note.message().map(str::to_string));
+
+    let note = match (existing, merge) {
+        (Some(_), NotesMerge::Keep) => return Ok(false),
+        (Some(existing), NotesMerge::Append) => {
+            format!("{}{NOTE_SEPARATOR}{text}", existing.trim_end())
+        }
+        _ => text.to_string(),
+    };
+    // Notes are authored by the user when git knows who they are
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("rv", "rv@localhost"))?;
+    repo.note(&signature, &signature, Some(NOTES_REF), oid, &note, true)
+        .with_context(|| format!("Failed to write the note of commit {oid}"))?;
+    Ok(true)
+}
+
+/// Print the review note of `rev`
+pub fn show_note(rev: &str) -> Result<()> {
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+    let oid = git_helpers::get_oid(rev).with_context(|| format!("Failed to resolve {rev}"))?;
+    let repo = git_helpers::open_repo()?;
+    match repo.find_note(Some(NOTES_REF), oid) {
+        Ok(note) => println!("{}", note.message().unwrap_or_default()),
+        Err(_) => println!(
+            "[NO NOTE] Commit {oid} has no review in {NOTES_REF}; store one with `rv --notes`, or fetch them with `git fetch origin {NOTES_REF}:{NOTES_REF}`."
+        ),
+    }
+    Ok(())
+}
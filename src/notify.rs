@@ -0,0 +1,132 @@
+//! Desktop notifications (`--notify` or `[notify]`): once a long review is done, its severity and
+//! the count of its findings are shown by the notification service of the platform
+
+use crate::config::NotifyConfig;
+use crate::findings::{SEVERITIES, finding_items, finding_severity, review_severity};
+use crate::report::{Report, Reporter};
+
+use anyhow::{Result, bail};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Sound of the notifications of macOS
+const MACOS_SOUND: &str = "Submarine";
+
+/// Title and body of the notification of `report`, reviewed in `elapsed`
+fn notification_text(report: &Report, elapsed: Duration) -> (String, String) {
+    let verdict = review_severity(&report.review).unwrap_or("no severity");
+    let items = finding_items(&report.review);
+    let counts: Vec<String> = SEVERITIES
+        .iter()
+        .filter_map(|severity| {
+            let count = items
+                .iter()
+                .filter(|item| finding_severity(&report.review, item) == Some(*severity))
+                .count();
+            (count > 0).then(|| format!("{count} {severity}"))
+        })
+        .collect();
+    let findings = match (items.len(), counts.is_empty()) {
+        (0, _) => "No findings".to_string(),
+        (1, true) => "1 finding".to_string(),
+        (count, true) => format!("{count} findings"),
+        (1, false) => format!("1 finding ({})", counts.join(", ")),
+        (count, false) => format!("{count} findings ({})", counts.join(", ")),
+    };
+    let secs = elapsed.as_secs();
+    (
+        format!("rv: {verdict} - {}", report.target),
+        format!("{findings}, reviewed in {}m {:02}s", secs / 60, secs % 60),
+    )
+}
+
+/// Show a notification through `notify-send` (Linux, BSD) or `osascript` (macOS)
+fn send_notification(title: &str, body: &str, sound: bool) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let sound = if sound {
+            format!(" sound name \"{MACOS_SOUND}\"")
+        } else {
+            String::new()
+        };
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{}\"{sound}",
+            escape(body),
+            escape(title)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command
+            .args(["--app-name", "rv", "--urgency"])
+            .arg(if sound { "critical" } else { "normal" });
+        if sound {
+            command.arg("--hint=string:sound-name:dialog-warning");
+        }
+        command.args([title, body]);
+        command
+    };
+    let status = command.stdout(Stdio::null()).stderr(Stdio::null()).status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("the notification command exited with {status}"),
+        Err(_) => bail!("no notification command (notify-send, osascript) is available"),
+    }
+}
+
+/// Notify the reviews of runs lasting at least `after_secs`, from the start of the run
+pub struct NotifyReporter {
+    config: NotifyConfig,
+    started: Instant,
+}
+
+impl NotifyReporter {
+    pub fn new(config: NotifyConfig) -> NotifyReporter {
+        NotifyReporter {
+            config,
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether `severities` names `severity` (never for reviews without a severity)
+    fn lists(severities: &[String], severity: Option<&str>) -> bool {
+        severity.is_some_and(|severity| {
+            severities
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(severity))
+        })
+    }
+}
+
+impl Reporter for NotifyReporter {
+    fn name(&self) -> &'static str {
+        "desktop notifications"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let elapsed = self.started.elapsed();
+            let severity = review_severity(&report.review);
+            if elapsed.as_secs() < self.config.after_secs
+                || !(self.config.severities.is_empty()
+                    || Self::lists(&self.config.severities, severity))
+            {
+                return Ok(());
+            }
+            let sound = Self::lists(&self.config.sound, severity);
+            let (title, body) = notification_text(report, elapsed);
+            let sent = send_notification(&title, &body, sound);
+            // The terminal bell still tells that the review is done
+            let mut stderr = std::io::stderr();
+            if sound && stderr.is_terminal() {
+                write!(stderr, "\x07")?;
+            }
+            sent
+        }
+        .boxed()
+    }
+}
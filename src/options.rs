@@ -0,0 +1,56 @@
+//! Effective boolean options of a review: the command line flags (`--x` or `--no-x`) over
+//! config.toml and its `RV_*` overrides, over the defaults
+
+use crate::config::RvConfig;
+
+/// Boolean options given on the command line, `None` when neither the flag nor its `--no-`
+/// counterpart was passed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CliFlags {
+    pub raw: Option<bool>,
+    pub recursive: Option<bool>,
+    pub log_xml_structure: Option<bool>,
+    pub actions_menu: Option<bool>,
+    pub show_reasoning: Option<bool>,
+}
+
+/// Options the review runs with
+#[derive(Debug, Clone, Copy)]
+pub struct EffectiveOptions {
+    /// Review the files without Git
+    pub raw: bool,
+    pub recursive: bool,
+    pub log_xml_structure: bool,
+    /// `None` leaves it to the `actions_menu` of the LLM configuration, only known once selected
+    pub actions_menu: Option<bool>,
+    pub show_reasoning: bool,
+}
+
+/// Value of a pair of flags (ex. `--recursive` and `--no-recursive`); the parser keeps the last
+/// one passed, so that at most one of them is set
+pub fn flag(set: bool, unset: bool) -> Option<bool> {
+    match (set, unset) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Resolve the options of a review of `path_given` (`--file` or `--dir`) or of a Git target
+/// (`git_target`, ex. `rv commit`): `[raw] by_default` only turns path reviews into raw ones
+pub fn resolve(
+    cli: CliFlags,
+    git_target: bool,
+    path_given: bool,
+    config: &RvConfig,
+) -> EffectiveOptions {
+    EffectiveOptions {
+        raw: cli
+            .raw
+            .unwrap_or(config.raw.by_default && path_given && !git_target),
+        recursive: cli.recursive.unwrap_or(config.raw.recursive),
+        log_xml_structure: cli.log_xml_structure.unwrap_or(config.log_xml_structure),
+        actions_menu: cli.actions_menu,
+        show_reasoning: cli.show_reasoning.unwrap_or(config.show_reasoning),
+    }
+}
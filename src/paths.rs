@@ -0,0 +1,157 @@
+//! Directories of rv (config, cache, state, logs), following the platform conventions. The
+//! `XDG_*_HOME` variables are honored on every platform, `RV_CONFIG_DIR` moves the configuration
+//! and `[paths]` of the configuration moves the other directories
+
+use crate::config::{CONFIG_FILE, PathsConfig};
+
+use anyhow::Result;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Environment variable moving the configuration directory
+pub const CONFIG_DIR_VAR: &str = "RV_CONFIG_DIR";
+
+/// `[paths]` of the configuration, set once it's loaded
+static OVERRIDES: OnceLock<PathsConfig> = OnceLock::new();
+
+/// Directories of rv
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir {
+    /// `config.toml`
+    Config,
+    /// Cached data that can be rebuilt
+    Cache,
+    /// Data kept between runs (review history)
+    State,
+    /// Logs of the runs, in the state directory by default
+    Logs,
+}
+
+impl Dir {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dir::Config => "config",
+            Dir::Cache => "cache",
+            Dir::State => "state",
+            Dir::Logs => "logs",
+        }
+    }
+}
+
+/// Where the path of a directory comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `RV_CONFIG_DIR`
+    Env,
+    /// `[paths]` of the configuration (or its `RV_PATHS__*` overrides)
+    Config,
+    /// Platform convention (`XDG_*_HOME` included)
+    Platform,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Env => CONFIG_DIR_VAR,
+            Source::Config => "[paths]",
+            Source::Platform => "default",
+        }
+    }
+}
+
+/// Use the `[paths]` directories of the configuration for the rest of the run
+pub fn configure(config: &PathsConfig) {
+    let _ = OVERRIDES.set(config.clone());
+}
+
+/// `var` if it's set to an absolute path, as the XDG specification requires
+fn xdg_home(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// `$HOME/<relative>`, when the platform has no directory of this kind
+fn home_fallback(relative: &str) -> io::Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::NotFound,
+            format!("could not determine the {relative} directory (HOME is not set)"),
+        )
+    })?;
+    Ok(PathBuf::from(home).join(relative))
+}
+
+/// Base directory of the platform convention, before the overrides
+fn platform_dir(dir: Dir) -> io::Result<PathBuf> {
+    let base = match dir {
+        Dir::Config => match xdg_home("XDG_CONFIG_HOME").or_else(dirs::config_dir) {
+            Some(base) => base,
+            None => home_fallback(".config")?,
+        },
+        Dir::Cache => match xdg_home("XDG_CACHE_HOME").or_else(dirs::cache_dir) {
+            Some(base) => base,
+            None => home_fallback(".cache")?,
+        },
+        Dir::State | Dir::Logs => match xdg_home("XDG_STATE_HOME")
+            .or_else(dirs::state_dir)
+            .or_else(dirs::data_local_dir)
+        {
+            Some(base) => base,
+            None => home_fallback(".local/state")?,
+        },
+    };
+    Ok(base.join("rv"))
+}
+
+/// Path of `dir` and where it comes from, without creating it
+pub fn locate(dir: Dir) -> io::Result<(PathBuf, Source)> {
+    let overridden = match dir {
+        Dir::Config => {
+            return match std::env::var_os(CONFIG_DIR_VAR).filter(|value| !value.is_empty()) {
+                Some(path) => Ok((PathBuf::from(path), Source::Env)),
+                None => Ok((platform_dir(dir)?, Source::Platform)),
+            };
+        }
+        Dir::Cache => OVERRIDES.get().and_then(|paths| paths.cache_dir.clone()),
+        Dir::State => OVERRIDES.get().and_then(|paths| paths.state_dir.clone()),
+        Dir::Logs => match OVERRIDES.get().and_then(|paths| paths.log_dir.clone()) {
+            Some(path) => Some(path),
+            None => {
+                let (state, source) = locate(Dir::State)?;
+                return Ok((state.join("logs"), source));
+            }
+        },
+    };
+    match overridden {
+        Some(path) => Ok((path, Source::Config)),
+        None => Ok((platform_dir(dir)?, Source::Platform)),
+    }
+}
+
+/// Path of `dir`, without creating it
+pub fn dir(dir: Dir) -> io::Result<PathBuf> {
+    locate(dir).map(|(path, _)| path)
+}
+
+/// Path of `dir`, created if missing
+pub fn ensure_dir(dir: Dir) -> io::Result<PathBuf> {
+    let path = self::dir(dir)?;
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// `rv paths`: print where every file of rv lives
+pub fn print_paths() -> Result<()> {
+    let config = dir(Dir::Config)?;
+    println!("{:<8} {}", "file", config.join(CONFIG_FILE).display());
+    for kind in [Dir::Config, Dir::Cache, Dir::State, Dir::Logs] {
+        let (path, source) = locate(kind)?;
+        println!("{:<8} {} ({})", kind.name(), path.display(), source.name());
+    }
+    let history = dir(Dir::State)?.join(crate::history::HISTORY_DIR);
+    println!("{:<8} {}", "history", history.display());
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use crate::config::{LLMConfig, OpenAIProvider, RvConfig};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// System-wide policy file, usually managed by an organization
+pub const DEFAULT_POLICY_PATH: &str = "/etc/rv/policy.toml";
+/// Environment variable naming an additional policy file, which can only restrict the system policy further
+pub const POLICY_PATH_ENV: &str = "RV_POLICY_FILE";
+
+/// Organization policy, enforced regardless of the user configuration
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Policy {
+    /// Providers that can be used; unset allows every provider
+    pub allowed_providers: Option<Vec<OpenAIProvider>>,
+    /// Glob patterns of the models that can be used (ex. `openai/*`); unset allows every model
+    pub allowed_models: Option<Vec<String>>,
+    /// Endpoints that requests can be sent to; unset allows every endpoint
+    pub allowed_endpoints: Option<Vec<String>>,
+    /// Never send full source files, only diffs
+    pub forbid_sources: bool,
+    /// Always redact secrets from the requests
+    pub require_redaction: bool,
+}
+
+/// Values allowed by both lists, `matches(a, b)` telling whether `b` is allowed by `a`;
+/// unset lists allow everything
+fn restrict_list<T: Clone>(
+    list: Option<Vec<T>>,
+    other: Option<Vec<T>>,
+    matches: impl Fn(&T, &T) -> bool,
+) -> Option<Vec<T>> {
+    match (list, other) {
+        (Some(list), Some(other)) => {
+            let mut allowed: Vec<T> = list
+                .iter()
+                .filter(|value| other.iter().any(|o| matches(o, value)))
+                .cloned()
+                .collect();
+            for value in other {
+                if list.iter().any(|l| matches(l, &value))
+                    && !allowed.iter().any(|a| matches(a, &value))
+                {
+                    allowed.push(value);
+                }
+            }
+            Some(allowed)
+        }
+        (list, other) => list.or(other),
+    }
+}
+
+fn same_endpoint(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// Whether the model pattern `b` is within the pattern `a`
+fn model_within(a: &str, b: &str) -> bool {
+    a == b || glob::Pattern::new(a).is_ok_and(|pattern| pattern.matches(b))
+}
+
+impl Policy {
+    /// Load the system policy, restricted further by the policy file in `RV_POLICY_FILE`, if any.
+    /// An unreadable or invalid policy is an error, so that a broken policy never silently allows everything.
+    pub fn load() -> Result<Option<Policy>> {
+        let extra = std::env::var_os(POLICY_PATH_ENV).map(PathBuf::from);
+        Policy::load_from(Path::new(DEFAULT_POLICY_PATH), extra.as_deref())
+    }
+
+    /// Load the policy at `system`, if it exists, and the one at `extra`, which must exist
+    pub fn load_from(system: &Path, extra: Option<&Path>) -> Result<Option<Policy>> {
+        let mut policy = if system.exists() {
+            Some(Policy::read(system)?)
+        } else {
+            None
+        };
+        if let Some(extra) = extra {
+            if !extra.exists() {
+                bail!(
+                    "[POLICY] Policy file {} (set by {POLICY_PATH_ENV}) doesn't exist",
+                    extra.display()
+                );
+            }
+            let extra = Policy::read(extra)?;
+            policy = Some(match policy {
+                Some(policy) => policy.restrict(extra),
+                None => extra,
+            });
+        }
+        Ok(policy)
+    }
+
+    fn read(path: &Path) -> Result<Policy> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse policy file {}", path.display()))
+    }
+
+    /// Combine two policies, allowing only what both of them allow. A model pattern is kept when
+    /// it is within a pattern of the other policy (ex. `openai/gpt-4*` within `openai/*`)
+    pub fn restrict(self, other: Policy) -> Policy {
+        Policy {
+            allowed_providers: restrict_list(
+                self.allowed_providers,
+                other.allowed_providers,
+                |a, b| a == b,
+            ),
+            allowed_models: restrict_list(self.allowed_models, other.allowed_models, |a, b| {
+                model_within(a, b)
+            }),
+            allowed_endpoints: restrict_list(
+                self.allowed_endpoints,
+                other.allowed_endpoints,
+                |a, b| same_endpoint(a, b),
+            ),
+            forbid_sources: self.forbid_sources || other.forbid_sources,
+            require_redaction: self.require_redaction || other.require_redaction,
+        }
+    }
+
+    /// Override the parts of the user configuration restricted by the policy
+    pub fn enforce_config(&self, rvconfig: &mut RvConfig) {
+        if self.forbid_sources {
+            rvconfig.diff_profile.report_sources = false;
+            // The related code retrieved from the index is whole chunks of source files
+            rvconfig.index.top_k = 0;
+        }
+        if self.require_redaction {
+            rvconfig.redact_secrets = true;
+        }
+    }
+
+    /// Check that the selected LLM configuration is allowed
+    pub fn check_llm(&self, llm_config: &LLMConfig) -> Result<()> {
+        let provider = llm_config.provider;
+        if let Some(providers) = &self.allowed_providers
+            && !providers.contains(&provider)
+        {
+            bail!(
+                "[POLICY] Provider {} is not allowed by the organization policy",
+                provider.name()
+            );
+        }
+
+        if let Some(models) = &self.allowed_models
+            && !models.iter().any(|m| {
+                glob::Pattern::new(m).is_ok_and(|pattern| pattern.matches(&llm_config.model_id))
+            })
+        {
+            bail!(
+                "[POLICY] Model {} is not allowed by the organization policy",
+                llm_config.model_id
+            );
+        }
+
+        let endpoint = llm_config.endpoint();
+        if let Some(endpoints) = &self.allowed_endpoints
+            && !endpoints.iter().any(|e| same_endpoint(e, &endpoint))
+        {
+            bail!("[POLICY] Endpoint {endpoint} is not allowed by the organization policy");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_policy(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn restricts_the_system_policy_with_the_extra_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = write_policy(
+            dir.path(),
+            "system.toml",
+            "allowed_models = [\"openai/*\", \"qwen/*\"]\nforbid_sources = true\n",
+        );
+        let extra = write_policy(
+            dir.path(),
+            "extra.toml",
+            "allowed_models = [\"openai/gpt-4*\", \"anthropic/*\"]\nrequire_redaction = true\n",
+        );
+
+        let policy = Policy::load_from(&system, Some(&extra)).unwrap().unwrap();
+        assert_eq!(
+            policy.allowed_models,
+            Some(vec![String::from("openai/gpt-4*")])
+        );
+        assert!(policy.forbid_sources && policy.require_redaction);
+
+        // The extra file can't lift the restrictions of the system policy
+        let lax = write_policy(dir.path(), "lax.toml", "forbid_sources = false\n");
+        let policy = Policy::load_from(&system, Some(&lax)).unwrap().unwrap();
+        assert!(policy.forbid_sources);
+        assert_eq!(policy.allowed_models.map(|m| m.len()), Some(2));
+    }
+
+    #[test]
+    fn fails_on_a_missing_or_invalid_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        assert_eq!(Policy::load_from(&missing, None).unwrap(), None);
+        assert!(Policy::load_from(&missing, Some(&missing)).is_err());
+
+        let invalid = write_policy(dir.path(), "invalid.toml", "forbid_sources = \"yes\"\n");
+        assert!(Policy::load_from(&invalid, None).is_err());
+    }
+
+    #[test]
+    fn enforces_the_configuration_and_the_llm() {
+        let policy = Policy {
+            allowed_providers: Some(vec![OpenAIProvider::OpenRouter]),
+            allowed_models: Some(vec![String::from("qwen/*")]),
+            forbid_sources: true,
+            require_redaction: true,
+            ..Policy::default()
+        };
+        let mut rvconfig = RvConfig::default();
+        policy.enforce_config(&mut rvconfig);
+        assert!(!rvconfig.diff_profile.report_sources);
+        assert_eq!(rvconfig.index.top_k, 0);
+        assert!(rvconfig.redact_secrets);
+
+        let mut llm_config = LLMConfig {
+            model_id: String::from("qwen/qwen3-235b-a22b-2507"),
+            ..LLMConfig::default()
+        };
+        assert!(policy.check_llm(&llm_config).is_ok());
+        llm_config.model_id = String::from("openai/gpt-4o");
+        assert!(policy.check_llm(&llm_config).is_err());
+        llm_config.model_id = String::from("qwen/qwen3-235b-a22b-2507");
+        llm_config.provider = OpenAIProvider::OpenAI;
+        assert!(policy.check_llm(&llm_config).is_err());
+    }
+}
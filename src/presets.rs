@@ -0,0 +1,288 @@
+//! `rv config add-llm --preset openrouter:claude`: catalog of LLM configurations with sensible
+//! defaults (provider, endpoint, recommended model, API key variable), appended to config.toml
+
+use crate::config::{LLMConfig, OpenAIProvider, QualityTier, RvConfig, default_config_path};
+use crate::llm::create_llm_provider;
+
+use anyhow::{Context, Result, anyhow, bail};
+use rig::message::Message;
+use serde::Serialize;
+use std::fs;
+use std::time::Duration;
+
+/// Longest wait for the answer of the test request of `--validate`
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Test request of `--validate`, as cheap as possible
+const VALIDATION_PROMPT: &str = "Reply with the single word OK.";
+
+/// LLM configuration of the catalog
+pub struct Preset {
+    /// `provider:name`, as passed to `--preset`
+    pub key: &'static str,
+    pub provider: OpenAIProvider,
+    pub model_id: &'static str,
+    /// Endpoint of the OpenAI-compatible servers of the Custom provider
+    pub base_url: Option<&'static str>,
+    pub quality_tier: Option<QualityTier>,
+    /// Environment variable read when the configuration has no `api_key`
+    pub api_key_env: Option<&'static str>,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        key: "openrouter:qwen",
+        provider: OpenAIProvider::OpenRouter,
+        model_id: "qwen/qwen3-235b-a22b-2507",
+        base_url: None,
+        quality_tier: Some(QualityTier::Balanced),
+        api_key_env: Some("OPENROUTER_API_KEY"),
+    },
+    Preset {
+        key: "openrouter:claude",
+        provider: OpenAIProvider::OpenRouter,
+        model_id: "anthropic/claude-sonnet-4.5",
+        base_url: None,
+        quality_tier: Some(QualityTier::Strong),
+        api_key_env: Some("OPENROUTER_API_KEY"),
+    },
+    Preset {
+        key: "openrouter:deepseek",
+        provider: OpenAIProvider::OpenRouter,
+        model_id: "deepseek/deepseek-v3.2",
+        base_url: None,
+        quality_tier: Some(QualityTier::Strong),
+        api_key_env: Some("OPENROUTER_API_KEY"),
+    },
+    Preset {
+        key: "openrouter:gemini",
+        provider: OpenAIProvider::OpenRouter,
+        model_id: "google/gemini-2.5-pro",
+        base_url: None,
+        quality_tier: Some(QualityTier::Strong),
+        api_key_env: Some("OPENROUTER_API_KEY"),
+    },
+    Preset {
+        key: "openrouter:free",
+        provider: OpenAIProvider::OpenRouter,
+        model_id: "mistralai/devstral-2512:free",
+        base_url: None,
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: Some("OPENROUTER_API_KEY"),
+    },
+    Preset {
+        key: "openai:gpt",
+        provider: OpenAIProvider::OpenAI,
+        model_id: "gpt-5",
+        base_url: None,
+        quality_tier: Some(QualityTier::Strong),
+        api_key_env: Some("OPENAI_API_KEY"),
+    },
+    Preset {
+        key: "openai:mini",
+        provider: OpenAIProvider::OpenAI,
+        model_id: "gpt-5-mini",
+        base_url: None,
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: Some("OPENAI_API_KEY"),
+    },
+    Preset {
+        key: "gemini:pro",
+        provider: OpenAIProvider::Gemini,
+        model_id: "gemini-2.5-pro",
+        base_url: None,
+        quality_tier: Some(QualityTier::Strong),
+        api_key_env: Some("GEMINI_API_KEY"),
+    },
+    Preset {
+        key: "gemini:flash",
+        provider: OpenAIProvider::Gemini,
+        model_id: "gemini-2.5-flash",
+        base_url: None,
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: Some("GEMINI_API_KEY"),
+    },
+    Preset {
+        key: "ollama:qwen",
+        provider: OpenAIProvider::Ollama,
+        model_id: "qwen2.5-coder:14b",
+        base_url: None,
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: None,
+    },
+    Preset {
+        key: "custom:lmstudio",
+        provider: OpenAIProvider::Custom,
+        model_id: "qwen2.5-coder-14b-instruct",
+        base_url: Some("http://localhost:1234/v1"),
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: None,
+    },
+    Preset {
+        key: "custom:vllm",
+        provider: OpenAIProvider::Custom,
+        model_id: "Qwen/Qwen2.5-Coder-32B-Instruct",
+        base_url: Some("http://localhost:8000/v1"),
+        quality_tier: Some(QualityTier::Balanced),
+        api_key_env: None,
+    },
+    Preset {
+        key: "custom:llamacpp",
+        provider: OpenAIProvider::Custom,
+        model_id: "default",
+        base_url: Some("http://localhost:8080/v1"),
+        quality_tier: Some(QualityTier::Fast),
+        api_key_env: None,
+    },
+];
+
+/// Preset named `key` (ex. `openrouter:claude`)
+pub fn find_preset(key: &str) -> Result<&'static Preset> {
+    let key = key.trim().to_ascii_lowercase();
+    match PRESETS.iter().find(|preset| preset.key == key) {
+        Some(preset) => Ok(preset),
+        None => bail!(
+            "Unknown preset `{key}`; available presets: {}",
+            PRESETS
+                .iter()
+                .map(|preset| preset.key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Print the catalog of presets
+pub fn print_presets() -> Result<()> {
+    println!(
+        "{:<20} {:<12} {:<36} API KEY",
+        "PRESET", "PROVIDER", "MODEL"
+    );
+    for preset in PRESETS {
+        println!(
+            "{:<20} {:<12} {:<36} {}",
+            preset.key,
+            preset.provider.name(),
+            preset.model_id,
+            preset.api_key_env.unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+/// Keys written for a preset: the others keep their defaults
+#[derive(Serialize)]
+struct PresetBlock<'a> {
+    configuration_name: &'a str,
+    provider: OpenAIProvider,
+    model_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_tier: Option<QualityTier>,
+}
+
+#[derive(Serialize)]
+struct PresetTable<'a> {
+    llm_configs: [PresetBlock<'a>; 1],
+}
+
+/// Send a test request to `config`, checking its endpoint, API key and model
+async fn validate(config: &LLMConfig) -> Result<()> {
+    let mut config = config.clone();
+    config.api_key = config.resolve_api_key()?;
+    let client = create_llm_provider(config.clone());
+    let request = client.request(String::new(), vec![Message::user(VALIDATION_PROMPT)]);
+    let answer = tokio::time::timeout(VALIDATION_TIMEOUT, request)
+        .await
+        .with_context(|| {
+            format!(
+                "{} didn't answer within {}s",
+                config.endpoint(),
+                VALIDATION_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| {
+            anyhow!(
+                "The test request to {} failed: {}",
+                config.endpoint(),
+                e.root_cause()
+            )
+        })?;
+    println!(
+        "[OK] {} answered the test request ({} characters)",
+        config.model_id,
+        answer.trim().chars().count()
+    );
+    Ok(())
+}
+
+/// Add the LLM configuration of `preset` to config.toml, named `name` (the preset with `-` in
+/// place of `:` by default) and using `model` instead of the recommended model if set
+pub async fn add_llm(
+    preset: &str,
+    name: Option<String>,
+    model: Option<String>,
+    validate_config: bool,
+) -> Result<()> {
+    let preset = find_preset(preset)?;
+    let name = name.unwrap_or_else(|| preset.key.replace(':', "-"));
+    let model_id = model.unwrap_or_else(|| preset.model_id.to_string());
+
+    // Creates the configuration file if it doesn't exist yet
+    let rvconfig = RvConfig::load_default()?;
+    if rvconfig
+        .llm_configs
+        .iter()
+        .any(|config| config.configuration_name == name)
+    {
+        bail!("An LLM configuration named `{name}` already exists, choose another one with --name");
+    }
+
+    let config = LLMConfig {
+        configuration_name: name.clone(),
+        provider: preset.provider,
+        model_id: model_id.clone(),
+        base_url: preset.base_url.map(str::to_string),
+        quality_tier: preset.quality_tier,
+        ..LLMConfig::default()
+    };
+    if validate_config {
+        validate(&config).await?;
+    }
+
+    let block = toml::to_string(&PresetTable {
+        llm_configs: [PresetBlock {
+            configuration_name: &name,
+            provider: preset.provider,
+            model_id: &model_id,
+            base_url: preset.base_url,
+            quality_tier: preset.quality_tier,
+        }],
+    })?;
+    let path = default_config_path()?;
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let updated = format!("{}\n\n{block}", contents.trim_end());
+    // Inline `llm_configs = [...]` arrays can't be extended with a table
+    toml::from_str::<RvConfig>(&updated).with_context(|| {
+        format!(
+            "Failed to add the configuration to {}, add this block by hand:\n\n{block}",
+            path.display()
+        )
+    })?;
+    fs::write(&path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    println!(
+        "Added the `{name}` configuration ({}, {model_id}) to {}",
+        preset.provider.name(),
+        path.display()
+    );
+    if let Some(env) = preset.api_key_env
+        && std::env::var_os(env).is_none()
+    {
+        println!("Set {env} or add `api_key` to the configuration before using it.");
+    }
+    println!("Review with it using `rv -l {name}`.");
+    Ok(())
+}
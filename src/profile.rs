@@ -0,0 +1,92 @@
+use crate::config::{AutoProfileConfig, DiffProfile};
+use crate::context::estimate_tokens;
+
+/// Extra instructions for small changes, which can be reviewed in depth
+pub const DEEP_REVIEW_PROMPT: &str = r#"
+DEPTH
+- The change is small: review it in depth, line by line.
+- Check every touched function against its callers in the <source>.
+- Consider edge cases, error paths and concurrency for each finding.
+"#;
+
+/// System prompt of the summary request sent before reviewing huge changes
+pub const SUMMARY_SYSTEM_PROMPT: &str = r#"
+You are a senior software engineer.
+You will receive a large set of diffs. Do NOT review them yet.
+Summarize the change set so that a reviewer can focus on what matters.
+
+OUTPUT FORMAT & STYLE
+- ASCII only. No emojis, no markdown, no color codes.
+- Soft-wrap at ~80 columns.
+
+STRICT STRUCTURE (in this exact order)
+1) INTENT: one or two sentences about the goal of the change set.
+2) FILES: one line per file or group of related files, with what changed.
+3) HOTSPOTS: numbered list, max 8 items, of the changes most likely to
+   contain bugs or security issues, with the file path.
+"#;
+
+/// Size class of a review target, used to pick how it gets reviewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewSize {
+    /// Full sources and a deep review
+    Small,
+    /// Diff hunks only
+    Medium,
+    /// Diff hunks only, summarized before being reviewed
+    Huge,
+}
+
+impl ReviewSize {
+    /// Classify a change set from the estimated tokens of its diffs
+    pub fn from_diffs(diffs: &[String], cfg: &AutoProfileConfig) -> ReviewSize {
+        let tokens: usize = diffs.iter().map(|d| estimate_tokens(d)).sum();
+        if tokens <= cfg.small_max_tokens {
+            ReviewSize::Small
+        } else if tokens <= cfg.medium_max_tokens {
+            ReviewSize::Medium
+        } else {
+            ReviewSize::Huge
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReviewSize::Small => "small",
+            ReviewSize::Medium => "medium",
+            ReviewSize::Huge => "huge",
+        }
+    }
+
+    /// Diff profile used for this size: only the fields the configuration leaves at their default
+    /// are filled, the ones set by the user win
+    pub fn diff_profile(self, configured: DiffProfile) -> DiffProfile {
+        let default = DiffProfile::default();
+        DiffProfile {
+            report_diffs: if configured.report_diffs == default.report_diffs {
+                true
+            } else {
+                configured.report_diffs
+            },
+            report_sources: if configured.report_sources == default.report_sources {
+                self == ReviewSize::Small
+            } else {
+                configured.report_sources
+            },
+            source_compression: configured.source_compression,
+        }
+    }
+
+    /// Instructions appended to the system prompt, if any
+    pub fn prompt_addendum(self) -> Option<&'static str> {
+        match self {
+            ReviewSize::Small => Some(DEEP_REVIEW_PROMPT),
+            ReviewSize::Medium | ReviewSize::Huge => None,
+        }
+    }
+
+    /// Whether the change set gets summarized before being reviewed
+    pub fn summary_first(self) -> bool {
+        self == ReviewSize::Huge
+    }
+}
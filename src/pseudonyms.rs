@@ -0,0 +1,228 @@
+//! `redact_paths`: the paths of the reviewed files are replaced with stable pseudonyms (ex.
+//! `file_01.rs`) in everything sent to the provider, and mapped back in the responses, for teams
+//! whose directory names are themselves confidential
+
+use crate::llm::defs::{LLMProvider, TokenStream};
+use crate::markup;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use rig::message::{AssistantContent, Message, UserContent};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Characters a path is made of; a path is only replaced when it isn't part of a longer one
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | '\\')
+}
+
+/// Prefixes of the paths in the headers of the diffs
+const DIFF_PREFIXES: [&str; 2] = ["a/", "b/"];
+
+/// Whether a path followed by `rest` ends there (ex. `a.rs:12` or `a.rs.`, but not `a.rsx` or
+/// `a.rs.bak`)
+fn ends_path(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        None => true,
+        Some('.') => !chars.next().is_some_and(char::is_alphanumeric),
+        Some(c) => !(c.is_alphanumeric() || matches!(c, '_' | '-' | '/' | '\\')),
+    }
+}
+
+/// Replace every occurrence of the first text of `pairs` (longest first) with the second one
+fn replace_all(text: &str, pairs: &[(String, String)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut at_boundary = true;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let replaced = at_boundary
+            .then(|| {
+                pairs.iter().find(|(from, _)| {
+                    rest.starts_with(from.as_str()) && ends_path(&rest[from.len()..])
+                })
+            })
+            .flatten();
+        match replaced {
+            Some((from, to)) => {
+                result.push_str(to);
+                rest = &rest[from.len()..];
+            }
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        at_boundary = !result.ends_with(is_path_char);
+    }
+    result
+}
+
+/// Apply `f` to the text of every message
+fn map_messages(mut messages: Vec<Message>, f: impl Fn(&str) -> String) -> Vec<Message> {
+    for message in &mut messages {
+        match message {
+            Message::User { content } => {
+                for item in content.iter_mut() {
+                    if let UserContent::Text(text) = item {
+                        text.text = f(&text.text);
+                    }
+                }
+            }
+            Message::Assistant { content, .. } => {
+                for item in content.iter_mut() {
+                    if let AssistantContent::Text(text) = item {
+                        text.text = f(&text.text);
+                    }
+                }
+            }
+        }
+    }
+    messages
+}
+
+/// Bidirectional mapping between the reviewed paths and their pseudonyms
+#[derive(Debug, Clone, Default)]
+pub struct PathMap {
+    /// Real path and pseudonym, longest path first
+    redactions: Vec<(String, String)>,
+    /// Pseudonym and real path, longest pseudonym first
+    restorations: Vec<(String, String)>,
+}
+
+impl PathMap {
+    /// Pseudonyms numbered in the order of the paths, keeping their extension (the language of
+    /// the file stays known to the model)
+    pub fn new<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> PathMap {
+        let paths: BTreeSet<String> = paths
+            .into_iter()
+            .map(|path| markup::path_text(path.as_ref()))
+            .filter(|path| !path.is_empty())
+            .collect();
+        let width = paths.len().to_string().len().max(2);
+        let mut redactions: Vec<(String, String)> = paths
+            .into_iter()
+            .enumerate()
+            .map(|(idx, path)| {
+                let extension = Path::new(&path)
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                let pseudonym = format!("file_{:0width$}{extension}", idx + 1);
+                (path, pseudonym)
+            })
+            .collect();
+        // `a/src/lib.rs` in the diff headers
+        let prefixed: Vec<(String, String)> = redactions
+            .iter()
+            .flat_map(|(path, pseudonym)| {
+                DIFF_PREFIXES
+                    .iter()
+                    .map(move |prefix| (format!("{prefix}{path}"), format!("{prefix}{pseudonym}")))
+            })
+            .collect();
+        redactions.extend(prefixed);
+        let mut restorations: Vec<(String, String)> = redactions
+            .iter()
+            .map(|(path, pseudonym)| (pseudonym.clone(), path.clone()))
+            .collect();
+        redactions.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        restorations.sort_by_key(|(pseudonym, _)| std::cmp::Reverse(pseudonym.len()));
+        PathMap {
+            redactions,
+            restorations,
+        }
+    }
+
+    /// Replace the real paths of `text` with their pseudonyms
+    pub fn redact(&self, text: &str) -> String {
+        replace_all(text, &self.redactions)
+    }
+
+    /// Replace the pseudonyms of `text` with the real paths
+    pub fn restore(&self, text: &str) -> String {
+        replace_all(text, &self.restorations)
+    }
+
+    /// Restore the paths of a streamed response; the path being streamed at the end of a chunk
+    /// is held back until it's complete
+    fn restore_stream(self: Arc<Self>, stream: TokenStream) -> TokenStream {
+        futures::stream::unfold(
+            (stream, String::new(), Some(self)),
+            |(mut stream, mut pending, paths)| async move {
+                let paths = paths?;
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        pending.push_str(&chunk);
+                        let complete = pending
+                            .char_indices()
+                            .rfind(|(_, c)| !is_path_char(*c))
+                            .map_or(0, |(idx, c)| idx + c.len_utf8());
+                        let text = paths.restore(&pending[..complete]);
+                        pending.drain(..complete);
+                        Some((Ok(text), (stream, pending, Some(paths))))
+                    }
+                    Some(Err(e)) => Some((Err(e), (stream, pending, Some(paths)))),
+                    None => Some((Ok(paths.restore(&pending)), (stream, String::new(), None))),
+                }
+            },
+        )
+        .boxed()
+    }
+}
+
+/// Provider wrapper sending pseudonyms in place of the paths
+pub struct PathRedactingProvider {
+    pub inner: Box<dyn LLMProvider>,
+    pub paths: Arc<PathMap>,
+}
+
+impl PathRedactingProvider {
+    fn redact(&self, sys_prompt: String, messages: Vec<Message>) -> (String, Vec<Message>) {
+        (
+            self.paths.redact(&sys_prompt),
+            map_messages(messages, |text| self.paths.redact(text)),
+        )
+    }
+}
+
+impl LLMProvider for PathRedactingProvider {
+    fn get_provider_name(&self) -> String {
+        self.inner.get_provider_name()
+    }
+
+    fn stream_request(&self, sys_prompt: String, messages: Vec<Message>) -> Result<TokenStream> {
+        let (sys_prompt, messages) = self.redact(sys_prompt, messages);
+        let stream = self.inner.stream_request(sys_prompt, messages)?;
+        Ok(self.paths.clone().restore_stream(stream))
+    }
+
+    fn request(
+        &self,
+        sys_prompt: String,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let (sys_prompt, messages) = self.redact(sys_prompt, messages);
+        let paths = self.paths.clone();
+        self.inner
+            .request(sys_prompt, messages)
+            .map(move |response| response.map(|text| paths.restore(&text)))
+            .boxed()
+    }
+}
+
+/// `client`, sending pseudonyms in place of the paths of `paths` when they are redacted
+pub fn redact_paths(
+    client: Box<dyn LLMProvider>,
+    paths: Option<&Arc<PathMap>>,
+) -> Box<dyn LLMProvider> {
+    match paths {
+        Some(paths) => Box::new(PathRedactingProvider {
+            inner: client,
+            paths: paths.clone(),
+        }),
+        None => client,
+    }
+}
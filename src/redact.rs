@@ -0,0 +1,131 @@
+/// Placeholder used in place of redacted secrets
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Key names whose assigned values are considered secrets
+const SECRET_KEYS: [&str; 8] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "private_key",
+    "access_key",
+];
+
+/// Prefixes of well-known credential formats
+const SECRET_PREFIXES: [&str; 9] = [
+    "sk-",
+    "ghp_",
+    "gho_",
+    "ghs_",
+    "github_pat_",
+    "glpat-",
+    "xoxb-",
+    "xoxp-",
+    "AKIA",
+];
+
+/// Minimum length of a token starting with a known prefix to be considered a secret
+const MIN_PREFIXED_SECRET_LEN: usize = 16;
+
+/// Whether a key or a variable name holds a secret (ex. `DB_PASSWORD`, `npm_token`)
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_KEYS.iter().any(|k| key.contains(k))
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | '+' | '=')
+}
+
+/// Redact values assigned to secret-looking keys (ex. `API_KEY = "..."`, `password: ...`)
+fn redact_assignment(line: &str) -> Option<String> {
+    let separator = line.find(['=', ':'])?;
+    let (key, value) = line.split_at(separator);
+    if !is_secret_key(key) {
+        return None;
+    }
+
+    let value = value[1..].trim();
+    // Leave empty values, references and expressions alone (ex. `token: Option<String>`)
+    let quoted = value.starts_with('"') || value.starts_with('\'');
+    if value.is_empty() || (!quoted && !value.chars().all(|c| is_token_char(c) || c == ',')) {
+        return None;
+    }
+
+    Some(format!(
+        "{}{} {REDACTED}",
+        key,
+        &line[separator..=separator]
+    ))
+}
+
+/// Redact tokens that start with the prefix of a well-known credential format
+fn redact_prefixed_tokens(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut token = String::new();
+
+    let flush = |token: &mut String, result: &mut String| {
+        if token.len() >= MIN_PREFIXED_SECRET_LEN
+            && SECRET_PREFIXES.iter().any(|p| token.starts_with(p))
+        {
+            result.push_str(REDACTED);
+        } else {
+            result.push_str(token);
+        }
+        token.clear();
+    };
+
+    for c in line.chars() {
+        if is_token_char(c) {
+            token.push(c);
+        } else {
+            flush(&mut token, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut token, &mut result);
+
+    result
+}
+
+/// Remove credentials and other secrets from text that is about to be sent to a provider
+pub fn redact_secrets(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+
+        match redact_assignment(content) {
+            Some(redacted) => result.push_str(&redacted),
+            None => result.push_str(&redact_prefixed_tokens(content)),
+        }
+        result.push_str(newline);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_the_secret_assignments_and_tokens() {
+        let text = "API_KEY = \"abc123\"\npassword: hunter2\nlet client = Client::new(\"ghp_0123456789abcdefghij\");\n";
+        assert_eq!(
+            redact_secrets(text),
+            "API_KEY = [REDACTED]\npassword: [REDACTED]\nlet client = Client::new(\"[REDACTED]\");\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_code_naming_secrets() {
+        let text = "    token: Option<String>,\nlet secret = load_secret(&path);\nsk-short";
+        assert_eq!(redact_secrets(text), text);
+    }
+}
@@ -0,0 +1,410 @@
+//! Destinations of the review results: the terminal, a JSON file, a pull request comment or a webhook
+
+use crate::artifact::ArtifactReporter;
+use crate::checks::{CheckFinding, format_checks};
+use crate::clipboard::{ClipboardReporter, CopyFormat};
+use crate::complexity::{FunctionComplexity, format_complexity};
+use crate::config::{NotesMerge, NotifyConfig};
+use crate::coverage::Coverage;
+use crate::editor::OpenReporter;
+use crate::fixes::{FixVerification, format_fixes};
+use crate::git_helpers::{FilePatch, SkippedFile, format_not_reviewed};
+use crate::inline::InlineReporter;
+use crate::network;
+use crate::next_steps::{NextStepsContext, NextStepsReporter};
+use crate::notes;
+use crate::notify::NotifyReporter;
+use crate::review_model::Review;
+use crate::suppress::{SuppressedFinding, format_fingerprints, format_suppressed};
+use crate::triage::TriageReporter;
+use anyhow::{Context, Result, anyhow, bail};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Reason of the files whose review request failed, followed by the error
+pub const REVIEW_FAILED_REASON: &str = "review failed";
+
+/// Exit code of the runs where some review requests failed, distinct from a failed run (1)
+pub const PARTIAL_FAILURE_EXIT_CODE: i32 = 3;
+
+/// Error of a multi-request run where some requests failed and the others were reported
+#[derive(Debug, Clone, Copy)]
+pub struct PartialFailure {
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} review requests failed, the review is partial",
+            self.failed, self.total
+        )
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+/// Exit code of a failed run
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if error.downcast_ref::<PartialFailure>().is_some() {
+        PARTIAL_FAILURE_EXIT_CODE
+    } else {
+        1
+    }
+}
+
+/// Outcome of a run, serialized as `REVIEWED` or `NO_CHANGES`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Verdict {
+    Reviewed,
+    /// Nothing to review: the diff is empty, or every changed file was excluded
+    NoChanges,
+}
+
+/// Result of a review, as handed to the reporters
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// ID of the review in the local history, if it was saved
+    pub id: Option<String>,
+    pub target: String,
+    pub verdict: Verdict,
+    /// Last reviewed commit, when the review is tied to commits
+    pub commit: Option<String>,
+    pub model: String,
+    /// Tags of the run (`--tag` and the `tags` of the configuration)
+    pub tags: Vec<String>,
+    pub review: String,
+    pub reviewed_files: Vec<String>,
+    pub not_reviewed: Vec<SkippedFile>,
+    /// Findings removed from the review by `.rv_suppressions`
+    pub suppressed: Vec<SuppressedFinding>,
+    /// Findings of the deterministic checks, run without the LLM
+    pub checks: Vec<CheckFinding>,
+    /// Outcome of the suggested fixes in a temporary worktree (`--verify-fixes`)
+    pub fixes: Vec<FixVerification>,
+    /// Complexity of the changed functions, the most complex first
+    pub complexity: Vec<FunctionComplexity>,
+    /// Share of the diff that reached the prompt, for reviews of diffs
+    pub coverage: Option<Coverage>,
+    /// Typed review, when the model was asked for JSON (`--json-review`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured: Option<Review>,
+    /// System prompt and review input sent to the model, kept out of the JSON output
+    #[serde(skip)]
+    pub prompt: String,
+    /// Typed patches of the reviewed diffs, kept for `--inline`
+    #[serde(skip)]
+    pub patches: BTreeMap<PathBuf, FilePatch>,
+}
+
+impl Report {
+    /// Review followed by the NOT REVIEWED and SUPPRESSED FINDINGS appendices, as shown to humans
+    pub fn text(&self) -> String {
+        let mut text = self.review.clone();
+        if !self.checks.is_empty() {
+            text.push_str(&format!("\n\n{}", format_checks(&self.checks)));
+        }
+        if !self.fixes.is_empty() {
+            text.push_str(&format!("\n\n{}", format_fixes(&self.fixes)));
+        }
+        if !self.complexity.is_empty() {
+            text.push_str(&format!("\n\n{}", format_complexity(&self.complexity)));
+        }
+        if !self.not_reviewed.is_empty() {
+            text.push_str(&format!("\n\n{}", format_not_reviewed(&self.not_reviewed)));
+        }
+        if !self.suppressed.is_empty() {
+            text.push_str(&format!("\n\n{}", format_suppressed(&self.suppressed)));
+        }
+        text
+    }
+
+    /// Markdown form of the review (report.md of the artifacts, `--copy markdown`)
+    pub fn markdown(&self) -> String {
+        format!(
+            "# rv review of {}\n\nModel: {}\n\n```\n{}\n```\n",
+            self.target,
+            self.model,
+            self.text().trim_end()
+        )
+    }
+}
+
+/// Destination of the review results
+pub trait Reporter: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>>;
+}
+
+/// The review itself is streamed to stdout by the provider, so only the appendices and the ID are printed
+pub struct TerminalReporter {
+    pub pipe: bool,
+}
+
+impl Reporter for TerminalReporter {
+    fn name(&self) -> &'static str {
+        "terminal"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if !report.checks.is_empty() {
+                println!("\n\n{}", format_checks(&report.checks));
+            }
+            if !report.fixes.is_empty() {
+                println!("\n\n{}", format_fixes(&report.fixes));
+            }
+            if !report.complexity.is_empty() {
+                println!("\n\n{}", format_complexity(&report.complexity));
+            }
+            if !report.not_reviewed.is_empty() {
+                println!("\n\n{}", format_not_reviewed(&report.not_reviewed));
+            }
+            if !report.suppressed.is_empty() {
+                println!("\n{}", format_suppressed(&report.suppressed));
+            }
+            if !self.pipe {
+                if let Some(coverage) = &report.coverage {
+                    println!("\n[Coverage: {}]", coverage.summary());
+                }
+                if let Some(fingerprints) = format_fingerprints(&report.review) {
+                    println!("\n{fingerprints}");
+                }
+                if let Some(id) = &report.id {
+                    println!("\n[Review ID: {id}]");
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Save the reports of the run as a JSON array
+pub struct JsonFileReporter {
+    path: PathBuf,
+    reports: Mutex<Vec<Report>>,
+}
+
+impl JsonFileReporter {
+    pub fn new(path: PathBuf) -> JsonFileReporter {
+        JsonFileReporter {
+            path,
+            reports: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Reporter for JsonFileReporter {
+    fn name(&self) -> &'static str {
+        "JSON file"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // Raw directory reviews produce a report per language, every one of them is kept
+            let json = {
+                let mut reports = self
+                    .reports
+                    .lock()
+                    .map_err(|_| anyhow!("JSON reports lock poisoned"))?;
+                reports.push(report.clone());
+                serde_json::to_string_pretty(&*reports)?
+            };
+            tokio::fs::write(&self.path, json)
+                .await
+                .with_context(|| format!("Failed to write {}", self.path.display()))
+        }
+        .boxed()
+    }
+}
+
+/// Post the report as a comment of a GitHub pull request, with `gh pr comment`
+pub struct GitHubCommentReporter {
+    pub pr: String,
+}
+
+impl Reporter for GitHubCommentReporter {
+    fn name(&self) -> &'static str {
+        "GitHub comment"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            network::ensure_allowed("`gh pr comment`")?;
+            let mut child = Command::from(crate::git_helpers::repo_command("gh"))
+                .args(["pr", "comment", &self.pr, "--body-file", "-"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context("Failed to invoke `gh pr comment`")?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // The review is plain text, keep its layout in the comment
+                let body = format!("```\n{}\n```", report.text());
+                stdin.write_all(body.as_bytes()).await?;
+            }
+
+            let output = child.wait_with_output().await?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                bail!("`gh pr comment` failed: {stderr}");
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// POST the report as JSON to a webhook
+pub struct WebhookReporter {
+    pub url: String,
+}
+
+impl Reporter for WebhookReporter {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            network::ensure_allowed("The webhook")?;
+            let response = reqwest::Client::new()
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(report)?)
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {}", self.url))?;
+            if !response.status().is_success() {
+                bail!("{} answered {}", self.url, response.status());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Attach the report as a git note (`refs/notes/rv`) to the reviewed commit
+pub struct GitNotesReporter {
+    pub merge: NotesMerge,
+}
+
+impl Reporter for GitNotesReporter {
+    fn name(&self) -> &'static str {
+        "git note"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if report.verdict == Verdict::NoChanges {
+                return Ok(());
+            }
+            let Some(commit) = &report.commit else {
+                bail!("the review isn't tied to a commit (staged changes or raw review)");
+            };
+            let note = format!(
+                "rv review of {} ({})\n\n{}",
+                report.target,
+                report.model,
+                report.text()
+            );
+            if !notes::write_note(commit, &note, self.merge)? {
+                println!("\n[NOTE] Commit {commit:.8} already has a review note, it was kept");
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Output destinations selected by the CLI flags and the configuration, on top of the terminal
+#[derive(Debug, Clone, Default)]
+pub struct OutputSinks {
+    pub json_path: Option<PathBuf>,
+    /// Pull request receiving the review as a comment
+    pub comment_pr: Option<String>,
+    pub webhook_url: Option<String>,
+    /// Directory receiving the artifact folder of every review
+    pub artifact_dir: Option<PathBuf>,
+    /// Merge strategy of the git note holding the review, when requested with `--notes`
+    pub notes: Option<NotesMerge>,
+    /// Interactive triage of the findings, after the other outputs
+    pub triage: bool,
+    /// Re-print the reviewed diff with the findings under their hunks
+    pub inline: bool,
+    /// Copy the review to the clipboard once it's complete, in this form
+    pub copy: Option<CopyFormat>,
+    /// Editor opening the files of the findings at their lines, after the other outputs
+    pub open: Option<String>,
+    /// Desktop notification of the reviews of long runs
+    pub notify: Option<NotifyConfig>,
+    /// Commands to run next, printed at the end of the review
+    pub next_steps: Option<NextStepsContext>,
+}
+
+impl OutputSinks {
+    pub fn reporters(&self, pipe: bool) -> Vec<Box<dyn Reporter>> {
+        let mut reporters: Vec<Box<dyn Reporter>> = vec![Box::new(TerminalReporter { pipe })];
+        if self.inline {
+            reporters.push(Box::new(InlineReporter));
+        }
+        if let Some(path) = &self.json_path {
+            reporters.push(Box::new(JsonFileReporter::new(path.clone())));
+        }
+        if let Some(pr) = &self.comment_pr {
+            reporters.push(Box::new(GitHubCommentReporter { pr: pr.clone() }));
+        }
+        if let Some(url) = &self.webhook_url {
+            reporters.push(Box::new(WebhookReporter { url: url.clone() }));
+        }
+        if let Some(dir) = &self.artifact_dir {
+            reporters.push(Box::new(ArtifactReporter::new(dir.clone())));
+        }
+        if let Some(merge) = self.notes {
+            reporters.push(Box::new(GitNotesReporter { merge }));
+        }
+        if self.triage && !pipe {
+            reporters.push(Box::new(TriageReporter));
+        }
+        if let Some(format) = self.copy {
+            reporters.push(Box::new(ClipboardReporter::new(format)));
+        }
+        if let Some(config) = &self.notify {
+            reporters.push(Box::new(NotifyReporter::new(config.clone())));
+        }
+        if let Some(editor) = self.open.as_ref().filter(|_| !pipe) {
+            reporters.push(Box::new(OpenReporter {
+                editor: editor.clone(),
+            }));
+        }
+        if let Some(context) = self.next_steps.as_ref().filter(|_| !pipe) {
+            reporters.push(Box::new(NextStepsReporter {
+                context: context.clone(),
+            }));
+        }
+        reporters
+    }
+}
+
+/// Hand `report` to every reporter; a failing reporter doesn't stop the others
+pub async fn dispatch(reporters: &[Box<dyn Reporter>], report: &Report) {
+    for reporter in reporters {
+        if let Err(e) = reporter.report(report).await {
+            eprintln!(
+                "[WARN] Failed to send the review to the {}: {e:#}",
+                reporter.name()
+            );
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Request pipeline shared by every command sending repository content to the model: the policy
+//! of the organization, secret redaction, the prompt injection guard, the choice of the LLM
+//! configuration, the context window and the confirmation of what is about to leave the machine
+
+use crate::config::{ContextOverflow, LLMConfig, RvConfig};
+use crate::git_helpers::SkippedFile;
+use crate::injection::{PromptGuard, warn_neutralized};
+use crate::markup;
+use crate::policy::Policy;
+use crate::redact::redact_secrets;
+use crate::review::{print_request_summary, select_llm_config};
+use crate::selection::resolve_llm_selection;
+use crate::term_helpers;
+use crate::tokens::{self, LeftOut};
+
+use anyhow::{Result, bail};
+use std::path::PathBuf;
+
+/// Settings of a run once the policy is enforced, with the injection guard of its prompts
+pub struct RequestPipeline {
+    pub rvconfig: RvConfig,
+    pub policy: Option<Policy>,
+    pub guard: PromptGuard,
+}
+
+impl RequestPipeline {
+    /// Load the policy of the organization and enforce it on `rvconfig`
+    pub fn new(rvconfig: RvConfig) -> Result<RequestPipeline> {
+        let policy = Policy::load()?;
+        let mut rvconfig = rvconfig;
+        if let Some(policy) = &policy {
+            policy.enforce_config(&mut rvconfig);
+        }
+        let guard = PromptGuard::new(rvconfig.prompt_injection_guard);
+        Ok(RequestPipeline {
+            rvconfig,
+            policy,
+            guard,
+        })
+    }
+
+    /// `content` without its secrets, when `redact_secrets` is on
+    pub fn redact(&self, content: String) -> String {
+        if self.rvconfig.redact_secrets {
+            redact_secrets(&content)
+        } else {
+            content
+        }
+    }
+
+    /// Configuration of the model answering prompts of `prompt_tokens` changing `paths`: the one
+    /// asked for, or the one picked by the model strategy, within the policy
+    pub fn select_llm(
+        &self,
+        llm_selection: Option<String>,
+        prompt_tokens: usize,
+        paths: &[String],
+        pipe: bool,
+    ) -> Result<(String, LLMConfig)> {
+        let llm_selection = resolve_llm_selection(
+            llm_selection,
+            &self.rvconfig,
+            self.policy.as_ref(),
+            prompt_tokens,
+            paths,
+            pipe,
+        );
+        select_llm_config(&self.rvconfig, llm_selection, self.policy.as_ref())
+    }
+
+    /// `system_prompt` without its secrets and with the rules of the injection guard
+    pub fn system_prompt(&self, system_prompt: String) -> String {
+        let mut system_prompt = self.redact(system_prompt);
+        self.guard.harden(&mut system_prompt);
+        system_prompt
+    }
+
+    /// Guard the untrusted `prompts` (already redacted) in place and fit each one into the context
+    /// window of `llm_config`, along with `system_prompt`
+    pub fn prepare<'a>(
+        &self,
+        llm_config: &LLMConfig,
+        system_prompt: &str,
+        prompts: impl IntoIterator<Item = &'a mut String>,
+    ) -> Result<()> {
+        let mut neutralized = 0;
+        for prompt in prompts {
+            let fitted = fit_context_window(llm_config, system_prompt, &self.guard, prompt)?;
+            *prompt = fitted.prompt;
+            neutralized += fitted.neutralized;
+        }
+        warn_neutralized(neutralized);
+        Ok(())
+    }
+}
+
+/// Prompt fitted into the context window of the model
+pub struct FittedPrompt {
+    pub prompt: String,
+    /// Instruction-like phrases found in the untrusted content
+    pub neutralized: usize,
+    /// Diffs and sources left out by the truncation
+    pub left_out: Vec<LeftOut>,
+    /// Path of the element whose end was cut by the truncation
+    pub cut: Option<String>,
+}
+
+impl FittedPrompt {
+    /// Files among `paths` the model doesn't fully see: their diff was left out or cut, or their
+    /// source was when the prompt has no diffs (`diffs_sent` is false)
+    pub fn skipped_files<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a PathBuf>,
+        diffs_sent: bool,
+    ) -> Vec<SkippedFile> {
+        let mut skipped: Vec<SkippedFile> = Vec::new();
+        for path in paths {
+            // The prompt holds the escaped paths
+            let text = markup::escape_attribute(&markup::path_text(path));
+            let left_out = self
+                .left_out
+                .iter()
+                .find(|left_out| left_out.path == text && (!left_out.is_source || !diffs_sent));
+            let reason = if let Some(left_out) = left_out {
+                let kind = if left_out.is_source { "source" } else { "diff" };
+                format!("{kind} left out to fit the context window of the model")
+            } else if self.cut.as_ref() == Some(&text) {
+                String::from("end cut to fit the context window of the model")
+            } else {
+                continue;
+            };
+            if !skipped.iter().any(|file| file.path == *path) {
+                skipped.push(SkippedFile {
+                    path: path.clone(),
+                    reason,
+                });
+            }
+        }
+        skipped
+    }
+}
+
+/// Guard the untrusted `content` and make it fit into the context window of `llm_config` along
+/// with `system_prompt`: oversized prompts are refused, or truncated with `context_overflow =
+/// "truncate"`
+pub fn fit_context_window(
+    llm_config: &LLMConfig,
+    system_prompt: &str,
+    guard: &PromptGuard,
+    content: &str,
+) -> Result<FittedPrompt> {
+    let (prompt, neutralized) = guard.untrusted(content);
+    let whole = |prompt| FittedPrompt {
+        prompt,
+        neutralized,
+        left_out: Vec::new(),
+        cut: None,
+    };
+    let Some(window) = tokens::context_window(llm_config) else {
+        return Ok(whole(prompt));
+    };
+    let budget = tokens::input_budget(window);
+    let system_tokens = tokens::count_tokens(system_prompt);
+    let prompt_tokens = system_tokens + tokens::count_tokens(&prompt);
+    if prompt_tokens <= budget {
+        return Ok(whole(prompt));
+    }
+    let message = tokens::overflow_message(llm_config, prompt_tokens, window);
+    // The boundary around the untrusted content takes its share of the budget
+    let guard_tokens = tokens::count_tokens(&prompt) - tokens::count_tokens(content);
+    if llm_config.context_overflow == ContextOverflow::Refuse
+        || system_tokens + guard_tokens >= budget
+    {
+        bail!(message);
+    }
+    let truncation =
+        tokens::truncate_prompt(content, budget.saturating_sub(system_tokens + guard_tokens));
+    eprintln!(
+        "[WARN] {message}, leaving {} file(s) out{}",
+        truncation.left_out.len(),
+        truncation
+            .cut
+            .as_ref()
+            .map(|path| format!(" and cutting the end of {path}"))
+            .unwrap_or_default()
+    );
+    Ok(FittedPrompt {
+        prompt: guard.untrusted(&truncation.prompt).0,
+        neutralized,
+        left_out: truncation.left_out,
+        cut: truncation.cut,
+    })
+}
+
+/// Show the request(s) about to be sent and ask to send them, unless `assume_yes` or the CI
+/// variable is set; `requests` describes runs of several requests. Returns `false` when cancelled
+pub fn confirm_requests(
+    llm_config: &LLMConfig,
+    files: usize,
+    input_tokens: usize,
+    requests: Option<String>,
+    assume_yes: bool,
+) -> Result<bool> {
+    if assume_yes || std::env::var_os("CI").is_some() {
+        return Ok(true);
+    }
+    print_request_summary(llm_config, files, input_tokens);
+    if let Some(requests) = &requests {
+        eprintln!("  Requests : {requests}");
+    }
+    let prompt = if requests.is_some() {
+        "Send these requests?"
+    } else {
+        "Send this request?"
+    };
+    if !term_helpers::confirm(prompt)? {
+        eprintln!("Cancelled, nothing was sent.");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(left_out: &[(&str, bool)], cut: Option<&str>) -> FittedPrompt {
+        FittedPrompt {
+            prompt: String::new(),
+            neutralized: 0,
+            left_out: left_out
+                .iter()
+                .map(|(path, is_source)| LeftOut {
+                    path: path.to_string(),
+                    is_source: *is_source,
+                })
+                .collect(),
+            cut: cut.map(String::from),
+        }
+    }
+
+    #[test]
+    fn skips_the_files_whose_diff_was_left_out_or_cut() {
+        let paths = [
+            PathBuf::from("src/a \"b\".rs"),
+            PathBuf::from("src/c.rs"),
+            PathBuf::from("src/d.rs"),
+            PathBuf::from("src/e.rs"),
+        ];
+        let fitted = fitted(
+            &[("src/a &quot;b&quot;.rs", false), ("src/d.rs", true)],
+            Some("src/c.rs"),
+        );
+        let skipped: Vec<PathBuf> = fitted
+            .skipped_files(&paths, true)
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(skipped, &paths[..2]);
+    }
+
+    #[test]
+    fn skips_the_files_whose_source_was_left_out_without_diffs() {
+        let paths = [PathBuf::from("src/d.rs")];
+        let skipped = fitted(&[("src/d.rs", true)], None).skipped_files(&paths, false);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].reason.starts_with("source left out"));
+    }
+}
@@ -1,16 +1,65 @@
-use crate::config::{BranchAgainst, CustomPrompt, LLMConfig, RvConfig};
+use crate::budget::{BUDGET_EXHAUSTED_REASON, TimeBudget};
+use crate::cache;
+use crate::checks;
+use crate::collect::FileCollector;
+use crate::complexity::{self, COMPLEXITY_PROMPT};
+use crate::compress::SOURCE_COMPRESSION_PROMPT;
+use crate::config::{
+    BranchAgainst, CustomPrompt, LLMConfig, OpenAIProvider, RawConfig, RvConfig, SourceCompression,
+};
+use crate::consensus;
+use crate::context::{estimate_tokens, load_context_file, process_context_commands, read_file};
+use crate::coverage::{Coverage, check_min_coverage};
+use crate::dump;
+use crate::encoding::{self, Decoded};
+use crate::findings;
+use crate::fixes;
+use crate::formats;
 use crate::git_helpers;
-use crate::git_helpers::ExpandedCommit;
+use crate::git_helpers::{ExpandedCommit, PathFilter, SkippedFile, format_not_reviewed};
 use crate::github;
+use crate::history::{self, ReviewRecord, Usage};
+use crate::ignore::IgnoreRules;
+use crate::imports::referenced_siblings;
+use crate::index;
+use crate::injection::{Boundary, PromptGuard, warn_neutralized};
+use crate::inline::INLINE_ANCHORS_PROMPT;
+use crate::knowledge;
+use crate::language::{Language, detect_language, language_hint};
+use crate::markup;
+use crate::minimize::{MinimizeOptions, minimize};
+use crate::network;
 use crate::term_helpers::{self, ActionSelection};
+use crate::variables::PromptVariables;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use rig::message::Message;
 
+use crate::llm::capabilities::ModelCapabilities;
 use crate::llm::create_llm_provider;
-use std::path::PathBuf;
-
-const SYSTEM_PROMPT: &str = r#"
+use crate::llm::defs::LLMProvider;
+use crate::policy::Policy;
+use crate::profile::{ReviewSize, SUMMARY_SYSTEM_PROMPT};
+use crate::pseudonyms::{PathMap, redact_paths};
+use crate::redact::redact_secrets;
+use crate::report::{self, PartialFailure, REVIEW_FAILED_REASON, Report, Reporter, Verdict};
+use crate::request;
+use crate::review_model::{JSON_REVIEW_PROMPT, Review};
+use crate::rules::{RULE_IDS_PROMPT, parse_rules};
+use crate::sandbox::Sandbox;
+use crate::sanitize::sanitize_output;
+use crate::selection::resolve_llm_selection;
+use crate::semver;
+use crate::snippet;
+use crate::structure::{REFORMAT_PROMPT, check_structure};
+use crate::suppress::Suppressions;
+use crate::telemetry;
+use crate::tickets;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const SYSTEM_PROMPT: &str = r#"
 You are a senior software engineer and professional code reviewer.
 Produce a concise, actionable, terminal-friendly review of the code
 I provide. Follow these rules exactly.
@@ -55,10 +104,27 @@ KEY RULES (must obey)
 - Assume latest stable toolchain unless told otherwise.
 
 INPUT FORMAT (what I'll send next)
-- <context FILE>   : text file containing context about the project
-- <guideline FILE> : text file containing guidelines and instructions
-- <diff FILE>      : git diff of the file to review
-- <source FILE>    : text file to be reviewed
+- <context path="FILE">   : text file containing context about the project
+- <guideline path="FILE"> : text file containing guidelines and instructions
+- <diff path="FILE">      : git diff of the file to review
+- <source path="FILE">    : text file to be reviewed
+- <info ticket="KEY">     : ticket the change implements; check that the
+  change matches its requirement and report what it misses
+- <info checks="duplicate-code"> : copy-pasted code found in the change;
+  recommend extracting it when the copies should stay in sync
+- <info checks="semver"> : breaking changes of the public API; suggest a
+  deprecation path for each (ex. keep the old item as #[deprecated],
+  forwarding to the new one)
+- <guideline format="NAME"> : review guidelines of a structured format
+  of the changed files (ex. OpenAPI, SQL migration)
+- <info checks="format-lint"> : output of the linters of these files;
+  report the problems the change introduces, ignore the existing ones
+- Content is XML-escaped: read &lt; as <, &gt; as > and &amp; as &.
+  Quote code with the original characters.
+- Paths are escaped the same way in the path attribute (&quot; for ",
+  &#10; for a newline); bytes that aren't UTF-8 are written as \xNN.
+  When a path has spaces or unusual characters, quote it in backticks
+  when naming it (ex. `docs/my notes.md`:12).
 
 Now review the input I will provide next. Produce the review using the
 exact structure and rules above.
@@ -93,10 +159,21 @@ KEY RULES (must obey)
 - Assume latest stable toolchain unless told otherwise.
 
 INPUT FORMAT (what I'll send next)
-- <context FILE>   : text file containing context about the project
-- <guideline FILE> : text file containing guidelines and instructions
-- <diff FILE>      : git diff of the file to review
-- <source FILE>    : text file to be reviewed
+- <context path="FILE">   : text file containing context about the project
+- <guideline path="FILE"> : text file containing guidelines and instructions
+- <diff path="FILE">      : git diff of the file to review
+- <source path="FILE">    : text file to be reviewed
+- <info ticket="KEY">     : ticket the change implements
+- <info checks="duplicate-code"> : copy-pasted code found in the change
+- <info checks="semver"> : breaking changes of the public API
+- <guideline format="NAME"> : review guidelines of a structured format
+- <info checks="format-lint"> : output of the linters of these files
+- Content is XML-escaped: read &lt; as <, &gt; as > and &amp; as &.
+  Quote code with the original characters.
+- Paths are escaped the same way in the path attribute (&quot; for ",
+  &#10; for a newline); bytes that aren't UTF-8 are written as \xNN.
+  When a path has spaces or unusual characters, quote it in backticks
+  when naming it (ex. `docs/my notes.md`:12).
 
 Now the conversation will start.
 Act following the rules above.
@@ -104,127 +181,118 @@ Act following the rules above.
 =============================
 "#;
 
-/// Process /load and /read-only commands within context files
-fn process_context_commands(content: &str) -> String {
-    let mut result = String::new();
-    let mut current_pos = 0;
-
-    // Find all /load and /read-only commands in the content
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("/load ") || trimmed.starts_with("/read-only ") {
-            // Extract file path from command
-            let parts: Vec<&str> = trimmed.splitn(2, ' ').collect();
-            if parts.len() >= 2 {
-                let file_path = parts[1].trim();
-
-                // Try to load the referenced file
-                if let Ok(loaded_content) = load_context_file(file_path) {
-                    result.push_str(&format!(
-                        "\n[Content loaded from '{}':]\n{}\n",
-                        file_path, loaded_content
-                    ));
-                } else if let Ok(loaded_content) = std::fs::read_to_string(file_path) {
-                    result.push_str(&format!(
-                        "\n[Content loaded from '{}':]\n{}\n",
-                        file_path, loaded_content
-                    ));
-                } else {
-                    result.push_str(&format!(
-                        "\n[Failed to load content from '{}']\n",
-                        file_path
-                    ));
-                }
-            }
-        } else {
-            // Regular line, add as-is
-            result.push_str(line);
-            result.push('\n');
-        }
-    }
-
-    result
-}
-
-fn read_file(filename: &str) -> Option<String> {
-    // Load files from project's root directory (where .git is) not current working directory
-    let repo = match git2::Repository::discover(".") {
-        Ok(repo) => repo,
-        Err(_) => return None,
-    };
-    let workdir = repo.workdir()?;
-    let full_path = workdir.join(filename);
-    std::fs::read_to_string(&full_path).ok()
-}
-
-fn load_context_file(file_path: &str) -> Result<String> {
-    // Try to read the file relative to current directory first
-    if let Ok(content) = std::fs::read_to_string(file_path) {
-        return Ok(content);
-    }
-
-    // If not found, try relative to git repository root
-    let repo = git2::Repository::discover(".")?;
-    let workdir = repo
-        .workdir()
-        .ok_or_else(|| anyhow::anyhow!("No working directory found"))?;
-    let full_path = workdir.join(file_path);
-
-    std::fs::read_to_string(&full_path)
-        .map_err(|e| anyhow::anyhow!("Failed to read context file '{}': {}", file_path, e))
-}
 /// Add context, guidelines and custom instructions to the LLM prompt
 pub fn pack_prompt(
     base_system_prompt: &str,
     rvconfig: &RvConfig,
     llm_config: Option<&LLMConfig>,
     load_context: Option<&PathBuf>,
+    language: Option<Language>,
+    skipped: &mut Vec<SkippedFile>,
 ) -> Result<String> {
     let mut system_prompt = base_system_prompt.to_string();
     let mut suffix_context: String = String::new();
+    let variables = PromptVariables::collect(rvconfig);
+
+    // Compressed sources come with an offset table the LLM must apply to line numbers
+    if rvconfig.diff_profile.report_sources
+        && rvconfig.diff_profile.source_compression != SourceCompression::None
+    {
+        suffix_context.push_str(SOURCE_COMPRESSION_PROMPT);
+    }
+
+    // Handle language-specific guidelines (ex. `.rv_guidelines.rust`)
+    let mut has_rules = false;
+    if let Some(language) = language {
+        let f = format!(".rv_guidelines.{}", language.id());
+        if let Some(content_str) = read_file(&f) {
+            let content_str = variables.resolve(&content_str);
+            has_rules |= !parse_rules(&content_str).is_empty();
+            suffix_context.push_str(&markup::element(
+                "guideline",
+                &[("path", &f)],
+                &process_context_commands(&content_str),
+            ));
+        }
+    }
 
     // Handle project guidelines files
     for f in rvconfig.project_guidelines_files.files.clone() {
         let content = read_file(&f);
         if content.is_some() {
-            let content_str = content.unwrap_or_default();
-            suffix_context.push_str(&format!("<guideline {f}>"));
+            let content_str = variables.resolve(&content.unwrap_or_default());
 
             // Process /load and /read-only commands in guideline files
             let processed_content = process_context_commands(&content_str);
-            suffix_context.push_str(&processed_content);
+            has_rules |= !parse_rules(&content_str).is_empty();
+            suffix_context.push_str(&markup::element(
+                "guideline",
+                &[("path", &f)],
+                &processed_content,
+            ));
+        }
+    }
+
+    // Guidelines with rule IDs get the findings tagged with the rules they violate
+    if has_rules {
+        suffix_context.push_str(RULE_IDS_PROMPT);
+    }
 
-            suffix_context.push_str("</guideline>");
+    // Handle project README, shrunk to the configured sections and budget
+    if let Some(readme) = crate::context::load_readme(&rvconfig.readme) {
+        suffix_context.push_str(&markup::element(
+            "context",
+            &[("path", &readme.file)],
+            &readme.content,
+        ));
+        if readme.truncated {
+            skipped.push(SkippedFile {
+                path: PathBuf::from(&readme.file),
+                reason: String::from("sections truncated to fit the README token budget"),
+            });
         }
     }
 
     // Handle project context files
     for f in rvconfig.project_context_files.files.clone() {
+        // READMEs listed here are already handled by the `readme` configuration
+        if rvconfig.readme.files.contains(&f) {
+            continue;
+        }
         let content = read_file(&f);
         if content.is_some() {
             let content_str = content.unwrap_or_default();
-            suffix_context.push_str(&format!("<context {f}>"));
 
             // Process /load and /read-only commands in context files
             let processed_content = process_context_commands(&content_str);
-            suffix_context.push_str(&processed_content);
-
-            suffix_context.push_str("</context>");
+            suffix_context.push_str(&markup::element(
+                "context",
+                &[("path", &f)],
+                &processed_content,
+            ));
         }
     }
 
+    // Handle the facts learned from the previous reviews
+    suffix_context.push_str(&knowledge::format_knowledge());
+
     // Handle load context file if provided
     if let Some(context_path) = load_context {
-        if let Ok(content) = load_context_file(&context_path.to_string_lossy()) {
-            suffix_context.push_str(&format!("<context {}>", context_path.display()));
-            suffix_context.push_str(&content);
-            suffix_context.push_str("</context>");
+        let context_name = context_path.to_string_lossy();
+        if let Ok(content) = load_context_file(&context_name) {
+            suffix_context.push_str(&markup::element(
+                "context",
+                &[("path", &context_name)],
+                &content,
+            ));
         } else {
             // Try to read it directly from the path
             if let Ok(content) = std::fs::read_to_string(context_path) {
-                suffix_context.push_str(&format!("<context {}>", context_path.display()));
-                suffix_context.push_str(&content);
-                suffix_context.push_str("</context>");
+                suffix_context.push_str(&markup::element(
+                    "context",
+                    &[("path", &context_name)],
+                    &content,
+                ));
             }
         }
     }
@@ -235,13 +303,15 @@ pub fn pack_prompt(
     {
         match custom_prompt {
             CustomPrompt::Suffix(suffix) => {
-                suffix_context.push_str("<custom_prompt>");
-                suffix_context.push_str(suffix);
-                suffix_context.push_str("</custom_prompt>");
+                suffix_context.push_str(&markup::element(
+                    "custom_prompt",
+                    &[],
+                    &variables.resolve(suffix),
+                ));
             }
             CustomPrompt::Replace(replacement) => {
                 // Replace the entire system prompt with custom content
-                system_prompt = replacement.clone();
+                system_prompt = variables.resolve(replacement);
                 // Still append other context files
                 system_prompt.push_str(&suffix_context);
                 return Ok(system_prompt);
@@ -254,17 +324,97 @@ pub fn pack_prompt(
     Ok(system_prompt)
 }
 
+/// Review a code snippet read from the clipboard or stdin
+#[allow(clippy::too_many_arguments)]
+pub async fn snippet_review(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    from_clipboard: bool,
+    lang: Option<String>,
+    pipe: bool,
+    start_as_chat: bool,
+    action_menu: Option<bool>,
+    load_context: Option<PathBuf>,
+    assume_yes: bool,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<()> {
+    let code = snippet::read_snippet(from_clipboard)?;
+    let language = snippet::snippet_language(lang.as_deref(), &code, !pipe && !assume_yes)?;
+    let lines = code.lines().count();
+    process_review(
+        &rvconfig,
+        llm_selection,
+        snippet::snippet_expanded(&code, language),
+        false,
+        pipe,
+        start_as_chat,
+        action_menu,
+        None,
+        load_context,
+        format!("snippet ({lines} lines)"),
+        (language != Language::Other).then_some(language),
+        assume_yes,
+        reporters,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Files imported by a raw single file, appended as read-only context within the raw size limits
+fn imported_context(
+    path: &Path,
+    language: Language,
+    content: &str,
+    raw_config: &RawConfig,
+) -> String {
+    let mut context = String::new();
+    for sibling in referenced_siblings(path, language, content) {
+        let size = std::fs::metadata(&sibling).map(|m| m.len()).unwrap_or(0);
+        if raw_config.max_file_bytes > 0 && size > raw_config.max_file_bytes {
+            continue;
+        }
+        let Ok(Decoded::Text(sibling_content)) = encoding::read_source(&sibling) else {
+            continue;
+        };
+        if raw_config.max_file_tokens > 0
+            && estimate_tokens(&sibling_content) > raw_config.max_file_tokens
+        {
+            continue;
+        }
+        context.push_str(&format!(
+            "\n\nImported file {} (read-only context, do not review it):\n{sibling_content}",
+            sibling.display()
+        ));
+    }
+    context
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn raw_review(
     rvconfig: RvConfig,
     llm_selection: Option<String>,
     file_path: Option<PathBuf>,
     dir_path: Option<PathBuf>,
-    recursive: Option<bool>,
+    recursive: bool,
+    force_include: bool,
+    follow_symlinks: bool,
+    hidden: bool,
+    no_ignore: bool,
+    with_imports: bool,
     pipe: bool,
     start_as_chat: bool,
     action_menu: Option<bool>,
     load_context: Option<PathBuf>,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    reporters: &[Box<dyn Reporter>],
 ) -> Result<()> {
+    // Whole files are sent in raw mode, so the policy must be applied before reading them
+    let mut rvconfig = rvconfig;
+    if let Some(policy) = Policy::load()? {
+        policy.enforce_config(&mut rvconfig);
+    }
+
     if let Some(path) = file_path {
         if !path.exists() {
             println!("[ERROR] File does not exist: {path:?}");
@@ -276,15 +426,33 @@ pub async fn raw_review(
         expcommit.sources = Some(vec![path.clone()]);
 
         // Read file content
-        match std::fs::read_to_string(&path) {
-            Ok(content) => {
+        match encoding::read_source(&path) {
+            Ok(Decoded::Binary) => {
+                println!(
+                    "[ERROR] {} is a binary file, it can't be reviewed",
+                    path.display()
+                );
+            }
+            Ok(Decoded::Text(content)) => {
+                let language = detect_language(&path);
+                // Standalone files (scripts, gists) come with no project around them
+                let hint = language_hint(&path);
                 if rvconfig.diff_profile.report_sources {
                     // For raw mode, we'll treat the file content as both source and "diff"
                     // Since there's no actual diff, we can show the entire file
-                    expcommit.diffs = Some(vec![format!("Raw file content:\n{}", content)]);
+                    let mut raw_content = format!("{hint}\nRaw file content:\n{content}");
+                    if with_imports {
+                        raw_content.push_str(&imported_context(
+                            &path,
+                            language,
+                            &content,
+                            &rvconfig.raw,
+                        ));
+                    }
+                    expcommit.diffs = Some(vec![raw_content]);
                 } else {
                     expcommit.diffs =
-                        Some(vec![String::from("File content not shown in diff mode")]);
+                        Some(vec![format!("{hint}\nFile content not shown in diff mode")]);
                 }
 
                 // Process the review
@@ -292,12 +460,16 @@ pub async fn raw_review(
                     &rvconfig,
                     llm_selection,
                     expcommit,
-                    None,
+                    false,
                     pipe,
                     start_as_chat,
                     action_menu,
                     None,
                     load_context.clone(),
+                    format!("raw file {}", path.display()),
+                    Some(language),
+                    assume_yes,
+                    reporters,
                 )
                 .await?;
             }
@@ -312,54 +484,177 @@ pub async fn raw_review(
             return Ok(());
         }
 
-        let recursive = recursive.unwrap_or(false);
+        // Size limits can be lifted for a single run with --force-include
+        let mut raw_config = rvconfig.raw.clone();
+        if force_include {
+            raw_config.max_file_bytes = 0;
+            raw_config.max_file_tokens = 0;
+        }
+        raw_config.follow_symlinks |= follow_symlinks;
+        raw_config.hidden |= hidden;
+
+        // Default artifact excludes and `.rvignore` can be disabled for a single run with --no-ignore
+        let mut ignore = IgnoreRules::new();
+        if !no_ignore {
+            ignore.add_patterns(raw_config.exclude.iter().map(String::as_str));
+            ignore.add_rvignore();
+        }
 
         // Collect all files in directory
-        let mut files = Vec::new();
-        if let Err(e) = collect_files(&path, recursive, &mut files) {
+        let mut collector = FileCollector::new(&path, recursive, raw_config, ignore)?;
+        if let Err(e) = collector.collect(&path) {
             println!("[ERROR] Failed to collect files: {e}");
             return Err(e.into());
         }
 
+        let files = collector.files;
+        // Skipped files are reported once, in the appendix of the first review
+        let mut skipped = collector.skipped;
+
         if files.is_empty() {
             println!("[ERROR] No files found in directory");
+            if !skipped.is_empty() {
+                println!("\n{}", format_not_reviewed(&skipped));
+            }
             return Ok(());
         }
 
-        // Create ExpandedCommit structure for directory
-        let mut expcommit = ExpandedCommit::new();
-        expcommit.sources = Some(files.clone());
-
-        // Read all file contents
-        let mut diffs = Vec::new();
+        // Group files by language, so that each review gets a homogeneous prompt
+        let mut groups: BTreeMap<Language, Vec<PathBuf>> = BTreeMap::new();
         for file_path in files {
-            match std::fs::read_to_string(&file_path) {
-                Ok(content) => {
-                    if rvconfig.diff_profile.report_sources {
-                        diffs.push(format!("File: {file_path:?}\n{content}"));
-                    } else {
-                        diffs.push(format!("File: {file_path:?} (content not shown)"));
+            groups
+                .entry(detect_language(&file_path))
+                .or_default()
+                .push(file_path);
+        }
+
+        let mut confirmed = assume_yes;
+        let mut out_of_budget: Vec<SkippedFile> = Vec::new();
+        let mut group_reviews: Vec<(String, String)> = Vec::new();
+        let mut requests = 0;
+        let mut failures: Vec<anyhow::Error> = Vec::new();
+        for (language, files) in groups {
+            // Once the budget is exhausted, the remaining groups are only reported
+            if time_budget.is_exhausted() {
+                out_of_budget.extend(files.into_iter().map(|path| SkippedFile {
+                    path,
+                    reason: String::from(BUDGET_EXHAUSTED_REASON),
+                }));
+                continue;
+            }
+
+            // Create ExpandedCommit structure for the language group
+            let mut expcommit = ExpandedCommit::new();
+            expcommit.sources = Some(files.clone());
+            expcommit.skipped = std::mem::take(&mut skipped);
+
+            // Read all file contents
+            let mut diffs = Vec::new();
+            for file_path in &files {
+                match encoding::read_source(file_path) {
+                    Ok(Decoded::Binary) => {
+                        diffs.push(format!("File: {file_path:?} (binary, content not shown)"));
+                    }
+                    Ok(Decoded::Text(content)) => {
+                        if rvconfig.diff_profile.report_sources {
+                            diffs.push(format!("File: {file_path:?}\n{content}"));
+                        } else {
+                            diffs.push(format!("File: {file_path:?} (content not shown)"));
+                        }
+                    }
+                    Err(e) => {
+                        diffs.push(format!("[ERROR] Failed to read file {file_path:?}: {e}"));
                     }
                 }
+            }
+
+            expcommit.diffs = Some(diffs);
+            let target = format!("raw dir {} ({})", path.display(), language.name());
+            let result = process_review(
+                &rvconfig,
+                llm_selection.clone(),
+                expcommit,
+                false,
+                pipe,
+                start_as_chat,
+                action_menu,
+                None,
+                load_context.clone(),
+                target.clone(),
+                Some(language),
+                confirmed,
+                reporters,
+            )
+            .await;
+            requests += 1;
+            match result {
+                Ok(Some(review)) => group_reviews.push((language.name().to_string(), review)),
+                Ok(None) => {}
+                // A failed language group doesn't stop the run, its files are reported instead
                 Err(e) => {
-                    diffs.push(format!("[ERROR] Failed to read file {file_path:?}: {e}"));
+                    eprintln!(
+                        "\n[ERROR] Failed to review the {} files: {e:#}",
+                        language.name()
+                    );
+                    let not_reviewed: Vec<SkippedFile> = files
+                        .iter()
+                        .map(|path| SkippedFile {
+                            path: path.clone(),
+                            reason: format!("{REVIEW_FAILED_REASON}: {e}"),
+                        })
+                        .collect();
+                    report::dispatch(
+                        reporters,
+                        &Report {
+                            id: None,
+                            target,
+                            verdict: Verdict::Reviewed,
+                            commit: None,
+                            model: String::new(),
+                            tags: rvconfig.tags.clone(),
+                            review: String::new(),
+                            reviewed_files: Vec::new(),
+                            not_reviewed,
+                            suppressed: Vec::new(),
+                            checks: Vec::new(),
+                            fixes: Vec::new(),
+                            complexity: Vec::new(),
+                            coverage: None,
+                            structured: None,
+                            prompt: String::new(),
+                            patches: BTreeMap::new(),
+                        },
+                    )
+                    .await;
+                    failures.push(e);
                 }
             }
+            // A single confirmation covers every language group of the run
+            confirmed = true;
         }
 
-        expcommit.diffs = Some(diffs);
-        process_review(
-            &rvconfig,
-            llm_selection,
-            expcommit,
-            None,
-            pipe,
-            start_as_chat,
-            action_menu,
-            None,
-            load_context.clone(),
-        )
-        .await?;
+        // The same pattern found in several language groups is reported once
+        let consolidated = findings::consolidate(&group_reviews);
+        if !consolidated.is_empty() {
+            println!("\n{}", findings::format_consolidated(&consolidated));
+        }
+
+        if !out_of_budget.is_empty() {
+            println!("\n[TIME BUDGET] The time budget ran out, the review is partial.");
+            println!("\n{}", format_not_reviewed(&out_of_budget));
+        }
+
+        // Every request failing is a failed run, some of them a partial one
+        if !failures.is_empty() {
+            if failures.len() == requests {
+                return Err(failures.remove(0));
+            }
+            return Err(PartialFailure {
+                failed: failures.len(),
+                total: requests,
+            }
+            .into());
+        }
     } else {
         println!(
             "[ERROR] In order to use the RAW mode, you need to specify a --file or a --dir input"
@@ -368,26 +663,6 @@ pub async fn raw_review(
     Ok(())
 }
 
-fn collect_files(
-    dir: &PathBuf,
-    recursive: bool,
-    files: &mut Vec<PathBuf>,
-) -> Result<(), std::io::Error> {
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_dir() {
-            if recursive {
-                collect_files(&path, recursive, files)?;
-            }
-        } else {
-            files.push(path);
-        }
-    }
-    Ok(())
-}
-
 /// Handle /load and /read-only commands in chat mode
 fn handle_load_command(command: &str, all_messages: &mut Vec<Message>) -> Result<String> {
     let parts: Vec<&str> = command.trim().splitn(3, ' ').collect();
@@ -400,9 +675,7 @@ fn handle_load_command(command: &str, all_messages: &mut Vec<Message>) -> Result
 
     // Try to load the context file
     match load_context_file(file_path) {
-        Ok(content) => {
-            // Add the context as a system-like message
-            let context_msg = format!("<context {}>\n{}\n</context>", file_path, content);
+        Ok(_) => {
             all_messages.push(Message::user(format!("[CONTEXT LOADED: {}]", file_path)));
             Ok(format!("Successfully loaded context from: {}", file_path))
         }
@@ -413,84 +686,647 @@ fn handle_load_command(command: &str, all_messages: &mut Vec<Message>) -> Result
     }
 }
 
+/// Select the LLM configuration to use and check it against the organization policy.
+/// Returns the name of the configuration and the configuration itself, with its API key resolved.
+pub fn select_llm_config(
+    rvconfig: &RvConfig,
+    llm_selection: Option<String>,
+    policy: Option<&Policy>,
+) -> Result<(String, LLMConfig)> {
+    let llm_configs = rvconfig.clone().get_llm_configs();
+    let llm_configuration_key = match llm_selection {
+        Some(selection) => selection,
+        None => {
+            if !llm_configs.contains_key(&rvconfig.default_llm_config) {
+                bail!(
+                    "[ERROR] No LLM configuration specified or wrong configuration specified; either create a `default`-named configuration or use the --llm parameter to change the configuration used."
+                );
+            }
+            rvconfig.default_llm_config.clone()
+        }
+    };
+    let Some(llm_configuration) = llm_configs.get(&llm_configuration_key) else {
+        bail!("[ERROR] Failed to load selected LLM configuration");
+    };
+
+    if let Some(policy) = policy {
+        policy.check_llm(llm_configuration)?;
+    }
+    if llm_configuration.provider == OpenAIProvider::Custom && llm_configuration.base_url.is_none()
+    {
+        bail!(
+            "[ERROR] Set `base_url` in the `{llm_configuration_key}` configuration to use the Custom provider"
+        );
+    }
+
+    let mut llm_config_with_key = llm_configuration.clone();
+    llm_config_with_key.api_key = llm_configuration.resolve_api_key()?;
+
+    for warning in ModelCapabilities::from_config(llm_configuration).warnings(llm_configuration) {
+        eprintln!("[WARN] {warning}");
+    }
+
+    Ok((llm_configuration_key, llm_config_with_key))
+}
+
+/// Output tokens assumed for a review when estimating its cost
+pub const ESTIMATED_REVIEW_OUTPUT_TOKENS: usize = 1000;
+
+/// Print provider, model, endpoint and size of the request that is about to be sent, on stderr so
+/// that the output of `--pipe` stays clean
+pub fn print_request_summary(llm_config: &LLMConfig, files: usize, input_tokens: usize) {
+    eprintln!("About to send a review request:");
+    eprintln!("  Provider : {}", llm_config.provider.name());
+    eprintln!("  Model    : {}", llm_config.model_id);
+    eprintln!("  Endpoint : {}", llm_config.endpoint());
+    eprintln!("  Files    : {files}");
+    eprintln!("  Tokens   : ~{input_tokens} input");
+    match llm_config.estimate_cost(input_tokens, ESTIMATED_REVIEW_OUTPUT_TOKENS) {
+        Some(cost) => eprintln!("  Cost     : ~${cost:.4}"),
+        None => eprintln!("  Cost     : unknown (set the model prices in the configuration)"),
+    }
+}
+
+/// Request a review without streaming it, asking once for a reformatted review
+/// when the reply doesn't follow the required structure
+pub async fn request_structured_review(
+    client: &dyn LLMProvider,
+    system_prompt: String,
+    messages: Vec<Message>,
+    reformat: bool,
+) -> Result<String> {
+    let response = client
+        .request(system_prompt.clone(), messages.clone())
+        .await?;
+    if !reformat || check_structure(&response).is_ok() {
+        return Ok(response);
+    }
+
+    let mut reformat_messages = messages;
+    reformat_messages.push(Message::assistant(response));
+    reformat_messages.push(Message::user(REFORMAT_PROMPT));
+    client.request(system_prompt, reformat_messages).await
+}
+
+/// Run a review and its follow-ups; returns the review, unless the run was cancelled or is a chat
+#[allow(clippy::too_many_arguments)]
 async fn process_review(
     rvconfig: &RvConfig,
     llm_selection: Option<String>,
-    expcommit: ExpandedCommit,
-    log_xml_structure: Option<bool>,
+    mut expcommit: ExpandedCommit,
+    log_xml_structure: bool,
     pipe: bool,
     start_as_chat: bool,
     action_menu: Option<bool>,
     current_commit_oid: Option<git2::Oid>,
     load_context: Option<PathBuf>,
-) -> Result<()> {
+    target: String,
+    language: Option<Language>,
+    assume_yes: bool,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<Option<String>> {
+    let mut reviewed_files: Vec<String> = expcommit
+        .sources
+        .iter()
+        .flatten()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    let mut skipped = expcommit.skipped.clone();
+
+    // The organization policy wins over the user configuration
+    let policy = Policy::load()?;
+    let mut rvconfig = rvconfig.clone();
+    // Pick the review profile from the size of the diffs (raw reviews have no diffs)
+    let review_size = match &expcommit.diffs {
+        Some(diffs) if rvconfig.auto_profile.enabled && !start_as_chat => {
+            let size = ReviewSize::from_diffs(diffs, &rvconfig.auto_profile);
+            rvconfig.diff_profile = size.diff_profile(rvconfig.diff_profile);
+            Some(size)
+        }
+        _ => None,
+    };
+    if let Some(policy) = &policy {
+        policy.enforce_config(&mut rvconfig);
+    }
+    let rvconfig = &rvconfig;
+
+    let mut check_findings = checks::run_checks(&rvconfig.checks, &expcommit);
+    check_findings.extend(semver::semver_findings(rvconfig, &expcommit));
+    let structured_files = if rvconfig.formats.enabled {
+        formats::detect_formats(&expcommit)
+    } else {
+        Vec::new()
+    };
+    check_findings.extend(formats::lint_findings(
+        rvconfig,
+        &expcommit,
+        &structured_files,
+    ));
+    check_findings.extend(formats::flag_findings(
+        rvconfig,
+        &expcommit,
+        &structured_files,
+    ));
+    let complexity = complexity::changed_functions(&rvconfig.complexity, &expcommit);
+    let reviewed_commit = expcommit.commits.last().map(|oid| oid.to_string());
+    let changed_lines = expcommit.changed_lines();
+    let reviewed_commits = expcommit.commits.clone();
+    let inline_patches = if rvconfig.inline {
+        expcommit.patches.clone()
+    } else {
+        BTreeMap::new()
+    };
+    let path_map = rvconfig.redact_paths.then(|| {
+        Arc::new(PathMap::new(
+            expcommit
+                .sources
+                .iter()
+                .flatten()
+                .chain(expcommit.patches.keys()),
+        ))
+    });
+    // The diffs are the query of the retrieval of related code
+    let mut retrieval_query = expcommit
+        .diffs
+        .as_ref()
+        .map(|diffs| diffs.join("\n"))
+        .unwrap_or_default();
+    if let Some(path_map) = &path_map {
+        retrieval_query = path_map.redact(&retrieval_query);
+    }
+
     // Convert to structured format
-    let review_prompt = expcommit.get_xml_structure(rvconfig.diff_profile);
+    // The change set stays around to account for what the context window leaves out
+    let mut review_prompt = expcommit.clone().get_xml_structure(rvconfig.diff_profile);
+    if rvconfig.redact_secrets {
+        review_prompt = redact_secrets(&review_prompt);
+    }
+    // The reviewed code is untrusted: it must not be able to steer the reviewer
+    let guard = PromptGuard::new(rvconfig.prompt_injection_guard);
+    let unguarded_prompt = review_prompt;
+    let (review_prompt, neutralized_instructions) = guard.untrusted(&unguarded_prompt);
 
     if !pipe {
         term_helpers::clear_term();
+        if let Some(language) = language {
+            println!("[{} review]\n", language.name());
+        }
+        if let Some(size) = review_size {
+            println!("[{} change set]\n", size.name());
+        }
     }
-    if !pipe && log_xml_structure.is_some() && log_xml_structure.unwrap() {
-        println!("{review_prompt}");
-        println!("  -------  ");
+    warn_neutralized(neutralized_instructions);
+    if !pipe
+        && log_xml_structure
+        && let Err(e) = dump::show_dump("xml-structure", &review_prompt)
+    {
+        eprintln!("[WARN] Failed to dump the XML structure: {e:#}");
     }
 
     // Select correct LLM configuration
-    let llm_configuration_default = rvconfig.clone().default_llm_config;
-    let mut llm_configuration_key = llm_configuration_default;
-    let llm_configs = rvconfig.clone().get_llm_configs();
-    if let Some(selection) = llm_selection {
-        llm_configuration_key = selection;
-    } else if !(llm_configs.contains_key(&llm_configuration_key.clone())) {
-        println!(
-            "[ERROR] No LLM configuration specified or wrong configuration specified; either create a `default`-named configuration or use the --llm parameter to change the configuration used."
-        );
-        std::process::exit(1);
-    }
-    let llm_configuration = match llm_configs.get(&llm_configuration_key.clone()) {
-        Some(config) => config,
-        None => {
-            println!("[ERROR] Failed to load selected LLM configuration");
-            std::process::exit(1);
-        }
-    };
-
-    let api_key = llm_configuration.resolve_api_key()?;
+    let llm_selection = resolve_llm_selection(
+        llm_selection,
+        rvconfig,
+        policy.as_ref(),
+        estimate_tokens(&review_prompt),
+        &reviewed_files,
+        pipe,
+    );
+    let (llm_configuration_key, llm_configuration) =
+        select_llm_config(rvconfig, llm_selection, policy.as_ref())?;
+    let llm_configuration = &llm_configuration;
 
     // If the CLI flag defines the value of action_mode, use that value
     // Otherwise, use the value defined by the LLMConfig
     let run_action_mode: bool = action_menu.unwrap_or(llm_configuration.actions_menu);
 
     // Create LLM provider using factory pattern
-    let mut llm_config_with_key = llm_configuration.clone();
-    llm_config_with_key.api_key = api_key;
-    let client = create_llm_provider(llm_config_with_key);
+    let client = redact_paths(
+        create_llm_provider(llm_configuration.clone()),
+        path_map.as_ref(),
+    );
 
     let mut messages: Vec<Message> = Vec::new();
 
     // Determine which system prompt to use
-    let system_prompt = if start_as_chat {
-        pack_prompt(
-            CHAT_SYSTEM_PROMPT,
-            rvconfig,
-            Some(llm_configuration),
-            load_context.as_ref(),
-        )?
+    let base_prompt = if start_as_chat {
+        CHAT_SYSTEM_PROMPT
     } else {
+        SYSTEM_PROMPT
+    };
+    let system_prompt = telemetry::in_span("prompt_packing", &[], || {
         pack_prompt(
-            SYSTEM_PROMPT,
+            base_prompt,
             rvconfig,
             Some(llm_configuration),
             load_context.as_ref(),
-        )?
+            language,
+            &mut skipped,
+        )
+    })?;
+
+    // Code related to the diffs, retrieved from the repository index built by `rv index`
+    let related =
+        match index::retrieve(rvconfig, policy.as_ref(), &retrieval_query, &reviewed_files).await {
+            Ok(related) => related,
+            Err(e) => {
+                eprintln!("[WARN] Failed to retrieve related code from the index: {e:#}");
+                Vec::new()
+            }
+        };
+    if !pipe && !related.is_empty() {
+        println!(
+            "[{} related code chunks from {}]\n",
+            related.len(),
+            index::INDEX_FILE
+        );
+    }
+
+    // Ticket the change implements, from the issue tracker
+    let ticket = match tickets::fetch_ticket(rvconfig, &reviewed_commits).await {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            eprintln!("[WARN] Failed to fetch the ticket of the change: {e:#}");
+            None
+        }
     };
+    let mut ticket_info = String::new();
+    if let Some(ticket) = &ticket {
+        if !pipe {
+            println!(
+                "[Ticket {}: {}]\n",
+                ticket.key,
+                sanitize_output(&ticket.title, false)
+            );
+        }
+        ticket_info = tickets::format_ticket(ticket, rvconfig.tickets.max_tokens);
+        // Anyone with access to the tracker writes the tickets, they are as untrusted as the code
+        ticket_info = guard.untrusted(&ticket_info).0;
+    }
+    // Related code, findings of the checks and output of the linters quote the repository
+    let untrusted_context = |context: String| {
+        if context.is_empty() {
+            context
+        } else {
+            guard.untrusted(&context).0
+        }
+    };
+    let system_prompt = system_prompt
+        + &untrusted_context(index::format_related(&related))
+        + &ticket_info
+        + &formats::format_guidelines(&structured_files)
+        + &untrusted_context(
+            checks::format_duplicates(&check_findings)
+                + &semver::format_breakages(&check_findings)
+                + &formats::format_lints(&check_findings)
+                + &formats::format_flags(rvconfig, &structured_files, &check_findings),
+        );
+    let system_prompt = if rvconfig.complexity.prioritize && !complexity.is_empty() {
+        system_prompt
+            + COMPLEXITY_PROMPT
+            + &untrusted_context(complexity::format_complexity_context(&complexity))
+    } else {
+        system_prompt
+    };
+
+    let mut system_prompt = if rvconfig.redact_secrets {
+        redact_secrets(&system_prompt)
+    } else {
+        system_prompt
+    };
+    if let Some(addendum) = review_size.and_then(ReviewSize::prompt_addendum) {
+        system_prompt.push_str(addendum);
+    }
+    if rvconfig.inline && !start_as_chat {
+        system_prompt.push_str(INLINE_ANCHORS_PROMPT);
+    }
+    let json_review = rvconfig.json_review && !start_as_chat;
+    if json_review {
+        system_prompt.push_str(JSON_REVIEW_PROMPT);
+    }
+    let mut summary_system_prompt = SUMMARY_SYSTEM_PROMPT.to_string();
+    guard.harden(&mut system_prompt);
+    guard.harden(&mut summary_system_prompt);
+    let summary_first = review_size.is_some_and(ReviewSize::summary_first);
+
+    // Prompts exceeding the context window are refused or truncated before reaching the provider
+    let fitted =
+        request::fit_context_window(llm_configuration, &system_prompt, &guard, &unguarded_prompt)?;
+    // What the truncation dropped wasn't reviewed, and counts against the coverage
+    let diffs_sent = rvconfig.diff_profile.report_diffs && expcommit.diffs.is_some();
+    let truncated = fitted.skipped_files(
+        expcommit
+            .sources
+            .iter()
+            .flatten()
+            .chain(expcommit.diff_stats.keys()),
+        diffs_sent,
+    );
+    reviewed_files.retain(|file| {
+        !truncated
+            .iter()
+            .any(|skipped| skipped.path.to_string_lossy() == *file)
+    });
+    expcommit.skipped.extend(truncated.iter().cloned());
+    skipped.extend(truncated);
+    let coverage = Coverage::of(&expcommit);
+    let review_prompt = fitted.prompt;
+
+    // `--no-network`: the request is shown instead of sent
+    if network::is_disabled() {
+        if !pipe {
+            println!(
+                "[NO NETWORK] Nothing was sent; request prepared for {} ({}):\n",
+                llm_configuration.provider.name(),
+                llm_configuration.model_id
+            );
+        }
+        let request = format!("{system_prompt}\n\n{review_prompt}");
+        match &path_map {
+            Some(paths) => println!("{}", paths.redact(&request)),
+            None => println!("{request}"),
+        }
+        return Ok(None);
+    }
+
+    // Unchanged prompts get the review of the previous run, without a request
+    let cache_key = (rvconfig.review_cache && !start_as_chat).then(|| {
+        cache::review_key(
+            llm_configuration,
+            &system_prompt,
+            &review_prompt,
+            guard.boundary().map(Boundary::token),
+        )
+    });
+    let cached = cache_key.as_deref().and_then(cache::lookup);
+    let from_cache = cached.is_some();
+    let summary_first = summary_first && !from_cache;
+
+    // Show what is going to leave the machine before sending it
+    if !from_cache {
+        let mut input_tokens = estimate_tokens(&system_prompt) + estimate_tokens(&review_prompt);
+        if summary_first {
+            input_tokens +=
+                estimate_tokens(&summary_system_prompt) + estimate_tokens(&review_prompt);
+        }
+        let requests = summary_first.then(|| "1 summary + 1 review".to_string());
+        if !request::confirm_requests(
+            llm_configuration,
+            reviewed_files.len(),
+            input_tokens,
+            requests,
+            assume_yes,
+        )? {
+            return Ok(None);
+        }
+    }
+
+    // Huge change sets get summarized first, so that the review can focus on the hotspots
+    let mut summary = String::new();
+    if summary_first {
+        if !pipe {
+            println!("Summarizing the change set...");
+        }
+        summary = sanitize_output(
+            &client
+                .request(
+                    summary_system_prompt.clone(),
+                    vec![Message::user(review_prompt.clone())],
+                )
+                .await?,
+            rvconfig.normalize_output,
+        );
+        println!("{summary}\n");
+        messages.push(Message::user(markup::element(
+            "change_summary",
+            &[],
+            &summary,
+        )));
+    }
 
     // Always include the review prompt as the first message
     // This provides the code context to the LLM
     messages.push(Message::user(review_prompt.clone()));
 
     let mut all_messages = messages;
-    let mut current_chat_mode = false;
+
+    // Reviews that don't follow the required structure get one corrective follow-up
+    let replaced_prompt = matches!(
+        llm_configuration.custom_prompt,
+        Some(CustomPrompt::Replace(_))
+    );
+    let checks_structure = !start_as_chat && !from_cache && !replaced_prompt;
+    // The review to reformat is checked before it is shown, so that only one review is
+    let check_first = checks_structure && rvconfig.reformat_invalid_reviews;
+
+    // Get the LLM response to the initial review context
+    let mut response = match cached {
+        Some(review) => {
+            eprintln!(
+                "[CACHE] Same prompts and model as a previous review, showing its result (`--no-cache` sends the request)\n"
+            );
+            if !json_review {
+                println!("{review}");
+            }
+            review
+        }
+        // The JSON isn't streamed to the terminal, its rendering is printed once parsed
+        None if json_review => {
+            client
+                .request(system_prompt.clone(), all_messages.clone())
+                .await?
+        }
+        None if check_first => sanitize_output(
+            &client
+                .request(system_prompt.clone(), all_messages.clone())
+                .await?,
+            rvconfig.normalize_output,
+        ),
+        None => {
+            term_helpers::print_stream(
+                client.stream_request(system_prompt.clone(), all_messages.clone())?,
+                rvconfig.normalize_output,
+            )
+            .await?
+        }
+    };
+
+    // JSON reviews are shown and reported in the text structure, the typed review is kept aside
+    let json_response = response.clone();
+    let mut structured = None;
+    if json_review {
+        let parsing = telemetry::span("parsing", &[("format", "json")]);
+        match Review::parse(&response) {
+            Ok(review) => {
+                response = review.render();
+                structured = Some(review);
+                parsing.end();
+            }
+            Err(e) => {
+                eprintln!("[WARN] Failed to parse the JSON review: {e:#}");
+                parsing.fail(&e);
+            }
+        }
+    }
+
+    let mut reformat_tokens = (0, 0);
+    if checks_structure && let Err(issue) = check_structure(&response) {
+        if rvconfig.reformat_invalid_reviews {
+            eprintln!(
+                "\n[WARN] The review doesn't follow the required structure ({issue}), asking for a reformatted review\n"
+            );
+            let mut reformat_messages = all_messages.clone();
+            reformat_messages.push(Message::assistant(response.clone()));
+            reformat_messages.push(Message::user(REFORMAT_PROMPT));
+            let reformatted = sanitize_output(
+                &client
+                    .request(system_prompt.clone(), reformat_messages)
+                    .await?,
+                rvconfig.normalize_output,
+            );
+            reformat_tokens = (
+                estimate_tokens(&system_prompt)
+                    + estimate_tokens(&review_prompt)
+                    + estimate_tokens(&response)
+                    + estimate_tokens(REFORMAT_PROMPT),
+                estimate_tokens(&reformatted),
+            );
+            response = reformatted;
+        }
+        // A review that still drops the structure may have been steered by the reviewed code
+        if let Err(issue) = check_structure(&response) {
+            let hint = if guard.boundary().is_some() {
+                "; the reviewed code may be trying to steer the reviewer"
+            } else {
+                ""
+            };
+            eprintln!("\n[WARN] The review doesn't follow the required structure ({issue}){hint}");
+        }
+    }
+    if json_review || check_first {
+        println!("{response}");
+    }
+    if let Some(key) = &cache_key
+        && !from_cache
+        && let Err(e) = cache::store(
+            key,
+            if structured.is_some() {
+                &json_response
+            } else {
+                &response
+            },
+        )
+    {
+        eprintln!("[WARN] Failed to cache the review: {e}");
+    }
+    // Accepted trade-offs are moved to an appendix of the report
+    let (reported_review, suppressed) = if start_as_chat {
+        (response.clone(), Vec::new())
+    } else {
+        // The findings of the review are parsed to match them against the suppressions
+        let parsing = telemetry::span("parsing", &[("format", "text")]);
+        let applied = Suppressions::load().apply(&response);
+        parsing.end();
+        applied
+    };
+    // Suggested fixes are applied to a temporary worktree and built
+    let mut fix_verifications = Vec::new();
+    if rvconfig.verify_fixes && !start_as_chat {
+        if !pipe {
+            println!("\nVerifying the suggested fixes...");
+        }
+        match fixes::verify_command(rvconfig.verify_command.as_deref()).and_then(|command| {
+            fixes::verify_fixes(
+                &reported_review,
+                reviewed_commit.as_deref(),
+                &command,
+                &Sandbox::new(&rvconfig.sandbox),
+            )
+        }) {
+            Ok(verifications) => fix_verifications = verifications,
+            Err(e) => eprintln!("[WARN] Failed to verify the suggested fixes: {e:#}"),
+        }
+    }
+    let mut review_id = None;
+    if !start_as_chat {
+        let mut record = ReviewRecord::new(
+            target.clone(),
+            current_commit_oid.map(|oid| oid.to_string()),
+            llm_configuration_key.clone(),
+            llm_configuration.model_id.clone(),
+            reviewed_files.clone(),
+            skipped.clone(),
+            reported_review.clone(),
+        );
+        record.tags = rvconfig.tags.clone();
+        record.changed_lines = changed_lines;
+        if rvconfig.usage_stats || rvconfig.show_usage {
+            let mut input_tokens =
+                estimate_tokens(&system_prompt) + estimate_tokens(&review_prompt);
+            let mut output_tokens = estimate_tokens(&response);
+            if summary_first {
+                input_tokens += estimate_tokens(&summary_system_prompt)
+                    + estimate_tokens(&review_prompt)
+                    + estimate_tokens(&summary);
+                output_tokens += estimate_tokens(&summary);
+            }
+            input_tokens += reformat_tokens.0;
+            output_tokens += reformat_tokens.1;
+            // Cached reviews cost nothing
+            let usage = if from_cache {
+                Usage::ZERO
+            } else {
+                Usage::estimate(llm_configuration, input_tokens, output_tokens)
+            };
+            record.set_usage(rvconfig, usage);
+        }
+        match record.save() {
+            Ok(_) => review_id = Some(record.id.clone()),
+            Err(e) => eprintln!("[WARN] Failed to save review to history: {e}"),
+        }
+    }
+    report::dispatch(
+        reporters,
+        &Report {
+            id: review_id,
+            target: target.clone(),
+            verdict: Verdict::Reviewed,
+            commit: reviewed_commit,
+            model: llm_configuration.model_id.clone(),
+            tags: rvconfig.tags.clone(),
+            review: reported_review.clone(),
+            reviewed_files,
+            not_reviewed: skipped,
+            suppressed,
+            checks: check_findings,
+            fixes: fix_verifications,
+            complexity,
+            coverage,
+            structured,
+            prompt: format!("{system_prompt}\n\n{review_prompt}"),
+            patches: inline_patches,
+        },
+    )
+    .await;
+    // Durable facts of the review are kept for the next ones
+    if rvconfig.learn_knowledge
+        && !start_as_chat
+        && !pipe
+        && let Err(e) = knowledge::learn_from_review(
+            rvconfig,
+            &llm_configuration_key,
+            llm_configuration,
+            client.as_ref(),
+            &target,
+            &reported_review,
+        )
+        .await
+    {
+        eprintln!("[WARN] Failed to update the knowledge base: {e:#}");
+    }
+    check_min_coverage(coverage.as_ref(), rvconfig.min_coverage)?;
+    let review = (!start_as_chat).then_some(reported_review);
+    all_messages.push(Message::assistant(response));
 
     // Note: We'll handle the chat mode messages appropriately in the branches below
     // The println messages for start_as_chat are moved to their respective branches
@@ -498,20 +1334,11 @@ async fn process_review(
     if run_action_mode {
         // When action mode is enabled, show the menu in a loop until Quit is selected
         loop {
-            // First, get the LLM response if we haven't already
-            if all_messages.len() == 1 {
-                // Only the review prompt is present, get LLM response
-                let response =
-                    client.stream_request_stdout(system_prompt.clone(), all_messages.clone())?;
-                all_messages.push(Message::assistant(response));
-            }
-
             // Show action menu
             println!("\n\n\n");
             let action: ActionSelection = term_helpers::select_action_menu();
             match action {
                 ActionSelection::EnterChatMode => {
-                    current_chat_mode = true;
                     println!(
                         "Entered chat mode. Type '/quit' or '/exit' to return to action menu."
                     );
@@ -541,21 +1368,23 @@ async fn process_review(
                         let user_input = Message::user(input_string);
                         all_messages.push(user_input);
                         // Get LLM response, which streams to stdout
-                        let response = client
-                            .stream_request_stdout(system_prompt.clone(), all_messages.clone())?;
+                        let response = term_helpers::print_stream(
+                            client.stream_request(system_prompt.clone(), all_messages.clone())?,
+                            rvconfig.normalize_output,
+                        )
+                        .await?;
                         all_messages.push(Message::assistant(response));
                     }
-                    current_chat_mode = false;
                 }
                 ActionSelection::GitAddAndFixup => {
                     if let Some(commit_oid) = current_commit_oid {
                         let commit_hash = commit_oid.to_string();
-                        let status = std::process::Command::new("git")
-                            .args(&["add", "."])
+                        let status = git_helpers::repo_command("git")
+                            .args(["add", "."])
                             .status()?;
                         if status.success() {
-                            let fixup_status = std::process::Command::new("git")
-                                .args(&["commit", "--fixup", &commit_hash])
+                            let fixup_status = git_helpers::repo_command("git")
+                                .args(["commit", "--fixup", &commit_hash])
                                 .status()?;
                             if fixup_status.success() {
                                 println!("Successfully created fixup commit for {}", commit_hash);
@@ -570,15 +1399,15 @@ async fn process_review(
                     }
                 }
                 ActionSelection::GitAddAndCommit => {
-                    let status = std::process::Command::new("git")
-                        .args(&["add", "."])
+                    let status = git_helpers::repo_command("git")
+                        .args(["add", "."])
                         .status()?;
                     if status.success() {
                         println!("Enter commit message: ");
                         let mut commit_msg = String::new();
                         std::io::stdin().read_line(&mut commit_msg)?;
-                        let commit_status = std::process::Command::new("git")
-                            .args(&["commit", "-m", &commit_msg.trim()])
+                        let commit_status = git_helpers::repo_command("git")
+                            .args(["commit", "-m", commit_msg.trim()])
                             .status()?;
                         if commit_status.success() {
                             println!("Successfully created commit");
@@ -594,8 +1423,8 @@ async fn process_review(
                         match git_helpers::get_parent_oid(commit_oid) {
                             Ok(parent_oid) => {
                                 let parent_hash = parent_oid.to_string();
-                                let status = std::process::Command::new("git")
-                                    .args(&["revert", "--no-edit", &parent_hash])
+                                let status = git_helpers::repo_command("git")
+                                    .args(["revert", "--no-edit", &parent_hash])
                                     .status()?;
                                 if status.success() {
                                     println!(
@@ -622,19 +1451,9 @@ async fn process_review(
     } else {
         // Original flow when action menu is not enabled
         if start_as_chat {
-            current_chat_mode = true;
             println!("Chat mode started. Type your questions about the code.");
             println!("Type '/quit' or '/exit' to exit.");
             loop {
-                // If we haven't gotten the initial review, do that first
-                if all_messages.len() == 1 {
-                    // The first message is always the review prompt
-                    // Get LLM response to the initial review context
-                    let response = client
-                        .stream_request_stdout(system_prompt.clone(), all_messages.clone())?;
-                    all_messages.push(Message::assistant(response));
-                }
-
                 println!("\n");
                 let input_string = term_helpers::get_terminal_input(String::from("[chat]> "));
                 if input_string.trim().is_empty() {
@@ -657,21 +1476,17 @@ async fn process_review(
 
                 let user_input = Message::user(input_string);
                 all_messages.push(user_input);
-                let response =
-                    client.stream_request_stdout(system_prompt.clone(), all_messages.clone())?;
-                all_messages.push(Message::assistant(response));
-            }
-        } else {
-            // Not in chat mode, just get the initial response
-            if all_messages.len() == 1 {
-                let response =
-                    client.stream_request_stdout(system_prompt.clone(), all_messages.clone())?;
+                let response = term_helpers::print_stream(
+                    client.stream_request(system_prompt.clone(), all_messages.clone())?,
+                    rvconfig.normalize_output,
+                )
+                .await?;
                 all_messages.push(Message::assistant(response));
             }
         }
     }
 
-    Ok(())
+    Ok(review)
 }
 
 pub fn generate_message_from_stdin() -> Message {
@@ -679,6 +1494,7 @@ pub fn generate_message_from_stdin() -> Message {
     Message::user(string)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn git_review(
     rvconfig: RvConfig,
     llm_selection: Option<String>,
@@ -686,56 +1502,163 @@ pub async fn git_review(
     branch: Option<String>,
     branch_mode: Option<BranchAgainst>,
     github_pr: Option<String>,
-    log_xml_structure: Option<bool>,
+    range: Option<String>,
+    per_commit: bool,
+    squashed: bool,
+    since_review: Option<String>,
+    path_filter: PathFilter,
+    log_xml_structure: bool,
     pipe: bool,
     start_as_chat: bool,
     action_menu: Option<bool>,
     load_context: Option<PathBuf>,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    reporters: &[Box<dyn Reporter>],
 ) -> Result<()> {
-    let mut expcommit: Option<ExpandedCommit> = None;
-    let mut current_commit_oid: Option<git2::Oid> = None;
+    let mut expcommit: Option<ExpandedCommit>;
+    let current_commit_oid: Option<git2::Oid>;
+    let mut target: String;
+    // Explanation and next steps shown when there is nothing to review
+    let no_changes_hint: String;
+
+    // Diffs use repository-relative paths, so the review runs from the root of the working tree
+    let load_context = load_context.map(|path| std::path::absolute(&path).unwrap_or(path));
+    git_helpers::enter_workdir().context("Failed to find the working tree of the repository")?;
+
+    if per_commit {
+        let (base_oid, head_oid, target) = if let Some(pr_id) = &github_pr {
+            let (base_oid, head_oid) = github::pr_base_and_head(pr_id)?;
+            (base_oid, head_oid, format!("pr {pr_id} per commit"))
+        } else if let Some(range) = &range {
+            let (base_oid, head_oid) =
+                git_helpers::resolve_range(range).context("Failed to resolve the commit range")?;
+            (base_oid, head_oid, format!("range {range} per commit"))
+        } else {
+            println!("[ERROR] --per-commit can only be used with --pr or --range");
+            return Ok(());
+        };
 
-    if let Some(commit_str) = commit {
+        let keep_pr_refs = rvconfig.keep_pr_refs;
+        let result = crate::series::per_commit_review(
+            rvconfig,
+            llm_selection,
+            base_oid,
+            head_oid,
+            pipe,
+            assume_yes,
+            time_budget,
+            target,
+            reporters,
+        )
+        .await;
+        if github_pr.is_some() {
+            github::cleanup_pr_refs(keep_pr_refs);
+        }
+        return result;
+    }
+
+    let extraction_span = telemetry::span("git_extraction", &[]);
+    if let Some(review_id) = since_review {
+        let record = history::load_record(&review_id)?;
+        let Some(recorded_commit) = record.commit else {
+            println!("[ERROR] Review {review_id} is not tied to a Git commit");
+            return Ok(());
+        };
+        let commit_oid = git_helpers::get_oid(&recorded_commit)
+            .context("Failed to find the commit recorded by the review")?;
+        target = format!("since review {review_id}");
+        no_changes_hint = format!(
+            "Nothing changed since review {review_id}; make some edits and run the same command again."
+        );
+        current_commit_oid = Some(git_helpers::get_oid("HEAD")?);
+        expcommit = Some(
+            git_helpers::expanded_since_commit(commit_oid)
+                .context("Failed to compute changes since the review")?,
+        );
+    } else if let Some(commit_str) = commit {
         //println!("[DEBUG] Reviewing commit: {}", commit_str);
         let commit_oid = git_helpers::get_oid(&commit_str).context("Failed to get commit OID")?;
         current_commit_oid = Some(commit_oid);
-        let exp_result = git_helpers::expanded_from_commit(commit_oid);
-
-        if let Ok(expanded) = exp_result {
-            expcommit = Some(expanded);
-        }
+        target = format!("commit {commit_oid}");
+        no_changes_hint = format!(
+            "Commit {commit_oid} doesn't change any file (empty commit?); pick another one with `rv -c [commit]`."
+        );
+        expcommit = Some(
+            git_helpers::expanded_from_commit(commit_oid)
+                .with_context(|| format!("Failed to compute the diff of commit {commit_oid}"))?,
+        );
     } else if let Some(branch_name) = branch {
         //println!("[DEBUG] Reviewing branch: {}", branch_name);
-        let mut used_branch_mode: BranchAgainst = rvconfig.default_branch_mode;
+        let mut used_branch_mode: BranchAgainst = rvconfig.default_branch_mode.clone();
         if let Some(mode) = branch_mode {
             used_branch_mode = mode;
         }
 
-        let exp_result = git_helpers::expanded_from_branch(&branch_name, used_branch_mode);
-        if let Ok(expanded) = exp_result {
-            // For branch comparison, the head of the branch is the current commit
-            // Let's get the head commit of the branch
-            let repo = git2::Repository::discover(".")?;
-            let branch_ref = repo.find_branch(&branch_name, git2::BranchType::Local)?;
-            let branch_commit = branch_ref.into_reference().peel_to_commit()?;
-            current_commit_oid = Some(branch_commit.id());
-            expcommit = Some(expanded);
-        }
+        target = format!("branch {branch_name}");
+        no_changes_hint = format!(
+            "Branch {branch_name} has no changes against its base (already merged?); compare it against another base with `--against [current|main|ref]`."
+        );
+        let (base_oid, tip_oid) = git_helpers::branch_base_and_tip(
+            &branch_name,
+            used_branch_mode,
+            &rvconfig.base_branches,
+            rvconfig.auto_fetch,
+        )
+        .context("Failed to resolve the branch")?;
+        let exp_result = if squashed {
+            git_helpers::expanded_net_diff(base_oid, tip_oid)
+        } else {
+            git_helpers::expanded_between_commits(base_oid, tip_oid)
+        };
+        expcommit = Some(exp_result.with_context(|| {
+            format!("Failed to compute the diff of branch {branch_name} ({base_oid}..{tip_oid})")
+        })?);
+        // For branch comparison, the head of the branch is the current commit
+        current_commit_oid = Some(tip_oid);
+    } else if let Some(range) = range {
+        let (base_oid, head_oid) =
+            git_helpers::resolve_range(&range).context("Failed to resolve the commit range")?;
+        target = format!("range {range}");
+        no_changes_hint = format!(
+            "Range {range} has no changes; check that it is written as `[base]..[head]`, with the older commit first."
+        );
+        current_commit_oid = Some(head_oid);
+        let exp_result = if squashed {
+            git_helpers::expanded_net_diff(base_oid, head_oid)
+        } else {
+            git_helpers::expanded_between_commits(base_oid, head_oid)
+        };
+        expcommit = Some(exp_result.context("Failed to compute diff of the commit range")?);
     } else if let Some(pr_id) = github_pr {
         //println!("[DEBUG] Reviewing GitHub PR: {}", pr_id);
-        let pr_expcommit = github::expanded_commit_from_pr(&pr_id)
-            .context("Failed to build diff from GitHub pull request")?;
+        target = format!("pr {pr_id}");
+        no_changes_hint = format!(
+            "Pull request {pr_id} has no changes against its base (already merged or empty?); check it with `gh pr diff {pr_id}`."
+        );
+        let pr_expcommit = if squashed {
+            let (base_oid, head_oid) = github::pr_base_and_head(&pr_id)?;
+            git_helpers::expanded_net_diff(base_oid, head_oid)
+                .context("Failed to compute the net diff of the GitHub pull request")?
+        } else {
+            github::expanded_commit_from_pr(&pr_id)
+                .context("Failed to build diff from GitHub pull request")?
+        };
         expcommit = Some(pr_expcommit);
+        // The diff is computed, the fetched pull request head isn't needed anymore
+        github::cleanup_pr_refs(rvconfig.keep_pr_refs);
         // For PRs, we don't have a specific OID, but we can use HEAD
         current_commit_oid = Some(git_helpers::get_oid("HEAD")?);
     } else {
         //println!("[DEBUG] Reviewing staged changes or HEAD");
         // Staging edits, if empty HEAD commit
-        let mut exp_result = git_helpers::staged_diffs(rvconfig.diff_profile);
-
-        if exp_result.is_ok() {
-            let exp_unwrapped: ExpandedCommit = exp_result.unwrap();
+        target = String::from("staged or HEAD");
+        no_changes_hint = String::from(
+            "Nothing is staged and the last commit doesn't change any file; stage your edits with `git add` or pick a commit with `rv -c [commit]`.",
+        );
+        let exp_result = git_helpers::staged_diffs(rvconfig.diff_profile);
 
+        if let Ok(exp_unwrapped) = exp_result {
             if exp_unwrapped.clone().is_empty() {
                 if !pipe {
                     println!("Staged is empty, switching to HEAD");
@@ -745,11 +1668,9 @@ pub async fn git_review(
                 let commit_oid =
                     git_helpers::get_oid(commit_str).context("Failed to get commit OID")?;
                 current_commit_oid = Some(commit_oid);
-                exp_result = git_helpers::expanded_from_commit(commit_oid);
-
-                if let Ok(expanded) = exp_result {
-                    expcommit = Some(expanded);
-                }
+                expcommit = Some(git_helpers::expanded_from_commit(commit_oid).with_context(
+                    || format!("Failed to compute the diff of HEAD ({commit_oid})"),
+                )?);
             } else {
                 expcommit = Some(exp_unwrapped);
                 // For staged diffs, the current commit is HEAD
@@ -764,15 +1685,103 @@ pub async fn git_review(
             let commit_oid =
                 git_helpers::get_oid(commit_str).context("Failed to get commit OID")?;
             current_commit_oid = Some(commit_oid);
-            exp_result = git_helpers::expanded_from_commit(commit_oid);
+            expcommit = Some(
+                git_helpers::expanded_from_commit(commit_oid).with_context(|| {
+                    format!("Failed to compute the diff of HEAD ({commit_oid})")
+                })?,
+            );
+        }
+    }
 
-            if let Ok(expanded) = exp_result {
-                expcommit = Some(expanded);
-            }
+    if squashed {
+        target.push_str(" (squashed)");
+    }
+    extraction_span.end();
+
+    // Drop the files excluded by `.rvignore`
+    let ignore = IgnoreRules::from_rvignore();
+    if !ignore.is_empty()
+        && let Some(expanded) = expcommit.take()
+    {
+        expcommit =
+            Some(expanded.exclude(|path| ignore.is_path_ignored(path), "excluded by .rvignore"));
+    }
+
+    // Scope the review down to the selected files, if any
+    if !path_filter.is_empty()
+        && let Some(expanded) = expcommit.take()
+    {
+        let filtered = expanded.retain_matching(&path_filter);
+        if filtered.clone().is_empty() {
+            println!("[ERROR] No changed files match the selected --file, --dir or --glob filters");
+            return Ok(());
+        }
+        expcommit = Some(filtered);
+    }
+
+    // Trim the diff context and whitespace-only hunks, as configured
+    if let Some(expanded) = expcommit.take() {
+        let (minimized, savings) = minimize(expanded, &MinimizeOptions::from_config(&rvconfig));
+        if !pipe && let Some(summary) = savings.summary() {
+            println!("{summary}");
+        }
+        expcommit = Some(minimized);
+    }
+
+    // Explain empty diffs instead of sending an empty review, the reporters still get the outcome
+    if let Some(expanded) = &expcommit
+        && expanded.is_empty()
+    {
+        let review = if expanded.skipped.is_empty() {
+            format!("[NO CHANGES] {no_changes_hint}")
+        } else {
+            "[NO CHANGES] Every changed file was excluded from the review.".to_string()
+        };
+        println!("{review}");
+        let coverage = Coverage::of(expanded);
+        let report = Report {
+            id: None,
+            target,
+            verdict: Verdict::NoChanges,
+            commit: current_commit_oid.map(|oid| oid.to_string()),
+            model: String::new(),
+            tags: rvconfig.tags.clone(),
+            review,
+            reviewed_files: Vec::new(),
+            not_reviewed: expanded.skipped.clone(),
+            suppressed: Vec::new(),
+            checks: Vec::new(),
+            fixes: Vec::new(),
+            complexity: Vec::new(),
+            coverage,
+            structured: None,
+            prompt: String::new(),
+            patches: BTreeMap::new(),
+        };
+        report::dispatch(reporters, &report).await;
+        if !expanded.skipped.is_empty() {
+            check_min_coverage(coverage.as_ref(), rvconfig.min_coverage)?;
         }
+        return Ok(());
     }
 
-    if let Some(expanded) = expcommit {
+    if rvconfig.consensus.models.len() > 1
+        && !start_as_chat
+        && let Some(expanded) = expcommit
+    {
+        consensus::consensus_review(
+            &rvconfig,
+            &rvconfig.consensus.models,
+            expanded,
+            current_commit_oid,
+            load_context,
+            target,
+            pipe,
+            assume_yes,
+            reporters,
+        )
+        .await?;
+    } else if let Some(expanded) = expcommit {
         process_review(
             &rvconfig,
             llm_selection,
@@ -783,6 +1792,10 @@ pub async fn git_review(
             action_menu,
             current_commit_oid,
             load_context,
+            target,
+            None,
+            assume_yes,
+            reporters,
         )
         .await?;
     } else {
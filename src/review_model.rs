@@ -0,0 +1,200 @@
+//! Reviews requested as JSON (`--json-review`): the model replies with an object following
+//! `JSON_REVIEW_PROMPT`, parsed into a typed [`Review`] for the machine-readable outputs and
+//! rendered back into the text structure of `SYSTEM_PROMPT` for everything else
+
+use crate::findings::SEVERITIES;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// Output format replacing the text structure, appended to the system prompt
+pub const JSON_REVIEW_PROMPT: &str = r#"
+JSON OUTPUT (overrides OUTPUT FORMAT & STYLE and STRICT STRUCTURE)
+- Reply with a single JSON object and nothing else: no markdown fences,
+  no text before or after it. Keep every other rule above.
+- The object follows this schema:
+{
+  "context": "filename or repo/PR id",
+  "summary": "one sentence, or \"No issues found.\"",
+  "severity": "CRITICAL | HIGH | MEDIUM | LOW | INFO",
+  "findings": [
+    {
+      "title": "one-line title",
+      "file": "path of the file, or null",
+      "line": 12,
+      "severity": "CRITICAL | HIGH | MEDIUM | LOW | INFO",
+      "explanation": "one or two short sentences",
+      "fix": "minimal fix (tiny unified diff or snippet), or null"
+    }
+  ],
+  "tests": ["exact command or test idea"],
+  "risk": "one line about backward-compat, perf, security",
+  "effort": "Trivial | Small | Medium | Large",
+  "verdict": "Approve | Request changes: X | Block: X"
+}
+"#;
+
+/// Severity of a review or of a finding, the most severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE", try_from = "String")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Info,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        SEVERITIES[self as usize]
+    }
+}
+
+impl TryFrom<String> for Severity {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Severity, String> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "CRITICAL" => Ok(Severity::Critical),
+            "HIGH" => Ok(Severity::High),
+            "MEDIUM" => Ok(Severity::Medium),
+            "LOW" => Ok(Severity::Low),
+            "INFO" => Ok(Severity::Info),
+            _ => Err(format!("unknown severity `{value}`")),
+        }
+    }
+}
+
+/// Finding of a JSON review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub title: String,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub line: Option<u32>,
+    pub severity: Severity,
+    #[serde(default)]
+    pub explanation: String,
+    /// Suggested fix, a unified diff or a snippet
+    #[serde(default)]
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    /// Place of the finding (ex. `src/a.rs:12`)
+    pub fn place(&self) -> Option<String> {
+        let file = self
+            .file
+            .as_deref()
+            .filter(|file| !file.trim().is_empty())?;
+        Some(match self.line {
+            Some(line) => format!("{file}:{line}"),
+            None => file.to_string(),
+        })
+    }
+}
+
+/// Review parsed from the JSON reply of the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    #[serde(default)]
+    pub context: String,
+    pub summary: String,
+    pub severity: Severity,
+    #[serde(default)]
+    pub findings: Vec<Finding>,
+    #[serde(default)]
+    pub tests: Vec<String>,
+    #[serde(default)]
+    pub risk: String,
+    #[serde(default)]
+    pub effort: String,
+    pub verdict: String,
+}
+
+/// JSON object of a reply, without the markdown fences or the text models add around it
+fn json_object(response: &str) -> Option<&str> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    (start < end).then(|| &response[start..=end])
+}
+
+impl Review {
+    /// Parse the reply of the model to `JSON_REVIEW_PROMPT`
+    pub fn parse(response: &str) -> Result<Review> {
+        let Some(json) = json_object(response) else {
+            bail!("The reply has no JSON object");
+        };
+        serde_json::from_str(json).context("The reply doesn't follow the JSON review schema")
+    }
+
+    /// Most severe of the review and of its findings
+    pub fn max_severity(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity)
+            .fold(self.severity, Ord::min)
+    }
+
+    /// Review in the text structure of `SYSTEM_PROMPT`, as shown to humans
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "1) FILE / CONTEXT: {}\n2) SUMMARY: {}\n3) SEVERITY: {}\n4) FINDINGS:\n",
+            self.context.trim(),
+            self.summary.trim(),
+            self.severity.name()
+        );
+        if self.findings.is_empty() {
+            text.push_str("   None.\n");
+        }
+        for (idx, finding) in self.findings.iter().enumerate() {
+            text.push_str(&format!(
+                "{}) [{}] {}",
+                idx + 1,
+                finding.severity.name(),
+                finding.title.trim()
+            ));
+            if let Some(place) = finding.place() {
+                text.push_str(&format!(" ({place})"));
+            }
+            text.push('\n');
+            push_indented(&mut text, &finding.explanation);
+        }
+        text.push_str("5) SUGGESTED FIX:\n");
+        let fixes: Vec<(usize, &str)> = self
+            .findings
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, finding)| {
+                let fix = finding.fix.as_deref()?.trim_matches('\n');
+                (!fix.trim().is_empty()).then_some((idx + 1, fix))
+            })
+            .collect();
+        if fixes.is_empty() {
+            text.push_str("   None.\n");
+        }
+        for (number, fix) in fixes {
+            text.push_str(&format!("   Fix {number}:\n"));
+            push_indented(&mut text, fix);
+        }
+        text.push_str("6) TESTS TO RUN:\n");
+        for test in &self.tests {
+            text.push_str(&format!("   - {}\n", test.trim()));
+        }
+        text.push_str(&format!(
+            "7) RISK / IMPACT: {}\n8) ESTIMATED EFFORT: {}\n9) FINAL VERDICT: {}",
+            self.risk.trim(),
+            self.effort.trim(),
+            self.verdict.trim()
+        ));
+        text
+    }
+}
+
+/// Append `content` to `text`, every line indented under its item
+fn push_indented(text: &mut String, content: &str) {
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        text.push_str(&format!("   {line}\n"));
+    }
+}
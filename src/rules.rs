@@ -0,0 +1,98 @@
+//! Rule IDs of the guidelines (ex. `[RG-012] Never use unwrap in library code`): the model tags
+//! the findings with the rules they violate, which enables per-rule statistics and suppressions
+
+/// Instructions added to the system prompt when the guidelines define rule IDs
+pub const RULE_IDS_PROMPT: &str = r#"
+RULE IDS
+- Some guidelines carry a rule ID in square brackets (ex. [RG-012]).
+- When a finding violates such a rule, end its title with the violated
+  rule IDs, ex. `1) unwrap on user input in src/a.rs:12 [RG-012]`.
+- Only use the IDs defined in the <guideline> elements; never invent one.
+"#;
+
+/// Rule of a guidelines file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuidelineRule {
+    pub id: String,
+    pub text: String,
+}
+
+/// Check the shape of a rule ID: a letter, then letters, digits, `-` and `_` (ex. `RG-012`)
+pub fn is_rule_id(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_uppercase())
+        && id.len() <= 32
+        && id.chars().any(|c| c.is_ascii_digit())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Rules defined by a guidelines file, one per line starting with its ID (list markers allowed)
+pub fn parse_rules(content: &str) -> Vec<GuidelineRule> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start().trim_start_matches(['-', '*', ' ']);
+            let (id, text) = line.strip_prefix('[')?.split_once(']')?;
+            is_rule_id(id).then(|| GuidelineRule {
+                id: id.to_string(),
+                text: text.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Rule IDs a finding title is tagged with (ex. `[RG-012]` or `[RG-012, RG-003]`)
+pub fn rule_ids(title: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut rest = title;
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        let tag_ids: Vec<&str> = tag.split(',').map(str::trim).collect();
+        if tag_ids.iter().all(|id| is_rule_id(id)) {
+            ids.extend(tag_ids.into_iter().map(str::to_string));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    ids
+}
+
+/// Title without its rule tags, so that tagging a finding doesn't change its fingerprint
+pub fn strip_rule_tags(title: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = title;
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        stripped.push_str(&rest[..start]);
+        if !tag.split(',').all(|id| is_rule_id(id.trim())) {
+            stripped.push_str(&rest[start..=start + end]);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    stripped.push_str(rest);
+    stripped.trim_end().to_string()
+}
+
+/// Rules of a guidelines file written as a numbered list (ex. `3. Log every error`), identified
+/// by their number; used when no rule carries an explicit ID
+pub fn parse_numbered_rules(content: &str) -> Vec<GuidelineRule> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim_start();
+            let rest = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+            let number = &trimmed[..trimmed.len() - rest.len()];
+            let text = rest.strip_prefix('.').or_else(|| rest.strip_prefix(')'))?;
+            (!number.is_empty() && !text.trim().is_empty()).then(|| GuidelineRule {
+                id: number.to_string(),
+                text: text.trim().to_string(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,194 @@
+//! Execution of project commands (ex. the `verify_command` of `--verify-fixes`): they run with a
+//! timeout, capped output and a scrubbed environment, optionally isolated with `bwrap` or in a
+//! container, as configured in the `[sandbox]` section of config.toml
+
+use crate::config::{SandboxConfig, SandboxIsolation};
+use crate::network;
+
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Interval between two checks of a running command
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Environment variables handed to the containers: the image brings its own `PATH` and `HOME`
+const CONTAINER_SKIPPED_ENV: &[&str] = &["PATH", "HOME"];
+
+/// Outcome of a sandboxed command
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub success: bool,
+    /// The command was killed when its time ran out
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Part of the output was dropped, over `max_output_bytes`
+    pub truncated: bool,
+}
+
+/// Runner of the project commands
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    config: SandboxConfig,
+}
+
+/// Read a stream until its end, keeping its first `cap` bytes
+fn capture(mut stream: impl Read + Send + 'static, cap: usize) -> JoinHandle<(Vec<u8>, bool)> {
+    std::thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        let mut buffer = [0u8; 8192];
+        // The stream is drained to the end, so that the command never blocks on a full pipe
+        while let Ok(read) = stream.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            let room = cap.saturating_sub(kept.len());
+            kept.extend_from_slice(&buffer[..read.min(room)]);
+            truncated |= read > room;
+        }
+        (kept, truncated)
+    })
+}
+
+/// Kill the command and every process it started that is still running
+fn kill_tree(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // The command leads its own process group
+        let _ = Command::new("kill")
+            .args(["-s", "KILL", "--", &format!("-{}", child.id())])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+    let _ = child.kill();
+}
+
+impl Sandbox {
+    pub fn new(config: &SandboxConfig) -> Sandbox {
+        Sandbox {
+            config: config.clone(),
+        }
+    }
+
+    /// Allowed environment variables that are set
+    fn allowed_env(&self) -> Vec<(String, String)> {
+        self.config
+            .env_allowlist
+            .iter()
+            .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+            .collect()
+    }
+
+    /// Process running `command` in `dir`, wrapped by the isolation of the configuration
+    fn command(&self, command: &str, dir: &Path) -> Result<Command> {
+        let dir_text = dir.to_string_lossy().to_string();
+        let mut process = match self.config.isolation {
+            SandboxIsolation::None => {
+                let mut process = Command::new("sh");
+                process.args(["-c", command]);
+                process
+            }
+            SandboxIsolation::Bwrap => {
+                // Read-only system, private /tmp, and only the checked directory writable
+                let mut process = Command::new("bwrap");
+                process.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+                process.args(["--tmpfs", "/tmp"]);
+                for path in &self.config.writable_paths {
+                    process.args(["--bind", path, path]);
+                }
+                process.args(["--bind", &dir_text, &dir_text]);
+                process.args(["--unshare-pid", "--die-with-parent"]);
+                if !self.config.network {
+                    process.arg("--unshare-net");
+                }
+                process.args(["--chdir", &dir_text, "sh", "-c", command]);
+                process
+            }
+            SandboxIsolation::Container => {
+                let image = self.config.container_image.as_deref().context(
+                    "Set `container_image` in [sandbox] to run the commands in a container",
+                )?;
+                let mut process = Command::new(&self.config.container_runtime);
+                process.args(["run", "--rm", "--init"]);
+                process.args(["-v", &format!("{dir_text}:{dir_text}"), "-w", &dir_text]);
+                for path in &self.config.writable_paths {
+                    process.args(["-v", &format!("{path}:{path}")]);
+                }
+                if !self.config.network {
+                    process.args(["--network", "none"]);
+                }
+                for (name, value) in self.allowed_env() {
+                    if !CONTAINER_SKIPPED_ENV.contains(&name.as_str()) {
+                        process.args(["-e", &format!("{name}={value}")]);
+                    }
+                }
+                process.args([image, "sh", "-c", command]);
+                process
+            }
+        };
+        process
+            .current_dir(dir)
+            .env_clear()
+            .envs(self.allowed_env())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            process.process_group(0);
+        }
+        Ok(process)
+    }
+
+    /// Run `command` in `dir`, killing it when `timeout_secs` run out
+    pub fn run(&self, command: &str, dir: &Path) -> Result<CommandOutput> {
+        // Only an isolation cutting the network keeps the command offline
+        if self.config.isolation == SandboxIsolation::None || self.config.network {
+            network::ensure_allowed(&format!("`{command}`"))?;
+        }
+        let mut child = self
+            .command(command, dir)?
+            .spawn()
+            .with_context(|| format!("Failed to run `{command}`"))?;
+        let cap = self.config.max_output_bytes;
+        let stdout = capture(child.stdout.take().context("No stdout")?, cap);
+        let stderr = capture(child.stderr.take().context("No stderr")?, cap);
+
+        let deadline = Instant::now() + Duration::from_secs(self.config.timeout_secs);
+        let mut timed_out = false;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                // Background processes left behind would hold the output pipes open
+                kill_tree(&mut child);
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                kill_tree(&mut child);
+                timed_out = true;
+                break child.wait().ok();
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        let (stdout, stdout_truncated) = stdout.join().unwrap_or_default();
+        let (stderr, stderr_truncated) = stderr.join().unwrap_or_default();
+        Ok(CommandOutput {
+            success: !timed_out && status.is_some_and(|status| status.success()),
+            timed_out,
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            truncated: stdout_truncated || stderr_truncated,
+        })
+    }
+
+    pub fn timeout_secs(&self) -> u64 {
+        self.config.timeout_secs
+    }
+}
@@ -0,0 +1,198 @@
+//! Filter of the model output before it reaches the terminal: the responses are untrusted data,
+//! so escape sequences and control characters are removed, and the text is optionally
+//! normalized to the ASCII, 80-columns format required by the prompts
+
+/// Column at which long lines of normalized output are soft-wrapped
+const MAX_COLUMNS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Text,
+    /// After ESC, until the final byte of the sequence
+    Escape,
+    /// Control Sequence Introducer (`ESC [`), until its final byte
+    Csi,
+    /// OSC, DCS, SOS, PM and APC strings, until their terminator
+    ControlString,
+    /// ESC inside a control string, possibly the start of its terminator (`ESC \`)
+    ControlStringEscape,
+}
+
+/// Streaming filter: escape sequences split across chunks are removed as a whole
+#[derive(Debug, Clone)]
+pub struct OutputFilter {
+    normalize: bool,
+    state: State,
+    /// Current line, buffered until its end when normalizing (to wrap it)
+    line: String,
+    in_code_fence: bool,
+}
+
+/// Invisible characters able to reorder or hide the displayed text (bidi overrides, zero-width)
+pub fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2069}' | '\u{feff}')
+}
+
+/// ASCII replacement of the typographic characters and emojis models tend to use
+fn ascii_replacement(c: char) -> Option<&'static str> {
+    let replacement = match c {
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' => "\"",
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2022}' | '\u{2023}' | '\u{25cf}' | '\u{25e6}' | '\u{00b7}' => "-",
+        '\u{00a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' => " ",
+        '\u{2192}' | '\u{27f6}' => "->",
+        '\u{2190}' | '\u{27f5}' => "<-",
+        '\u{21d2}' => "=>",
+        '\u{2264}' => "<=",
+        '\u{2265}' => ">=",
+        '\u{2260}' => "!=",
+        '\u{00d7}' => "x",
+        // Emojis, pictographs and dingbats, with their variation selectors and joiners
+        '\u{1f000}'..='\u{1faff}' | '\u{2600}'..='\u{27bf}' | '\u{2b00}'..='\u{2bff}' => "",
+        '\u{fe00}'..='\u{fe0f}' | '\u{200d}' => "",
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+/// Soft-wrap `line` at `MAX_COLUMNS`, keeping its indentation on the continuation lines
+fn wrap_line(line: &str) -> String {
+    if line.chars().count() <= MAX_COLUMNS {
+        return line.to_string();
+    }
+    let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+    let indent_width = indent.chars().count();
+    let mut wrapped = String::new();
+    let mut current = indent.clone();
+    let mut width = indent_width;
+    for word in line.trim_start().split(' ').filter(|word| !word.is_empty()) {
+        let word_width = word.chars().count();
+        if width > indent_width && width + 1 + word_width > MAX_COLUMNS {
+            wrapped.push_str(&current);
+            wrapped.push('\n');
+            current = indent.clone();
+            width = indent_width;
+        }
+        if width > indent_width {
+            current.push(' ');
+            width += 1;
+        }
+        current.push_str(word);
+        width += word_width;
+    }
+    wrapped.push_str(&current);
+    wrapped
+}
+
+impl OutputFilter {
+    pub fn new(normalize: bool) -> OutputFilter {
+        OutputFilter {
+            normalize,
+            state: State::Text,
+            line: String::new(),
+            in_code_fence: false,
+        }
+    }
+
+    /// Filter the next chunk of the response, returning the text that can be printed now
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut output = String::new();
+        for c in chunk.chars() {
+            self.state = match self.state {
+                State::Text => match c {
+                    '\x1b' => State::Escape,
+                    '\u{9b}' => State::Csi,
+                    '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => State::ControlString,
+                    _ => {
+                        self.text_char(c, &mut output);
+                        State::Text
+                    }
+                },
+                // Intermediate bytes (ex. `ESC ( B`) are part of the sequence
+                State::Escape => match c {
+                    '[' => State::Csi,
+                    ']' | 'P' | 'X' | '^' | '_' => State::ControlString,
+                    '\x20'..='\x2f' => State::Escape,
+                    _ => State::Text,
+                },
+                State::Csi => match c {
+                    '\x40'..='\x7e' => State::Text,
+                    '\x20'..='\x3f' => State::Csi,
+                    // A malformed sequence ends at the first byte that can't be part of it
+                    _ => {
+                        self.text_char(c, &mut output);
+                        State::Text
+                    }
+                },
+                // Unterminated strings end with the line, so they can't swallow the whole review
+                State::ControlString => match c {
+                    '\x07' | '\u{9c}' => State::Text,
+                    '\x1b' => State::ControlStringEscape,
+                    '\n' => {
+                        self.text_char(c, &mut output);
+                        State::Text
+                    }
+                    _ => State::ControlString,
+                },
+                State::ControlStringEscape => match c {
+                    '\\' => State::Text,
+                    _ => State::ControlString,
+                },
+            };
+        }
+        output
+    }
+
+    /// End of the response: the rest of the buffered line
+    pub fn finish(&mut self) -> String {
+        self.state = State::Text;
+        if self.line.is_empty() {
+            return String::new();
+        }
+        let line = std::mem::take(&mut self.line);
+        if self.in_code_fence {
+            line
+        } else {
+            wrap_line(&line)
+        }
+    }
+
+    fn text_char(&mut self, c: char, output: &mut String) {
+        // Carriage returns would let the text overwrite what was printed before
+        if c == '\r' || (c.is_control() && c != '\n' && c != '\t') || is_invisible(c) {
+            return;
+        }
+        if !self.normalize {
+            output.push(c);
+            return;
+        }
+        if c == '\n' {
+            let line = std::mem::take(&mut self.line);
+            // Code blocks keep their layout
+            if line.trim_start().starts_with("```") {
+                self.in_code_fence = !self.in_code_fence;
+                output.push_str(&line);
+            } else if self.in_code_fence {
+                output.push_str(&line);
+            } else {
+                output.push_str(&wrap_line(&line));
+            }
+            output.push('\n');
+            return;
+        }
+        match ascii_replacement(c) {
+            Some(replacement) => self.line.push_str(replacement),
+            None => self.line.push(c),
+        }
+    }
+}
+
+/// Filter a whole response
+pub fn sanitize_output(text: &str, normalize: bool) -> String {
+    let mut filter = OutputFilter::new(normalize);
+    let mut output = filter.push(text);
+    output.push_str(&filter.finish());
+    output
+}
@@ -0,0 +1,268 @@
+//! Automatic choice of the LLM configuration (`model_strategy = "auto"`), from the size and risk
+//! of the review and the cost ceiling
+
+use crate::config::{LLMConfig, ModelStrategy, QualityTier, RvConfig};
+use crate::policy::Policy;
+use crate::review::ESTIMATED_REVIEW_OUTPUT_TOKENS;
+
+/// Path fragments of changes that deserve a stronger model than their size alone would get
+const RISKY_PATH_HINTS: [&str; 10] = [
+    "auth",
+    "crypto",
+    "security",
+    "password",
+    "secret",
+    "token",
+    "permission",
+    "migration",
+    "payment",
+    "unsafe",
+];
+
+/// LLM configuration picked automatically, with the reason of the choice
+#[derive(Debug, Clone)]
+pub struct ModelChoice {
+    pub configuration_name: String,
+    pub reason: String,
+}
+
+/// Whether any of the reviewed paths looks security or data sensitive
+pub fn is_risky(paths: &[String]) -> bool {
+    paths.iter().any(|path| {
+        let path = path.to_ascii_lowercase();
+        RISKY_PATH_HINTS.iter().any(|hint| path.contains(hint))
+    })
+}
+
+/// Tier wanted for a review of `prompt_tokens`, using the `auto_profile` size thresholds
+pub fn wanted_tier(rvconfig: &RvConfig, prompt_tokens: usize, risky: bool) -> QualityTier {
+    let tier = if prompt_tokens <= rvconfig.auto_profile.small_max_tokens {
+        QualityTier::Fast
+    } else if prompt_tokens <= rvconfig.auto_profile.medium_max_tokens {
+        QualityTier::Balanced
+    } else {
+        QualityTier::Strong
+    };
+    match (risky, tier) {
+        (true, QualityTier::Fast) => QualityTier::Balanced,
+        (true, _) => QualityTier::Strong,
+        (false, tier) => tier,
+    }
+}
+
+/// Estimated cost of the review; configurations without prices rank as the most expensive
+fn review_cost(llm_config: &LLMConfig, prompt_tokens: usize) -> Option<f64> {
+    llm_config.estimate_cost(prompt_tokens, ESTIMATED_REVIEW_OUTPUT_TOKENS)
+}
+
+/// Pick the configuration for a review: the cheapest one of the lowest tier reaching the wanted one,
+/// otherwise the strongest one available, among those allowed by the policy and within the cost ceiling.
+/// Configurations without prices can't be checked against the ceiling and are kept.
+pub fn auto_select(
+    rvconfig: &RvConfig,
+    policy: Option<&Policy>,
+    prompt_tokens: usize,
+    paths: &[String],
+) -> Option<ModelChoice> {
+    let risky = is_risky(paths);
+    let wanted = wanted_tier(rvconfig, prompt_tokens, risky);
+
+    let candidates: Vec<(&LLMConfig, QualityTier, Option<f64>)> = rvconfig
+        .llm_configs
+        .iter()
+        .filter_map(|llm_config| {
+            let tier = llm_config.quality_tier?;
+            let cost = review_cost(llm_config, prompt_tokens);
+            if let (Some(ceiling), Some(cost)) = (rvconfig.max_review_cost, cost)
+                && cost > ceiling
+            {
+                return None;
+            }
+            if policy.is_some_and(|policy| policy.check_llm(llm_config).is_err()) {
+                return None;
+            }
+            Some((llm_config, tier, cost))
+        })
+        .collect();
+
+    let cost_key = |cost: Option<f64>| cost.unwrap_or(f64::MAX);
+    let (llm_config, tier, cost) = candidates
+        .iter()
+        .filter(|(_, tier, _)| *tier >= wanted)
+        .min_by(|a, b| a.1.cmp(&b.1).then(cost_key(a.2).total_cmp(&cost_key(b.2))))
+        .or_else(|| {
+            candidates
+                .iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(cost_key(b.2).total_cmp(&cost_key(a.2))))
+        })?;
+
+    let mut reason = format!(
+        "~{prompt_tokens} tokens{}, {} tier wanted",
+        if risky { ", sensitive paths" } else { "" },
+        wanted.name()
+    );
+    if *tier < wanted {
+        reason.push_str(&format!(", best available is {}", tier.name()));
+    }
+    if let Some(cost) = cost {
+        reason.push_str(&format!(", ~${cost:.4}"));
+    }
+    Some(ModelChoice {
+        configuration_name: llm_config.configuration_name.clone(),
+        reason,
+    })
+}
+
+/// Configuration requested for a review: `--llm` if given, otherwise the automatic choice when enabled.
+/// `None` falls back to `default_llm_config`.
+pub fn resolve_llm_selection(
+    llm_selection: Option<String>,
+    rvconfig: &RvConfig,
+    policy: Option<&Policy>,
+    prompt_tokens: usize,
+    paths: &[String],
+    pipe: bool,
+) -> Option<String> {
+    if llm_selection.is_some() || rvconfig.model_strategy != ModelStrategy::Auto {
+        return llm_selection;
+    }
+    match auto_select(rvconfig, policy, prompt_tokens, paths) {
+        Some(choice) => {
+            if !pipe {
+                println!("[{} model: {}]\n", choice.configuration_name, choice.reason);
+            }
+            Some(choice.configuration_name)
+        }
+        None => {
+            eprintln!(
+                "[WARN] model_strategy = \"auto\" found no LLM configuration with a `quality_tier` within `max_review_cost`, using `{}`",
+                rvconfig.default_llm_config
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn llm_config(name: &str, tier: Option<QualityTier>, price_per_mtok: Option<f64>) -> LLMConfig {
+        LLMConfig {
+            configuration_name: name.to_string(),
+            model_id: format!("vendor/{name}"),
+            quality_tier: tier,
+            input_price_per_mtok: price_per_mtok,
+            output_price_per_mtok: price_per_mtok,
+            ..LLMConfig::default()
+        }
+    }
+
+    fn rvconfig(llm_configs: Vec<LLMConfig>) -> RvConfig {
+        RvConfig {
+            llm_configs,
+            model_strategy: ModelStrategy::Auto,
+            ..RvConfig::default()
+        }
+    }
+
+    fn tiers() -> RvConfig {
+        rvconfig(vec![
+            llm_config("strong", Some(QualityTier::Strong), Some(10.0)),
+            llm_config("fast", Some(QualityTier::Fast), Some(0.1)),
+            llm_config("balanced", Some(QualityTier::Balanced), Some(1.0)),
+            llm_config("untiered", None, Some(0.01)),
+        ])
+    }
+
+    fn selected(rvconfig: &RvConfig, prompt_tokens: usize, paths: &[&str]) -> Option<String> {
+        let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+        auto_select(rvconfig, None, prompt_tokens, &paths).map(|choice| choice.configuration_name)
+    }
+
+    #[test]
+    fn picks_the_tier_of_the_review_size() {
+        let rvconfig = tiers();
+        assert_eq!(selected(&rvconfig, 1_000, &["src/lib.rs"]).unwrap(), "fast");
+        assert_eq!(
+            selected(&rvconfig, 10_000, &["src/lib.rs"]).unwrap(),
+            "balanced"
+        );
+        assert_eq!(
+            selected(&rvconfig, 50_000, &["src/lib.rs"]).unwrap(),
+            "strong"
+        );
+    }
+
+    #[test]
+    fn raises_the_tier_of_sensitive_paths() {
+        let rvconfig = tiers();
+        assert_eq!(
+            selected(&rvconfig, 1_000, &["src/Auth/login.rs"]).unwrap(),
+            "balanced"
+        );
+        assert_eq!(
+            selected(&rvconfig, 10_000, &["db/migrations/1.sql"]).unwrap(),
+            "strong"
+        );
+        assert!(!is_risky(&[String::from("src/main.rs")]));
+    }
+
+    #[test]
+    fn picks_the_cheapest_configuration_of_a_tier() {
+        let rvconfig = rvconfig(vec![
+            llm_config("unpriced", Some(QualityTier::Balanced), None),
+            llm_config("expensive", Some(QualityTier::Balanced), Some(5.0)),
+            llm_config("cheap", Some(QualityTier::Balanced), Some(0.5)),
+        ]);
+        assert_eq!(selected(&rvconfig, 1_000, &[]).unwrap(), "cheap");
+    }
+
+    #[test]
+    fn keeps_within_the_cost_ceiling() {
+        let mut rvconfig = tiers();
+        // 50k tokens at $10/Mtok cost more than $0.10, at $1/Mtok less
+        rvconfig.max_review_cost = Some(0.1);
+        let choice = auto_select(&rvconfig, None, 50_000, &[]).unwrap();
+        assert_eq!(choice.configuration_name, "balanced");
+        assert!(choice.reason.contains("best available is balanced"));
+
+        rvconfig.max_review_cost = Some(0.000_001);
+        assert!(selected(&rvconfig, 50_000, &[]).is_none());
+    }
+
+    #[test]
+    fn skips_the_configurations_forbidden_by_the_policy() {
+        let rvconfig = tiers();
+        let policy = Policy {
+            allowed_models: Some(vec![String::from("vendor/balanced")]),
+            ..Policy::default()
+        };
+        let choice = auto_select(&rvconfig, Some(&policy), 1_000, &[]).unwrap();
+        assert_eq!(choice.configuration_name, "balanced");
+    }
+
+    #[test]
+    fn keeps_the_requested_configuration() {
+        let rvconfig = tiers();
+        let selection = resolve_llm_selection(
+            Some(String::from("strong")),
+            &rvconfig,
+            None,
+            1_000,
+            &[],
+            true,
+        );
+        assert_eq!(selection.as_deref(), Some("strong"));
+
+        let manual = RvConfig {
+            model_strategy: ModelStrategy::default(),
+            ..tiers()
+        };
+        assert!(resolve_llm_selection(None, &manual, None, 1_000, &[], true).is_none());
+        assert_eq!(
+            resolve_llm_selection(None, &rvconfig, None, 1_000, &[], true).as_deref(),
+            Some("fast")
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! API breakage of Rust crates: when the changes touch public items, `cargo semver-checks` compares
+//! the reviewed code with its baseline and the breaking changes it finds are reported as checks,
+//! and sent to the model so that it can suggest deprecation paths (requires the `rust` feature)
+
+use crate::checks::CheckFinding;
+use crate::config::RvConfig;
+use crate::git_helpers::ExpandedCommit;
+use crate::markup;
+
+#[cfg(feature = "rust")]
+use crate::fixes::TempWorktree;
+#[cfg(feature = "rust")]
+use crate::git_helpers;
+#[cfg(feature = "rust")]
+use crate::sandbox::Sandbox;
+#[cfg(feature = "rust")]
+use anyhow::{Context, Result, bail};
+
+/// Name of the check of the breaking changes
+pub const SEMVER_CHECK: &str = "semver";
+
+/// Whether a changed line declares a public item (`pub`, but not `pub(crate)` and the like)
+#[cfg(feature = "rust")]
+fn is_public_item(line: &str) -> bool {
+    let line = line.trim_start();
+    let restricted = ["pub(crate)", "pub(super)", "pub(self)", "pub(in "];
+    line.starts_with("pub ")
+        || (line.starts_with("pub(") && !restricted.iter().any(|r| line.starts_with(r)))
+}
+
+/// Whether the changes add, remove or modify the declaration of a public Rust item
+#[cfg(feature = "rust")]
+fn touches_public_items(expcommit: &ExpandedCommit) -> bool {
+    expcommit.patches.iter().any(|(path, patch)| {
+        expcommit.diff_stats.contains_key(path)
+            && path.extension().is_some_and(|ext| ext == "rs")
+            && patch
+                .hunks
+                .iter()
+                .flat_map(|hunk| &hunk.lines)
+                .any(|line| line.is_change() && is_public_item(&line.content))
+    })
+}
+
+/// Breaking changes of the output of `cargo semver-checks`: a finding for every place listed
+/// under the `Failed in:` line of a failed lint
+#[cfg(feature = "rust")]
+fn parse_failures(output: &str) -> Vec<CheckFinding> {
+    let mut findings = Vec::new();
+    let mut lint: Option<(&str, &str)> = None;
+    let mut in_places = false;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        // `--- failure function_missing: pub fn removed or renamed ---`
+        if let Some(header) = trimmed
+            .strip_prefix("--- failure ")
+            .and_then(|rest| rest.strip_suffix("---"))
+        {
+            lint = header.trim().split_once(": ");
+            in_places = false;
+        } else if trimmed == "Failed in:" {
+            in_places = lint.is_some();
+        } else if trimmed.is_empty() || !line.starts_with(char::is_whitespace) {
+            in_places = false;
+        } else if in_places && let Some((name, description)) = lint {
+            // `function my_crate::foo, previously in file src/lib.rs:1`
+            let location = trimmed
+                .rsplit_once(" in file ")
+                .map(|(_, file)| file.to_string())
+                .unwrap_or_default();
+            findings.push(CheckFinding::new(
+                SEMVER_CHECK,
+                location,
+                format!("{description} ({name}): {trimmed}"),
+            ));
+        }
+    }
+    findings
+}
+
+/// Baseline of the comparison: the configured revision, or the parent of the first reviewed
+/// commit, or `HEAD` for the uncommitted changes
+#[cfg(feature = "rust")]
+fn baseline(rvconfig: &RvConfig, expcommit: &ExpandedCommit) -> Result<String> {
+    if let Some(baseline) = &rvconfig.semver.baseline {
+        return Ok(baseline.clone());
+    }
+    let Some(first) = expcommit.commits.first() else {
+        return Ok(String::from("HEAD"));
+    };
+    let repo = git_helpers::open_repo()?;
+    let parent = repo
+        .find_commit(*first)?
+        .parent_id(0)
+        .context("The first reviewed commit has no parent to compare with")?;
+    Ok(parent.to_string())
+}
+
+/// Run `cargo semver-checks` on a worktree of the reviewed code
+#[cfg(feature = "rust")]
+fn run_semver_checks(rvconfig: &RvConfig, expcommit: &ExpandedCommit) -> Result<Vec<CheckFinding>> {
+    let baseline = baseline(rvconfig, expcommit)?;
+    let repo = git_helpers::open_repo()?;
+    let repo_dir = repo
+        .workdir()
+        .context("The repository has no working tree")?
+        .to_path_buf();
+    let commit = expcommit.commits.last().map(|oid| oid.to_string());
+    let worktree = TempWorktree::create(&repo_dir, commit.as_deref())?;
+
+    let command = format!("{} --baseline-rev {baseline}", rvconfig.semver.command);
+    let sandbox = Sandbox::new(&rvconfig.sandbox);
+    let output = sandbox.run(&command, &worktree.dir)?;
+    if output.timed_out {
+        bail!("`{command}` timed out after {}s", sandbox.timeout_secs());
+    }
+    let findings = parse_failures(&format!("{}\n{}", output.stdout, output.stderr));
+    // A failure without breaking changes is an error of the tool itself (ex. not installed)
+    if !output.success && findings.is_empty() {
+        bail!(
+            "`{command}` failed: {}",
+            output.stderr.lines().last().unwrap_or_default().trim()
+        );
+    }
+    Ok(findings)
+}
+
+/// Breaking changes of the public API, when enabled in `[semver]` and the changes touch public items
+#[cfg(feature = "rust")]
+pub fn semver_findings(rvconfig: &RvConfig, expcommit: &ExpandedCommit) -> Vec<CheckFinding> {
+    if !rvconfig.semver.enabled || !touches_public_items(expcommit) {
+        return Vec::new();
+    }
+    match run_semver_checks(rvconfig, expcommit) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("[WARN] Failed to check the API breakage: {e:#}");
+            Vec::new()
+        }
+    }
+}
+
+/// Breaking changes of the public API (none, rv was built without the `rust` feature)
+#[cfg(not(feature = "rust"))]
+pub fn semver_findings(rvconfig: &RvConfig, _expcommit: &ExpandedCommit) -> Vec<CheckFinding> {
+    if rvconfig.semver.enabled {
+        eprintln!("[WARN] The API breakage check needs rv built with the `rust` feature");
+    }
+    Vec::new()
+}
+
+/// Breaking changes found by the checks, as a context element of the system prompt (empty
+/// without breaking changes)
+pub fn format_breakages(findings: &[CheckFinding]) -> String {
+    let breakages: Vec<&str> = findings
+        .iter()
+        .filter(|finding| finding.check == SEMVER_CHECK)
+        .map(|finding| finding.message.as_str())
+        .collect();
+    if breakages.is_empty() {
+        return String::new();
+    }
+    markup::element(
+        "info",
+        &[("checks", SEMVER_CHECK)],
+        &format!("- {}", breakages.join("\n- ")),
+    )
+}
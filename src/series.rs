@@ -0,0 +1,395 @@
+use crate::budget::{BUDGET_EXHAUSTED_REASON, TimeBudget};
+use crate::checks::{CheckFinding, run_checks};
+use crate::complexity::{FunctionComplexity, changed_functions, most_complex_first};
+use crate::config::{CustomPrompt, RvConfig};
+use crate::context::estimate_tokens;
+use crate::coverage::{Coverage, check_min_coverage};
+use crate::findings;
+use crate::git_helpers::{self, SkippedFile};
+use crate::history::{ReviewRecord, Usage};
+use crate::llm::create_llm_provider;
+use crate::markup;
+use crate::minimize::{MinimizeOptions, minimize};
+use crate::pseudonyms::{PathMap, redact_paths};
+use crate::report::{self, PartialFailure, REVIEW_FAILED_REASON, Report, Reporter, Verdict};
+use crate::request::{self, RequestPipeline};
+use crate::review::{
+    ESTIMATED_REVIEW_OUTPUT_TOKENS, SYSTEM_PROMPT, pack_prompt, request_structured_review,
+};
+use crate::sanitize::sanitize_output;
+use crate::suppress::{SuppressedFinding, Suppressions};
+use crate::term_helpers;
+
+use anyhow::{Context, Result, bail};
+use git2::Oid;
+use rig::message::Message;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Maximum number of commit reviews requested at the same time
+const MAX_PARALLEL_REVIEWS: usize = 4;
+
+const SYNTHESIS_SYSTEM_PROMPT: &str = r#"
+You are a senior software engineer and professional code reviewer.
+You will receive the individual reviews of every commit of a series,
+oldest first, each one with the commit message describing its intent.
+Findings repeated across commits are listed once in <consolidated_findings>:
+report each of them as a single issue with all of its locations.
+Produce an overall review of the series. Follow these rules exactly.
+
+OUTPUT FORMAT & STYLE
+- ASCII only. No emojis, no markdown, no color codes.
+- Soft-wrap at ~80 columns.
+- Keep output minimal and actionable. Short sentences.
+
+STRICT STRUCTURE (in this exact order)
+1) SERIES: single line with the number of commits and the overall goal.
+2) OPEN ISSUES: numbered list of issues still present at the end of the
+   series, with the commit that introduced them.
+3) FIXED WITHIN SERIES: issues introduced by a commit and fixed by a
+   later one, with both commits.
+4) COMMIT HYGIENE: commits whose message doesn't match their content,
+   commits that should be squashed or split.
+5) FINAL VERDICT: one concise action sentence.
+
+=============================
+"#;
+
+/// Single commit of the series, with its review
+struct CommitReview {
+    oid: Oid,
+    message: String,
+    review: String,
+}
+
+/// Review each commit of `base..head` separately, then synthesize an overall review of the series
+#[allow(clippy::too_many_arguments)]
+pub async fn per_commit_review(
+    rvconfig: RvConfig,
+    llm_selection: Option<String>,
+    base_oid: Oid,
+    head_oid: Oid,
+    pipe: bool,
+    assume_yes: bool,
+    time_budget: TimeBudget,
+    target: String,
+    reporters: &[Box<dyn Reporter>],
+) -> Result<()> {
+    let pipeline = RequestPipeline::new(rvconfig)?;
+    let rvconfig = &pipeline.rvconfig;
+
+    let commits = git_helpers::commits_between(base_oid, head_oid)
+        .context("Failed to list the commits of the series")?;
+    if commits.is_empty() {
+        bail!("There are no commits between {base_oid} and {head_oid}");
+    }
+
+    // Build the prompt of every commit, using its message as the declared intent
+    let mut prompts: Vec<(Oid, String, String)> = Vec::new();
+    let mut commit_files: Vec<Vec<PathBuf>> = Vec::new();
+    let mut commit_coverage: Vec<Coverage> = Vec::new();
+    let mut check_findings: Vec<CheckFinding> = Vec::new();
+    let mut complexity: Vec<FunctionComplexity> = Vec::new();
+    let mut changed_lines: Option<usize> = None;
+    for oid in &commits {
+        let message = git_helpers::commit_message(*oid)?;
+        let (expanded, _) = minimize(
+            git_helpers::expanded_from_commit(*oid)?,
+            &MinimizeOptions::from_config(rvconfig),
+        );
+        check_findings.extend(run_checks(&rvconfig.checks, &expanded));
+        // A function changed by several commits is measured as of the last one
+        let measured = changed_functions(&rvconfig.complexity, &expanded);
+        complexity.retain(|function| {
+            !measured
+                .iter()
+                .any(|newer| newer.path == function.path && newer.name == function.name)
+        });
+        complexity.extend(measured);
+        if let Some(lines) = expanded.changed_lines() {
+            *changed_lines.get_or_insert(0) += lines;
+        }
+        commit_files.push(expanded.sources.clone().unwrap_or_default());
+        commit_coverage.push(Coverage::of(&expanded).unwrap_or_default());
+        let prompt = format!(
+            "{}{}",
+            markup::element("commit_message", &[("oid", &oid.to_string())], &message),
+            expanded.get_xml_structure(rvconfig.diff_profile)
+        );
+        prompts.push((*oid, message, pipeline.redact(prompt)));
+    }
+
+    let prompt_tokens: usize = prompts
+        .iter()
+        .map(|(_, _, prompt)| estimate_tokens(prompt))
+        .sum();
+    let paths: Vec<String> = commit_files
+        .iter()
+        .flatten()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    let (llm_configuration_key, llm_configuration) =
+        pipeline.select_llm(llm_selection, prompt_tokens, &paths, pipe)?;
+    let path_map = rvconfig
+        .redact_paths
+        .then(|| Arc::new(PathMap::new(commit_files.iter().flatten())));
+
+    let system_prompt = pipeline.system_prompt(pack_prompt(
+        SYSTEM_PROMPT,
+        rvconfig,
+        Some(&llm_configuration),
+        None,
+        None,
+        &mut Vec::new(),
+    )?);
+    let synthesis_system_prompt = pipeline.system_prompt(SYNTHESIS_SYSTEM_PROMPT.to_string());
+    // The messages and the code of the commits are untrusted
+    pipeline.prepare(
+        &llm_configuration,
+        &system_prompt,
+        prompts.iter_mut().map(|(_, _, prompt)| prompt),
+    )?;
+
+    let input_tokens: usize = prompts
+        .iter()
+        .map(|(_, _, prompt)| estimate_tokens(&system_prompt) + estimate_tokens(prompt))
+        .sum();
+    let requests = format!(
+        "{} commit reviews + 1 synthesis (~{} more output tokens)",
+        commits.len(),
+        ESTIMATED_REVIEW_OUTPUT_TOKENS * commits.len()
+    );
+    if !request::confirm_requests(
+        &llm_configuration,
+        commits.len(),
+        input_tokens,
+        Some(requests),
+        assume_yes,
+    )? {
+        return Ok(());
+    }
+
+    if !pipe {
+        term_helpers::clear_term();
+        println!("Reviewing {} commits...", commits.len());
+    }
+
+    // Commit reviews that don't follow the required structure get one corrective follow-up
+    let reformat = rvconfig.reformat_invalid_reviews
+        && !matches!(
+            llm_configuration.custom_prompt,
+            Some(CustomPrompt::Replace(_))
+        );
+
+    // Review the commits in parallel, a few at a time
+    let mut reviews: Vec<CommitReview> = Vec::new();
+    let mut reviewed_files: BTreeSet<String> = BTreeSet::new();
+    let mut not_reviewed: Vec<SkippedFile> = Vec::new();
+    let suppressions = Suppressions::load();
+    let mut suppressed: Vec<SuppressedFinding> = Vec::new();
+    let mut coverage = Coverage::default();
+    let mut failed_requests = 0;
+    let mut usage = Usage::ZERO;
+    for ((chunk, chunk_files), chunk_coverage) in prompts
+        .chunks(MAX_PARALLEL_REVIEWS)
+        .zip(commit_files.chunks(MAX_PARALLEL_REVIEWS))
+        .zip(commit_coverage.chunks(MAX_PARALLEL_REVIEWS))
+    {
+        // Once the budget is exhausted, the remaining commits are only reported
+        if time_budget.is_exhausted() {
+            for commit in chunk_coverage {
+                coverage.total += commit.total;
+            }
+            for ((oid, _, _), files) in chunk.iter().zip(chunk_files) {
+                not_reviewed.extend(files.iter().map(|path| SkippedFile {
+                    path: path.clone(),
+                    reason: format!("{BUDGET_EXHAUSTED_REASON} (commit {oid})"),
+                }));
+            }
+            continue;
+        }
+        let requests: Vec<_> = chunk
+            .iter()
+            .map(|(_, _, prompt)| {
+                let client = redact_paths(
+                    create_llm_provider(llm_configuration.clone()),
+                    path_map.as_ref(),
+                );
+                let system_prompt = system_prompt.clone();
+                let messages = vec![Message::user(prompt.clone())];
+                async move {
+                    request_structured_review(client.as_ref(), system_prompt, messages, reformat)
+                        .await
+                }
+            })
+            .collect();
+
+        let responses = futures::future::join_all(requests).await;
+        for ((((oid, message, prompt), files), commit_coverage), response) in chunk
+            .iter()
+            .zip(chunk_files)
+            .zip(chunk_coverage)
+            .zip(responses)
+        {
+            // A failed commit review doesn't stop the run, its files are reported instead
+            let response = match response {
+                Ok(response) => sanitize_output(&response, rvconfig.normalize_output),
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to review commit {oid}: {e:#}");
+                    failed_requests += 1;
+                    coverage.total += commit_coverage.total;
+                    not_reviewed.extend(files.iter().map(|path| SkippedFile {
+                        path: path.clone(),
+                        reason: format!("{REVIEW_FAILED_REASON}: {e} (commit {oid})"),
+                    }));
+                    continue;
+                }
+            };
+            coverage += *commit_coverage;
+            reviewed_files.extend(files.iter().map(|p| p.to_string_lossy().to_string()));
+            usage += Usage::estimate(
+                &llm_configuration,
+                estimate_tokens(&system_prompt) + estimate_tokens(prompt),
+                estimate_tokens(&response),
+            );
+            // Accepted trade-offs are moved to an appendix of the report
+            let (review, commit_suppressed) = suppressions.apply(&response);
+            suppressed.extend(commit_suppressed);
+            reviews.push(CommitReview {
+                oid: *oid,
+                message: message.clone(),
+                review,
+            });
+        }
+    }
+
+    if reviews.is_empty() && failed_requests > 0 {
+        bail!("Every commit review failed");
+    }
+
+    // Print every commit review, then stream the synthesis
+    let mut synthesis_input = String::new();
+    let mut series_text = String::new();
+    for commit_review in &reviews {
+        let summary = commit_review.message.lines().next().unwrap_or_default();
+        let section = format!(
+            "\n=== COMMIT {} {summary} ===\n\n{}\n",
+            commit_review.oid, commit_review.review
+        );
+        print!("{section}");
+        series_text.push_str(&section);
+
+        synthesis_input.push_str(&format!(
+            "<commit_review oid=\"{}\">\n{}{}</commit_review>\n",
+            commit_review.oid,
+            markup::element("commit_message", &[], &commit_review.message),
+            markup::element("review", &[], &commit_review.review)
+        ));
+    }
+
+    // The same pattern found in many commits is reported once, with all of its locations
+    let consolidated = findings::consolidate(
+        &reviews
+            .iter()
+            .map(|r| (format!("commit {:.8}", r.oid.to_string()), r.review.clone()))
+            .collect::<Vec<_>>(),
+    );
+    if !consolidated.is_empty() {
+        let section = findings::format_consolidated(&consolidated);
+        print!("\n=== CONSOLIDATED FINDINGS ===\n\n{section}");
+        series_text.push_str(&format!("\n=== CONSOLIDATED FINDINGS ===\n\n{section}"));
+        synthesis_input.push_str(&markup::element("consolidated_findings", &[], &section));
+    }
+
+    // The synthesis of a partial series would be misleading, so it's skipped
+    let response = if not_reviewed.is_empty() {
+        println!("\n=== SERIES REVIEW ===\n");
+        let client = redact_paths(
+            create_llm_provider(llm_configuration.clone()),
+            path_map.as_ref(),
+        );
+        // The reviews quote the commit messages and the code
+        let synthesis_prompt = pipeline.guard.untrusted(&synthesis_input).0;
+        let input_tokens =
+            estimate_tokens(&synthesis_system_prompt) + estimate_tokens(&synthesis_prompt);
+        let synthesis = term_helpers::print_stream(
+            client.stream_request(
+                synthesis_system_prompt,
+                vec![Message::user(synthesis_prompt)],
+            )?,
+            rvconfig.normalize_output,
+        )
+        .await?;
+        usage += Usage::estimate(
+            &llm_configuration,
+            input_tokens,
+            estimate_tokens(&synthesis),
+        );
+        series_text.push_str(&format!("\n=== SERIES REVIEW ===\n\n{synthesis}"));
+        synthesis
+    } else {
+        if failed_requests > 0 {
+            println!("\n[PARTIAL] Some commit reviews failed, the series review is partial.");
+        } else {
+            println!("\n[TIME BUDGET] The time budget ran out, the series review is partial.");
+        }
+        synthesis_input
+    };
+
+    let reviewed_files: Vec<String> = reviewed_files.into_iter().collect();
+    let mut record = ReviewRecord::new(
+        target.clone(),
+        Some(head_oid.to_string()),
+        llm_configuration_key,
+        llm_configuration.model_id.clone(),
+        reviewed_files.clone(),
+        not_reviewed.clone(),
+        response,
+    );
+    record.tags = rvconfig.tags.clone();
+    record.changed_lines = changed_lines;
+    record.set_usage(rvconfig, usage);
+    let review_id = match record.save() {
+        Ok(_) => Some(record.id.clone()),
+        Err(e) => {
+            eprintln!("[WARN] Failed to save review to history: {e}");
+            None
+        }
+    };
+
+    let mut prompt = system_prompt.clone();
+    for (_, _, commit_prompt) in &prompts {
+        prompt.push_str(&format!("\n\n{commit_prompt}"));
+    }
+    most_complex_first(&mut complexity, rvconfig.complexity.max_functions);
+    let report = Report {
+        id: review_id,
+        target,
+        verdict: Verdict::Reviewed,
+        commit: Some(head_oid.to_string()),
+        model: llm_configuration.model_id.clone(),
+        tags: rvconfig.tags.clone(),
+        review: series_text,
+        reviewed_files,
+        not_reviewed,
+        suppressed,
+        checks: check_findings,
+        fixes: Vec::new(),
+        complexity,
+        coverage: Some(coverage),
+        structured: None,
+        prompt,
+        patches: BTreeMap::new(),
+    };
+    report::dispatch(reporters, &report).await;
+
+    if failed_requests > 0 {
+        return Err(PartialFailure {
+            failed: failed_requests,
+            total: commits.len(),
+        }
+        .into());
+    }
+    check_min_coverage(Some(&coverage), rvconfig.min_coverage)
+}
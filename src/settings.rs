@@ -0,0 +1,187 @@
+//! Layering of `RV_*` environment variable overrides over the file configuration
+
+use crate::config::RvConfig;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use toml::Value;
+
+/// Prefix of the environment variables overriding configuration options
+pub const ENV_PREFIX: &str = "RV_";
+/// Separator between the table and the key in full-path overrides (ex. `RV_README__ENABLED`)
+const PATH_SEPARATOR: &str = "__";
+/// Environment variables starting with the prefix that are not configuration options
+const NON_CONFIG_VARS: [&str; 2] = ["RV_POLICY_FILE", crate::paths::CONFIG_DIR_VAR];
+/// Short names of the options of the LLM configurations
+const LLM_ALIASES: [(&str, &str); 3] = [
+    ("MODEL", "model_id"),
+    ("PROVIDER", "provider"),
+    ("API_KEY", "api_key"),
+];
+
+/// Where an override is applied
+#[derive(Debug, Clone)]
+enum Target {
+    /// Path of tables and key from the root of the configuration
+    Path(Vec<String>),
+    /// Key of every LLM configuration
+    Llm(String),
+}
+
+/// Convert the string of an environment variable into a value of the same type as `current`
+fn parse_value(raw: &str, current: Option<&Value>) -> Result<Value> {
+    let parsed = match current {
+        Some(Value::Boolean(_)) => match raw.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Value::Boolean(true),
+            "0" | "false" | "no" | "off" => Value::Boolean(false),
+            _ => bail!("expected a boolean, found `{raw}`"),
+        },
+        Some(Value::Integer(_)) => Value::Integer(raw.parse().context("expected an integer")?),
+        Some(Value::Float(_)) => Value::Float(raw.parse().context("expected a number")?),
+        Some(Value::Array(_)) => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        // Unset options: guess the type from the content
+        _ => {
+            if let Ok(b) = raw.parse::<bool>() {
+                Value::Boolean(b)
+            } else if let Ok(i) = raw.parse::<i64>() {
+                Value::Integer(i)
+            } else if let Ok(f) = raw.parse::<f64>() {
+                Value::Float(f)
+            } else {
+                Value::String(raw.to_string())
+            }
+        }
+    };
+    Ok(parsed)
+}
+
+/// Map every overridable option to its environment variable names.
+/// `config` is serialized as JSON, which keeps the unset options (unlike TOML).
+fn override_targets(config: &serde_json::Value) -> BTreeMap<String, Target> {
+    let mut targets: BTreeMap<String, Target> = BTreeMap::new();
+    let mut leaves: BTreeMap<String, Vec<Vec<String>>> = BTreeMap::new();
+
+    let Some(root) = config.as_object() else {
+        return targets;
+    };
+    for (key, value) in root {
+        match value {
+            serde_json::Value::Object(table) => {
+                for sub_key in table.keys() {
+                    let path = vec![key.clone(), sub_key.clone()];
+                    targets.insert(
+                        format!("{key}{PATH_SEPARATOR}{sub_key}").to_ascii_uppercase(),
+                        Target::Path(path.clone()),
+                    );
+                    leaves
+                        .entry(sub_key.to_ascii_uppercase())
+                        .or_default()
+                        .push(path);
+                }
+            }
+            serde_json::Value::Array(items) if key == "llm_configs" => {
+                if let Some(serde_json::Value::Object(llm)) = items.first() {
+                    for llm_key in llm.keys() {
+                        targets.insert(
+                            format!("LLM{PATH_SEPARATOR}{llm_key}").to_ascii_uppercase(),
+                            Target::Llm(llm_key.clone()),
+                        );
+                    }
+                }
+            }
+            _ => {
+                targets.insert(key.to_ascii_uppercase(), Target::Path(vec![key.clone()]));
+            }
+        }
+    }
+
+    // Keys of tables can be used without the table name, when unambiguous (ex. `RV_REPORT_SOURCES`)
+    for (leaf, paths) in leaves {
+        if let [path] = paths.as_slice() {
+            targets
+                .entry(leaf)
+                .or_insert_with(|| Target::Path(path.clone()));
+        }
+    }
+    for (alias, key) in LLM_ALIASES {
+        targets.insert(alias.to_string(), Target::Llm(key.to_string()));
+    }
+
+    targets
+}
+
+fn set_value(config: &mut Value, target: &Target, raw: &str) -> Result<()> {
+    match target {
+        Target::Path(path) => {
+            let Some((key, tables)) = path.split_last() else {
+                return Ok(());
+            };
+            let mut table = config
+                .as_table_mut()
+                .context("configuration is not a table")?;
+            for name in tables {
+                table = table
+                    .get_mut(name)
+                    .and_then(Value::as_table_mut)
+                    .with_context(|| format!("`{name}` is not a table"))?;
+            }
+            let value = parse_value(raw, table.get(key))?;
+            table.insert(key.clone(), value);
+        }
+        Target::Llm(key) => {
+            if let Some(Value::Array(llm_configs)) = config.get_mut("llm_configs") {
+                for llm in llm_configs.iter_mut().filter_map(Value::as_table_mut) {
+                    let value = parse_value(raw, llm.get(key))?;
+                    llm.insert(key.clone(), value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply the `vars` overrides (name and value of `RV_*` variables) on top of `config`
+pub fn apply_overrides(
+    config: RvConfig,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<RvConfig> {
+    let mut value = Value::try_from(&config).context("Failed to serialize the configuration")?;
+    let targets = override_targets(
+        &serde_json::to_value(&config).context("Failed to serialize the configuration")?,
+    );
+
+    let mut overridden = false;
+    for (name, raw) in vars {
+        let Some(option) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if NON_CONFIG_VARS.contains(&name.as_str()) {
+            continue;
+        }
+        match targets.get(option) {
+            Some(target) => {
+                set_value(&mut value, target, &raw).with_context(|| format!("Invalid {name}"))?;
+                overridden = true;
+            }
+            None => eprintln!("[WARN] {name} doesn't match any configuration option"),
+        }
+    }
+
+    if !overridden {
+        return Ok(config);
+    }
+    value
+        .try_into()
+        .context("Invalid configuration after applying the RV_* environment variables")
+}
+
+/// Apply the `RV_*` environment variables on top of `config`
+pub fn apply_env_overrides(config: RvConfig) -> Result<RvConfig> {
+    apply_overrides(config, std::env::vars())
+}
@@ -0,0 +1,62 @@
+//! Code snippets read from the clipboard or stdin (ex. code pasted from a chat or an issue),
+//! turned into an `ExpandedCommit` so that they go through the standard review pipeline
+
+use crate::clipboard::read_clipboard;
+use crate::git_helpers::ExpandedCommit;
+use crate::language::{Language, guess_language};
+use crate::term_helpers;
+use anyhow::{Result, bail};
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+
+/// Read the snippet from the clipboard or from stdin (a heredoc, a pipe or pasted in the terminal)
+pub fn read_snippet(from_clipboard: bool) -> Result<String> {
+    let code = if from_clipboard {
+        read_clipboard()?
+    } else {
+        if std::io::stdin().is_terminal() {
+            println!("Paste the code to review, then press Ctrl-D:");
+        }
+        let mut code = String::new();
+        std::io::stdin().read_to_string(&mut code)?;
+        code
+    };
+    if code.trim().is_empty() {
+        bail!("The snippet is empty, there is nothing to review");
+    }
+    Ok(code)
+}
+
+/// Language of the snippet: the `--lang` value, a guess, or the user's answer when the guess fails
+pub fn snippet_language(lang: Option<&str>, code: &str, interactive: bool) -> Result<Language> {
+    if let Some(lang) = lang {
+        return match Language::parse(lang) {
+            Some(language) => Ok(language),
+            None => bail!("Unknown language '{lang}'"),
+        };
+    }
+    let guessed = guess_language(code);
+    // Stdin was used for the snippet unless it is a terminal, so only ask on a terminal
+    if guessed != Language::Other || !interactive || !std::io::stdin().is_terminal() {
+        return Ok(guessed);
+    }
+    let answer = term_helpers::get_terminal_input(String::from(
+        "Language of the snippet (ex. rust, python, typescript; empty if unknown): ",
+    ));
+    if answer.trim().is_empty() {
+        return Ok(Language::Other);
+    }
+    Language::parse(&answer).ok_or_else(|| anyhow::anyhow!("Unknown language '{answer}'"))
+}
+
+/// Review input holding the snippet; it has no file on disk, so only its content is sent
+pub fn snippet_expanded(code: &str, language: Language) -> ExpandedCommit {
+    let mut expcommit = ExpandedCommit::new();
+    expcommit.sources = Some(vec![PathBuf::from(format!("snippet.{}", language.id()))]);
+    expcommit.diffs = Some(vec![format!(
+        "Language: {}\nCode snippet (not part of a repository, pasted by the user):\n{code}",
+        language.name()
+    )]);
+    expcommit.diff_only = true;
+    expcommit
+}
@@ -0,0 +1,517 @@
+use crate::findings::{finding_files, finding_items, review_severity};
+use crate::history::{self, ReviewRecord};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Rows of the file and directory tables shown in the terminal (the CSV export has them all)
+const TOP_ROWS: usize = 20;
+
+/// Columns of the severity table, in order
+const SEVERITIES: [&str; 5] = ["CRITICAL", "HIGH", "MEDIUM", "LOW", "INFO"];
+
+/// Time period used to group usage statistics
+#[derive(Debug, Clone, Copy, ValueEnum, Default)]
+pub enum StatsPeriod {
+    /// Group by week (starting on Monday)
+    Week,
+    /// Group by calendar month
+    #[default]
+    Month,
+}
+
+/// History analytics selected with `--by`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsDimension {
+    /// Findings per severity of their review, per period
+    Severity,
+    /// Most-flagged files
+    File,
+    /// Most-flagged directories
+    Directory,
+    /// Findings per thousand changed lines, per period
+    Kloc,
+    /// Tokens and cost per period
+    Cost,
+    /// Tokens and cost of every review, the newest first
+    Run,
+}
+
+/// Table of analytics, shown in the terminal or exported as CSV
+struct Table {
+    /// Name of the CSV file
+    name: &'static str,
+    title: String,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Print the first `max_rows` rows, the first column aligned left and the others right
+    fn print(&self, max_rows: usize) {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.len()).collect();
+        for row in self.rows.iter().take(max_rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let format_row = |cells: Vec<&str>| {
+            let mut line = String::from(" ");
+            for (idx, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+                if idx == 0 {
+                    line.push_str(&format!(" {cell:<width$}"));
+                } else {
+                    line.push_str(&format!("  {cell:>width$}"));
+                }
+            }
+            line.trim_end().to_string()
+        };
+
+        println!("{}", self.title);
+        println!("{}", format_row(self.headers.clone()));
+        for row in self.rows.iter().take(max_rows) {
+            println!("{}", format_row(row.iter().map(String::as_str).collect()));
+        }
+        if self.rows.len() > max_rows {
+            println!("  ... {} more", self.rows.len() - max_rows);
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in std::iter::once(self.headers.iter().map(|h| h.to_string()).collect())
+            .chain(self.rows.iter().cloned())
+        {
+            let cells: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+            csv.push_str(&cells.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// CSV field, quoted when it contains a separator, a quote or a line break
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Aggregated usage of a group of reviews
+#[derive(Debug, Clone, Default)]
+struct UsageTotals {
+    reviews: usize,
+    input_tokens: usize,
+    output_tokens: usize,
+    cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &ReviewRecord) {
+        self.reviews += 1;
+        if let Some(usage) = record.usage {
+            self.input_tokens += usage.input_tokens;
+            self.output_tokens += usage.output_tokens;
+            self.cost_usd += usage.cost_usd.unwrap_or_default();
+        }
+    }
+
+    fn print_row(&self, label: &str) {
+        println!(
+            "  {label:<40} {:>6} reviews {:>10} in {:>9} out  ${:.4}",
+            self.reviews, self.input_tokens, self.output_tokens, self.cost_usd
+        );
+    }
+}
+
+/// Convert days since UNIX epoch into a (year, month, day) civil date
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's `civil_from_days` algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Label of the period containing `timestamp`, sortable as a string
+fn period_label(timestamp: u64, period: StatsPeriod) -> String {
+    let days = (timestamp / SECONDS_PER_DAY) as i64;
+    match period {
+        StatsPeriod::Week => {
+            // 1970-01-01 was a Thursday, shift to the previous Monday
+            let monday = days - (days + 3).rem_euclid(7);
+            let (year, month, day) = civil_from_days(monday);
+            format!("week of {year:04}-{month:02}-{day:02}")
+        }
+        StatsPeriod::Month => {
+            let (year, month, _) = civil_from_days(days);
+            format!("{year:04}-{month:02}")
+        }
+    }
+}
+
+/// Findings per severity of their review, per period
+fn severity_table(records: &[ReviewRecord], period: StatsPeriod) -> Table {
+    let mut by_period: BTreeMap<String, [usize; SEVERITIES.len() + 1]> = BTreeMap::new();
+    for record in records {
+        let column = review_severity(&record.response)
+            .and_then(|severity| SEVERITIES.iter().position(|s| *s == severity))
+            .unwrap_or(SEVERITIES.len());
+        by_period
+            .entry(period_label(record.timestamp, period))
+            .or_default()[column] += finding_items(&record.response).len();
+    }
+
+    let mut headers = vec!["PERIOD"];
+    headers.extend(SEVERITIES);
+    headers.push("UNKNOWN");
+    Table {
+        name: "severity",
+        title: String::from("FINDINGS BY SEVERITY"),
+        headers,
+        rows: by_period
+            .into_iter()
+            .map(|(label, counts)| {
+                std::iter::once(label)
+                    .chain(counts.iter().map(usize::to_string))
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+/// Reviewed file a finding names, matched against the files of its review
+fn reviewed_file(record: &ReviewRecord, named: &str) -> Option<String> {
+    if record.files.is_empty() {
+        return Some(named.to_string());
+    }
+    record
+        .files
+        .iter()
+        .find(|file| *file == named || file.ends_with(&format!("/{named}")))
+        .cloned()
+}
+
+/// Most-flagged files (or their directories), as (findings, reviews), the most flagged first
+fn flagged_table(records: &[ReviewRecord], directories: bool) -> Table {
+    let mut flagged: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for record in records {
+        let mut in_review: Vec<String> = Vec::new();
+        for item in finding_items(&record.response) {
+            let mut in_finding: Vec<String> = Vec::new();
+            for named in finding_files(&record.response, &item) {
+                let Some(file) = reviewed_file(record, &named) else {
+                    continue;
+                };
+                let key = if directories {
+                    match Path::new(&file).parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => {
+                            parent.to_string_lossy().to_string()
+                        }
+                        _ => String::from("."),
+                    }
+                } else {
+                    file
+                };
+                if !in_finding.contains(&key) {
+                    in_finding.push(key);
+                }
+            }
+            for key in in_finding {
+                flagged.entry(key.clone()).or_default().0 += 1;
+                if !in_review.contains(&key) {
+                    in_review.push(key);
+                }
+            }
+        }
+        for key in in_review {
+            flagged.entry(key).or_default().1 += 1;
+        }
+    }
+
+    let mut flagged: Vec<(String, (usize, usize))> = flagged.into_iter().collect();
+    flagged.sort_by(|(a, (a_findings, _)), (b, (b_findings, _))| {
+        b_findings.cmp(a_findings).then_with(|| a.cmp(b))
+    });
+    Table {
+        name: if directories { "directory" } else { "file" },
+        title: String::from(if directories {
+            "MOST FLAGGED DIRECTORIES"
+        } else {
+            "MOST FLAGGED FILES"
+        }),
+        headers: vec![
+            if directories { "DIRECTORY" } else { "FILE" },
+            "FINDINGS",
+            "REVIEWS",
+        ],
+        rows: flagged
+            .into_iter()
+            .map(|(key, (findings, reviews))| vec![key, findings.to_string(), reviews.to_string()])
+            .collect(),
+    }
+}
+
+/// Findings per thousand changed lines, per period; reviews without a diff are left out
+fn kloc_table(records: &[ReviewRecord], period: StatsPeriod) -> Table {
+    let mut by_period: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    for record in records {
+        let Some(lines) = record.changed_lines.filter(|lines| *lines > 0) else {
+            continue;
+        };
+        let totals = by_period
+            .entry(period_label(record.timestamp, period))
+            .or_default();
+        totals.0 += 1;
+        totals.1 += lines;
+        totals.2 += finding_items(&record.response).len();
+    }
+    Table {
+        name: "kloc",
+        title: String::from("FINDINGS PER KLOC CHANGED"),
+        headers: vec!["PERIOD", "REVIEWS", "CHANGED LINES", "FINDINGS", "PER KLOC"],
+        rows: by_period
+            .into_iter()
+            .map(|(label, (reviews, lines, findings))| {
+                vec![
+                    label,
+                    reviews.to_string(),
+                    lines.to_string(),
+                    findings.to_string(),
+                    format!("{:.2}", findings as f64 * 1000.0 / lines as f64),
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// Tokens and cost per period
+fn cost_table(records: &[ReviewRecord], period: StatsPeriod) -> Table {
+    let mut by_period: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    for record in records {
+        by_period
+            .entry(period_label(record.timestamp, period))
+            .or_default()
+            .add(record);
+    }
+    Table {
+        name: "cost",
+        title: String::from("COST"),
+        headers: vec![
+            "PERIOD",
+            "REVIEWS",
+            "INPUT TOKENS",
+            "OUTPUT TOKENS",
+            "COST USD",
+        ],
+        rows: by_period
+            .into_iter()
+            .map(|(label, totals)| {
+                vec![
+                    label,
+                    totals.reviews.to_string(),
+                    totals.input_tokens.to_string(),
+                    totals.output_tokens.to_string(),
+                    format!("{:.4}", totals.cost_usd),
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// Tokens and cost of every review with a recorded usage, the newest first
+fn run_table(records: &[ReviewRecord]) -> Table {
+    Table {
+        name: "run",
+        title: String::from("USAGE PER REVIEW"),
+        headers: vec![
+            "DATE",
+            "REPOSITORY",
+            "TARGET",
+            "MODEL",
+            "INPUT TOKENS",
+            "OUTPUT TOKENS",
+            "COST USD",
+        ],
+        rows: records
+            .iter()
+            .rev()
+            .filter_map(|record| {
+                let usage = record.usage?;
+                Some(vec![
+                    history::format_timestamp(record.timestamp),
+                    record.repo.clone().unwrap_or_default(),
+                    record.target.clone(),
+                    record.model_id.clone(),
+                    usage.input_tokens.to_string(),
+                    usage.output_tokens.to_string(),
+                    usage
+                        .cost_usd
+                        .map(|cost| format!("{cost:.4}"))
+                        .unwrap_or_default(),
+                ])
+            })
+            .collect(),
+    }
+}
+
+/// Print the analytics of `dimensions` over the history, and export them as CSV files in `csv_dir`
+fn print_analytics(
+    records: &[ReviewRecord],
+    period: StatsPeriod,
+    dimensions: &[StatsDimension],
+    csv_dir: Option<&Path>,
+) -> Result<()> {
+    // Exporting without `--by` exports everything
+    let selected = if dimensions.is_empty() {
+        StatsDimension::value_variants()
+    } else {
+        dimensions
+    };
+    let mut dimensions: Vec<StatsDimension> = Vec::new();
+    for dimension in selected {
+        if !dimensions.contains(dimension) {
+            dimensions.push(*dimension);
+        }
+    }
+
+    let mut written: Vec<PathBuf> = Vec::new();
+    for (idx, dimension) in dimensions.into_iter().enumerate() {
+        let table = match dimension {
+            StatsDimension::Severity => severity_table(records, period),
+            StatsDimension::File => flagged_table(records, false),
+            StatsDimension::Directory => flagged_table(records, true),
+            StatsDimension::Kloc => kloc_table(records, period),
+            StatsDimension::Cost => cost_table(records, period),
+            StatsDimension::Run => run_table(records),
+        };
+        if idx > 0 {
+            println!();
+        }
+        table.print(TOP_ROWS);
+        if let Some(dir) = csv_dir {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            let path = dir.join(format!("{}.csv", table.name));
+            std::fs::write(&path, table.to_csv())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            written.push(path);
+        }
+    }
+    if !written.is_empty() {
+        println!();
+        for path in written {
+            println!("Exported {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Print usage summaries aggregated over the local review history; with `dimensions` or
+/// `csv_dir`, print (and export) the selected analytics instead
+pub fn print_stats(
+    period: StatsPeriod,
+    usage_stats_enabled: bool,
+    dimensions: &[StatsDimension],
+    csv_dir: Option<&Path>,
+) -> Result<()> {
+    let records = history::load_all_records()?;
+    if records.is_empty() {
+        println!("No reviews in the history yet.");
+        return Ok(());
+    }
+    if !dimensions.is_empty() || csv_dir.is_some() {
+        if !usage_stats_enabled
+            && dimensions
+                .iter()
+                .any(|dimension| matches!(dimension, StatsDimension::Cost | StatsDimension::Run))
+        {
+            println!(
+                "NOTE: token usage is only recorded when `usage_stats = true` or `show_usage = true` is set in config.toml\n"
+            );
+        }
+        return print_analytics(&records, period, dimensions, csv_dir);
+    }
+    if !usage_stats_enabled {
+        println!(
+            "NOTE: token usage is only recorded when `usage_stats = true` or `show_usage = true` is set in config.toml\n"
+        );
+    }
+
+    let mut total = UsageTotals::default();
+    let mut by_period: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    let mut by_model: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    let mut by_repo: BTreeMap<String, UsageTotals> = BTreeMap::new();
+
+    for record in &records {
+        total.add(record);
+        by_period
+            .entry(period_label(record.timestamp, period))
+            .or_default()
+            .add(record);
+        by_model
+            .entry(record.model_id.clone())
+            .or_default()
+            .add(record);
+        by_repo
+            .entry(
+                record
+                    .repo
+                    .clone()
+                    .unwrap_or_else(|| String::from("(no repository)")),
+            )
+            .or_default()
+            .add(record);
+    }
+
+    println!("BY PERIOD");
+    for (label, totals) in &by_period {
+        totals.print_row(label);
+    }
+    println!("\nBY MODEL");
+    for (label, totals) in &by_model {
+        totals.print_row(label);
+    }
+    println!("\nBY REPOSITORY");
+    for (label, totals) in &by_repo {
+        totals.print_row(label);
+    }
+    println!();
+    total.print_row("TOTAL");
+
+    // Findings tagged with the rule IDs of the guidelines
+    let mut by_rule: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for record in &records {
+        let mut rules: Vec<String> = Vec::new();
+        for item in finding_items(&record.response) {
+            for rule in item.rules {
+                by_rule.entry(rule.clone()).or_default().0 += 1;
+                if !rules.contains(&rule) {
+                    rules.push(rule);
+                }
+            }
+        }
+        for rule in rules {
+            by_rule.entry(rule).or_default().1 += 1;
+        }
+    }
+    if !by_rule.is_empty() {
+        println!("\nBY GUIDELINE RULE");
+        for (rule, (findings, reviews)) in &by_rule {
+            println!("  {rule:<40} {findings:>6} findings in {reviews:>6} reviews");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Checks of the review output against the structure required by the system prompt
+
+/// Headings of the sections required by `SYSTEM_PROMPT`, in order
+pub const REVIEW_SECTIONS: [&str; 9] = [
+    "FILE / CONTEXT",
+    "SUMMARY",
+    "SEVERITY",
+    "FINDINGS",
+    "SUGGESTED FIX",
+    "TESTS TO RUN",
+    "RISK / IMPACT",
+    "ESTIMATED EFFORT",
+    "FINAL VERDICT",
+];
+
+/// Strip list numbering and decorations (ex. `4) `, `5. `, `**`) from the start of a line
+fn heading_text(line: &str) -> String {
+    line.trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, ')' | '.' | '*' | '#'))
+        .trim_start()
+        .to_ascii_uppercase()
+}
+
+/// Follow-up request sent when a review doesn't follow the required structure
+pub const REFORMAT_PROMPT: &str = r#"
+Your reply doesn't follow the required structure.
+Reformat it into the required structure: the nine sections FILE / CONTEXT,
+SUMMARY, SEVERITY, FINDINGS, SUGGESTED FIX, TESTS TO RUN, RISK / IMPACT,
+ESTIMATED EFFORT and FINAL VERDICT, each one with its heading, in this exact
+order. Keep the content of the review, don't add new findings.
+"#;
+
+/// Way a review deviates from the required structure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureIssue {
+    /// Sections without a heading
+    Missing(Vec<&'static str>),
+    /// Sections whose heading comes before the heading of a previous section
+    OutOfOrder(Vec<&'static str>),
+}
+
+impl std::fmt::Display for StructureIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructureIssue::Missing(sections) => write!(f, "missing {}", sections.join(", ")),
+            StructureIssue::OutOfOrder(sections) => {
+                write!(f, "out of order {}", sections.join(", "))
+            }
+        }
+    }
+}
+
+/// Section of `REVIEW_SECTIONS` whose heading is `line`, if any
+pub fn section_of_heading(line: &str) -> Option<&'static str> {
+    let heading = heading_text(line);
+    REVIEW_SECTIONS
+        .into_iter()
+        .find(|section| heading.starts_with(section))
+}
+
+/// Line of the first heading of every section of `REVIEW_SECTIONS`
+fn section_positions(review: &str) -> Vec<(&'static str, Option<usize>)> {
+    let headings: Vec<String> = review.lines().map(heading_text).collect();
+    REVIEW_SECTIONS
+        .into_iter()
+        .map(|section| {
+            (
+                section,
+                headings.iter().position(|h| h.starts_with(section)),
+            )
+        })
+        .collect()
+}
+
+/// Sections of `REVIEW_SECTIONS` without a heading in `review`
+pub fn missing_sections(review: &str) -> Vec<&'static str> {
+    section_positions(review)
+        .into_iter()
+        .filter_map(|(section, position)| position.is_none().then_some(section))
+        .collect()
+}
+
+/// Check that `review` contains every required section, in order
+pub fn check_structure(review: &str) -> Result<(), StructureIssue> {
+    let positions = section_positions(review);
+    let missing = missing_sections(review);
+    if !missing.is_empty() {
+        return Err(StructureIssue::Missing(missing));
+    }
+
+    let mut last = 0;
+    let mut out_of_order = Vec::new();
+    for (section, position) in positions {
+        let position = position.unwrap_or_default();
+        if position < last {
+            out_of_order.push(section);
+        } else {
+            last = position;
+        }
+    }
+    if out_of_order.is_empty() {
+        Ok(())
+    } else {
+        Err(StructureIssue::OutOfOrder(out_of_order))
+    }
+}
@@ -0,0 +1,271 @@
+//! Accepted findings listed in the project's `.rv_suppressions` file, moved out of the reports
+//!
+//! Every line holds a finding fingerprint, an optional expiry date and the justification;
+//! `rule=ID` instead of a fingerprint accepts every finding tagged with a guideline rule:
+//!
+//! ```text
+//! # fingerprint      [expires=YYYY-MM-DD]  justification
+//! 3f2a9c0d1e2b4a5c   expires=2026-12-31    unwrap on the startup config is accepted
+//! rule=RG-007                              naming rule not enforced in the legacy modules
+//! ```
+
+use crate::context::resolve_project_file;
+use crate::findings::{finding_items, fingerprint};
+use crate::git_helpers;
+use crate::history::now_timestamp;
+use crate::rules::is_rule_id;
+use crate::stats::civil_from_days;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Name of the project file listing the accepted findings
+pub const SUPPRESSIONS_FILE: &str = ".rv_suppressions";
+
+/// Findings accepted by a suppression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuppressionTarget {
+    /// Single finding, by fingerprint
+    Fingerprint(String),
+    /// Every finding tagged with a guideline rule
+    Rule(String),
+}
+
+impl std::fmt::Display for SuppressionTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuppressionTarget::Fingerprint(fingerprint) => write!(f, "{fingerprint}"),
+            SuppressionTarget::Rule(rule) => write!(f, "rule {rule}"),
+        }
+    }
+}
+
+/// Single entry of `.rv_suppressions`
+#[derive(Debug, Clone)]
+pub struct Suppression {
+    pub target: SuppressionTarget,
+    /// Last day (`YYYY-MM-DD`) the suppression applies
+    pub expires: Option<String>,
+    pub justification: String,
+}
+
+/// Finding removed from a review because of a suppression
+#[derive(Debug, Clone, Serialize)]
+pub struct SuppressedFinding {
+    pub fingerprint: String,
+    pub title: String,
+    /// Guideline rule the finding was accepted by, for `rule=` suppressions
+    pub rule: Option<String>,
+    pub justification: String,
+    pub expires: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    entries: Vec<Suppression>,
+}
+
+/// Today's date as `YYYY-MM-DD`, in UTC
+fn today() -> String {
+    let (year, month, day) = civil_from_days((now_timestamp() / 86_400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Check the `YYYY-MM-DD` shape, so that dates compare correctly as strings
+pub fn is_date(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(idx, b)| match idx {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+impl Suppressions {
+    /// Parse the content of a suppressions file; invalid lines are reported and ignored
+    pub fn parse(content: &str) -> Suppressions {
+        let mut entries = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut rest = line;
+            let (first, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let target = match first.strip_prefix("rule=") {
+                Some(rule) if is_rule_id(rule) => SuppressionTarget::Rule(rule.to_string()),
+                Some(_) => {
+                    eprintln!("[WARN] Invalid rule ID in {SUPPRESSIONS_FILE}: '{line}'");
+                    continue;
+                }
+                None if first.len() == 16 && first.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    SuppressionTarget::Fingerprint(first.to_ascii_lowercase())
+                }
+                None => {
+                    eprintln!("[WARN] Invalid fingerprint in {SUPPRESSIONS_FILE}: '{line}'");
+                    continue;
+                }
+            };
+            rest = after.trim_start();
+
+            let mut expires = None;
+            if let Some(after) = rest.strip_prefix("expires=") {
+                let (date, after) = after.split_once(char::is_whitespace).unwrap_or((after, ""));
+                if !is_date(date) {
+                    eprintln!(
+                        "[WARN] Invalid expiry date in {SUPPRESSIONS_FILE} (expected YYYY-MM-DD): '{line}'"
+                    );
+                    continue;
+                }
+                expires = Some(date.to_string());
+                rest = after.trim_start();
+            }
+
+            entries.push(Suppression {
+                target,
+                expires,
+                justification: rest.to_string(),
+            });
+        }
+        Suppressions { entries }
+    }
+
+    /// Load the project's suppressions file, if any; expired entries are reported and dropped
+    pub fn load() -> Suppressions {
+        let Some(path) = resolve_project_file(SUPPRESSIONS_FILE) else {
+            return Suppressions::default();
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("[WARN] Failed to read {}: {e}", path.display());
+                return Suppressions::default();
+            }
+        };
+
+        let today = today();
+        let mut suppressions = Suppressions::parse(&content);
+        suppressions.entries.retain(|entry| match &entry.expires {
+            Some(expires) if *expires < today => {
+                eprintln!(
+                    "[WARN] The suppression of {} expired on {expires}, the finding is reported again",
+                    entry.target
+                );
+                false
+            }
+            _ => true,
+        });
+        suppressions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove the suppressed findings from `review`, returning the remaining review and what was removed
+    pub fn apply(&self, review: &str) -> (String, Vec<SuppressedFinding>) {
+        if self.is_empty() {
+            return (review.to_string(), Vec::new());
+        }
+
+        let mut removed_lines = vec![false; review.lines().count()];
+        let mut suppressed = Vec::new();
+        for item in finding_items(review) {
+            let item_fingerprint = fingerprint(review, &item);
+            let Some(entry) = self.entries.iter().find(|entry| match &entry.target {
+                SuppressionTarget::Fingerprint(fingerprint) => *fingerprint == item_fingerprint,
+                SuppressionTarget::Rule(rule) => item.rules.contains(rule),
+            }) else {
+                continue;
+            };
+            removed_lines[item.lines.clone()].fill(true);
+            suppressed.push(SuppressedFinding {
+                fingerprint: item_fingerprint,
+                title: item.title,
+                rule: match &entry.target {
+                    SuppressionTarget::Rule(rule) => Some(rule.clone()),
+                    SuppressionTarget::Fingerprint(_) => None,
+                },
+                justification: entry.justification.clone(),
+                expires: entry.expires.clone(),
+            });
+        }
+
+        let remaining: Vec<&str> = review
+            .lines()
+            .zip(removed_lines)
+            .filter_map(|(line, removed)| (!removed).then_some(line))
+            .collect();
+        (remaining.join("\n"), suppressed)
+    }
+}
+
+/// Path of the suppressions file: the existing one, or a new one at the root of the repository
+fn suppressions_path() -> PathBuf {
+    if let Some(path) = resolve_project_file(SUPPRESSIONS_FILE) {
+        return path;
+    }
+    match git_helpers::open_repo().ok().and_then(|repo| {
+        repo.workdir()
+            .map(|workdir| workdir.join(SUPPRESSIONS_FILE))
+    }) {
+        Some(path) => path,
+        None => PathBuf::from(SUPPRESSIONS_FILE),
+    }
+}
+
+/// Add the suppression of a finding to the suppressions file, returning the path of the file
+pub fn append_suppression(
+    fingerprint: &str,
+    expires: Option<&str>,
+    justification: &str,
+) -> Result<PathBuf> {
+    let path = suppressions_path();
+    let mut content = std::fs::read_to_string(&path).unwrap_or_default();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    let expires = expires
+        .map(|date| format!("expires={date}"))
+        .unwrap_or_default();
+    content.push_str(&format!("{fingerprint}   {expires:<21} {justification}\n"));
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Collapsed appendix listing the suppressed findings, one line each
+pub fn format_suppressed(suppressed: &[SuppressedFinding]) -> String {
+    let mut text = format!(
+        "SUPPRESSED FINDINGS ({}, accepted in {SUPPRESSIONS_FILE})\n",
+        suppressed.len()
+    );
+    for finding in suppressed {
+        let until = finding
+            .expires
+            .as_ref()
+            .map(|date| format!(" (until {date})"))
+            .unwrap_or_default();
+        let rule = finding
+            .rule
+            .as_ref()
+            .map(|rule| format!(" (rule {rule})"))
+            .unwrap_or_default();
+        text.push_str(&format!(
+            "- {} {}: {}{rule}{until}\n",
+            finding.fingerprint, finding.title, finding.justification
+        ));
+    }
+    text
+}
+
+/// Fingerprints of the findings of `review`, to be copied into `.rv_suppressions`
+pub fn format_fingerprints(review: &str) -> Option<String> {
+    let fingerprints: Vec<String> = finding_items(review)
+        .iter()
+        .map(|item| format!("{}) {}", item.number, fingerprint(review, item)))
+        .collect();
+    (!fingerprints.is_empty())
+        .then(|| format!("[Finding fingerprints: {}]", fingerprints.join(", ")))
+}
@@ -0,0 +1,271 @@
+//! Spans of the review pipeline, recorded with `tracing` and exported over OTLP/HTTP by the
+//! OpenTelemetry SDK when rv is built with the `otel` feature; without it, every function is a no-op
+
+use crate::llm::defs::TokenStream;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+
+/// Environment variable used for the collector endpoint when `otel_endpoint` is not configured
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Start collecting spans, if an endpoint is configured
+#[cfg(feature = "otel")]
+pub fn init(otel_endpoint: Option<&str>) {
+    let endpoint = otel_endpoint
+        .map(str::to_string)
+        .or_else(|| std::env::var(OTLP_ENDPOINT_ENV).ok());
+    if let Some(endpoint) = endpoint
+        && let Err(e) = otel::init(endpoint)
+    {
+        eprintln!("[WARN] Failed to set up the trace export: {e:#}");
+    }
+}
+
+/// Start collecting spans (no-op, rv was built without the `otel` feature)
+#[cfg(not(feature = "otel"))]
+pub fn init(_otel_endpoint: Option<&str>) {}
+
+/// Send the collected spans to the collector
+#[cfg(feature = "otel")]
+pub async fn export() {
+    if let Err(e) = otel::export().await {
+        eprintln!("[WARN] Failed to export traces: {e:#}");
+    }
+}
+
+/// Send the collected spans to the collector (no-op, rv was built without the `otel` feature)
+#[cfg(not(feature = "otel"))]
+pub async fn export() {}
+
+/// Step of the review pipeline; dropping it without calling `end` or `fail` marks the step as failed
+#[cfg(feature = "otel")]
+pub struct Span(Option<tracing::Span>);
+
+/// Step of the review pipeline (no-op, rv was built without the `otel` feature)
+#[cfg(not(feature = "otel"))]
+pub struct Span;
+
+/// Start a span named `name`
+pub fn span(name: &str, attributes: &[(&str, &str)]) -> Span {
+    #[cfg(feature = "otel")]
+    {
+        Span(otel::start(name, attributes))
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = (name, attributes);
+        Span
+    }
+}
+
+impl Span {
+    /// Record the step as successful
+    pub fn end(self) {
+        #[cfg(feature = "otel")]
+        {
+            let mut span = self;
+            if let Some(span) = span.0.take() {
+                otel::finish(span, None);
+            }
+        }
+    }
+
+    /// Record the step as failed
+    pub fn fail(self, error: &anyhow::Error) {
+        #[cfg(feature = "otel")]
+        {
+            let mut span = self;
+            if let Some(span) = span.0.take() {
+                otel::finish(span, Some(format!("{error:#}")));
+            }
+        }
+        #[cfg(not(feature = "otel"))]
+        let _ = error;
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(span) = self.0.take() {
+            otel::finish(span, Some(String::from("interrupted")));
+        }
+    }
+}
+
+/// Run `f` inside a span named `name`, marking the span as failed if `f` returns an error
+pub fn in_span<T>(
+    name: &str,
+    attributes: &[(&str, &str)],
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let span = span(name, attributes);
+    let result = f();
+    match &result {
+        Ok(_) => span.end(),
+        Err(e) => span.fail(e),
+    }
+    result
+}
+
+/// Run `future` inside a span named `name`, marking the span as failed if it returns an error
+pub fn in_span_future<T: Send + 'static>(
+    name: &str,
+    attributes: &[(&str, &str)],
+    future: impl Future<Output = Result<T>> + Send + 'static,
+) -> BoxFuture<'static, Result<T>> {
+    let span = span(name, attributes);
+    async move {
+        let result = future.await;
+        match &result {
+            Ok(_) => span.end(),
+            Err(e) => span.fail(e),
+        }
+        result
+    }
+    .boxed()
+}
+
+/// Wrap `stream` in a span named `name`, ending when the stream is exhausted or fails
+pub fn in_span_stream(name: &str, attributes: &[(&str, &str)], stream: TokenStream) -> TokenStream {
+    let span = span(name, attributes);
+    futures::stream::unfold((stream, Some(span)), |(mut stream, mut span)| async move {
+        let item = stream.next().await;
+        match &item {
+            Some(Err(e)) => {
+                if let Some(span) = span.take() {
+                    span.fail(e);
+                }
+            }
+            None => {
+                if let Some(span) = span.take() {
+                    span.end();
+                }
+            }
+            Some(Ok(_)) => {}
+        }
+        item.map(|item| (item, (stream, span)))
+    })
+    .boxed()
+}
+
+#[cfg(feature = "otel")]
+pub(crate) mod otel {
+    use crate::network;
+    use anyhow::{Context, Result, anyhow};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::{Mutex, OnceLock};
+    use tracing::field::Empty;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::filter::filter_fn;
+    use tracing_subscriber::layer::{Layer, SubscriberExt};
+
+    const SERVICE_NAME: &str = "rv";
+
+    /// Trace of a single rv invocation; every span is a child of the root `rv` span
+    struct Trace {
+        url: String,
+        provider: SdkTracerProvider,
+        root: Mutex<Option<tracing::Span>>,
+    }
+
+    static TRACE: OnceLock<Trace> = OnceLock::new();
+
+    pub fn init(endpoint: String) -> Result<()> {
+        let base = endpoint.trim_end_matches('/');
+        let url = if base.ends_with("/v1/traces") {
+            base.to_string()
+        } else {
+            format!("{base}/v1/traces")
+        };
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(&url)
+            .build()
+            .context("Failed to create the OTLP exporter")?;
+        // The batch processor exports from its own thread, so the blocking client fits
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+            .build();
+        // Only the spans of rv are exported, the ones of the dependencies (ex. rig) carry the prompts
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(SERVICE_NAME))
+            .with_location(false)
+            .with_threads(false)
+            .with_tracked_inactivity(false)
+            .with_filter(filter_fn(|metadata| metadata.target() == module_path!()));
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+            .context("Failed to install the trace subscriber")?;
+        let root = tracing::info_span!("rv", otel.status_code = Empty);
+        let _ = TRACE.set(Trace {
+            url,
+            provider,
+            root: Mutex::new(Some(root)),
+        });
+        Ok(())
+    }
+
+    /// Start a child of the root span, `None` when no trace is collected
+    pub fn start(name: &str, attributes: &[(&str, &str)]) -> Option<tracing::Span> {
+        let trace = TRACE.get()?;
+        let root = trace.root.lock().ok()?.clone()?;
+        let span = tracing::info_span!(
+            parent: &root,
+            "step",
+            otel.name = name,
+            otel.status_code = Empty,
+            otel.status_description = Empty
+        );
+        for (key, value) in attributes {
+            span.set_attribute(key.to_string(), value.to_string());
+        }
+        Some(span)
+    }
+
+    /// Record the outcome of `span`, which ends once dropped
+    pub fn finish(span: tracing::Span, error: Option<String>) {
+        match error {
+            Some(error) => {
+                span.record("otel.status_code", "ERROR");
+                span.record("otel.status_description", error.as_str());
+                if let Some(trace) = TRACE.get()
+                    && let Ok(root) = trace.root.lock()
+                    && let Some(root) = root.as_ref()
+                {
+                    root.record("otel.status_code", "ERROR");
+                }
+            }
+            None => {
+                span.record("otel.status_code", "OK");
+            }
+        }
+    }
+
+    pub async fn export() -> Result<()> {
+        let Some(trace) = TRACE.get() else {
+            return Ok(());
+        };
+        // Ending the root span completes the trace
+        if let Ok(mut root) = trace.root.lock() {
+            root.take();
+        }
+        network::ensure_allowed("The OTLP exporter")?;
+        let provider = trace.provider.clone();
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await?
+            .map_err(|e| anyhow!("{e}"))
+            .with_context(|| {
+                format!(
+                    "Failed to export the spans to the OTLP collector at {}",
+                    trace.url
+                )
+            })
+    }
+}
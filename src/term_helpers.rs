@@ -1,17 +1,61 @@
+use crate::llm::defs::TokenStream;
+use crate::sanitize::OutputFilter;
+#[cfg(feature = "tui")]
+use anyhow::Context;
+use anyhow::{Result, bail};
+#[cfg(feature = "tui")]
 use crossterm::{
     execute,
     terminal::{Clear, ClearType},
 };
-use dialoguer::Select;
+#[cfg(feature = "tui")]
+use dialoguer::{Confirm, Select};
+use futures::StreamExt;
+#[cfg(feature = "tui")]
 use rustyline::DefaultEditor;
+#[cfg(feature = "tui")]
 use rustyline::error::ReadlineError;
-use std::io::stdout;
+use std::io::{IsTerminal, Write, stdout};
 use std::process;
 
+#[cfg(feature = "tui")]
 pub fn clear_term() {
     execute!(stdout(), Clear(ClearType::All)).unwrap();
 }
 
+/// Clear the terminal (no-op, rv was built without the `tui` feature)
+#[cfg(not(feature = "tui"))]
+pub fn clear_term() {}
+
+/// Print `prompt` and read a line from stdin; `None` on end of input
+#[cfg(not(feature = "tui"))]
+fn read_stdin_line(prompt: &str) -> Option<String> {
+    print!("{prompt}");
+    let _ = stdout().flush();
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+    }
+}
+
+/// Print a streamed response as it arrives, filtered by `OutputFilter`, returning the filtered text
+pub async fn print_stream(mut stream: TokenStream, normalize: bool) -> Result<String> {
+    let mut filter = OutputFilter::new(normalize);
+    let mut full_text = String::new();
+    while let Some(chunk) = stream.next().await {
+        let text = filter.push(&chunk?);
+        print!("{text}");
+        stdout().flush()?;
+        full_text.push_str(&text);
+    }
+    let text = filter.finish();
+    println!("{text}");
+    full_text.push_str(&text);
+    Ok(full_text)
+}
+
+#[cfg(feature = "tui")]
 pub fn get_terminal_input(prompt: String) -> String {
     let mut rl = DefaultEditor::new().unwrap();
     match rl.readline(&prompt) {
@@ -35,6 +79,51 @@ pub fn get_terminal_input(prompt: String) -> String {
     }
 }
 
+/// Read a line of input, exiting on end of input (plain stdin, rv was built without the `tui` feature)
+#[cfg(not(feature = "tui"))]
+pub fn get_terminal_input(prompt: String) -> String {
+    read_stdin_line(&prompt).unwrap_or_else(|| process::exit(0))
+}
+
+/// Fail when nothing can answer `prompt`, instead of taking the silence for a "no"
+/// Whether questions can be asked: stdin is a terminal
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+fn ensure_interactive(prompt: &str) -> Result<()> {
+    if !is_interactive() {
+        bail!("Can't ask \"{prompt}\", stdin is not a terminal; pass --yes to confirm up front");
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stderr, keeping stdout for the output
+#[cfg(feature = "tui")]
+pub fn confirm(prompt: &str) -> Result<bool> {
+    ensure_interactive(prompt)?;
+    Confirm::new()
+        .with_prompt(prompt)
+        .default(true)
+        .interact()
+        .context("Failed to read the answer")
+}
+
+/// Ask a yes/no question on stderr, keeping stdout for the output
+#[cfg(not(feature = "tui"))]
+pub fn confirm(prompt: &str) -> Result<bool> {
+    ensure_interactive(prompt)?;
+    eprint!("{prompt} [Y/n] ");
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        bail!("No answer to \"{prompt}\"; pass --yes to confirm up front");
+    }
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
 pub enum ActionSelection {
     /// Enter Chat Mode
     EnterChatMode,
@@ -48,22 +137,43 @@ pub enum ActionSelection {
     Quit,
 }
 
-pub fn select_action_menu() -> ActionSelection {
-    let items = vec![
-        "Enter Chat Mode",
-        "Fix Git commit",
-        "Create new Git commit",
-        "Revert to previous Git commit",
-        "Exit rv",
-    ];
+const ACTION_ITEMS: [&str; 5] = [
+    "Enter Chat Mode",
+    "Fix Git commit",
+    "Create new Git commit",
+    "Revert to previous Git commit",
+    "Exit rv",
+];
 
-    let selection = Select::new()
-        //.with_prompt(">")
-        .items(&items)
+/// Menu of `items`; the last item is chosen when the terminal isn't interactive
+#[cfg(feature = "tui")]
+pub fn select_item(items: &[&str]) -> usize {
+    Select::new()
+        .items(items)
+        .default(0)
         .interact()
-        .unwrap();
+        .unwrap_or(items.len() - 1)
+}
 
-    match selection {
+/// Numbered menu on stdin (rv was built without the `tui` feature); end of input chooses the last item
+#[cfg(not(feature = "tui"))]
+pub fn select_item(items: &[&str]) -> usize {
+    for (idx, item) in items.iter().enumerate() {
+        println!("{}) {item}", idx + 1);
+    }
+    loop {
+        let Some(answer) = read_stdin_line("> ") else {
+            return items.len() - 1;
+        };
+        match answer.trim().parse::<usize>() {
+            Ok(choice) if (1..=items.len()).contains(&choice) => return choice - 1,
+            _ => println!("Enter a number between 1 and {}", items.len()),
+        }
+    }
+}
+
+pub fn select_action_menu() -> ActionSelection {
+    match select_item(&ACTION_ITEMS) {
         0 => ActionSelection::EnterChatMode,
         1 => ActionSelection::GitAddAndFixup,
         2 => ActionSelection::GitAddAndCommit,
@@ -0,0 +1,86 @@
+//! Fixtures of the unit tests: temporary Git repositories, the current directory, which is
+//! shared by every test of the process, and runs without network
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard};
+use tempfile::TempDir;
+
+static CWD: Mutex<()> = Mutex::new(());
+
+/// Current directory of the process for the lifetime of the guard, restored when dropped
+pub struct CurrentDir {
+    previous: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl CurrentDir {
+    /// Move to `dir`, waiting for the other tests moving around to be done
+    pub fn enter(dir: &Path) -> CurrentDir {
+        let lock = CWD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir).unwrap();
+        CurrentDir {
+            previous,
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for CurrentDir {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous);
+    }
+}
+
+/// Network disabled as with `--no-network` on the current test thread, until the guard is dropped
+pub struct Offline;
+
+impl Offline {
+    pub fn enter() -> Offline {
+        crate::network::disable();
+        Offline
+    }
+}
+
+impl Drop for Offline {
+    fn drop(&mut self) {
+        crate::network::enable();
+    }
+}
+
+/// Run `git` in `dir`, panicking when it fails
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=rv", "-c", "user.email=rv@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE")
+        .output()
+        .expect("git must be installed to run the tests");
+    assert!(
+        output.status.success(),
+        "git {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Temporary repository with `files` committed on `main`
+pub fn repo_with(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    git(dir.path(), &["add", "-A"]);
+    git(
+        dir.path(),
+        &["commit", "-q", "--allow-empty", "-m", "initial"],
+    );
+    dir
+}
@@ -0,0 +1,206 @@
+//! Ticket context: the ticket referenced by the branch or the commit messages (ex. `PAY-123`)
+//! is fetched from the issue tracker and sent with the review, so that the model can judge
+//! whether the change matches the requirement
+
+use crate::config::{RvConfig, TicketsConfig, TrackerKind};
+use crate::git_helpers;
+use crate::markup;
+use crate::network;
+use crate::variables::ticket_key;
+
+use anyhow::{Context, Result, bail};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use git2::Oid;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Requests to the tracker give up after this long, the review goes on without the ticket
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LINEAR_API_URL: &str = "https://api.linear.app/graphql";
+
+/// Ticket of the issue tracker
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub key: String,
+    pub title: String,
+    pub description: String,
+}
+
+/// Issue tracker the tickets are fetched from
+pub trait Tracker: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn fetch<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Ticket>>;
+}
+
+fn http_client() -> Result<reqwest::Client> {
+    network::ensure_allowed("The issue tracker")?;
+    Ok(reqwest::Client::builder()
+        .timeout(TRACKER_TIMEOUT)
+        .build()?)
+}
+
+/// Jira, through the REST API v2 (the description is plain text there)
+pub struct JiraTracker {
+    pub url: String,
+    pub user: String,
+    pub token: String,
+}
+
+impl Tracker for JiraTracker {
+    fn name(&self) -> &'static str {
+        "Jira"
+    }
+
+    fn fetch<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Ticket>> {
+        async move {
+            let url = format!(
+                "{}/rest/api/2/issue/{key}?fields=summary,description",
+                self.url.trim_end_matches('/')
+            );
+            let response = http_client()?
+                .get(&url)
+                .basic_auth(&self.user, Some(&self.token))
+                .header("Accept", "application/json")
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {}", self.url))?;
+            if !response.status().is_success() {
+                bail!("Jira answered {} for {key}", response.status());
+            }
+            let body: Value = response.json().await?;
+            let fields = &body["fields"];
+            Ok(Ticket {
+                key: key.to_string(),
+                title: fields["summary"].as_str().unwrap_or_default().to_string(),
+                description: fields["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Linear, through its GraphQL API (issues can be queried by identifier)
+pub struct LinearTracker {
+    pub token: String,
+}
+
+impl Tracker for LinearTracker {
+    fn name(&self) -> &'static str {
+        "Linear"
+    }
+
+    fn fetch<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Ticket>> {
+        async move {
+            let query = json!({
+                "query": "query($id: String!) { issue(id: $id) { title description } }",
+                "variables": { "id": key },
+            });
+            let response = http_client()?
+                .post(LINEAR_API_URL)
+                .header("Authorization", &self.token)
+                .header("Content-Type", "application/json")
+                .body(query.to_string())
+                .send()
+                .await
+                .context("Failed to reach Linear")?;
+            if !response.status().is_success() {
+                bail!("Linear answered {} for {key}", response.status());
+            }
+            let body: Value = response.json().await?;
+            let issue = &body["data"]["issue"];
+            if issue.is_null() {
+                bail!("Linear has no issue {key}");
+            }
+            Ok(Ticket {
+                key: key.to_string(),
+                title: issue["title"].as_str().unwrap_or_default().to_string(),
+                description: issue["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// API token of the configuration, or of the environment variable `var`
+fn api_token(config: &TicketsConfig, var: &str) -> Result<String> {
+    match &config.api_token {
+        Some(token) if !token.is_empty() => Ok(token.clone()),
+        _ => std::env::var(var)
+            .with_context(|| format!("Set `api_token` in [tickets] or the {var} variable")),
+    }
+}
+
+/// Tracker of the configuration, `None` when tickets aren't fetched
+pub fn tracker(config: &TicketsConfig) -> Result<Option<Box<dyn Tracker>>> {
+    Ok(match config.tracker {
+        TrackerKind::None => None,
+        TrackerKind::Jira => {
+            let (Some(url), Some(user)) = (&config.url, &config.user) else {
+                bail!("Jira needs `url` and `user` in [tickets]");
+            };
+            Some(Box::new(JiraTracker {
+                url: url.clone(),
+                user: user.clone(),
+                token: api_token(config, "JIRA_API_TOKEN")?,
+            }))
+        }
+        TrackerKind::Linear => Some(Box::new(LinearTracker {
+            token: api_token(config, "LINEAR_API_KEY")?,
+        })),
+    })
+}
+
+/// Ticket key referenced by the current branch, or else by the messages of `commits` (newest first)
+pub fn referenced_ticket(rvconfig: &RvConfig, commits: &[Oid]) -> Option<String> {
+    let prefixes = &rvconfig.ticket_prefixes;
+    let branch = git_helpers::open_repo().ok().and_then(|repo| {
+        let head = repo.head().ok()?;
+        head.is_branch()
+            .then(|| head.shorthand().map(str::to_string))
+            .flatten()
+    });
+    if let Some(key) = branch.and_then(|branch| ticket_key(&branch, prefixes)) {
+        return Some(key);
+    }
+    commits.iter().rev().find_map(|oid| {
+        let message = git_helpers::commit_message(*oid).ok()?;
+        ticket_key(&message, prefixes)
+    })
+}
+
+/// Fetch the ticket referenced by the reviewed change, if any
+pub async fn fetch_ticket(rvconfig: &RvConfig, commits: &[Oid]) -> Result<Option<Ticket>> {
+    let Some(tracker) = tracker(&rvconfig.tickets)? else {
+        return Ok(None);
+    };
+    let Some(key) = referenced_ticket(rvconfig, commits) else {
+        return Ok(None);
+    };
+    let ticket = tracker
+        .fetch(&key)
+        .await
+        .with_context(|| format!("Failed to fetch {key} from {}", tracker.name()))?;
+    Ok(Some(ticket))
+}
+
+/// Ticket as a context element of the system prompt, its description cut to `max_tokens`
+pub fn format_ticket(ticket: &Ticket, max_tokens: usize) -> String {
+    // ~4 characters per token, like `estimate_tokens`
+    let mut description: String = ticket.description.chars().take(max_tokens * 4).collect();
+    if description.len() < ticket.description.len() {
+        description.push_str("\n[...]");
+    }
+    markup::element(
+        "info",
+        &[("ticket", &ticket.key)],
+        &format!("{}\n\n{description}", ticket.title),
+    )
+}
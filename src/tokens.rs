@@ -0,0 +1,290 @@
+//! Token accounting of the prompts against the context window of the model, so that oversized
+//! reviews are truncated or refused before reaching the provider
+
+use crate::config::{ContextOverflow, LLMConfig, OpenAIProvider};
+
+use anyhow::{Result, bail};
+
+/// Context windows of the known models, matched in order against the model ID without its vendor
+const CONTEXT_WINDOWS: [(&str, usize); 22] = [
+    ("gpt-4.1", 1_047_576),
+    ("gpt-5", 400_000),
+    ("gpt-4o", 128_000),
+    ("o4-mini", 200_000),
+    ("o3", 200_000),
+    ("o1", 200_000),
+    ("claude", 200_000),
+    ("gemini-2", 1_048_576),
+    ("gemini-1.5", 1_048_576),
+    ("deepseek", 128_000),
+    ("qwen3-coder", 262_144),
+    ("qwen3-235b-a22b-2507", 262_144),
+    ("qwen3", 131_072),
+    ("qwen2.5-coder", 32_768),
+    ("devstral", 128_000),
+    ("codestral", 256_000),
+    ("mistral", 128_000),
+    ("llama-3", 128_000),
+    ("llama-4", 1_000_000),
+    ("grok", 256_000),
+    ("kimi", 131_072),
+    ("glm", 128_000),
+];
+
+/// Tokens kept for the response, at most a quarter of the window
+const RESERVED_OUTPUT_TOKENS: usize = 8192;
+
+/// Closing tags of the elements of the review prompt, whose content is escaped
+const ELEMENT_ENDS: [&str; 2] = ["\n</diff>\n", "\n</source>\n"];
+
+/// Tokens of a run of characters of the same class, as BPE tokenizers (cl100k, o200k) split them:
+/// words in pieces of a few letters, numbers in groups of 3 digits, whitespace runs merged,
+/// punctuation mostly in pairs. Rounded up, so that the count errs on the safe side
+fn run_tokens(class: CharClass, chars: usize) -> usize {
+    match class {
+        CharClass::Letter => chars.div_ceil(6),
+        CharClass::Digit => chars.div_ceil(3),
+        CharClass::Space => chars.div_ceil(16),
+        CharClass::Punctuation => chars.div_ceil(2),
+        // Scripts without spaces (CJK...) take about a token per character
+        CharClass::Other => chars,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Letter,
+    Digit,
+    Space,
+    Punctuation,
+    Other,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_ascii_alphabetic() || c == '_' {
+        CharClass::Letter
+    } else if c.is_ascii_digit() {
+        CharClass::Digit
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_ascii_punctuation() {
+        CharClass::Punctuation
+    } else if c.is_alphabetic() && (c as u32) < 0x2E80 {
+        // Accented Latin, Greek, Cyrillic...
+        CharClass::Letter
+    } else {
+        CharClass::Other
+    }
+}
+
+/// Tokens of `text`, from the runs a BPE pre-tokenizer would split it into
+pub fn count_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: Option<(CharClass, usize)> = None;
+    for c in text.chars() {
+        let class = class(c);
+        run = match run {
+            Some((current, chars)) if current == class => Some((current, chars + 1)),
+            Some((current, chars)) => {
+                // A single space is merged into the word following it
+                if !(current == CharClass::Space && chars == 1 && c != '\n') {
+                    tokens += run_tokens(current, chars);
+                }
+                Some((class, 1))
+            }
+            None => Some((class, 1)),
+        };
+    }
+    if let Some((class, chars)) = run {
+        tokens += run_tokens(class, chars);
+    }
+    tokens
+}
+
+/// Context window of the model of `config`: the configured one, else the known one. Ollama and
+/// custom servers set their own window, so only the configured one is used for them
+pub fn context_window(config: &LLMConfig) -> Option<usize> {
+    if config.context_window.is_some() {
+        return config.context_window;
+    }
+    if matches!(
+        config.provider,
+        OpenAIProvider::Ollama | OpenAIProvider::Custom
+    ) {
+        return None;
+    }
+    let model_id = config.model_id.to_ascii_lowercase();
+    let model = model_id.rsplit('/').next().unwrap_or(&model_id);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(pattern, _)| model.starts_with(pattern) || model.contains(&format!("-{pattern}")))
+        .map(|(_, window)| *window)
+}
+
+/// Tokens the prompt can take in `window`, the rest being kept for the response
+pub fn input_budget(window: usize) -> usize {
+    window.saturating_sub(RESERVED_OUTPUT_TOKENS.min(window / 4))
+}
+
+/// Diff or source left out of the review prompt
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeftOut {
+    /// Path of the element, as written in the prompt (escaped)
+    pub path: String,
+    pub is_source: bool,
+}
+
+impl std::fmt::Display for LeftOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.is_source { "source" } else { "diff" };
+        write!(f, "{} ({kind})", self.path)
+    }
+}
+
+/// Review prompt cut down to a token budget
+pub struct Truncation {
+    pub prompt: String,
+    /// Diffs and sources left out
+    pub left_out: Vec<LeftOut>,
+    /// Path of the element whose end was cut, when leaving whole elements out wasn't enough
+    pub cut: Option<String>,
+}
+
+/// Element of the review prompt (a diff or a source, with the text before it)
+struct Element<'a> {
+    text: &'a str,
+    tokens: usize,
+    is_source: bool,
+}
+
+impl Element<'_> {
+    fn path(&self) -> String {
+        self.text
+            .split("path=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or("?")
+            .to_string()
+    }
+}
+
+/// Split the review prompt of `ExpandedCommit::get_xml_structure` into its elements
+fn elements(prompt: &str) -> Vec<Element<'_>> {
+    let mut elements = Vec::new();
+    let mut rest = prompt;
+    while !rest.is_empty() {
+        let end = ELEMENT_ENDS
+            .iter()
+            .filter_map(|tag| rest.find(tag).map(|at| (at + tag.len(), *tag)))
+            .min_by_key(|(end, _)| *end);
+        let (text, is_source) = match end {
+            Some((end, tag)) => (&rest[..end], tag == ELEMENT_ENDS[1]),
+            None => (rest, false),
+        };
+        elements.push(Element {
+            text,
+            tokens: count_tokens(text),
+            is_source,
+        });
+        rest = &rest[text.len()..];
+    }
+    elements
+}
+
+/// Leave elements of `prompt` out until it takes at most `max_tokens`: the sources first, then
+/// the diffs from the last one; the end of the first diff is cut when it's too large by itself
+pub fn truncate_prompt(prompt: &str, max_tokens: usize) -> Truncation {
+    let elements = elements(prompt);
+    let mut kept = vec![true; elements.len()];
+    let mut total: usize = elements.iter().map(|element| element.tokens).sum();
+    let mut left_out = Vec::new();
+
+    let sources = (0..elements.len()).rev().filter(|&i| elements[i].is_source);
+    let diffs = (1..elements.len())
+        .rev()
+        .filter(|&i| !elements[i].is_source);
+    for i in sources.chain(diffs) {
+        if total <= max_tokens {
+            break;
+        }
+        kept[i] = false;
+        total -= elements[i].tokens;
+        left_out.push(LeftOut {
+            path: elements[i].path(),
+            is_source: elements[i].is_source,
+        });
+    }
+
+    let mut prompt = String::new();
+    let mut cut = None;
+    for (i, element) in elements.iter().enumerate() {
+        if !kept[i] {
+            continue;
+        }
+        if total <= max_tokens {
+            prompt.push_str(element.text);
+            continue;
+        }
+        // Keep the first lines of the element that fit, and close it again
+        let closing = ELEMENT_ENDS
+            .iter()
+            .find(|tag| element.text.ends_with(*tag))
+            .map_or("", |tag| &tag[..tag.len() - 1]);
+        let budget = max_tokens.saturating_sub(total - element.tokens + count_tokens(closing) + 8);
+        let mut used = 0;
+        for line in element.text[..element.text.len() - closing.len()].split_inclusive('\n') {
+            used += count_tokens(line);
+            if used > budget {
+                break;
+            }
+            prompt.push_str(line);
+        }
+        prompt.push_str("[...]");
+        prompt.push_str(closing);
+        prompt.push('\n');
+        total = max_tokens;
+        cut = Some(element.path());
+    }
+    if !left_out.is_empty() {
+        prompt.push_str(&format!(
+            "\n[Left out of the review to fit the context window of the model: {}]\n",
+            left_out
+                .iter()
+                .map(LeftOut::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Truncation {
+        prompt,
+        left_out,
+        cut,
+    }
+}
+
+/// Fail when `prompt_tokens` exceed the context window of the model of `config`
+pub fn ensure_fits(config: &LLMConfig, prompt_tokens: usize) -> Result<()> {
+    let Some(window) = context_window(config) else {
+        return Ok(());
+    };
+    let budget = input_budget(window);
+    if prompt_tokens > budget {
+        bail!(overflow_message(config, prompt_tokens, window));
+    }
+    Ok(())
+}
+
+/// Error of a prompt exceeding the context window, with the ways out
+pub fn overflow_message(config: &LLMConfig, prompt_tokens: usize, window: usize) -> String {
+    let hint = match config.context_overflow {
+        ContextOverflow::Refuse => {
+            "; review fewer files (`-f`, `-d`, `-g`), use a model with a larger window or set `context_overflow = \"truncate\"`"
+        }
+        ContextOverflow::Truncate => "",
+    };
+    format!(
+        "The prompt takes ~{prompt_tokens} tokens, more than the {} tokens the {window}-token context window of {} leaves for it{hint}",
+        input_budget(window),
+        config.model_id,
+    )
+}
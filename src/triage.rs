@@ -0,0 +1,270 @@
+//! `--triage`: after the review, go through its findings one by one to accept, suppress or
+//! assign them. Suppressions are written to `.rv_suppressions`; the accepted and assigned
+//! findings can be saved as a task list, opened as GitHub issues or start a fix branch.
+
+use crate::findings::{FindingItem, finding_items, fingerprint};
+use crate::git_helpers;
+use crate::report::{Report, Reporter};
+use crate::suppress::{append_suppression, is_date};
+use crate::term_helpers;
+
+use anyhow::{Context, Result, bail};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::path::PathBuf;
+
+/// Task list of the accepted findings, in the repository
+pub const TRIAGE_FILE: &str = ".rv/triage.md";
+
+const DECISION_ITEMS: [&str; 5] = [
+    "Accept (to fix)",
+    "Suppress (accepted trade-off, written to .rv_suppressions)",
+    "Assign to someone",
+    "Skip",
+    "Stop triage",
+];
+
+/// Destination of the accepted findings
+#[derive(Debug, Clone, Copy)]
+enum Export {
+    TaskList,
+    Issues,
+    FixBranch,
+    Done,
+}
+
+const EXPORTS: &[(Export, &str)] = &[
+    (Export::TaskList, "Save the task list to .rv/triage.md"),
+    (Export::Issues, "Open a GitHub issue for each finding"),
+    (Export::FixBranch, "Start a fix branch with the task list"),
+    (Export::Done, "Done"),
+];
+
+/// Finding kept to be fixed, with its assignee if any
+struct AcceptedFinding {
+    fingerprint: String,
+    title: String,
+    text: String,
+    assignee: Option<String>,
+}
+
+/// Counts of the decisions of a triage
+#[derive(Debug, Default)]
+struct TriageSummary {
+    accepted: usize,
+    suppressed: usize,
+    assigned: usize,
+    skipped: usize,
+}
+
+/// Ask for a non-empty answer; `None` when the answer is empty
+fn ask(prompt: &str) -> Option<String> {
+    let answer = term_helpers::get_terminal_input(prompt.to_string());
+    let answer = answer.trim();
+    (!answer.is_empty()).then(|| answer.to_string())
+}
+
+/// Ask for the justification and the expiry of a suppression, then write it
+fn suppress(finding_fingerprint: &str) -> Result<bool> {
+    let Some(justification) = ask("Justification: ") else {
+        println!("A suppression needs a justification, the finding was skipped.");
+        return Ok(false);
+    };
+    let expires = loop {
+        match ask("Expires on (YYYY-MM-DD, empty for never): ") {
+            Some(date) if !is_date(&date) => println!("Expected a date as YYYY-MM-DD"),
+            date => break date,
+        }
+    };
+    let path = append_suppression(finding_fingerprint, expires.as_deref(), &justification)?;
+    println!("Suppressed in {}", path.display());
+    Ok(true)
+}
+
+/// Task list of the accepted findings, in markdown
+fn task_list(target: &str, accepted: &[AcceptedFinding]) -> String {
+    let mut tasks = format!("# Findings to fix ({target})\n\n");
+    for finding in accepted {
+        let assignee = finding
+            .assignee
+            .as_ref()
+            .map(|name| format!(" (assigned to {name})"))
+            .unwrap_or_default();
+        tasks.push_str(&format!(
+            "- [ ] {}{assignee} `{}`\n",
+            finding.title, finding.fingerprint
+        ));
+        // Details of the finding, under its title
+        for line in finding.text.lines().skip(1) {
+            tasks.push_str(&format!("  {}\n", line.trim_end()));
+        }
+    }
+    tasks
+}
+
+fn triage_path() -> PathBuf {
+    match git_helpers::open_repo()
+        .ok()
+        .and_then(|repo| repo.workdir().map(|workdir| workdir.join(TRIAGE_FILE)))
+    {
+        Some(path) => path,
+        None => PathBuf::from(TRIAGE_FILE),
+    }
+}
+
+fn save_task_list(target: &str, accepted: &[AcceptedFinding]) -> Result<PathBuf> {
+    let path = triage_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, task_list(target, accepted))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Open a GitHub issue for every accepted finding, with `gh issue create`
+fn open_issues(target: &str, accepted: &[AcceptedFinding]) -> Result<()> {
+    crate::network::ensure_allowed("`gh issue create`")?;
+    for finding in accepted {
+        let body = format!(
+            "Found by rv in the review of {target}.\n\n```\n{}\n```\n\nFingerprint: `{}`",
+            finding.text, finding.fingerprint
+        );
+        let mut command = git_helpers::repo_command("gh");
+        command.args([
+            "issue",
+            "create",
+            "--title",
+            &finding.title,
+            "--body",
+            &body,
+        ]);
+        if let Some(assignee) = &finding.assignee {
+            command.args(["--assignee", assignee]);
+        }
+        let output = command
+            .output()
+            .context("Failed to invoke `gh issue create`")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("`gh issue create` failed: {stderr}");
+        }
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+    }
+    Ok(())
+}
+
+/// Create and switch to a `rv/fix-...` branch, then save the task list in it
+fn start_fix_branch(report: &Report, accepted: &[AcceptedFinding]) -> Result<()> {
+    let name = match &report.commit {
+        Some(commit) => format!("rv/fix-{commit:.8}"),
+        None => format!("rv/fix-{}", accepted[0].fingerprint),
+    };
+    let status = git_helpers::repo_command("git")
+        .args(["switch", "-c", &name])
+        .status()
+        .context("Failed to invoke `git switch`")?;
+    if !status.success() {
+        bail!("Failed to create the branch {name}");
+    }
+    let path = save_task_list(&report.target, accepted)?;
+    println!(
+        "Switched to {name}; the findings to fix are listed in {}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Decide what to do with every finding of `report`, then export the accepted ones
+fn triage(report: &Report) -> Result<()> {
+    let items: Vec<FindingItem> = finding_items(&report.review);
+    if items.is_empty() {
+        println!("\n[TRIAGE] No findings to triage.");
+        return Ok(());
+    }
+
+    let lines: Vec<&str> = report.review.lines().collect();
+    let mut summary = TriageSummary::default();
+    let mut accepted: Vec<AcceptedFinding> = Vec::new();
+    println!("\n[TRIAGE] {} findings of {}", items.len(), report.target);
+    for (idx, item) in items.iter().enumerate() {
+        let finding_fingerprint = fingerprint(&report.review, item);
+        let text = lines[item.lines.clone()].join("\n");
+        println!(
+            "\n[{}/{}] {finding_fingerprint}\n{text}\n",
+            idx + 1,
+            items.len()
+        );
+
+        let assignee = match term_helpers::select_item(&DECISION_ITEMS) {
+            0 => None,
+            1 => {
+                if suppress(&finding_fingerprint)? {
+                    summary.suppressed += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+                continue;
+            }
+            2 => match ask("Assignee: ") {
+                Some(assignee) => Some(assignee),
+                None => {
+                    summary.skipped += 1;
+                    continue;
+                }
+            },
+            3 => {
+                summary.skipped += 1;
+                continue;
+            }
+            _ => {
+                summary.skipped += items.len() - idx;
+                break;
+            }
+        };
+        if assignee.is_some() {
+            summary.assigned += 1;
+        } else {
+            summary.accepted += 1;
+        }
+        accepted.push(AcceptedFinding {
+            fingerprint: finding_fingerprint,
+            title: item.title.clone(),
+            text,
+            assignee,
+        });
+    }
+
+    println!(
+        "\n[TRIAGE] {} accepted, {} assigned, {} suppressed, {} skipped",
+        summary.accepted, summary.assigned, summary.suppressed, summary.skipped
+    );
+    if accepted.is_empty() {
+        return Ok(());
+    }
+
+    let labels: Vec<&str> = EXPORTS.iter().map(|(_, label)| *label).collect();
+    match EXPORTS[term_helpers::select_item(&labels)].0 {
+        Export::TaskList => {
+            let path = save_task_list(&report.target, &accepted)?;
+            println!("Saved the task list to {}", path.display());
+        }
+        Export::Issues => open_issues(&report.target, &accepted)?,
+        Export::FixBranch => start_fix_branch(report, &accepted)?,
+        Export::Done => {}
+    }
+    Ok(())
+}
+
+/// Run the triage of every report, after the other outputs
+pub struct TriageReporter;
+
+impl Reporter for TriageReporter {
+    fn name(&self) -> &'static str {
+        "triage"
+    }
+
+    fn report<'a>(&'a self, report: &'a Report) -> BoxFuture<'a, Result<()>> {
+        async move { triage(report) }.boxed()
+    }
+}
@@ -0,0 +1,113 @@
+//! Template variables of the guidelines and custom prompts (ex. `{{branch}}`, `{{ticket}}`),
+//! resolved from git, the environment and the configuration before the prompt is packed
+
+use crate::config::RvConfig;
+use crate::git_helpers;
+
+use std::collections::BTreeMap;
+
+/// Prefix of the variables read from the environment (ex. `{{env.TEAM}}`)
+const ENV_PREFIX: &str = "env.";
+
+/// Values of the template variables
+#[derive(Debug, Clone, Default)]
+pub struct PromptVariables {
+    values: BTreeMap<String, String>,
+}
+
+/// First ticket key of `text` (ex. `PAY-123` in `feature/pay-123-refunds`), restricted to the
+/// project keys of `prefixes` when there are any
+pub fn ticket_key(text: &str, prefixes: &[String]) -> Option<String> {
+    for word in text.split(|c: char| !c.is_ascii_alphanumeric() && c != '-') {
+        let parts: Vec<&str> = word.split('-').collect();
+        for pair in parts.windows(2) {
+            let (key, number) = (pair[0], pair[1]);
+            let is_key = key.starts_with(|c: char| c.is_ascii_alphabetic())
+                && key.chars().all(|c| c.is_ascii_alphanumeric());
+            let is_number = !number.is_empty() && number.chars().all(|c| c.is_ascii_digit());
+            let allowed = prefixes.is_empty()
+                || prefixes
+                    .iter()
+                    .any(|prefix| prefix.eq_ignore_ascii_case(key));
+            if is_key && is_number && allowed {
+                return Some(format!("{}-{number}", key.to_ascii_uppercase()));
+            }
+        }
+    }
+    None
+}
+
+/// Ticket of a branch name: its ticket key, or without `prefixes` its first issue number
+/// (ex. `123` in `fix/123-crash`)
+pub fn ticket_from_branch(branch: &str, prefixes: &[String]) -> Option<String> {
+    if let Some(key) = ticket_key(branch, prefixes) {
+        return Some(key);
+    }
+    if !prefixes.is_empty() {
+        return None;
+    }
+    branch
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+impl PromptVariables {
+    /// Variables of the current repository: `branch`, `author`, `repo_name` and `ticket`, plus
+    /// the `prompt_variables` of the configuration (which take precedence)
+    pub fn collect(rvconfig: &RvConfig) -> PromptVariables {
+        let mut values: BTreeMap<String, String> = BTreeMap::new();
+        if let Ok(repo) = git_helpers::open_repo() {
+            if let Ok(head) = repo.head()
+                && head.is_branch()
+                && let Some(branch) = head.shorthand()
+            {
+                values.insert(String::from("branch"), branch.to_string());
+                if let Some(ticket) = ticket_from_branch(branch, &rvconfig.ticket_prefixes) {
+                    values.insert(String::from("ticket"), ticket);
+                }
+            }
+            if let Ok(author) = repo.config().and_then(|c| c.get_string("user.name")) {
+                values.insert(String::from("author"), author);
+            }
+            if let Some(name) = repo.workdir().and_then(|w| w.file_name()) {
+                values.insert(
+                    String::from("repo_name"),
+                    name.to_string_lossy().to_string(),
+                );
+            }
+        }
+        for (name, value) in &rvconfig.prompt_variables {
+            values.insert(name.clone(), value.clone());
+        }
+        PromptVariables { values }
+    }
+
+    /// Value of a variable; `env.NAME` reads the environment variable `NAME`
+    fn value(&self, name: &str) -> Option<String> {
+        if let Some(var) = name.strip_prefix(ENV_PREFIX) {
+            return std::env::var(var).ok();
+        }
+        self.values.get(name).cloned()
+    }
+
+    /// Replace the `{{name}}` variables of `text`; unknown ones are left as they are
+    pub fn resolve(&self, text: &str) -> String {
+        let mut resolved = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + end].trim();
+            resolved.push_str(&rest[..start]);
+            match self.value(name) {
+                Some(value) => resolved.push_str(&value),
+                None => resolved.push_str(&rest[start..start + 2 + end + 2]),
+            }
+            rest = &rest[start + 2 + end + 2..];
+        }
+        resolved.push_str(rest);
+        resolved
+    }
+}